@@ -0,0 +1,107 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+use bitcoin::Network;
+use clap::Parser;
+use pl_00_intro::ch3_keys::bip39::Mnemonic;
+use pl_00_intro::ch3_keys::exercises::SimpleKeysManager;
+use pl_00_intro::ch3_keys::policy::{PolicyConfig, PolicySigner, DEFAULT_MAX_FEERATE_PER_KW};
+use pl_00_intro::ch3_keys::remote_signer::serve;
+use pl_00_intro::interactive::keys::read_secret;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+
+/// Holds our keys and signs for the node over a local socket
+#[derive(Parser)]
+#[command(name = "Programming Lightning signer daemon")]
+#[command(version = "1.0")]
+struct Args {
+    #[arg(short = 's', long, default_value = "signer.sock", help = "Socket to listen on")]
+    socket: PathBuf,
+    #[arg(short = 'm', long, help = "File holding the BIP39 mnemonic, - for stdin")]
+    mnemonic_file: PathBuf,
+    #[arg(short = 'p', long, help = "File holding an optional BIP39 passphrase, - for stdin")]
+    passphrase_file: Option<PathBuf>,
+    #[arg(short = 'n', long, default_value = "regtest", help = "Network")]
+    network: Network,
+    #[arg(long, default_value = "signer-policy.json", help = "File the signing policy keeps its state in")]
+    policy_state: PathBuf,
+    #[arg(long, default_value_t = DEFAULT_MAX_FEERATE_PER_KW, help = "Highest fee rate we sign for, in sat/kw")]
+    max_feerate_per_kw: u64,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let words = match read_secret(&args.mnemonic_file) {
+        Ok(words) => words,
+        Err(e) => {
+            eprintln!("Can't read {}: {}", args.mnemonic_file.display(), e);
+            process::exit(1);
+        }
+    };
+    let mnemonic = match Mnemonic::parse(&words) {
+        Ok(mnemonic) => mnemonic,
+        Err(e) => {
+            eprintln!("Invalid mnemonic: {}", e);
+            process::exit(1);
+        }
+    };
+    let passphrase = match &args.passphrase_file {
+        Some(path) => match read_secret(path) {
+            Ok(passphrase) => passphrase,
+            Err(e) => {
+                eprintln!("Can't read {}: {}", path.display(), e);
+                process::exit(1);
+            }
+        },
+        None => String::new(),
+    };
+    let keys_manager = SimpleKeysManager::from_mnemonic(&mnemonic, &passphrase, args.network);
+    let node_id = keys_manager.node_id;
+
+    let mut config = PolicyConfig::for_keys_manager(&keys_manager);
+    config.max_feerate_per_kw = args.max_feerate_per_kw;
+    let signer = match PolicySigner::with_state_file(keys_manager, config, args.policy_state.clone()) {
+        Ok(signer) => signer,
+        Err(e) => {
+            eprintln!("Can't load {}: {}", args.policy_state.display(), e);
+            process::exit(1);
+        }
+    };
+    // The index of new channels starts over with every run, so pick up after
+    // the channels of earlier runs, whose requests we keep answering.
+    for channel_keys_id in signer.state().channel_keys_ids() {
+        signer.inner().restore_channel_keys_id(channel_keys_id);
+    }
+
+    // A socket left behind by an earlier run would make bind fail. Anything
+    // else at that path is not ours to remove.
+    if let Ok(metadata) = fs::symlink_metadata(&args.socket) {
+        if metadata.file_type().is_socket() {
+            let _ = fs::remove_file(&args.socket);
+        }
+    }
+    // Whoever can connect can sign with our keys, so the socket is ours
+    // alone from the moment it exists.
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(&args.socket);
+    unsafe { libc::umask(umask) };
+    let listener = match listener {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Can't listen on {}: {}", args.socket.display(), e);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = fs::set_permissions(&args.socket, fs::Permissions::from_mode(0o600)) {
+        eprintln!("Can't restrict {} to our user: {}", args.socket.display(), e);
+        process::exit(1);
+    }
+
+    println!("Node ID: {}", node_id);
+    println!("Signing on {}", args.socket.display());
+    serve(listener, Arc::new(signer));
+}
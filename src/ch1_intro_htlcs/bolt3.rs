@@ -0,0 +1,560 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! Data-driven conformance checks against the BOLT 3 appendix test vectors.
+//!
+//! The vectors live in `bolt3_vectors.json` next to this file and are bundled
+//! into the crate with `include_str!`. Each check runs a vector through the
+//! builders in `ch1_intro_htlcs::solutions` and reports a [`VectorResult`];
+//! failures carry a byte-level diff of the serialized data.
+use crate::ch1_intro_htlcs::solutions::{
+    build_htlc_success_transaction, build_htlc_success_witness, build_htlc_timeout_transaction,
+    build_htlc_timeout_witness, build_fee_aware_commitment_transaction, htlc_second_stage_fee,
+    derive_private_key, derive_private_revocation_key, derive_public_key, generate_revocation_pubkey, htlc_witness_script, two_of_two_multisig_witness_script,
+    ChannelType, HtlcOutput,
+};
+use crate::internal::helper::{p2wpkh_output_script, pubkey_from_private_key, secp256k1_private_key};
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::ecdsa::Signature as BitcoinSignature;
+use bitcoin::hash_types::Txid;
+use bitcoin::hashes::ripemd160::Hash as Ripemd160;
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::Hash;
+use bitcoin::script::ScriptBuf;
+use bitcoin::secp256k1::ecdsa::Signature;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, Witness};
+use serde::Deserialize;
+use std::fmt;
+
+const VECTORS_JSON: &str = include_str!("bolt3_vectors.json");
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Bolt3Vectors {
+    pub key_derivation: KeyDerivationVector,
+    pub channel: ChannelVector,
+    pub htlcs: Vec<HtlcVector>,
+    pub commitments: Vec<CommitmentVector>,
+}
+
+/// BOLT 3 Appendix E.
+#[derive(Clone, Debug, Deserialize)]
+pub struct KeyDerivationVector {
+    pub base_secret: String,
+    pub per_commitment_secret: String,
+    pub base_point: String,
+    pub per_commitment_point: String,
+    pub localpubkey: String,
+    pub localprivkey: String,
+    pub revocationpubkey: String,
+    pub revocationprivkey: String,
+}
+
+/// The channel shared by every BOLT 3 Appendix C and F commitment vector,
+/// seen from the funder's ("local") side.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChannelVector {
+    pub funding_txid: String,
+    pub funding_output_index: u32,
+    pub funding_amount_satoshis: u64,
+    pub commitment_number: u64,
+    pub to_self_delay: u16,
+    pub local_funding_privkey: String,
+    pub local_funding_pubkey: String,
+    pub remote_funding_pubkey: String,
+    pub local_payment_basepoint: String,
+    pub remote_payment_basepoint: String,
+    pub local_delayed_payment_basepoint: String,
+    pub local_htlc_basepoint: String,
+    pub remote_htlc_basepoint: String,
+    pub remote_revocation_basepoint: String,
+    pub per_commitment_secret: String,
+    pub per_commitment_point: String,
+    pub local_revocation_pubkey: String,
+    pub local_delayed_pubkey: String,
+    pub local_htlc_pubkey: String,
+    pub remote_htlc_pubkey: String,
+    pub funding_witness_script: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct HtlcVector {
+    pub id: u64,
+    pub offered: bool,
+    pub amount_msat: u64,
+    pub cltv_expiry: u32,
+    pub payment_preimage: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CommitmentVector {
+    pub name: String,
+    pub anchors: bool,
+    pub feerate_per_kw: u32,
+    pub dust_limit_satoshis: u64,
+    pub to_local_msat: u64,
+    pub to_remote_msat: u64,
+    /// Every HTLC pending on the commitment, trimmed or not.
+    pub htlcs: Vec<u64>,
+    /// The HTLCs that end up with an output on the commitment.
+    pub untrimmed_htlcs: Vec<u64>,
+    /// Output amounts as they appear in the commitment; 0 if the output was dropped.
+    pub to_local_sat: u64,
+    pub to_remote_sat: u64,
+    pub remote_signature: String,
+    pub local_signature: String,
+    pub tx_hex: String,
+    pub htlc_txs: Vec<HtlcTxVector>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct HtlcTxVector {
+    pub remote_htlc_signature: String,
+    pub local_htlc_signature: String,
+    pub tx_hex: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VectorResult {
+    pub name: String,
+    pub outcome: Outcome,
+}
+
+impl VectorResult {
+    fn pass(name: &str) -> Self {
+        VectorResult { name: name.to_string(), outcome: Outcome::Pass }
+    }
+
+    fn fail(name: &str, reason: String) -> Self {
+        VectorResult { name: name.to_string(), outcome: Outcome::Fail(reason) }
+    }
+
+    fn compare(name: &str, what: &str, expected: &[u8], actual: &[u8]) -> Self {
+        match byte_diff(expected, actual) {
+            None => VectorResult::pass(name),
+            Some(diff) => VectorResult::fail(name, format!("{} mismatch\n{}", what, diff)),
+        }
+    }
+}
+
+impl fmt::Display for VectorResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.outcome {
+            Outcome::Pass => write!(f, "PASS {}", self.name),
+            Outcome::Fail(reason) => write!(f, "FAIL {}\n{}", self.name, reason),
+        }
+    }
+}
+
+pub fn load_vectors() -> Bolt3Vectors {
+    serde_json::from_str(VECTORS_JSON).expect("bolt3_vectors.json is valid")
+}
+
+/// Returns `None` if the two byte strings are equal, otherwise a description of
+/// the first differing offset with a few bytes of hex context from each side.
+pub fn byte_diff(expected: &[u8], actual: &[u8]) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+
+    let offset = expected
+        .iter()
+        .zip(actual.iter())
+        .position(|(e, a)| e != a)
+        .unwrap_or(expected.len().min(actual.len()));
+
+    let start = offset.saturating_sub(8);
+    let window = |bytes: &[u8]| hex::encode(&bytes[start.min(bytes.len())..(offset + 16).min(bytes.len())]);
+
+    Some(format!(
+        "first difference at byte {} (expected {} bytes, got {} bytes)\n  expected[{}..]: {}\n  actual[{}..]:   {}",
+        offset,
+        expected.len(),
+        actual.len(),
+        start,
+        window(expected),
+        start,
+        window(actual),
+    ))
+}
+
+fn pubkey(hex_str: &str) -> PublicKey {
+    hex_str.parse::<PublicKey>().expect("valid public key in vector")
+}
+
+fn bytes32(hex_str: &str) -> [u8; 32] {
+    hex::decode(hex_str)
+        .expect("valid hex in vector")
+        .try_into()
+        .expect("32 bytes")
+}
+
+fn decode_tx(hex_str: &str) -> Transaction {
+    deserialize(&hex::decode(hex_str).expect("valid hex in vector")).expect("valid transaction in vector")
+}
+
+fn signature(der_hex: &str, sighash_type: EcdsaSighashType) -> BitcoinSignature {
+    let signature =
+        Signature::from_der(&hex::decode(der_hex).expect("valid hex in vector")).expect("valid DER signature");
+    BitcoinSignature { signature, sighash_type }
+}
+
+fn sighash_all(der_hex: &str) -> BitcoinSignature {
+    signature(der_hex, EcdsaSighashType::All)
+}
+
+impl CommitmentVector {
+    pub fn channel_type(&self) -> ChannelType {
+        if self.anchors {
+            ChannelType::AnchorsZeroFeeHtlcTx
+        } else {
+            ChannelType::StaticRemoteKey
+        }
+    }
+}
+
+/// Converts a vector HTLC into the crate's representation. Commitment
+/// transactions only carry whole satoshis.
+pub fn htlc_output(htlc: &HtlcVector) -> HtlcOutput {
+    let payment_hash = Sha256::hash(&bytes32(&htlc.payment_preimage)).to_byte_array();
+    HtlcOutput {
+        offered: htlc.offered,
+        amount: htlc.amount_msat / 1000,
+        cltv_expiry: htlc.cltv_expiry,
+        payment_hash160: Ripemd160::hash(&payment_hash).to_byte_array(),
+    }
+}
+
+fn funding_txin(channel: &ChannelVector) -> TxIn {
+    TxIn {
+        previous_output: OutPoint {
+            txid: channel.funding_txid.parse::<Txid>().expect("valid funding txid"),
+            vout: channel.funding_output_index,
+        },
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence::MAX,
+        witness: Witness::new(),
+    }
+}
+
+fn untrimmed_htlcs<'a>(vectors: &'a Bolt3Vectors, commitment: &CommitmentVector) -> Vec<&'a HtlcVector> {
+    commitment
+        .untrimmed_htlcs
+        .iter()
+        .map(|id| vectors.htlcs.iter().find(|htlc| htlc.id == *id).expect("HTLC id defined in vector"))
+        .collect()
+}
+
+pub fn check_key_derivation(vector: &KeyDerivationVector) -> Vec<VectorResult> {
+    let mut results = Vec::new();
+
+    let base_point = pubkey_from_private_key(&bytes32(&vector.base_secret));
+    results.push(VectorResult::compare(
+        "key derivation: base_point",
+        "base_point",
+        &pubkey(&vector.base_point).serialize(),
+        &base_point.serialize(),
+    ));
+
+    let per_commitment_point = pubkey_from_private_key(&bytes32(&vector.per_commitment_secret));
+    results.push(VectorResult::compare(
+        "key derivation: per_commitment_point",
+        "per_commitment_point",
+        &pubkey(&vector.per_commitment_point).serialize(),
+        &per_commitment_point.serialize(),
+    ));
+
+    let localpubkey = derive_public_key(&base_point, &per_commitment_point);
+    results.push(VectorResult::compare(
+        "key derivation: localpubkey",
+        "localpubkey",
+        &pubkey(&vector.localpubkey).serialize(),
+        &localpubkey.serialize(),
+    ));
+
+    let localprivkey =
+        derive_private_key(&secp256k1_private_key(&bytes32(&vector.base_secret)), &per_commitment_point);
+    results.push(VectorResult::compare(
+        "key derivation: localprivkey",
+        "localprivkey",
+        &bytes32(&vector.localprivkey),
+        &localprivkey.secret_bytes(),
+    ));
+
+    let revocation_pubkey = generate_revocation_pubkey(base_point, per_commitment_point);
+    results.push(VectorResult::compare(
+        "key derivation: revocationpubkey",
+        "revocationpubkey",
+        &pubkey(&vector.revocationpubkey).serialize(),
+        &revocation_pubkey.serialize(),
+    ));
+
+    let revocation_private_key = derive_private_revocation_key(
+        &secp256k1_private_key(&bytes32(&vector.per_commitment_secret)),
+        &secp256k1_private_key(&bytes32(&vector.base_secret)),
+    );
+    results.push(VectorResult::compare(
+        "key derivation: revocationprivkey",
+        "revocationprivkey",
+        &bytes32(&vector.revocationprivkey),
+        &revocation_private_key.secret_bytes(),
+    ));
+
+    results
+}
+
+pub fn check_channel(channel: &ChannelVector) -> Vec<VectorResult> {
+    let mut results = Vec::new();
+
+    let funding_script = two_of_two_multisig_witness_script(
+        &pubkey(&channel.local_funding_pubkey),
+        &pubkey(&channel.remote_funding_pubkey),
+    );
+    results.push(VectorResult::compare(
+        "channel: funding witness script",
+        "funding witness script",
+        &hex::decode(&channel.funding_witness_script).expect("valid hex in vector"),
+        funding_script.as_bytes(),
+    ));
+
+    let revocation_pubkey = generate_revocation_pubkey(
+        pubkey(&channel.remote_revocation_basepoint),
+        pubkey(&channel.per_commitment_point),
+    );
+    results.push(VectorResult::compare(
+        "channel: local revocation pubkey",
+        "revocation pubkey",
+        &pubkey(&channel.local_revocation_pubkey).serialize(),
+        &revocation_pubkey.serialize(),
+    ));
+
+    let per_commitment_point = pubkey(&channel.per_commitment_point);
+    let derived_keys = [
+        ("channel: local delayed pubkey", &channel.local_delayed_payment_basepoint, &channel.local_delayed_pubkey),
+        ("channel: local htlc pubkey", &channel.local_htlc_basepoint, &channel.local_htlc_pubkey),
+        ("channel: remote htlc pubkey", &channel.remote_htlc_basepoint, &channel.remote_htlc_pubkey),
+    ];
+    for (name, basepoint, expected) in derived_keys {
+        let derived = derive_public_key(&pubkey(basepoint), &per_commitment_point);
+        results.push(VectorResult::compare(name, "derived pubkey", &pubkey(expected).serialize(), &derived.serialize()));
+    }
+
+    results
+}
+
+/// Witness spending the 2-of-2 funding output. Signatures follow the
+/// lexicographic order of the funding pubkeys in the witness script.
+fn funding_witness(channel: &ChannelVector, commitment: &CommitmentVector) -> Witness {
+    let local_signature = sighash_all(&commitment.local_signature);
+    let remote_signature = sighash_all(&commitment.remote_signature);
+    let funding_script = hex::decode(&channel.funding_witness_script).expect("valid hex in vector");
+
+    let (first, second) =
+        if pubkey(&channel.local_funding_pubkey) < pubkey(&channel.remote_funding_pubkey) {
+            (local_signature, remote_signature)
+        } else {
+            (remote_signature, local_signature)
+        };
+
+    let mut witness = Witness::new();
+    witness.push(Vec::new());
+    witness.push(first.to_vec());
+    witness.push(second.to_vec());
+    witness.push(funding_script);
+    witness
+}
+
+/// Rebuilds the funder's commitment transaction from the vector's balances,
+/// feerate and dust limit, attaches the vector's signatures and compares the
+/// fully signed transaction byte for byte.
+pub fn check_commitment(vectors: &Bolt3Vectors, commitment: &CommitmentVector) -> VectorResult {
+    let name = commitment.name.as_str();
+    let channel = &vectors.channel;
+
+    let htlcs: Vec<HtlcOutput> = commitment
+        .htlcs
+        .iter()
+        .map(|id| htlc_output(vectors.htlcs.iter().find(|htlc| htlc.id == *id).expect("HTLC id defined in vector")))
+        .collect();
+
+    let (mut tx, breakdown) = build_fee_aware_commitment_transaction(
+        funding_txin(channel),
+        channel.commitment_number,
+        &pubkey(&channel.local_payment_basepoint),
+        &pubkey(&channel.remote_payment_basepoint),
+        true,
+        commitment.channel_type(),
+        &pubkey(&channel.local_funding_pubkey),
+        &pubkey(&channel.remote_funding_pubkey),
+        &pubkey(&channel.local_revocation_pubkey),
+        &pubkey(&channel.remote_htlc_pubkey),
+        &pubkey(&channel.local_htlc_pubkey),
+        &pubkey(&channel.local_delayed_pubkey),
+        channel.to_self_delay as i64,
+        commitment.feerate_per_kw,
+        commitment.dust_limit_satoshis,
+        &htlcs,
+        commitment.to_local_msat,
+        commitment.to_remote_msat,
+    );
+
+    let expected_untrimmed: Vec<HtlcOutput> = untrimmed_htlcs(vectors, commitment)
+        .into_iter()
+        .map(htlc_output)
+        .collect();
+    if breakdown.untrimmed_htlcs != expected_untrimmed {
+        return VectorResult::fail(
+            name,
+            format!(
+                "expected {} untrimmed HTLCs, got {}: {:?}",
+                expected_untrimmed.len(),
+                breakdown.untrimmed_htlcs.len(),
+                breakdown.trimmed.iter().map(|trimmed| trimmed.to_string()).collect::<Vec<_>>(),
+            ),
+        );
+    }
+
+    tx.input[0].witness = funding_witness(channel, commitment);
+
+    VectorResult::compare(
+        name,
+        "signed commitment transaction",
+        &hex::decode(&commitment.tx_hex).expect("valid hex in vector"),
+        &serialize(&tx),
+    )
+}
+
+/// Rebuilds and signs every second-stage HTLC transaction of a commitment
+/// vector and compares the fully signed transaction byte for byte. The
+/// counterparty's signature is also checked against the rebuilt transaction
+/// using the channel type's HTLC sighash type.
+pub fn check_htlc_transactions(vectors: &Bolt3Vectors, commitment: &CommitmentVector) -> Vec<VectorResult> {
+    let channel = &vectors.channel;
+    let channel_type = commitment.channel_type();
+    let secp = Secp256k1::verification_only();
+    let expected_commitment = decode_tx(&commitment.tx_hex);
+    let commitment_txid = expected_commitment.compute_txid();
+
+    let revocation_pubkey = pubkey(&channel.local_revocation_pubkey);
+    let to_local_delayed_pubkey = pubkey(&channel.local_delayed_pubkey);
+    let remote_htlc_pubkey = pubkey(&channel.remote_htlc_pubkey);
+    let local_htlc_pubkey = pubkey(&channel.local_htlc_pubkey);
+    let to_self_delay = channel.to_self_delay as i64;
+
+    let mut results = Vec::new();
+    let mut used = Vec::new();
+
+    for (idx, htlc_tx) in commitment.htlc_txs.iter().enumerate() {
+        let name = format!("{}: htlc tx #{}", commitment.name, idx);
+
+        let expected = decode_tx(&htlc_tx.tx_hex);
+        let vout = expected.input[0].previous_output.vout;
+        let script_pubkey = &expected_commitment.output[vout as usize].script_pubkey;
+
+        // Offered HTLCs with the same amount and payment hash share a script;
+        // their outputs are ordered by cltv_expiry, so take the lowest unused one.
+        let matched = untrimmed_htlcs(vectors, commitment)
+            .into_iter()
+            .filter(|htlc| !used.contains(&htlc.id))
+            .filter(|htlc| {
+                let script = htlc_witness_script(
+                    channel_type,
+                    &htlc_output(htlc),
+                    &revocation_pubkey,
+                    &remote_htlc_pubkey,
+                    &local_htlc_pubkey,
+                );
+                &script.to_p2wsh() == script_pubkey
+            })
+            .min_by_key(|htlc| htlc.cltv_expiry);
+
+        let htlc = match matched {
+            Some(htlc) => htlc,
+            None => {
+                results.push(VectorResult::fail(&name, format!("no HTLC script matches commitment output {}", vout)));
+                continue;
+            }
+        };
+        used.push(htlc.id);
+
+        let output = htlc_output(htlc);
+        let witness_script =
+            htlc_witness_script(channel_type, &output, &revocation_pubkey, &remote_htlc_pubkey, &local_htlc_pubkey);
+
+        let htlc_txin = TxIn {
+            previous_output: OutPoint { txid: commitment_txid, vout },
+            script_sig: ScriptBuf::new(),
+            sequence: channel_type.htlc_input_sequence(),
+            witness: Witness::new(),
+        };
+
+        let fee = htlc_second_stage_fee(channel_type, &output, commitment.feerate_per_kw);
+
+        let remote_signature =
+            signature(&htlc_tx.remote_htlc_signature, channel_type.counterparty_htlc_sighash_type());
+        let local_signature = sighash_all(&htlc_tx.local_htlc_signature);
+
+        let mut tx = if htlc.offered {
+            build_htlc_timeout_transaction(
+                htlc_txin,
+                &revocation_pubkey,
+                &to_local_delayed_pubkey,
+                to_self_delay,
+                htlc.cltv_expiry,
+                output.amount - fee,
+            )
+        } else {
+            build_htlc_success_transaction(
+                htlc_txin,
+                &revocation_pubkey,
+                &to_local_delayed_pubkey,
+                to_self_delay,
+                output.amount - fee,
+            )
+        };
+
+        let sighash = SighashCache::new(&tx)
+            .p2wsh_signature_hash(0, &witness_script, Amount::from_sat(output.amount), remote_signature.sighash_type)
+            .expect("input 0 exists");
+        let message = Message::from_digest(sighash.to_byte_array());
+        if secp.verify_ecdsa(&message, &remote_signature.signature, &remote_htlc_pubkey).is_err() {
+            results.push(VectorResult::fail(&name, "remote HTLC signature does not verify".to_string()));
+            continue;
+        }
+
+        tx.input[0].witness = if htlc.offered {
+            build_htlc_timeout_witness(&remote_signature, &local_signature, &witness_script)
+        } else {
+            build_htlc_success_witness(
+                &remote_signature,
+                &local_signature,
+                &bytes32(&htlc.payment_preimage),
+                &witness_script,
+            )
+        };
+
+        results.push(VectorResult::compare(&name, "signed HTLC transaction", &serialize(&expected), &serialize(&tx)));
+    }
+
+    results
+}
+
+/// Runs every bundled vector.
+pub fn run_all() -> Vec<VectorResult> {
+    let vectors = load_vectors();
+
+    let mut results = check_key_derivation(&vectors.key_derivation);
+    results.extend(check_channel(&vectors.channel));
+
+    for commitment in vectors.commitments.iter() {
+        results.push(check_commitment(&vectors, commitment));
+        results.extend(check_htlc_transactions(&vectors, commitment));
+    }
+
+    results
+}
@@ -0,0 +1,315 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! Recognises the Lightning outputs and spends in an arbitrary transaction.
+//!
+//! P2WSH outputs only commit to a hash of their witness script, so outputs are
+//! recognised by rebuilding every script a channel can produce from a
+//! [`CommitmentContext`] and comparing scriptPubKeys. Inputs carry their
+//! witness script, so they are recognised from the witness alone, including
+//! which branch of the script the witness takes.
+use crate::ch1_intro_htlcs::solutions::{
+    htlc_witness_script, to_local, to_remote_output_script, two_of_two_multisig_witness_script,
+    ChannelType, HtlcOutput,
+};
+use crate::internal::helper::build_anchor_witness_script;
+use crate::internal::script_disasm::{identify_template, ScriptTemplate};
+use bitcoin::hashes::{hash160, Hash};
+use bitcoin::script::ScriptBuf;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Transaction, TxIn, TxOut};
+use std::fmt;
+
+/// The keys and HTLCs of one commitment transaction, from the point of view
+/// of its broadcaster, as passed to `build_fee_aware_commitment_transaction`.
+#[derive(Clone, Debug)]
+pub struct CommitmentContext {
+    pub channel_type: ChannelType,
+    pub local_funding_pubkey: PublicKey,
+    pub remote_funding_pubkey: PublicKey,
+    pub revocation_pubkey: PublicKey,
+    pub local_delayed_pubkey: PublicKey,
+    pub to_self_delay: i64,
+    pub remote_payment_basepoint: PublicKey,
+    pub local_htlc_pubkey: PublicKey,
+    pub remote_htlc_pubkey: PublicKey,
+    pub htlcs: Vec<HtlcOutput>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutputRole {
+    /// The 2-of-2 multisig output of a funding transaction.
+    Funding,
+    /// The broadcaster's balance, also the output of HTLC-success and
+    /// HTLC-timeout transactions.
+    ToLocal,
+    ToRemote,
+    OfferedHtlc(HtlcOutput),
+    ReceivedHtlc(HtlcOutput),
+    LocalAnchor,
+    RemoteAnchor,
+    /// A P2WPKH output paying none of the channel's keys, e.g. wallet change.
+    P2wpkh,
+    /// A P2WSH output whose witness script we could not rebuild.
+    UnknownP2wsh,
+    Unknown,
+}
+
+impl fmt::Display for OutputRole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputRole::Funding => write!(f, "funding 2-of-2"),
+            OutputRole::ToLocal => write!(f, "to_local"),
+            OutputRole::ToRemote => write!(f, "to_remote"),
+            OutputRole::OfferedHtlc(htlc) => {
+                write!(f, "offered HTLC {}", hex::encode(htlc.payment_hash160))
+            }
+            OutputRole::ReceivedHtlc(htlc) => write!(
+                f,
+                "received HTLC {} expiring at {}",
+                hex::encode(htlc.payment_hash160),
+                htlc.cltv_expiry
+            ),
+            OutputRole::LocalAnchor => write!(f, "local anchor"),
+            OutputRole::RemoteAnchor => write!(f, "remote anchor"),
+            OutputRole::P2wpkh => write!(f, "P2WPKH"),
+            OutputRole::UnknownP2wsh => write!(f, "unknown P2WSH"),
+            OutputRole::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// The branch of a witness script an input's witness takes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpendPath {
+    /// Both funding keys signed the 2-of-2.
+    Multisig,
+    /// The counterparty spent a revoked output with the revocation key.
+    Revocation,
+    /// The owner spent an output after its relative timelock.
+    Delayed,
+    /// An HTLC was spent after its expiry.
+    Timeout,
+    /// An HTLC was spent with the payment preimage.
+    Success { preimage: Vec<u8> },
+    /// A single signature with no timelock: P2WPKH, the anchor owner, or
+    /// to_remote on an anchors channel.
+    KeySpend,
+    /// Anyone swept an anchor after 16 blocks.
+    AnchorSweep,
+}
+
+impl fmt::Display for SpendPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpendPath::Multisig => write!(f, "2-of-2 signatures"),
+            SpendPath::Revocation => write!(f, "revocation"),
+            SpendPath::Delayed => write!(f, "delayed"),
+            SpendPath::Timeout => write!(f, "timeout"),
+            SpendPath::Success { preimage } => write!(f, "success with preimage {}", hex::encode(preimage)),
+            SpendPath::KeySpend => write!(f, "key spend"),
+            SpendPath::AnchorSweep => write!(f, "anchor sweep"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputClassification {
+    /// The template of the script being spent, read from the witness.
+    pub template: Option<ScriptTemplate>,
+    pub path: Option<SpendPath>,
+}
+
+impl fmt::Display for InputClassification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.template, &self.path) {
+            (Some(template), Some(path)) => write!(f, "{} via {}", template, path),
+            (Some(template), None) => write!(f, "{} via an unknown path", template),
+            _ => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionClassification {
+    pub inputs: Vec<InputClassification>,
+    pub outputs: Vec<OutputRole>,
+}
+
+impl fmt::Display for TransactionClassification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, input) in self.inputs.iter().enumerate() {
+            writeln!(f, "input {}: {}", index, input)?;
+        }
+        for (index, output) in self.outputs.iter().enumerate() {
+            writeln!(f, "output {}: {}", index, output)?;
+        }
+        Ok(())
+    }
+}
+
+impl CommitmentContext {
+    /// The scriptPubKey of every output the channel can produce, with its role.
+    pub fn output_scripts(&self) -> Vec<(ScriptBuf, OutputRole)> {
+        // BOLT 3 sorts the funding keys, but callers may not have
+        let mut scripts = vec![
+            (
+                two_of_two_multisig_witness_script(&self.local_funding_pubkey, &self.remote_funding_pubkey).to_p2wsh(),
+                OutputRole::Funding,
+            ),
+            (
+                two_of_two_multisig_witness_script(&self.remote_funding_pubkey, &self.local_funding_pubkey).to_p2wsh(),
+                OutputRole::Funding,
+            ),
+            (
+                to_local(&self.revocation_pubkey, &self.local_delayed_pubkey, self.to_self_delay).to_p2wsh(),
+                OutputRole::ToLocal,
+            ),
+            (
+                to_remote_output_script(self.channel_type, &self.remote_payment_basepoint),
+                OutputRole::ToRemote,
+            ),
+        ];
+
+        for htlc in self.htlcs.iter() {
+            let script = htlc_witness_script(
+                self.channel_type,
+                htlc,
+                &self.revocation_pubkey,
+                &self.remote_htlc_pubkey,
+                &self.local_htlc_pubkey,
+            );
+            let role = if htlc.offered {
+                OutputRole::OfferedHtlc(htlc.clone())
+            } else {
+                OutputRole::ReceivedHtlc(htlc.clone())
+            };
+            scripts.push((script.to_p2wsh(), role));
+        }
+
+        if self.channel_type == ChannelType::AnchorsZeroFeeHtlcTx {
+            scripts.push((build_anchor_witness_script(&self.local_funding_pubkey).to_p2wsh(), OutputRole::LocalAnchor));
+            scripts.push((build_anchor_witness_script(&self.remote_funding_pubkey).to_p2wsh(), OutputRole::RemoteAnchor));
+        }
+        scripts
+    }
+}
+
+fn classify_output(output: &TxOut, scripts: &[(ScriptBuf, OutputRole)]) -> OutputRole {
+    let mut candidates = scripts.iter().filter(|(script, _)| *script == output.script_pubkey);
+
+    // offered HTLCs with the same payment hash share a script, so prefer the
+    // one whose amount matches
+    let first = candidates.clone().next();
+    let same_amount = candidates.find(|(_, role)| match role {
+        OutputRole::OfferedHtlc(htlc) | OutputRole::ReceivedHtlc(htlc) => htlc.amount == output.value.to_sat(),
+        _ => false,
+    });
+    match same_amount.or(first) {
+        Some((_, role)) => role.clone(),
+        None if output.script_pubkey.is_p2wpkh() => OutputRole::P2wpkh,
+        None if output.script_pubkey.is_p2wsh() => OutputRole::UnknownP2wsh,
+        None => OutputRole::Unknown,
+    }
+}
+
+/// Classifies each output of `tx`. Without a context, only the output type
+/// (P2WPKH, P2WSH or unknown) can be told.
+pub fn classify_outputs(tx: &Transaction, context: Option<&CommitmentContext>) -> Vec<OutputRole> {
+    let scripts = context.map(|context| context.output_scripts()).unwrap_or_default();
+    tx.output.iter().map(|output| classify_output(output, &scripts)).collect()
+}
+
+fn is_signature(element: &[u8]) -> bool {
+    !element.is_empty()
+}
+
+fn is_pubkey(element: &[u8]) -> bool {
+    element.len() == 33
+}
+
+/// Works out which branch of `template` a witness with these `args` (every
+/// element but the witness script) takes.
+fn spend_path(template: &ScriptTemplate, args: &[&[u8]]) -> Option<SpendPath> {
+    let path = match (template, args) {
+        (ScriptTemplate::TwoOfTwoMultisig { .. }, [dummy, sig1, sig2])
+            if dummy.is_empty() && is_signature(sig1) && is_signature(sig2) =>
+        {
+            SpendPath::Multisig
+        }
+        (ScriptTemplate::ToLocal { .. }, [sig, selector]) if is_signature(sig) => match *selector {
+            [1] => SpendPath::Revocation,
+            [] => SpendPath::Delayed,
+            _ => return None,
+        },
+        (ScriptTemplate::OfferedHtlc { .. } | ScriptTemplate::ReceivedHtlc { .. }, [sig, revocation_pubkey])
+            if is_signature(sig) && is_pubkey(revocation_pubkey) =>
+        {
+            SpendPath::Revocation
+        }
+        // the HTLC-timeout transaction, signed by both parties
+        (ScriptTemplate::OfferedHtlc { .. }, [dummy, remote_sig, local_sig, empty])
+            if dummy.is_empty() && is_signature(remote_sig) && is_signature(local_sig) && empty.is_empty() =>
+        {
+            SpendPath::Timeout
+        }
+        // the counterparty claiming directly with the preimage
+        (ScriptTemplate::OfferedHtlc { .. }, [remote_sig, preimage]) if is_signature(remote_sig) && preimage.len() == 32 => {
+            SpendPath::Success { preimage: preimage.to_vec() }
+        }
+        // the HTLC-success transaction, signed by both parties
+        (ScriptTemplate::ReceivedHtlc { .. }, [dummy, remote_sig, local_sig, preimage])
+            if dummy.is_empty() && is_signature(remote_sig) && is_signature(local_sig) && preimage.len() == 32 =>
+        {
+            SpendPath::Success { preimage: preimage.to_vec() }
+        }
+        // the counterparty claiming directly after expiry
+        (ScriptTemplate::ReceivedHtlc { .. }, [remote_sig, empty]) if is_signature(remote_sig) && empty.is_empty() => {
+            SpendPath::Timeout
+        }
+        (ScriptTemplate::HashLocked { .. }, [preimage, [1]]) => SpendPath::Success { preimage: preimage.to_vec() },
+        (ScriptTemplate::HashLocked { .. }, [sig, empty]) if is_signature(sig) && empty.is_empty() => {
+            SpendPath::Timeout
+        }
+        (ScriptTemplate::TimelockedP2pkh { .. }, [sig, pubkey]) if is_signature(sig) && is_pubkey(pubkey) => {
+            SpendPath::Delayed
+        }
+        (ScriptTemplate::Anchor { .. }, [sig]) if is_signature(sig) => SpendPath::KeySpend,
+        (ScriptTemplate::Anchor { .. }, [[]]) => SpendPath::AnchorSweep,
+        (ScriptTemplate::ToRemoteAnchor { .. }, [sig]) if is_signature(sig) => SpendPath::KeySpend,
+        _ => return None,
+    };
+    Some(path)
+}
+
+/// Classifies an input from its witness.
+pub fn classify_input(input: &TxIn) -> InputClassification {
+    let witness: Vec<&[u8]> = input.witness.iter().collect();
+
+    // a P2WPKH spend is a signature and a compressed pubkey
+    if let [sig, pubkey] = witness[..] {
+        if is_signature(sig) && is_pubkey(pubkey) && PublicKey::from_slice(pubkey).is_ok() {
+            return InputClassification {
+                template: Some(ScriptTemplate::P2wpkh { pubkey_hash: hash160::Hash::hash(pubkey).to_byte_array() }),
+                path: Some(SpendPath::KeySpend),
+            };
+        }
+    }
+
+    let (witness_script, args) = match witness.split_last() {
+        Some((witness_script, args)) => (ScriptBuf::from_bytes(witness_script.to_vec()), args),
+        None => return InputClassification { template: None, path: None },
+    };
+    match identify_template(&witness_script) {
+        Some(template) => {
+            let path = spend_path(&template, args);
+            InputClassification { template: Some(template), path }
+        }
+        None => InputClassification { template: None, path: None },
+    }
+}
+
+pub fn classify_transaction(tx: &Transaction, context: Option<&CommitmentContext>) -> TransactionClassification {
+    TransactionClassification {
+        inputs: tx.input.iter().map(classify_input).collect(),
+        outputs: classify_outputs(tx, context),
+    }
+}
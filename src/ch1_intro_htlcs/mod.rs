@@ -1,3 +1,8 @@
+pub mod bolt3;
+pub mod classify;
 pub mod exercises;
+pub mod ptlc;
 pub mod solutions;
+pub mod splice;
+pub mod taproot;
 pub mod test;
@@ -0,0 +1,309 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! Point time-locked contracts: the taproot commitment outputs of `taproot`
+//! with payments locked to a point instead of a hash.
+//!
+//! A PTLC output has no hash lock. The payee's claim needs a signature from
+//! the payer, who hands it over as a Schnorr adaptor signature encrypted to
+//! the payment point `T = t * G`. Only the holder of the payment secret `t`
+//! can turn it into a valid signature, and once that signature is on chain
+//! the payer subtracts the adaptor signature from it to learn `t`, the same
+//! way a preimage is read from an HTLC witness.
+use crate::ch1_intro_htlcs::taproot::{
+    leaf_control_block, offered_htlc_timeout_script, received_htlc_timeout_script, script_spend_sighash,
+    spend_info_output_script, tapscript_witness, to_local_spend_info, to_remote_spend_info,
+};
+use crate::internal::builder::Builder;
+use crate::internal::helper::{build_output, build_transaction};
+use crate::internal::musig2::{
+    has_odd_y, point_add, point_mul, scalar_add, scalar_from_bytes, scalar_mul, scalar_negate_if, tagged_hash,
+    xonly,
+};
+use bitcoin::blockdata::opcodes::all as opcodes;
+use bitcoin::key::Parity;
+use bitcoin::locktime::absolute::LockTime;
+use bitcoin::script::{Script, ScriptBuf};
+use bitcoin::secp256k1::{self, schnorr, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+use bitcoin::taproot::{TaprootBuilder, TaprootSpendInfo};
+use bitcoin::transaction::Version;
+use bitcoin::{Transaction, TxIn, TxOut, Witness};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdaptorError {
+    /// The signature was not completed from this adaptor signature.
+    NonceMismatch,
+    /// The secret does not belong to the adaptor point.
+    SecretMismatch,
+    /// The adaptor signature's nonce is the negated adaptor point, so the
+    /// completed nonce would be the point at infinity.
+    InfiniteNonce,
+}
+
+impl fmt::Display for AdaptorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AdaptorError::NonceMismatch => write!(f, "signature does not use the adaptor signature's nonce"),
+            AdaptorError::SecretMismatch => write!(f, "secret does not match the adaptor point"),
+            AdaptorError::InfiniteNonce => write!(f, "adaptor nonce cancels out the adaptor point"),
+        }
+    }
+}
+
+/// A BIP 340 signature with its nonce shifted by an adaptor point `T`.
+///
+/// `nonce` is `R' = k * G`; the completed signature uses `R = R' + T`, so its
+/// `s` is `s'` plus the adaptor secret (negated when `R` has an odd y).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AdaptorSignature {
+    pub nonce: PublicKey,
+    pub s: [u8; 32],
+}
+
+impl AdaptorSignature {
+    pub fn serialize(&self) -> [u8; 65] {
+        let mut bytes = [0u8; 65];
+        bytes[..33].copy_from_slice(&self.nonce.serialize());
+        bytes[33..].copy_from_slice(&self.s);
+        bytes
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<AdaptorSignature, secp256k1::Error> {
+        if bytes.len() != 65 {
+            return Err(secp256k1::Error::InvalidSignature);
+        }
+        let mut s = [0u8; 32];
+        s.copy_from_slice(&bytes[33..]);
+        Ok(AdaptorSignature { nonce: PublicKey::from_slice(&bytes[..33])?, s })
+    }
+
+    /// The nonce of the completed signature, `R' + T`, or `None` if a peer
+    /// sent `R' = -T`.
+    fn final_nonce(&self, adaptor_point: &PublicKey) -> Option<PublicKey> {
+        self.nonce.combine(adaptor_point).ok()
+    }
+}
+
+pub fn payment_point(payment_secret: &SecretKey) -> PublicKey {
+    PublicKey::from_secret_key(&Secp256k1::signing_only(), payment_secret)
+}
+
+fn challenge(nonce: &PublicKey, pubkey: &XOnlyPublicKey, msg: &[u8; 32]) -> Option<SecretKey> {
+    scalar_from_bytes(tagged_hash("BIP0340/challenge", &[&xonly(nonce), &pubkey.serialize(), msg]))
+}
+
+/// Signs `msg` with `secret_key`, encrypted to `adaptor_point`. `rand` must
+/// be fresh for every signature.
+pub fn adaptor_sign(
+    secret_key: &SecretKey,
+    msg: [u8; 32],
+    adaptor_point: &PublicKey,
+    rand: [u8; 32],
+) -> AdaptorSignature {
+    let secp = Secp256k1::signing_only();
+    let (pubkey, parity) = secret_key.x_only_public_key(&secp);
+    let d = scalar_negate_if(Some(*secret_key), parity == Parity::Odd);
+
+    let k = scalar_from_bytes(tagged_hash(
+        "PTLC/adaptor/nonce",
+        &[&rand, &secret_key.secret_bytes(), &adaptor_point.serialize(), &msg],
+    ))
+    .expect("nonce is not zero");
+    let nonce = PublicKey::from_secret_key(&secp, &k);
+
+    let adaptor = AdaptorSignature { nonce, s: [0; 32] };
+    // k is hashed from the adaptor point, so nobody can pick T = -k * G
+    let final_nonce = adaptor.final_nonce(adaptor_point).expect("adaptor nonce is not infinity");
+    let e = challenge(&final_nonce, &pubkey, &msg);
+    let s = scalar_add(scalar_negate_if(Some(k), has_odd_y(&final_nonce)), scalar_mul(e, d));
+    AdaptorSignature { nonce, s: s.map_or([0; 32], |s| s.secret_bytes()) }
+}
+
+/// Checks that completing `adaptor_signature` with the discrete logarithm of
+/// `adaptor_point` gives a valid signature of `msg` by `pubkey`.
+pub fn adaptor_verify(
+    pubkey: &XOnlyPublicKey,
+    msg: [u8; 32],
+    adaptor_point: &PublicKey,
+    adaptor_signature: &AdaptorSignature,
+) -> bool {
+    let secp = Secp256k1::new();
+    let final_nonce = match adaptor_signature.final_nonce(adaptor_point) {
+        Some(final_nonce) => final_nonce,
+        None => return false,
+    };
+    let e = challenge(&final_nonce, pubkey, &msg);
+
+    let nonce = if has_odd_y(&final_nonce) { adaptor_signature.nonce.negate(&secp) } else { adaptor_signature.nonce };
+    let expected = point_add(Some(nonce), point_mul(&pubkey.public_key(Parity::Even), e));
+
+    let actual = scalar_from_bytes(adaptor_signature.s).map(|s| PublicKey::from_secret_key(&secp, &s));
+    actual == expected
+}
+
+/// Turns an adaptor signature into a BIP 340 signature with the adaptor secret.
+pub fn complete_adaptor_signature(
+    adaptor_signature: &AdaptorSignature,
+    adaptor_secret: &SecretKey,
+) -> Result<schnorr::Signature, AdaptorError> {
+    let final_nonce = adaptor_signature
+        .final_nonce(&payment_point(adaptor_secret))
+        .ok_or(AdaptorError::InfiniteNonce)?;
+    let t = scalar_negate_if(Some(*adaptor_secret), has_odd_y(&final_nonce));
+    let s = scalar_add(scalar_from_bytes(adaptor_signature.s), t);
+
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(&xonly(&final_nonce));
+    signature[32..].copy_from_slice(&s.map_or([0; 32], |s| s.secret_bytes()));
+    Ok(schnorr::Signature::from_slice(&signature).expect("64 bytes"))
+}
+
+/// Recovers the adaptor secret from the signature completed with it.
+pub fn extract_adaptor_secret(
+    adaptor_signature: &AdaptorSignature,
+    signature: &schnorr::Signature,
+    adaptor_point: &PublicKey,
+) -> Result<SecretKey, AdaptorError> {
+    let final_nonce = adaptor_signature.final_nonce(adaptor_point).ok_or(AdaptorError::InfiniteNonce)?;
+    let bytes = signature.as_ref();
+    if bytes[..32] != xonly(&final_nonce) {
+        return Err(AdaptorError::NonceMismatch);
+    }
+
+    let mut s = [0u8; 32];
+    s.copy_from_slice(&bytes[32..]);
+    let t = scalar_add(scalar_from_bytes(s), scalar_negate_if(scalar_from_bytes(adaptor_signature.s), true));
+    let t = scalar_negate_if(t, has_odd_y(&final_nonce)).ok_or(AdaptorError::SecretMismatch)?;
+    if payment_point(&t) != *adaptor_point {
+        return Err(AdaptorError::SecretMismatch);
+    }
+    Ok(t)
+}
+
+/// A PTLC output, the counterpart of `HtlcOutput` with a payment point in
+/// place of the payment hash. The point is not part of any script; it only
+/// decides which adaptor signatures the peers exchange.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PtlcOutput {
+    pub offered: bool,
+    pub amount: u64,
+    pub cltv_expiry: u32,
+    pub payment_point: PublicKey,
+}
+
+/// Offered PTLC leaf claimed directly by the counterparty. Our signature
+/// for the claim is handed over as an adaptor signature, so broadcasting the
+/// claim reveals the payment secret to us.
+pub fn offered_ptlc_success_script(local_htlc_pubkey: &PublicKey, remote_htlc_pubkey: &PublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_x_only_key(&local_htlc_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_CHECKSIGVERIFY)
+        .push_x_only_key(&remote_htlc_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .push_int(1)
+        .push_opcode(opcodes::OP_CSV)
+        .push_opcode(opcodes::OP_DROP)
+        .into_script()
+}
+
+/// Received PTLC leaf for the second-stage success transaction, where the
+/// counterparty's signature is the adaptor signature we complete.
+pub fn received_ptlc_success_script(local_htlc_pubkey: &PublicKey, remote_htlc_pubkey: &PublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_x_only_key(&local_htlc_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_CHECKSIGVERIFY)
+        .push_x_only_key(&remote_htlc_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .into_script()
+}
+
+/// The leaf through which the payee claims the PTLC.
+pub fn ptlc_success_script(ptlc: &PtlcOutput, local_htlc_pubkey: &PublicKey, remote_htlc_pubkey: &PublicKey) -> ScriptBuf {
+    if ptlc.offered {
+        offered_ptlc_success_script(local_htlc_pubkey, remote_htlc_pubkey)
+    } else {
+        received_ptlc_success_script(local_htlc_pubkey, remote_htlc_pubkey)
+    }
+}
+
+/// Like `htlc_spend_info`, the revocation key is the internal key and the
+/// timeout leaves are the HTLC ones.
+pub fn ptlc_spend_info(
+    ptlc: &PtlcOutput,
+    revocation_pubkey: &PublicKey,
+    remote_htlc_pubkey: &PublicKey,
+    local_htlc_pubkey: &PublicKey,
+) -> TaprootSpendInfo {
+    let secp = Secp256k1::verification_only();
+    let timeout_script = if ptlc.offered {
+        offered_htlc_timeout_script(local_htlc_pubkey, remote_htlc_pubkey)
+    } else {
+        received_htlc_timeout_script(remote_htlc_pubkey, ptlc.cltv_expiry)
+    };
+    TaprootBuilder::new()
+        .add_leaf(1, ptlc_success_script(ptlc, local_htlc_pubkey, remote_htlc_pubkey))
+        .expect("valid depth")
+        .add_leaf(1, timeout_script)
+        .expect("valid depth")
+        .finalize(&secp, revocation_pubkey.x_only_public_key().0)
+        .expect("tree is complete")
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn build_ptlc_commitment_transaction(
+    funding_txin: TxIn,
+    revocation_pubkey: &PublicKey,
+    to_local_delayed_pubkey: &PublicKey,
+    remote_pubkey: &PublicKey,
+    to_self_delay: i64,
+    local_htlc_pubkey: &PublicKey,
+    remote_htlc_pubkey: &PublicKey,
+    ptlcs: &[PtlcOutput],
+    local_amount: u64,
+    remote_amount: u64,
+) -> Transaction {
+    let to_local_info = to_local_spend_info(revocation_pubkey, to_local_delayed_pubkey, to_self_delay);
+
+    let to_remote_info = to_remote_spend_info(remote_pubkey);
+
+    let mut outputs = vec![
+        build_output(local_amount, spend_info_output_script(&to_local_info)),
+        build_output(remote_amount, spend_info_output_script(&to_remote_info)),
+    ];
+
+    for ptlc in ptlcs {
+        let ptlc_info = ptlc_spend_info(ptlc, revocation_pubkey, remote_htlc_pubkey, local_htlc_pubkey);
+        outputs.push(build_output(ptlc.amount, spend_info_output_script(&ptlc_info)));
+    }
+
+    build_transaction(Version::TWO, LockTime::ZERO, vec![funding_txin], outputs)
+}
+
+/// Our adaptor signature for the transaction claiming `ptlc` through its
+/// success leaf, encrypted to the PTLC's payment point.
+pub fn sign_ptlc_claim(
+    claim_tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+    success_script: &Script,
+    secret_key: &SecretKey,
+    ptlc: &PtlcOutput,
+    rand: [u8; 32],
+) -> AdaptorSignature {
+    let sighash = script_spend_sighash(claim_tx, input_index, prevouts, success_script);
+    adaptor_sign(secret_key, sighash, &ptlc.payment_point, rand)
+}
+
+/// The success-leaf witness. Both leaves check the local HTLC signature
+/// first, so it goes on top of the stack.
+pub fn ptlc_success_witness(
+    spend_info: &TaprootSpendInfo,
+    success_script: &Script,
+    local_htlc_signature: &schnorr::Signature,
+    remote_htlc_signature: &schnorr::Signature,
+) -> Witness {
+    tapscript_witness(
+        vec![remote_htlc_signature.as_ref().to_vec(), local_htlc_signature.as_ref().to_vec()],
+        success_script,
+        &leaf_control_block(spend_info, success_script),
+    )
+}
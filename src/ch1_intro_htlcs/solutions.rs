@@ -2,16 +2,26 @@
 use crate::internal;
 use bitcoin::script::ScriptBuf;
 use internal::builder::Builder;
+use internal::messages::ClosingSignedFeeRange;
+use internal::script_disasm::{identify_template, ScriptTemplate};
 use internal::helper::{tweak_pubkey, hash_pubkeys,
                       build_output, build_transaction, p2wpkh_output_script,
-                      build_htlc_offerer_witness_script, add_pubkeys};
+                      build_htlc_offerer_witness_script, build_htlc_receiver_witness_script,
+                      build_anchor_htlc_offerer_witness_script, build_anchor_htlc_receiver_witness_script,
+                      build_anchor_witness_script, build_anchor_to_remote_witness_script,
+                      add_pubkeys};
 use bitcoin::blockdata::opcodes::all as opcodes;
-use bitcoin::secp256k1::PublicKey;
+use bitcoin::secp256k1::{self, Message, PublicKey, Scalar, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::Amount;
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+use bitcoin::ecdsa::Signature as BitcoinSignature;
 use bitcoin::PublicKey as BitcoinPublicKey;
 use bitcoin::hashes::Hash;
 use bitcoin::{Block, OutPoint, PubkeyHash, Sequence, Transaction, TxIn, TxOut, Witness};
 use bitcoin::transaction::Version;
 use bitcoin::locktime::absolute::LockTime;
+use bitcoin::psbt::Psbt;
 
 
 pub fn two_of_two_multisig_witness_script(
@@ -64,6 +74,40 @@ pub fn build_funding_transaction(
     tx
 }
 
+/// Fills in the witness script of the output paying to the funding script,
+/// so signers can check what they are funding, and returns its index.
+pub fn fill_funding_output(psbt: &mut Psbt, alice_pubkey: &PublicKey, bob_pubkey: &PublicKey) -> Option<usize> {
+    let witness_script = two_of_two_multisig_witness_script(alice_pubkey, bob_pubkey);
+    let script_pubkey = witness_script.to_p2wsh();
+
+    let index = psbt.unsigned_tx.output.iter().position(|txout| txout.script_pubkey == script_pubkey)?;
+    psbt.outputs[index].witness_script = Some(witness_script);
+    Some(index)
+}
+
+/// The funding transaction as a BIP 174 PSBT. Each input comes with the
+/// output it spends when we know it; when several parties fund the channel,
+/// each fills in its own inputs and the copies are combined with
+/// `combine_psbts`.
+pub fn build_funding_psbt(
+    inputs: Vec<(TxIn, Option<TxOut>)>,
+    alice_pubkey: &PublicKey,
+    bob_pubkey: &PublicKey,
+    amount: u64,
+) -> Psbt {
+    let (txins, witness_utxos): (Vec<TxIn>, Vec<Option<TxOut>>) = inputs.into_iter().unzip();
+
+    let tx = build_funding_transaction(txins, alice_pubkey, bob_pubkey, amount);
+
+    let mut psbt = Psbt::from_unsigned_tx(tx).expect("unsigned transaction");
+    for (input, witness_utxo) in psbt.inputs.iter_mut().zip(witness_utxos) {
+        input.witness_utxo = witness_utxo;
+    }
+    fill_funding_output(&mut psbt, alice_pubkey, bob_pubkey);
+
+    psbt
+}
+
 pub fn build_refund_transaction(
     funding_txin: TxIn,
     alice_pubkey: PublicKey,
@@ -111,6 +155,65 @@ pub fn generate_revocation_pubkey(
     revocation_pubkey
 }
 
+/// Derives a per-commitment pubkey (localpubkey, remotepubkey, local_htlcpubkey,
+/// remote_htlcpubkey or local_delayedpubkey) from its basepoint:
+/// `basepoint + SHA256(per_commitment_point || basepoint) * G`.
+pub fn derive_public_key(basepoint: &PublicKey, per_commitment_point: &PublicKey) -> PublicKey {
+    let secp = Secp256k1::new();
+
+    let tweak = hash_pubkeys(*per_commitment_point, *basepoint);
+
+    let tweak_pubkey = SecretKey::from_slice(&tweak)
+        .expect("A SHA256 output is a valid secret key with overwhelming probability")
+        .public_key(&secp);
+
+    add_pubkeys(*basepoint, tweak_pubkey)
+}
+
+/// The private counterpart of `derive_public_key`:
+/// `basepoint_secret + SHA256(per_commitment_point || basepoint)`.
+pub fn derive_private_key(basepoint_secret: &SecretKey, per_commitment_point: &PublicKey) -> SecretKey {
+    let secp = Secp256k1::new();
+    let basepoint = PublicKey::from_secret_key(&secp, basepoint_secret);
+
+    let tweak = hash_pubkeys(*per_commitment_point, basepoint);
+
+    basepoint_secret
+        .add_tweak(&Scalar::from_be_bytes(tweak).unwrap())
+        .expect("Addition only fails if the tweak is the inverse of the key")
+}
+
+/// The private counterpart of `generate_revocation_pubkey`. Only computable
+/// once the broadcaster has handed over `per_commitment_secret`, i.e. once the
+/// commitment has been revoked.
+pub fn derive_private_revocation_key(
+    per_commitment_secret: &SecretKey,
+    countersignatory_revocation_base_secret: &SecretKey,
+) -> SecretKey {
+    let secp = Secp256k1::new();
+    let countersignatory_basepoint =
+        PublicKey::from_secret_key(&secp, countersignatory_revocation_base_secret);
+    let per_commitment_point = PublicKey::from_secret_key(&secp, per_commitment_secret);
+
+    let rev_append_commit_hash_key =
+        hash_pubkeys(countersignatory_basepoint, per_commitment_point);
+
+    let commit_append_rev_hash_key =
+        hash_pubkeys(per_commitment_point, countersignatory_basepoint);
+
+    let countersignatory_contrib = countersignatory_revocation_base_secret
+        .mul_tweak(&Scalar::from_be_bytes(rev_append_commit_hash_key).unwrap())
+        .expect("Multiplying a valid secret key by a hash is expected to never fail");
+
+    let broadcaster_contrib = per_commitment_secret
+        .mul_tweak(&Scalar::from_be_bytes(commit_append_rev_hash_key).unwrap())
+        .expect("Multiplying a valid secret key by a hash is expected to never fail");
+
+    countersignatory_contrib
+        .add_tweak(&Scalar::from(broadcaster_contrib))
+        .expect("Addition only fails if the tweak is the inverse of the key")
+}
+
 pub fn to_local(
     revocation_key: &PublicKey,
     to_local_delayed_pubkey: &PublicKey,
@@ -231,3 +334,945 @@ pub fn build_htlc_timeout_transaction(
 
 
 
+
+
+/// An HTLC carried by a commitment transaction. `offered` is from the point of
+/// view of the commitment's broadcaster: an offered HTLC pays to the
+/// counterparty on success and back to us on timeout.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HtlcOutput {
+    pub offered: bool,
+    pub amount: u64,
+    pub cltv_expiry: u32,
+    pub payment_hash160: [u8; 20],
+}
+
+/// The commitment format negotiated for a channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelType {
+    /// `option_static_remotekey`: to_remote pays the counterparty's payment
+    /// basepoint directly and HTLC transactions carry their own fee.
+    StaticRemoteKey,
+    /// `option_anchors_zero_fee_htlc_tx`: each party gets an anchor output to
+    /// bump the commitment with CPFP, every output but to_local's revocation
+    /// path is CSV-delayed by one block, and HTLC transactions pay no fee.
+    AnchorsZeroFeeHtlcTx,
+}
+
+/// Value of each anchor output on an `option_anchors` commitment.
+pub const ANCHOR_OUTPUT_VALUE: u64 = 330;
+
+impl ChannelType {
+    /// Weight of a commitment transaction with no HTLC outputs.
+    pub fn commitment_base_weight(&self) -> u64 {
+        match self {
+            ChannelType::StaticRemoteKey => COMMITMENT_BASE_WEIGHT,
+            ChannelType::AnchorsZeroFeeHtlcTx => ANCHORS_COMMITMENT_BASE_WEIGHT,
+        }
+    }
+
+    /// nSequence of the input of an HTLC-success or HTLC-timeout transaction.
+    pub fn htlc_input_sequence(&self) -> Sequence {
+        match self {
+            ChannelType::StaticRemoteKey => Sequence::ZERO,
+            ChannelType::AnchorsZeroFeeHtlcTx => Sequence(1),
+        }
+    }
+
+    /// Sighash type the counterparty signs our HTLC transactions with. Under
+    /// anchors it lets us attach extra inputs and outputs to pay the fee.
+    pub fn counterparty_htlc_sighash_type(&self) -> EcdsaSighashType {
+        match self {
+            ChannelType::StaticRemoteKey => EcdsaSighashType::All,
+            ChannelType::AnchorsZeroFeeHtlcTx => EcdsaSighashType::SinglePlusAnyoneCanPay,
+        }
+    }
+}
+
+pub fn htlc_witness_script(
+    channel_type: ChannelType,
+    htlc: &HtlcOutput,
+    revocation_pubkey: &PublicKey,
+    remote_htlc_pubkey: &PublicKey,
+    local_htlc_pubkey: &PublicKey,
+) -> ScriptBuf {
+    match (channel_type, htlc.offered) {
+        (ChannelType::StaticRemoteKey, true) => build_htlc_offerer_witness_script(
+            revocation_pubkey,
+            remote_htlc_pubkey,
+            local_htlc_pubkey,
+            &htlc.payment_hash160,
+        ),
+        (ChannelType::StaticRemoteKey, false) => build_htlc_receiver_witness_script(
+            revocation_pubkey,
+            remote_htlc_pubkey,
+            local_htlc_pubkey,
+            &htlc.payment_hash160,
+            htlc.cltv_expiry,
+        ),
+        (ChannelType::AnchorsZeroFeeHtlcTx, true) => build_anchor_htlc_offerer_witness_script(
+            revocation_pubkey,
+            remote_htlc_pubkey,
+            local_htlc_pubkey,
+            &htlc.payment_hash160,
+        ),
+        (ChannelType::AnchorsZeroFeeHtlcTx, false) => build_anchor_htlc_receiver_witness_script(
+            revocation_pubkey,
+            remote_htlc_pubkey,
+            local_htlc_pubkey,
+            &htlc.payment_hash160,
+            htlc.cltv_expiry,
+        ),
+    }
+}
+
+/// scriptPubKey of the to_remote output.
+pub fn to_remote_output_script(channel_type: ChannelType, remote_payment_basepoint: &PublicKey) -> ScriptBuf {
+    match channel_type {
+        ChannelType::StaticRemoteKey => p2wpkh_output_script(*remote_payment_basepoint),
+        ChannelType::AnchorsZeroFeeHtlcTx => {
+            build_anchor_to_remote_witness_script(remote_payment_basepoint).to_p2wsh()
+        }
+    }
+}
+
+/// Signs input 0 of an HTLC-success or HTLC-timeout transaction, which
+/// spends an HTLC output worth `htlc_amount` from a commitment transaction.
+pub fn sign_htlc_transaction(
+    htlc_tx: &Transaction,
+    htlc_witness_script: &ScriptBuf,
+    htlc_amount: u64,
+    sighash_type: EcdsaSighashType,
+    private_key: &SecretKey,
+) -> BitcoinSignature {
+    let secp = Secp256k1::signing_only();
+
+    let sighash = SighashCache::new(htlc_tx)
+        .p2wsh_signature_hash(0, htlc_witness_script, Amount::from_sat(htlc_amount), sighash_type)
+        .unwrap();
+
+    let message = Message::from_digest(sighash.to_byte_array());
+
+    BitcoinSignature {
+        signature: secp.sign_ecdsa(&message, private_key),
+        sighash_type,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn build_htlcs_commitment_transaction(
+    funding_txin: TxIn,
+    revocation_pubkey: &PublicKey,
+    remote_htlc_pubkey: &PublicKey,
+    local_htlc_pubkey: &PublicKey,
+    to_local_delayed_pubkey: &PublicKey,
+    remote_pubkey: PublicKey,
+    to_self_delay: i64,
+    htlcs: &[HtlcOutput],
+    local_amount: u64,
+    remote_amount: u64,
+) -> Transaction {
+    let to_local_script =
+        to_local(revocation_pubkey, to_local_delayed_pubkey, to_self_delay);
+
+    let to_remote_script = p2wpkh_output_script(remote_pubkey);
+
+    let local_output = build_output(local_amount, to_local_script.to_p2wsh());
+
+    let remote_output = build_output(remote_amount, to_remote_script);
+
+    let mut outputs = vec![local_output, remote_output];
+
+    for htlc in htlcs {
+        let htlc_script = htlc_witness_script(
+            ChannelType::StaticRemoteKey,
+            htlc,
+            revocation_pubkey,
+            remote_htlc_pubkey,
+            local_htlc_pubkey,
+        );
+        outputs.push(build_output(htlc.amount, htlc_script.to_p2wsh()));
+    }
+
+    let version = Version::TWO;
+    let locktime = LockTime::ZERO;
+
+    build_transaction(version,
+                      locktime,
+                      vec![funding_txin],
+                      outputs)
+}
+
+pub fn build_htlc_success_transaction(
+    htlc_txin: TxIn,
+    revocation_pubkey: &PublicKey,
+    to_local_delayed_pubkey: &PublicKey,
+    to_self_delay: i64,
+    htlc_amount: u64,
+) -> Transaction {
+    let htlc_success_script = to_local(
+        revocation_pubkey,
+        to_local_delayed_pubkey,
+        to_self_delay,
+    );
+
+    let htlc_output = build_output(htlc_amount, htlc_success_script.to_p2wsh());
+
+    let version = Version::TWO;
+    let locktime = LockTime::ZERO;
+
+    build_transaction(
+        version,
+        locktime,
+        vec![htlc_txin],
+        vec![htlc_output])
+}
+
+/// Witness for an HTLC-success transaction spending a received HTLC output
+/// on our own commitment: `0 <remotehtlcsig> <localhtlcsig> <payment_preimage>`.
+/// The leading empty element is consumed by the OP_CHECKMULTISIG off-by-one.
+pub fn build_htlc_success_witness(
+    remote_htlc_signature: &BitcoinSignature,
+    local_htlc_signature: &BitcoinSignature,
+    payment_preimage: &[u8; 32],
+    htlc_witness_script: &ScriptBuf,
+) -> Witness {
+    let mut witness = Witness::new();
+    witness.push(Vec::new());
+    witness.push(remote_htlc_signature.to_vec());
+    witness.push(local_htlc_signature.to_vec());
+    witness.push(payment_preimage);
+    witness.push(htlc_witness_script.as_bytes());
+    witness
+}
+
+/// Witness for an HTLC-timeout transaction spending an offered HTLC output
+/// on our own commitment. The empty element in place of the preimage selects
+/// the 2-of-2 branch of the offerer script.
+pub fn build_htlc_timeout_witness(
+    remote_htlc_signature: &BitcoinSignature,
+    local_htlc_signature: &BitcoinSignature,
+    htlc_witness_script: &ScriptBuf,
+) -> Witness {
+    let mut witness = Witness::new();
+    witness.push(Vec::new());
+    witness.push(remote_htlc_signature.to_vec());
+    witness.push(local_htlc_signature.to_vec());
+    witness.push(Vec::new());
+    witness.push(htlc_witness_script.as_bytes());
+    witness
+}
+
+/// Witness for the counterparty claiming an HTLC we offered directly from our
+/// commitment transaction: `<remotehtlcsig> <payment_preimage>`.
+pub fn build_offered_htlc_preimage_witness(
+    remote_htlc_signature: &BitcoinSignature,
+    payment_preimage: &[u8; 32],
+    htlc_witness_script: &ScriptBuf,
+) -> Witness {
+    let mut witness = Witness::new();
+    witness.push(remote_htlc_signature.to_vec());
+    witness.push(payment_preimage);
+    witness.push(htlc_witness_script.as_bytes());
+    witness
+}
+
+/// The lower 48 bits of SHA256(opener_payment_basepoint || accepter_payment_basepoint),
+/// XORed with the commitment number before it is written into the commitment transaction.
+pub fn commitment_number_obscure_factor(
+    opener_payment_basepoint: &PublicKey,
+    accepter_payment_basepoint: &PublicKey,
+) -> u64 {
+    let hash = hash_pubkeys(*opener_payment_basepoint, *accepter_payment_basepoint);
+
+    let mut factor = [0u8; 8];
+    factor[2..].copy_from_slice(&hash[26..]);
+    u64::from_be_bytes(factor)
+}
+
+/// Splits the obscured commitment number across the commitment transaction's
+/// nLockTime (lower 24 bits, upper byte 0x20) and its input's nSequence
+/// (upper 24 bits, upper byte 0x80).
+pub fn obscured_commitment_locktime_and_sequence(
+    commitment_number: u64,
+    obscure_factor: u64,
+) -> (LockTime, Sequence) {
+    let obscured = (commitment_number ^ obscure_factor) & 0xffff_ffff_ffff;
+
+    let locktime = LockTime::from_consensus((0x20 << 24) | (obscured & 0xff_ffff) as u32);
+    let sequence = Sequence((0x80 << 24) | (obscured >> 24) as u32);
+
+    (locktime, sequence)
+}
+
+/// Recovers the commitment number from a commitment transaction, given the
+/// channel's obscuring factor.
+pub fn commitment_number_from_transaction(tx: &Transaction, obscure_factor: u64) -> u64 {
+    let lower = (tx.lock_time.to_consensus_u32() & 0xff_ffff) as u64;
+    let upper = (tx.input[0].sequence.0 & 0xff_ffff) as u64;
+
+    ((upper << 24) | lower) ^ obscure_factor
+}
+
+/// Orders commitment outputs per BIP69: by amount, then by scriptPubKey. HTLC
+/// outputs that are otherwise identical are ordered by their `cltv_expiry`,
+/// which is carried alongside each output (`None` for to_local/to_remote).
+pub fn sort_commitment_outputs(outputs: &mut [(TxOut, Option<u32>)]) {
+    outputs.sort_by(|(a, a_cltv), (b, b_cltv)| {
+        a.value
+            .cmp(&b.value)
+            .then_with(|| a.script_pubkey.as_bytes().cmp(b.script_pubkey.as_bytes()))
+            .then_with(|| a_cltv.cmp(b_cltv))
+    });
+}
+
+/// Builds a commitment transaction the way a BOLT 3 peer would: the
+/// commitment number is obscured into nLockTime and nSequence, to_remote
+/// pays the counterparty's payment basepoint, and outputs are BIP69-sorted.
+#[allow(clippy::too_many_arguments)]
+pub fn build_numbered_commitment_transaction(
+    funding_txin: TxIn,
+    commitment_number: u64,
+    local_payment_basepoint: &PublicKey,
+    remote_payment_basepoint: &PublicKey,
+    local_is_opener: bool,
+    revocation_pubkey: &PublicKey,
+    remote_htlc_pubkey: &PublicKey,
+    local_htlc_pubkey: &PublicKey,
+    to_local_delayed_pubkey: &PublicKey,
+    to_self_delay: i64,
+    htlcs: &[HtlcOutput],
+    local_amount: u64,
+    remote_amount: u64,
+) -> Transaction {
+    numbered_commitment_transaction(
+        funding_txin,
+        commitment_number,
+        local_payment_basepoint,
+        remote_payment_basepoint,
+        local_is_opener,
+        ChannelType::StaticRemoteKey,
+        revocation_pubkey,
+        remote_htlc_pubkey,
+        local_htlc_pubkey,
+        to_local_delayed_pubkey,
+        to_self_delay,
+        htlcs,
+        Some(local_amount),
+        Some(remote_amount),
+        None,
+        None,
+    )
+}
+
+/// Shared by the commitment builders: `None` omits to_local or to_remote,
+/// and an anchor output is added for each funding pubkey passed in.
+#[allow(clippy::too_many_arguments)]
+fn numbered_commitment_transaction(
+    funding_txin: TxIn,
+    commitment_number: u64,
+    local_payment_basepoint: &PublicKey,
+    remote_payment_basepoint: &PublicKey,
+    local_is_opener: bool,
+    channel_type: ChannelType,
+    revocation_pubkey: &PublicKey,
+    remote_htlc_pubkey: &PublicKey,
+    local_htlc_pubkey: &PublicKey,
+    to_local_delayed_pubkey: &PublicKey,
+    to_self_delay: i64,
+    htlcs: &[HtlcOutput],
+    local_amount: Option<u64>,
+    remote_amount: Option<u64>,
+    local_anchor_pubkey: Option<&PublicKey>,
+    remote_anchor_pubkey: Option<&PublicKey>,
+) -> Transaction {
+    let obscure_factor = if local_is_opener {
+        commitment_number_obscure_factor(local_payment_basepoint, remote_payment_basepoint)
+    } else {
+        commitment_number_obscure_factor(remote_payment_basepoint, local_payment_basepoint)
+    };
+
+    let (locktime, sequence) =
+        obscured_commitment_locktime_and_sequence(commitment_number, obscure_factor);
+
+    let mut outputs = Vec::new();
+
+    if let Some(local_amount) = local_amount {
+        let to_local_script =
+            to_local(revocation_pubkey, to_local_delayed_pubkey, to_self_delay);
+        outputs.push((build_output(local_amount, to_local_script.to_p2wsh()), None));
+    }
+
+    if let Some(remote_amount) = remote_amount {
+        let to_remote_script = to_remote_output_script(channel_type, remote_payment_basepoint);
+        outputs.push((build_output(remote_amount, to_remote_script), None));
+    }
+
+    for anchor_pubkey in [local_anchor_pubkey, remote_anchor_pubkey].into_iter().flatten() {
+        let anchor_script = build_anchor_witness_script(anchor_pubkey);
+        outputs.push((build_output(ANCHOR_OUTPUT_VALUE, anchor_script.to_p2wsh()), None));
+    }
+
+    for htlc in htlcs {
+        let htlc_script = htlc_witness_script(
+            channel_type,
+            htlc,
+            revocation_pubkey,
+            remote_htlc_pubkey,
+            local_htlc_pubkey,
+        );
+        outputs.push((build_output(htlc.amount, htlc_script.to_p2wsh()), Some(htlc.cltv_expiry)));
+    }
+
+    sort_commitment_outputs(&mut outputs);
+
+    let mut funding_txin = funding_txin;
+    funding_txin.sequence = sequence;
+
+    build_transaction(Version::TWO,
+                      locktime,
+                      vec![funding_txin],
+                      outputs.into_iter().map(|(output, _)| output).collect())
+}
+
+/// Weight of a commitment transaction with no HTLC outputs (BOLT 3).
+pub const COMMITMENT_BASE_WEIGHT: u64 = 724;
+/// Weight of an `option_anchors` commitment transaction with no HTLC outputs.
+pub const ANCHORS_COMMITMENT_BASE_WEIGHT: u64 = 1124;
+/// Weight added to a commitment transaction by each untrimmed HTLC output.
+pub const COMMITMENT_HTLC_WEIGHT: u64 = 172;
+/// Weight of an HTLC-timeout transaction, used to compute its fee (BOLT 3).
+pub const HTLC_TIMEOUT_WEIGHT: u64 = 663;
+/// Weight of an HTLC-success transaction, used to compute its fee (BOLT 3).
+pub const HTLC_SUCCESS_WEIGHT: u64 = 703;
+
+/// Fee paid by the second-stage transaction spending this HTLC from the
+/// broadcaster's commitment. Zero under `option_anchors_zero_fee_htlc_tx`,
+/// where the fee is brought in by extra inputs instead.
+pub fn htlc_second_stage_fee(channel_type: ChannelType, htlc: &HtlcOutput, feerate_per_kw: u32) -> u64 {
+    if channel_type == ChannelType::AnchorsZeroFeeHtlcTx {
+        return 0;
+    }
+    let weight = if htlc.offered { HTLC_TIMEOUT_WEIGHT } else { HTLC_SUCCESS_WEIGHT };
+    feerate_per_kw as u64 * weight / 1000
+}
+
+/// An output that was left off a commitment transaction, and why.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TrimmedOutput {
+    /// The HTLC's amount does not cover `dust_limit + second-stage fee`.
+    Htlc { htlc: HtlcOutput, threshold: u64 },
+    /// to_local fell below the dust limit, after paying the fee if we are the opener.
+    ToLocal { amount: u64, dust_limit: u64 },
+    /// to_remote fell below the dust limit, after paying the fee if they are the opener.
+    ToRemote { amount: u64, dust_limit: u64 },
+}
+
+impl std::fmt::Display for TrimmedOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TrimmedOutput::Htlc { htlc, threshold } => write!(
+                f,
+                "{} HTLC of {} sat (cltv {}) is below dust limit plus second-stage fee of {} sat",
+                if htlc.offered { "offered" } else { "received" },
+                htlc.amount,
+                htlc.cltv_expiry,
+                threshold,
+            ),
+            TrimmedOutput::ToLocal { amount, dust_limit } => {
+                write!(f, "to_local of {} sat is below dust limit of {} sat", amount, dust_limit)
+            }
+            TrimmedOutput::ToRemote { amount, dust_limit } => {
+                write!(f, "to_remote of {} sat is below dust limit of {} sat", amount, dust_limit)
+            }
+        }
+    }
+}
+
+/// How the funds of a fee-aware commitment transaction were split up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitmentFeeBreakdown {
+    pub weight: u64,
+    pub fee: u64,
+    /// to_local and to_remote after the opener paid the fee (and the anchors,
+    /// if any), `None` if trimmed.
+    pub to_local: Option<u64>,
+    pub to_remote: Option<u64>,
+    pub local_anchor: bool,
+    pub remote_anchor: bool,
+    pub untrimmed_htlcs: Vec<HtlcOutput>,
+    pub trimmed: Vec<TrimmedOutput>,
+}
+
+/// Builds a commitment transaction from channel balances the way BOLT 3
+/// does: HTLCs that would be dust once their second-stage fee is paid are
+/// trimmed, the commitment fee is taken from the opener's balance, and
+/// to_local/to_remote are dropped if they end up below the dust limit.
+///
+/// Under `option_anchors` the opener also pays for both anchors, and each
+/// side's anchor is only added if that side has to_local/to_remote or there
+/// are untrimmed HTLCs.
+#[allow(clippy::too_many_arguments)]
+pub fn build_fee_aware_commitment_transaction(
+    funding_txin: TxIn,
+    commitment_number: u64,
+    local_payment_basepoint: &PublicKey,
+    remote_payment_basepoint: &PublicKey,
+    local_is_opener: bool,
+    channel_type: ChannelType,
+    local_funding_pubkey: &PublicKey,
+    remote_funding_pubkey: &PublicKey,
+    revocation_pubkey: &PublicKey,
+    remote_htlc_pubkey: &PublicKey,
+    local_htlc_pubkey: &PublicKey,
+    to_local_delayed_pubkey: &PublicKey,
+    to_self_delay: i64,
+    feerate_per_kw: u32,
+    dust_limit_satoshis: u64,
+    htlcs: &[HtlcOutput],
+    to_local_msat: u64,
+    to_remote_msat: u64,
+) -> (Transaction, CommitmentFeeBreakdown) {
+    let mut trimmed = Vec::new();
+    let mut untrimmed_htlcs = Vec::new();
+
+    for htlc in htlcs {
+        let threshold = dust_limit_satoshis + htlc_second_stage_fee(channel_type, htlc, feerate_per_kw);
+        if htlc.amount < threshold {
+            trimmed.push(TrimmedOutput::Htlc { htlc: htlc.clone(), threshold });
+        } else {
+            untrimmed_htlcs.push(htlc.clone());
+        }
+    }
+
+    let weight = channel_type.commitment_base_weight()
+        + COMMITMENT_HTLC_WEIGHT * untrimmed_htlcs.len() as u64;
+    let fee = feerate_per_kw as u64 * weight / 1000;
+
+    let opener_pays = match channel_type {
+        ChannelType::StaticRemoteKey => fee,
+        ChannelType::AnchorsZeroFeeHtlcTx => fee + 2 * ANCHOR_OUTPUT_VALUE,
+    };
+
+    let mut local_amount = to_local_msat / 1000;
+    let mut remote_amount = to_remote_msat / 1000;
+    if local_is_opener {
+        local_amount = local_amount.saturating_sub(opener_pays);
+    } else {
+        remote_amount = remote_amount.saturating_sub(opener_pays);
+    }
+
+    let to_local = if local_amount >= dust_limit_satoshis {
+        Some(local_amount)
+    } else {
+        trimmed.push(TrimmedOutput::ToLocal { amount: local_amount, dust_limit: dust_limit_satoshis });
+        None
+    };
+
+    let to_remote = if remote_amount >= dust_limit_satoshis {
+        Some(remote_amount)
+    } else {
+        trimmed.push(TrimmedOutput::ToRemote { amount: remote_amount, dust_limit: dust_limit_satoshis });
+        None
+    };
+
+    let anchors = channel_type == ChannelType::AnchorsZeroFeeHtlcTx;
+    let local_anchor = anchors && (to_local.is_some() || !untrimmed_htlcs.is_empty());
+    let remote_anchor = anchors && (to_remote.is_some() || !untrimmed_htlcs.is_empty());
+
+    let tx = numbered_commitment_transaction(
+        funding_txin,
+        commitment_number,
+        local_payment_basepoint,
+        remote_payment_basepoint,
+        local_is_opener,
+        channel_type,
+        revocation_pubkey,
+        remote_htlc_pubkey,
+        local_htlc_pubkey,
+        to_local_delayed_pubkey,
+        to_self_delay,
+        &untrimmed_htlcs,
+        to_local,
+        to_remote,
+        local_anchor.then_some(local_funding_pubkey),
+        remote_anchor.then_some(remote_funding_pubkey),
+    );
+
+    let breakdown = CommitmentFeeBreakdown {
+        weight,
+        fee,
+        to_local,
+        to_remote,
+        local_anchor,
+        remote_anchor,
+        untrimmed_htlcs,
+        trimmed,
+    };
+
+    (tx, breakdown)
+}
+
+/// Upper bound on an ECDSA signature with its sighash byte.
+const MAX_SIGNATURE_SIZE: u64 = 73;
+
+/// The witness an input will be signed with, which fixes how much weight it
+/// adds to the transaction. Script-spending variants carry the witness script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InputSatisfaction {
+    /// `<sig> <pubkey>`, e.g. a wallet UTXO.
+    P2wpkh,
+    /// `<schnorr sig>`, a P2TR key-path spend with the default sighash type.
+    TaprootKeySpend,
+    /// `0 <sig1> <sig2> <2-of-2 script>`, spending the funding output.
+    Multisig(ScriptBuf),
+    /// `<local_delayedsig> 0 <to_local script>`, after `to_self_delay`.
+    ToLocalDelayed(ScriptBuf),
+    /// `<revocation_sig> 1 <to_local script>`, or
+    /// `<revocation_sig> <revocationpubkey> <HTLC script>` for HTLC outputs.
+    Revocation(ScriptBuf),
+    /// `0 <remotehtlcsig> <localhtlcsig> 0 <offered HTLC script>`, as on an
+    /// HTLC-timeout transaction.
+    HtlcTimeout(ScriptBuf),
+    /// `0 <remotehtlcsig> <localhtlcsig> <payment_preimage> <received HTLC script>`,
+    /// as on an HTLC-success transaction.
+    HtlcSuccess(ScriptBuf),
+    /// Any other P2WSH spend: the sizes of the elements before the witness script.
+    Custom { element_sizes: Vec<u64>, witness_script: ScriptBuf },
+}
+
+impl InputSatisfaction {
+    /// Sizes of the witness elements, using the largest possible signatures.
+    fn element_sizes(&self) -> Vec<u64> {
+        match self {
+            InputSatisfaction::P2wpkh => vec![MAX_SIGNATURE_SIZE, 33],
+            InputSatisfaction::TaprootKeySpend => vec![64],
+            InputSatisfaction::Multisig(script) => vec![0, MAX_SIGNATURE_SIZE, MAX_SIGNATURE_SIZE, script.len() as u64],
+            InputSatisfaction::ToLocalDelayed(script) => vec![MAX_SIGNATURE_SIZE, 0, script.len() as u64],
+            InputSatisfaction::Revocation(script) => {
+                let element_size = match identify_template(script) {
+                    Some(ScriptTemplate::ToLocal { .. }) => 1,
+                    _ => 33,
+                };
+                vec![MAX_SIGNATURE_SIZE, element_size, script.len() as u64]
+            }
+            InputSatisfaction::HtlcTimeout(script) => {
+                vec![0, MAX_SIGNATURE_SIZE, MAX_SIGNATURE_SIZE, 0, script.len() as u64]
+            }
+            InputSatisfaction::HtlcSuccess(script) => {
+                vec![0, MAX_SIGNATURE_SIZE, MAX_SIGNATURE_SIZE, 32, script.len() as u64]
+            }
+            InputSatisfaction::Custom { element_sizes, witness_script } => {
+                let mut sizes = element_sizes.clone();
+                sizes.push(witness_script.len() as u64);
+                sizes
+            }
+        }
+    }
+
+    /// Weight of the serialized witness, including its element count.
+    pub fn witness_weight(&self) -> u64 {
+        let sizes = self.element_sizes();
+        let mut weight = bitcoin::VarInt(sizes.len() as u64).size() as u64;
+        for size in sizes {
+            weight += bitcoin::VarInt(size).size() as u64 + size;
+        }
+        weight
+    }
+}
+
+/// Predicts the weight of `tx` once each input carries the witness described
+/// by the matching entry of `satisfactions`. Any witness already on `tx` is
+/// ignored, so this works on unsigned and signed transactions alike.
+pub fn estimate_signed_weight(tx: &Transaction, satisfactions: &[InputSatisfaction]) -> u64 {
+    assert_eq!(tx.input.len(), satisfactions.len(), "one satisfaction per input");
+
+    let mut unsigned_tx = tx.clone();
+    for input in unsigned_tx.input.iter_mut() {
+        input.witness = Witness::new();
+    }
+
+    // The non-witness data counts four times, the segwit marker and flag once.
+    let witness_weight: u64 = satisfactions.iter().map(|satisfaction| satisfaction.witness_weight()).sum();
+    unsigned_tx.weight().to_wu() + 2 + witness_weight
+}
+
+/// Fee for `weight` at `feerate_per_kw`, rounded down as in BOLT 3.
+pub fn fee_for_weight(feerate_per_kw: u32, weight: u64) -> u64 {
+    feerate_per_kw as u64 * weight / 1000
+}
+
+/// Sets `tx.output[output_index]` to whatever `input_amount` has left after
+/// the other outputs and the fee for the signed weight at `feerate_per_kw`.
+/// Returns the fee, or `None` if the inputs cannot cover it.
+pub fn set_output_amount_for_feerate(
+    tx: &mut Transaction,
+    output_index: usize,
+    input_amount: u64,
+    satisfactions: &[InputSatisfaction],
+    feerate_per_kw: u32,
+) -> Option<u64> {
+    // An output's value has a fixed size, so changing it leaves the weight alone.
+    let fee = fee_for_weight(feerate_per_kw, estimate_signed_weight(tx, satisfactions));
+    let other_outputs: u64 = tx
+        .output
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != output_index)
+        .map(|(_, output)| output.value.to_sat())
+        .sum();
+
+    let amount = input_amount.checked_sub(other_outputs)?.checked_sub(fee)?;
+    tx.output[output_index].value = Amount::from_sat(amount);
+    Some(fee)
+}
+
+/// Sweeps every output of a revoked commitment transaction that we can claim
+/// through the revocation path - the cheater's to_local and all HTLC outputs -
+/// to `destination_script` in a single transaction.
+///
+/// The revocation private key is derived from the per-commitment secret the
+/// cheater gave us when revoking, and our own revocation basepoint secret.
+/// Keys are from the broadcaster's (cheater's) point of view, as are the
+/// `offered` flags of `htlcs`. The fee is taken from the `fee_estimator`'s
+/// urgent on-chain sweep feerate.
+///
+/// Returns `None` if no output is ours to claim, or if the claimable outputs
+/// can't cover the fee.
+#[allow(clippy::too_many_arguments)]
+pub fn build_justice_transaction<F: FeeEstimator + ?Sized>(
+    revoked_commitment_tx: &Transaction,
+    channel_type: ChannelType,
+    per_commitment_secret: &SecretKey,
+    revocation_basepoint_secret: &SecretKey,
+    to_local_delayed_pubkey: &PublicKey,
+    to_self_delay: i64,
+    remote_htlc_pubkey: &PublicKey,
+    local_htlc_pubkey: &PublicKey,
+    htlcs: &[HtlcOutput],
+    destination_script: ScriptBuf,
+    fee_estimator: &F,
+) -> Option<Transaction> {
+    let secp = Secp256k1::new();
+
+    let revocation_private_key =
+        derive_private_revocation_key(per_commitment_secret, revocation_basepoint_secret);
+    let revocation_pubkey = PublicKey::from_secret_key(&secp, &revocation_private_key);
+
+    let to_local_script = to_local(&revocation_pubkey, to_local_delayed_pubkey, to_self_delay);
+
+    let mut witness_scripts = vec![to_local_script.clone()];
+    for htlc in htlcs {
+        witness_scripts.push(htlc_witness_script(
+            channel_type,
+            htlc,
+            &revocation_pubkey,
+            remote_htlc_pubkey,
+            local_htlc_pubkey,
+        ));
+    }
+
+    let commitment_txid = revoked_commitment_tx.compute_txid();
+
+    // (input, amount, witness script) for every output we can claim.
+    let mut claims = Vec::new();
+    for (vout, output) in revoked_commitment_tx.output.iter().enumerate() {
+        let witness_script = witness_scripts
+            .iter()
+            .find(|script| script.to_p2wsh() == output.script_pubkey);
+
+        if let Some(witness_script) = witness_script {
+            let txin = TxIn {
+                previous_output: OutPoint { txid: commitment_txid, vout: vout as u32 },
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            };
+            claims.push((txin, output.value.to_sat(), witness_script.clone()));
+        }
+    }
+
+    if claims.is_empty() {
+        return None;
+    }
+    let total_amount: u64 = claims.iter().map(|(_, amount, _)| amount).sum();
+
+    let mut tx = build_transaction(
+        Version::TWO,
+        LockTime::ZERO,
+        claims.iter().map(|(txin, _, _)| txin.clone()).collect(),
+        vec![build_output(total_amount, destination_script)],
+    );
+
+    let satisfactions: Vec<InputSatisfaction> = claims
+        .iter()
+        .map(|(_, _, witness_script)| InputSatisfaction::Revocation(witness_script.clone()))
+        .collect();
+    let feerate_per_kw =
+        fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::UrgentOnChainSweep);
+    set_output_amount_for_feerate(&mut tx, 0, total_amount, &satisfactions, feerate_per_kw)?;
+
+    let mut witnesses = Vec::new();
+    for (input_idx, (_, amount, witness_script)) in claims.iter().enumerate() {
+        let sighash = SighashCache::new(&tx)
+            .p2wsh_signature_hash(input_idx, witness_script, Amount::from_sat(*amount), EcdsaSighashType::All)
+            .unwrap();
+        let message = Message::from_digest(sighash.to_byte_array());
+        let signature = BitcoinSignature::sighash_all(secp.sign_ecdsa(&message, &revocation_private_key));
+
+        let mut witness = Witness::new();
+        witness.push(signature.to_vec());
+        if *witness_script == to_local_script {
+            witness.push([1u8]);
+        } else {
+            witness.push(revocation_pubkey.serialize());
+        }
+        witness.push(witness_script.as_bytes());
+        witnesses.push(witness);
+    }
+
+    for (input, witness) in tx.input.iter_mut().zip(witnesses) {
+        input.witness = witness;
+    }
+
+    Some(tx)
+}
+
+/// Builds the mutual close transaction for a channel (BOLT 3 "Closing
+/// Transaction").
+///
+/// Each side is paid to the script it sent in `shutdown`, rounded down to
+/// whole satoshis. The opener pays the whole `fee_satoshis`, and any output
+/// below `dust_limit_satoshis` is left out. Outputs are BIP69-sorted.
+#[allow(clippy::too_many_arguments)]
+pub fn build_closing_transaction(
+    funding_txin: TxIn,
+    local_shutdown_script: ScriptBuf,
+    remote_shutdown_script: ScriptBuf,
+    to_local_msat: u64,
+    to_remote_msat: u64,
+    local_is_opener: bool,
+    dust_limit_satoshis: u64,
+    fee_satoshis: u64,
+) -> Transaction {
+    let mut to_local = to_local_msat / 1000;
+    let mut to_remote = to_remote_msat / 1000;
+
+    if local_is_opener {
+        to_local = to_local.saturating_sub(fee_satoshis);
+    } else {
+        to_remote = to_remote.saturating_sub(fee_satoshis);
+    }
+
+    let mut outputs = Vec::new();
+    if to_local >= dust_limit_satoshis {
+        outputs.push((build_output(to_local, local_shutdown_script), None));
+    }
+    if to_remote >= dust_limit_satoshis {
+        outputs.push((build_output(to_remote, remote_shutdown_script), None));
+    }
+    sort_commitment_outputs(&mut outputs);
+
+    // The closing transaction is final: no relative or absolute timelock.
+    let mut funding_txin = funding_txin;
+    funding_txin.sequence = Sequence::MAX;
+
+    build_transaction(
+        Version::TWO,
+        LockTime::ZERO,
+        vec![funding_txin],
+        outputs.into_iter().map(|(output, _)| output).collect(),
+    )
+}
+
+/// What a node should do with a `closing_signed` it received.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClosingFeeResponse {
+    /// The proposed fee works for us: sign the closing transaction with it
+    /// and reply with the same fee.
+    Accept(u64),
+    /// Reply with a `closing_signed` proposing this fee instead.
+    CounterPropose(u64),
+    /// Our `fee_range`s do not overlap, so there is no fee we can agree on.
+    Fail,
+}
+
+/// The fees acceptable to both sides, or `None` if there are none.
+fn closing_fee_overlap(
+    ours: &ClosingSignedFeeRange,
+    theirs: &ClosingSignedFeeRange,
+) -> Option<ClosingSignedFeeRange> {
+    let min_fee_satoshis = ours.min_fee_satoshis.max(theirs.min_fee_satoshis);
+    let max_fee_satoshis = ours.max_fee_satoshis.min(theirs.max_fee_satoshis);
+    if min_fee_satoshis > max_fee_satoshis {
+        return None;
+    }
+    Some(ClosingSignedFeeRange { min_fee_satoshis, max_fee_satoshis })
+}
+
+/// Handles a received `closing_signed` the way BOLT 2 describes for peers
+/// that both send `fee_range`: a fee inside the overlap of the two ranges is
+/// accepted, otherwise we propose the fee in the overlap closest to
+/// `our_preferred_fee`.
+pub fn respond_to_closing_signed(
+    our_fee_range: &ClosingSignedFeeRange,
+    our_preferred_fee: u64,
+    their_fee_satoshis: u64,
+    their_fee_range: &ClosingSignedFeeRange,
+) -> ClosingFeeResponse {
+    let overlap = match closing_fee_overlap(our_fee_range, their_fee_range) {
+        Some(overlap) => overlap,
+        None => return ClosingFeeResponse::Fail,
+    };
+
+    if their_fee_satoshis >= overlap.min_fee_satoshis && their_fee_satoshis <= overlap.max_fee_satoshis {
+        return ClosingFeeResponse::Accept(their_fee_satoshis);
+    }
+
+    ClosingFeeResponse::CounterPropose(
+        our_preferred_fee.clamp(overlap.min_fee_satoshis, overlap.max_fee_satoshis),
+    )
+}
+
+/// Runs a `closing_signed` exchange to completion and returns the agreed fee.
+///
+/// The opener speaks first, proposing its preferred fee. Since every
+/// counter-proposal lies in the overlap of both ranges, the opener always
+/// accepts it, so negotiation settles within two rounds. A `fee_range` whose
+/// minimum is above its maximum fails the negotiation.
+pub fn negotiate_closing_fee(
+    opener_preferred_fee: u64,
+    opener_fee_range: &ClosingSignedFeeRange,
+    accepter_preferred_fee: u64,
+    accepter_fee_range: &ClosingSignedFeeRange,
+) -> Option<u64> {
+    if opener_fee_range.min_fee_satoshis > opener_fee_range.max_fee_satoshis
+        || accepter_fee_range.min_fee_satoshis > accepter_fee_range.max_fee_satoshis
+    {
+        return None;
+    }
+
+    let opener_fee = opener_preferred_fee
+        .clamp(opener_fee_range.min_fee_satoshis, opener_fee_range.max_fee_satoshis);
+
+    let counter_proposal = match respond_to_closing_signed(
+        accepter_fee_range,
+        accepter_preferred_fee,
+        opener_fee,
+        opener_fee_range,
+    ) {
+        ClosingFeeResponse::Accept(fee) => return Some(fee),
+        ClosingFeeResponse::CounterPropose(fee) => fee,
+        ClosingFeeResponse::Fail => return None,
+    };
+
+    match respond_to_closing_signed(
+        opener_fee_range,
+        opener_preferred_fee,
+        counter_proposal,
+        accepter_fee_range,
+    ) {
+        ClosingFeeResponse::Accept(fee) => Some(fee),
+        _ => None,
+    }
+}
@@ -0,0 +1,343 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! Splicing: resizing a channel without closing it.
+//!
+//! A splice transaction spends the current 2-of-2 funding output, along with
+//! any wallet inputs, into a new funding output and any wallet outputs.
+//! Until it confirms, both peers keep signing commitment transactions for the
+//! old funding output and for every splice candidate, so whichever ends up
+//! on chain can be closed from.
+use crate::ch1_intro_htlcs::solutions::{
+    estimate_signed_weight, fee_for_weight, set_output_amount_for_feerate, two_of_two_multisig_witness_script,
+    InputSatisfaction,
+};
+use crate::internal::helper::{build_output, build_transaction};
+use bitcoin::ecdsa::Signature as BitcoinSignature;
+use bitcoin::hashes::Hash;
+use bitcoin::locktime::absolute::LockTime;
+use bitcoin::script::ScriptBuf;
+use bitcoin::secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{Amount, OutPoint, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpliceError {
+    /// The wallet inputs cannot pay for the amount spliced in and the fee.
+    InsufficientFunds { available: u64, required: u64 },
+    /// A side's channel balance cannot cover what is taken out of it.
+    InsufficientBalance { balance_msat: u64, required_msat: u64 },
+    /// The transaction does not spend the channel's current funding output.
+    NotSpendingFunding,
+    /// The transaction has no output paying to the funding script.
+    MissingFundingOutput,
+    /// No pending splice has this txid.
+    UnknownSplice(Txid),
+}
+
+impl fmt::Display for SpliceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpliceError::InsufficientFunds { available, required } => {
+                write!(f, "wallet inputs hold {} sats, but {} sats are needed", available, required)
+            }
+            SpliceError::InsufficientBalance { balance_msat, required_msat } => {
+                write!(f, "balance of {} msat cannot cover {} msat", balance_msat, required_msat)
+            }
+            SpliceError::NotSpendingFunding => write!(f, "transaction does not spend the funding output"),
+            SpliceError::MissingFundingOutput => write!(f, "transaction has no funding output"),
+            SpliceError::UnknownSplice(txid) => write!(f, "no pending splice with txid {}", txid),
+        }
+    }
+}
+
+/// The splice transaction: the current funding output is spent by input 0
+/// and the new funding output is output 0, followed by the wallet inputs and
+/// outputs. Both funding outputs use the same funding keys.
+pub fn build_splice_transaction(
+    funding_txin: TxIn,
+    wallet_inputs: Vec<TxIn>,
+    alice_pubkey: &PublicKey,
+    bob_pubkey: &PublicKey,
+    new_funding_amount: u64,
+    wallet_outputs: Vec<TxOut>,
+) -> Transaction {
+    let output_script = two_of_two_multisig_witness_script(alice_pubkey, bob_pubkey);
+
+    let mut inputs = vec![funding_txin];
+    inputs.extend(wallet_inputs);
+
+    let mut outputs = vec![build_output(new_funding_amount, output_script.to_p2wsh())];
+    outputs.extend(wallet_outputs);
+
+    build_transaction(Version::TWO, LockTime::ZERO, inputs, outputs)
+}
+
+/// How a wallet input will be signed, judging by the output it spends.
+fn wallet_input_satisfaction(prevout: &TxOut) -> InputSatisfaction {
+    if prevout.script_pubkey.is_p2tr() {
+        InputSatisfaction::TaprootKeySpend
+    } else {
+        InputSatisfaction::P2wpkh
+    }
+}
+
+/// Adds `splice_in_amount` from our wallet to the channel.
+///
+/// The wallet inputs (P2WPKH or P2TR) pay for the amount and the whole fee
+/// at `feerate_per_kw`; what is left goes to `change_script`, unless it is
+/// dust. Returns the transaction and its fee.
+#[allow(clippy::too_many_arguments)]
+pub fn build_splice_in_transaction(
+    funding_txin: TxIn,
+    funding_amount: u64,
+    wallet_inputs: Vec<(TxIn, TxOut)>,
+    splice_in_amount: u64,
+    change_script: ScriptBuf,
+    alice_pubkey: &PublicKey,
+    bob_pubkey: &PublicKey,
+    feerate_per_kw: u32,
+) -> Result<(Transaction, u64), SpliceError> {
+    let witness_script = two_of_two_multisig_witness_script(alice_pubkey, bob_pubkey);
+    let available: u64 = wallet_inputs.iter().map(|(_, prevout)| prevout.value.to_sat()).sum();
+
+    let mut satisfactions = vec![InputSatisfaction::Multisig(witness_script)];
+    satisfactions.extend(wallet_inputs.iter().map(|(_, prevout)| wallet_input_satisfaction(prevout)));
+    let txins = wallet_inputs.into_iter().map(|(txin, _)| txin).collect();
+
+    let new_funding_amount = funding_amount + splice_in_amount;
+    let mut tx = build_splice_transaction(
+        funding_txin,
+        txins,
+        alice_pubkey,
+        bob_pubkey,
+        new_funding_amount,
+        vec![build_output(0, change_script)],
+    );
+
+    let input_amount = funding_amount + available;
+    if let Some(fee) = set_output_amount_for_feerate(&mut tx, 1, input_amount, &satisfactions, feerate_per_kw) {
+        if tx.output[1].value >= tx.output[1].script_pubkey.minimal_non_dust() {
+            return Ok((tx, fee));
+        }
+    }
+
+    // without change, whatever the inputs have left over goes to the fee
+    tx.output.pop();
+    let fee = fee_for_weight(feerate_per_kw, estimate_signed_weight(&tx, &satisfactions));
+    let required = splice_in_amount + fee;
+    if available < required {
+        return Err(SpliceError::InsufficientFunds { available, required });
+    }
+    Ok((tx, available - splice_in_amount))
+}
+
+/// Takes `destination.value` out of the channel and pays it to
+/// `destination.script_pubkey`.
+///
+/// The splicer's channel balance pays for the amount and the whole fee at
+/// `feerate_per_kw`, so it must hold at least their sum. Returns the
+/// transaction and its fee.
+pub fn build_splice_out_transaction(
+    funding_txin: TxIn,
+    funding_amount: u64,
+    splicer_balance_msat: u64,
+    destination: TxOut,
+    alice_pubkey: &PublicKey,
+    bob_pubkey: &PublicKey,
+    feerate_per_kw: u32,
+) -> Result<(Transaction, u64), SpliceError> {
+    let witness_script = two_of_two_multisig_witness_script(alice_pubkey, bob_pubkey);
+    let splice_out_amount = destination.value.to_sat();
+
+    let mut tx = build_splice_transaction(funding_txin, vec![], alice_pubkey, bob_pubkey, 0, vec![destination]);
+
+    // the new funding output gets whatever is left
+    let satisfactions = [InputSatisfaction::Multisig(witness_script)];
+    let fee = set_output_amount_for_feerate(&mut tx, 0, funding_amount, &satisfactions, feerate_per_kw);
+
+    let required_msat = fee.map_or(u64::MAX, |fee| (splice_out_amount + fee) * 1000);
+    if required_msat > splicer_balance_msat {
+        return Err(SpliceError::InsufficientBalance { balance_msat: splicer_balance_msat, required_msat });
+    }
+
+    Ok((tx, fee.expect("balance covers the fee")))
+}
+
+/// A signature for input 0 of `tx`, which spends the current funding output.
+pub fn sign_splice_funding_input(
+    tx: &Transaction,
+    funding_amount: u64,
+    funding_witness_script: &ScriptBuf,
+    funding_key: &SecretKey,
+) -> Signature {
+    let sighash = SighashCache::new(tx)
+        .p2wsh_signature_hash(0, funding_witness_script, Amount::from_sat(funding_amount), EcdsaSighashType::All)
+        .expect("splice transaction spends the funding output");
+
+    let message = Message::from_digest(sighash.to_byte_array());
+    Secp256k1::new().sign_ecdsa(&message, funding_key)
+}
+
+/// The witness spending the funding output built with
+/// `two_of_two_multisig_witness_script(alice_pubkey, bob_pubkey)`. The
+/// signatures go in the same order as the keys in the script.
+pub fn splice_funding_witness(
+    alice_signature: &Signature,
+    bob_signature: &Signature,
+    funding_witness_script: &ScriptBuf,
+) -> Witness {
+    let mut witness = Witness::new();
+
+    // CHECKMULTISIG pops one element too many
+    witness.push(Vec::new());
+    witness.push(BitcoinSignature::sighash_all(*alice_signature).to_vec());
+    witness.push(BitcoinSignature::sighash_all(*bob_signature).to_vec());
+    witness.push(funding_witness_script.as_bytes());
+
+    witness
+}
+
+/// A funding output the channel may end up on, with the balances its
+/// commitment transactions pay out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FundingScope {
+    pub outpoint: OutPoint,
+    pub value: u64,
+    pub witness_script: ScriptBuf,
+    pub to_local_msat: u64,
+    pub to_remote_msat: u64,
+}
+
+impl FundingScope {
+    /// The input commitment transactions of this scope spend.
+    pub fn funding_txin(&self) -> TxIn {
+        TxIn {
+            previous_output: self.outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }
+    }
+}
+
+/// The funding state of a channel while a splice is unconfirmed.
+///
+/// `current` is the funding output the channel is on now, and `pending`
+/// holds every splice transaction spending it that both peers have signed:
+/// the first one, and any fee bumps. Each of them may confirm, so every
+/// channel update must be valid for all scopes, and a commitment transaction
+/// is signed for each one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpliceState {
+    pub current: FundingScope,
+    pub pending: Vec<FundingScope>,
+}
+
+impl SpliceState {
+    pub fn new(current: FundingScope) -> Self {
+        SpliceState { current, pending: Vec::new() }
+    }
+
+    pub fn is_splicing(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// The current funding scope, followed by the pending ones.
+    pub fn funding_scopes(&self) -> impl Iterator<Item = &FundingScope> {
+        std::iter::once(&self.current).chain(self.pending.iter())
+    }
+
+    /// Records a signed splice transaction.
+    ///
+    /// Whatever the channel gains or loses, fee included, is added to or
+    /// taken from the splicer's balance: ours if `local_is_splicer`.
+    pub fn add_splice(
+        &mut self,
+        splice_tx: &Transaction,
+        local_is_splicer: bool,
+    ) -> Result<&FundingScope, SpliceError> {
+        if !splice_tx.input.iter().any(|txin| txin.previous_output == self.current.outpoint) {
+            return Err(SpliceError::NotSpendingFunding);
+        }
+
+        let funding_script = self.current.witness_script.to_p2wsh();
+        let vout = splice_tx
+            .output
+            .iter()
+            .position(|txout| txout.script_pubkey == funding_script)
+            .ok_or(SpliceError::MissingFundingOutput)?;
+        let value = splice_tx.output[vout].value.to_sat();
+
+        let (splicer_msat, other_msat) = if local_is_splicer {
+            (self.current.to_local_msat, self.current.to_remote_msat)
+        } else {
+            (self.current.to_remote_msat, self.current.to_local_msat)
+        };
+        let splicer_msat = (splicer_msat + value * 1000)
+            .checked_sub(self.current.value * 1000)
+            .ok_or(SpliceError::InsufficientBalance {
+                balance_msat: splicer_msat,
+                required_msat: (self.current.value - value) * 1000,
+            })?;
+        let (to_local_msat, to_remote_msat) = if local_is_splicer {
+            (splicer_msat, other_msat)
+        } else {
+            (other_msat, splicer_msat)
+        };
+
+        self.pending.push(FundingScope {
+            outpoint: OutPoint { txid: splice_tx.compute_txid(), vout: vout as u32 },
+            value,
+            witness_script: self.current.witness_script.clone(),
+            to_local_msat,
+            to_remote_msat,
+        });
+        Ok(self.pending.last().expect("just pushed"))
+    }
+
+    /// Moves `amount_msat` from our balance to theirs if `outbound`, the
+    /// other way otherwise. It has to fit in every funding scope.
+    pub fn apply_payment(&mut self, amount_msat: u64, outbound: bool) -> Result<(), SpliceError> {
+        for scope in self.funding_scopes() {
+            let balance_msat = if outbound { scope.to_local_msat } else { scope.to_remote_msat };
+            if balance_msat < amount_msat {
+                return Err(SpliceError::InsufficientBalance { balance_msat, required_msat: amount_msat });
+            }
+        }
+
+        for scope in std::iter::once(&mut self.current).chain(self.pending.iter_mut()) {
+            if outbound {
+                scope.to_local_msat -= amount_msat;
+                scope.to_remote_msat += amount_msat;
+            } else {
+                scope.to_remote_msat -= amount_msat;
+                scope.to_local_msat += amount_msat;
+            }
+        }
+        Ok(())
+    }
+
+    /// One commitment transaction per funding scope, built by `build` from
+    /// that scope's funding input and balances.
+    pub fn build_commitments<F>(&self, build: F) -> Vec<Transaction>
+    where
+        F: FnMut(&FundingScope) -> Transaction,
+    {
+        self.funding_scopes().map(build).collect()
+    }
+
+    /// The splice transaction `txid` confirmed: its funding output becomes
+    /// the current one, and the other candidates can never confirm.
+    pub fn splice_locked(&mut self, txid: Txid) -> Result<(), SpliceError> {
+        let index = self
+            .pending
+            .iter()
+            .position(|scope| scope.outpoint.txid == txid)
+            .ok_or(SpliceError::UnknownSplice(txid))?;
+
+        self.current = self.pending.swap_remove(index);
+        self.pending.clear();
+        Ok(())
+    }
+}
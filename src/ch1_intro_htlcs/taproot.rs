@@ -0,0 +1,381 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! Simple taproot channels: the funding and commitment builders of
+//! `solutions` with a MuSig2 funding output and tapscript commitment outputs.
+//!
+//! The funding output is a P2TR output whose key is the MuSig2 aggregate of
+//! both funding keys, so a cooperative spend is a single key-path signature.
+//! Commitment outputs keep the same spending conditions as their segwit v0
+//! counterparts, but split every branch into its own tapscript leaf.
+use crate::ch1_intro_htlcs::solutions::HtlcOutput;
+use crate::internal::builder::Builder;
+use crate::internal::helper::{build_output, build_transaction};
+use crate::internal::musig2::{
+    aggregate_nonces, nonce_gen, sort_pubkeys, KeyAggContext, MusigError, PartialSignature,
+    PubNonce, SecNonce, Session,
+};
+use bitcoin::blockdata::opcodes::all as opcodes;
+use bitcoin::hashes::Hash;
+use bitcoin::key::TweakedPublicKey;
+use bitcoin::locktime::absolute::LockTime;
+use bitcoin::script::{Script, ScriptBuf};
+use bitcoin::secp256k1::{schnorr, Keypair, Message, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+use bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+use bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TaprootBuilder, TaprootSpendInfo};
+use bitcoin::transaction::Version;
+use bitcoin::{Transaction, TxIn, TxOut, Witness};
+use rand::{thread_rng, Rng};
+
+/// The "nothing up my sleeve" point used as the internal key of outputs that
+/// must only be spendable through their scripts: `H(G)` lifted to a point,
+/// for which nobody knows the discrete logarithm.
+pub const NUMS_POINT: [u8; 33] = [
+    0x02, 0xdc, 0xa0, 0x94, 0x75, 0x11, 0x09, 0xd0, 0xbd, 0x05, 0x5d, 0x03, 0x56, 0x58, 0x74, 0xe8,
+    0x27, 0x6d, 0xd5, 0x3e, 0x92, 0x6b, 0x44, 0xe3, 0xbd, 0x1b, 0xb6, 0xbf, 0x4b, 0xc1, 0x30, 0xa2,
+    0x79,
+];
+
+pub fn nums_internal_key() -> XOnlyPublicKey {
+    PublicKey::from_slice(&NUMS_POINT).expect("valid point").x_only_public_key().0
+}
+
+/// The MuSig2 context of the funding output. Keys are sorted so both peers
+/// aggregate them in the same order, and the BIP 86 tweak commits to an
+/// empty script tree.
+pub fn funding_key_agg_context(local_funding_pubkey: &PublicKey, remote_funding_pubkey: &PublicKey) -> KeyAggContext {
+    KeyAggContext::new(&sort_pubkeys(&[*local_funding_pubkey, *remote_funding_pubkey])).with_taproot_tweak(None)
+}
+
+pub fn funding_output_script(local_funding_pubkey: &PublicKey, remote_funding_pubkey: &PublicKey) -> ScriptBuf {
+    let output_key = funding_key_agg_context(local_funding_pubkey, remote_funding_pubkey).x_only_public_key();
+    ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(output_key))
+}
+
+pub fn build_funding_transaction(
+    txins: Vec<TxIn>,
+    local_funding_pubkey: &PublicKey,
+    remote_funding_pubkey: &PublicKey,
+    amount: u64,
+) -> Transaction {
+    let output_script = funding_output_script(local_funding_pubkey, remote_funding_pubkey);
+
+    let txout = build_output(amount, output_script);
+
+    build_transaction(Version::TWO, LockTime::ZERO, txins, vec![txout])
+}
+
+/// to_local leaf spent by the owner after `to_self_delay` blocks.
+pub fn to_local_delay_script(local_delayed_pubkey: &PublicKey, to_self_delay: i64) -> ScriptBuf {
+    Builder::new()
+        .push_x_only_key(&local_delayed_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .push_int(to_self_delay)
+        .push_opcode(opcodes::OP_CSV)
+        .push_opcode(opcodes::OP_DROP)
+        .into_script()
+}
+
+/// to_local leaf spent by the counterparty with the revocation key. The
+/// delayed key is pushed and dropped so the leaf commits to it, which lets
+/// the counterparty rebuild the tree without knowing the other leaf.
+pub fn to_local_revoke_script(local_delayed_pubkey: &PublicKey, revocation_pubkey: &PublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_x_only_key(&local_delayed_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_DROP)
+        .push_x_only_key(&revocation_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .into_script()
+}
+
+pub fn to_remote_script(remote_pubkey: &PublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_x_only_key(&remote_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .push_int(1)
+        .push_opcode(opcodes::OP_CSV)
+        .push_opcode(opcodes::OP_DROP)
+        .into_script()
+}
+
+/// Offered HTLC leaf for the second-stage timeout transaction, which needs
+/// both HTLC signatures.
+pub fn offered_htlc_timeout_script(local_htlc_pubkey: &PublicKey, remote_htlc_pubkey: &PublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_x_only_key(&local_htlc_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_CHECKSIGVERIFY)
+        .push_x_only_key(&remote_htlc_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .into_script()
+}
+
+/// Offered HTLC leaf claimed directly by the counterparty with the preimage.
+pub fn offered_htlc_success_script(remote_htlc_pubkey: &PublicKey, payment_hash160: &[u8; 20]) -> ScriptBuf {
+    Builder::new()
+        .push_opcode(opcodes::OP_SIZE)
+        .push_int(32)
+        .push_opcode(opcodes::OP_EQUALVERIFY)
+        .push_opcode(opcodes::OP_HASH160)
+        .push_slice(payment_hash160)
+        .push_opcode(opcodes::OP_EQUALVERIFY)
+        .push_x_only_key(&remote_htlc_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .push_int(1)
+        .push_opcode(opcodes::OP_CSV)
+        .push_opcode(opcodes::OP_DROP)
+        .into_script()
+}
+
+/// Received HTLC leaf for the second-stage success transaction, which needs
+/// the preimage and both HTLC signatures.
+pub fn received_htlc_success_script(
+    local_htlc_pubkey: &PublicKey,
+    remote_htlc_pubkey: &PublicKey,
+    payment_hash160: &[u8; 20],
+) -> ScriptBuf {
+    Builder::new()
+        .push_opcode(opcodes::OP_SIZE)
+        .push_int(32)
+        .push_opcode(opcodes::OP_EQUALVERIFY)
+        .push_opcode(opcodes::OP_HASH160)
+        .push_slice(payment_hash160)
+        .push_opcode(opcodes::OP_EQUALVERIFY)
+        .push_x_only_key(&local_htlc_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_CHECKSIGVERIFY)
+        .push_x_only_key(&remote_htlc_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .into_script()
+}
+
+/// Received HTLC leaf reclaimed directly by the counterparty once the HTLC
+/// has expired.
+pub fn received_htlc_timeout_script(remote_htlc_pubkey: &PublicKey, cltv_expiry: u32) -> ScriptBuf {
+    Builder::new()
+        .push_x_only_key(&remote_htlc_pubkey.x_only_public_key().0)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .push_int(1)
+        .push_opcode(opcodes::OP_CSV)
+        .push_opcode(opcodes::OP_DROP)
+        .push_int(cltv_expiry as i64)
+        .push_opcode(opcodes::OP_CLTV)
+        .push_opcode(opcodes::OP_DROP)
+        .into_script()
+}
+
+fn two_leaf_spend_info(internal_key: XOnlyPublicKey, first: ScriptBuf, second: ScriptBuf) -> TaprootSpendInfo {
+    let secp = Secp256k1::verification_only();
+    TaprootBuilder::new()
+        .add_leaf(1, first)
+        .expect("valid depth")
+        .add_leaf(1, second)
+        .expect("valid depth")
+        .finalize(&secp, internal_key)
+        .expect("tree is complete")
+}
+
+/// The to_local tree has no key path: its internal key is the NUMS point.
+pub fn to_local_spend_info(
+    revocation_pubkey: &PublicKey,
+    local_delayed_pubkey: &PublicKey,
+    to_self_delay: i64,
+) -> TaprootSpendInfo {
+    two_leaf_spend_info(
+        nums_internal_key(),
+        to_local_delay_script(local_delayed_pubkey, to_self_delay),
+        to_local_revoke_script(local_delayed_pubkey, revocation_pubkey),
+    )
+}
+
+pub fn to_remote_spend_info(remote_pubkey: &PublicKey) -> TaprootSpendInfo {
+    let secp = Secp256k1::verification_only();
+    TaprootBuilder::new()
+        .add_leaf(0, to_remote_script(remote_pubkey))
+        .expect("valid depth")
+        .finalize(&secp, nums_internal_key())
+        .expect("tree is complete")
+}
+
+/// HTLC outputs use the revocation key as internal key, so a revoked HTLC is
+/// swept with a key-path spend.
+pub fn htlc_spend_info(
+    htlc: &HtlcOutput,
+    revocation_pubkey: &PublicKey,
+    remote_htlc_pubkey: &PublicKey,
+    local_htlc_pubkey: &PublicKey,
+) -> TaprootSpendInfo {
+    let internal_key = revocation_pubkey.x_only_public_key().0;
+    if htlc.offered {
+        two_leaf_spend_info(
+            internal_key,
+            offered_htlc_timeout_script(local_htlc_pubkey, remote_htlc_pubkey),
+            offered_htlc_success_script(remote_htlc_pubkey, &htlc.payment_hash160),
+        )
+    } else {
+        two_leaf_spend_info(
+            internal_key,
+            received_htlc_success_script(local_htlc_pubkey, remote_htlc_pubkey, &htlc.payment_hash160),
+            received_htlc_timeout_script(remote_htlc_pubkey, htlc.cltv_expiry),
+        )
+    }
+}
+
+pub fn spend_info_output_script(spend_info: &TaprootSpendInfo) -> ScriptBuf {
+    ScriptBuf::new_p2tr_tweaked(spend_info.output_key())
+}
+
+/// The control block proving `leaf_script` is part of `spend_info`'s tree.
+pub fn leaf_control_block(spend_info: &TaprootSpendInfo, leaf_script: &Script) -> ControlBlock {
+    spend_info
+        .control_block(&(leaf_script.to_owned(), LeafVersion::TapScript))
+        .expect("leaf is in the tree")
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn build_commitment_transaction(
+    funding_txin: TxIn,
+    revocation_pubkey: &PublicKey,
+    to_local_delayed_pubkey: &PublicKey,
+    remote_pubkey: &PublicKey,
+    to_self_delay: i64,
+    local_htlc_pubkey: &PublicKey,
+    remote_htlc_pubkey: &PublicKey,
+    htlcs: &[HtlcOutput],
+    local_amount: u64,
+    remote_amount: u64,
+) -> Transaction {
+    let to_local_info = to_local_spend_info(revocation_pubkey, to_local_delayed_pubkey, to_self_delay);
+
+    let to_remote_info = to_remote_spend_info(remote_pubkey);
+
+    let mut outputs = vec![
+        build_output(local_amount, spend_info_output_script(&to_local_info)),
+        build_output(remote_amount, spend_info_output_script(&to_remote_info)),
+    ];
+
+    for htlc in htlcs {
+        let htlc_info = htlc_spend_info(htlc, revocation_pubkey, remote_htlc_pubkey, local_htlc_pubkey);
+        outputs.push(build_output(htlc.amount, spend_info_output_script(&htlc_info)));
+    }
+
+    build_transaction(Version::TWO, LockTime::ZERO, vec![funding_txin], outputs)
+}
+
+/// BIP 341 sighash of a key-path spend. Taproot signatures commit to every
+/// spent output, so `prevouts` lists them all in input order.
+pub fn key_spend_sighash(tx: &Transaction, input_index: usize, prevouts: &[TxOut]) -> [u8; 32] {
+    SighashCache::new(tx)
+        .taproot_key_spend_signature_hash(input_index, &Prevouts::All(prevouts), TapSighashType::Default)
+        .expect("valid input index")
+        .to_byte_array()
+}
+
+pub fn script_spend_sighash(
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+    leaf_script: &Script,
+) -> [u8; 32] {
+    let leaf_hash = TapLeafHash::from_script(leaf_script, LeafVersion::TapScript);
+    SighashCache::new(tx)
+        .taproot_script_spend_signature_hash(input_index, &Prevouts::All(prevouts), leaf_hash, TapSighashType::Default)
+        .expect("valid input index")
+        .to_byte_array()
+}
+
+pub fn sign_tapscript(
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+    leaf_script: &Script,
+    secret_key: &SecretKey,
+) -> schnorr::Signature {
+    let secp = Secp256k1::new();
+    let sighash = script_spend_sighash(tx, input_index, prevouts, leaf_script);
+    let keypair = Keypair::from_secret_key(&secp, secret_key);
+    secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash), &keypair)
+}
+
+/// A script-path witness: the stack elements the leaf consumes (last one on
+/// top), then the leaf itself and its control block.
+pub fn tapscript_witness(stack: Vec<Vec<u8>>, leaf_script: &Script, control_block: &ControlBlock) -> Witness {
+    let mut witness = Witness::from_slice(&stack);
+    witness.push(leaf_script.as_bytes());
+    witness.push(control_block.serialize());
+    witness
+}
+
+pub fn key_spend_witness(signature: &schnorr::Signature) -> Witness {
+    Witness::from_slice(&[signature.as_ref().to_vec()])
+}
+
+/// One side of the two-party MuSig2 session that signs for the funding
+/// output. Nonces are exchanged ahead of the message (as in
+/// `commitment_signed`), and each secret nonce signs exactly once. Nonces
+/// are drawn from our own randomness, never the caller's, so a nonce can't
+/// be reused by passing the same bytes twice.
+pub struct FundingSigner {
+    secret_key: SecretKey,
+    key_agg: KeyAggContext,
+    secnonce: Option<SecNonce>,
+    pubnonce: PubNonce,
+    session: Option<(Session, PubNonce, PartialSignature)>,
+}
+
+impl FundingSigner {
+    pub fn new(secret_key: SecretKey, remote_funding_pubkey: &PublicKey) -> FundingSigner {
+        let secp = Secp256k1::signing_only();
+        let local_funding_pubkey = PublicKey::from_secret_key(&secp, &secret_key);
+        let key_agg = funding_key_agg_context(&local_funding_pubkey, remote_funding_pubkey);
+        let (secnonce, pubnonce) = Self::generate_nonce(&secret_key, &key_agg);
+        FundingSigner { secret_key, key_agg, secnonce: Some(secnonce), pubnonce, session: None }
+    }
+
+    fn generate_nonce(secret_key: &SecretKey, key_agg: &KeyAggContext) -> (SecNonce, PubNonce) {
+        let secp = Secp256k1::signing_only();
+        let pubkey = PublicKey::from_secret_key(&secp, secret_key);
+        let rand = thread_rng().gen();
+        nonce_gen(rand, Some(secret_key), &pubkey, Some(&key_agg.x_only_public_key()), None)
+    }
+
+    /// Replaces the nonce, e.g. after it has been used, with a fresh one.
+    pub fn next_nonce(&mut self) -> PubNonce {
+        let (secnonce, pubnonce) = Self::generate_nonce(&self.secret_key, &self.key_agg);
+        self.secnonce = Some(secnonce);
+        self.pubnonce = pubnonce;
+        pubnonce
+    }
+
+    pub fn public_nonce(&self) -> PubNonce {
+        self.pubnonce
+    }
+
+    pub fn funding_output_key(&self) -> XOnlyPublicKey {
+        self.key_agg.x_only_public_key()
+    }
+
+    /// Produces our partial signature of `sighash`, spending our nonce.
+    pub fn partial_sign(&mut self, remote_nonce: &PubNonce, sighash: [u8; 32]) -> Result<PartialSignature, MusigError> {
+        let secnonce = self.secnonce.take().ok_or(MusigError::MissingNonce)?;
+        let aggnonce = aggregate_nonces(&[self.pubnonce, *remote_nonce]);
+        let session = Session::new(&self.key_agg, &aggnonce, sighash);
+        let partial_signature = session.partial_sign(secnonce, &self.secret_key)?;
+        self.session = Some((session, *remote_nonce, partial_signature));
+        Ok(partial_signature)
+    }
+
+    /// Checks the counterparty's partial signature for the message we last
+    /// signed and combines both into the funding key-path signature.
+    pub fn aggregate(
+        &self,
+        remote_funding_pubkey: &PublicKey,
+        remote_partial_signature: &PartialSignature,
+    ) -> Result<schnorr::Signature, MusigError> {
+        let (session, remote_nonce, local_partial_signature) = self.session.as_ref().ok_or(MusigError::MissingNonce)?;
+        if !session.partial_verify(remote_partial_signature, remote_nonce, remote_funding_pubkey) {
+            let signer = self.key_agg.pubkeys().iter().position(|key| key == remote_funding_pubkey);
+            return Err(match signer {
+                Some(signer) => MusigError::InvalidPartialSignature { signer },
+                None => MusigError::UnknownSigner,
+            });
+        }
+        Ok(session.aggregate(&[*local_partial_signature, *remote_partial_signature]))
+    }
+}
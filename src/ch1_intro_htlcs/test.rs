@@ -3,14 +3,75 @@ use crate::ch1_intro_htlcs::exercises::{
     build_commitment_transaction, build_funding_transaction, build_htlc_commitment_transaction,
     build_htlc_timeout_transaction, build_refund_transaction, generate_revocation_pubkey, to_local, two_of_two_multisig_witness_script,
 };
+use crate::ch1_intro_htlcs::bolt3::{
+    byte_diff, htlc_output, check_channel, check_commitment, check_htlc_transactions, check_key_derivation,
+    load_vectors, Outcome, VectorResult,
+};
+use crate::ch1_intro_htlcs::classify::{
+    classify_input, classify_outputs, classify_transaction, CommitmentContext, InputClassification, OutputRole,
+    SpendPath,
+};
+use crate::ch1_intro_htlcs::solutions::{
+    build_closing_transaction, build_fee_aware_commitment_transaction, build_htlc_success_transaction,
+    build_justice_transaction, negotiate_closing_fee, respond_to_closing_signed, ClosingFeeResponse,
+    build_htlc_success_witness, build_htlcs_commitment_transaction, htlc_witness_script,
+    sign_htlc_transaction, to_remote_output_script, ChannelType,
+    commitment_number_from_transaction, commitment_number_obscure_factor, derive_private_key,
+    derive_private_revocation_key, derive_public_key,
+    generate_revocation_pubkey as generate_revocation_pubkey_solution, to_local as to_local_solution,
+    timelocked_p2pkh, two_of_two_multisig_witness_script as two_of_two_multisig_witness_script_solution,
+    obscured_commitment_locktime_and_sequence, sort_commitment_outputs, HtlcOutput, TrimmedOutput,
+    estimate_signed_weight, fee_for_weight, set_output_amount_for_feerate, InputSatisfaction,
+    build_funding_psbt,
+};
 use crate::internal::helper::{
-    bitcoin_pubkey_from_private_key, pubkey_from_private_key, secp256k1_private_key,
+    bitcoin_pubkey_from_private_key, build_anchor_to_remote_witness_script, build_anchor_witness_script,
+    build_htlc_receiver_witness_script, build_output, p2wpkh_output_script,
+    build_transaction, pubkey_from_private_key, secp256k1_private_key, combine_psbts, CombinePsbtsError,
+};
+use crate::ch1_intro_htlcs::ptlc::{
+    adaptor_sign, adaptor_verify, build_ptlc_commitment_transaction, complete_adaptor_signature,
+    extract_adaptor_secret, payment_point, ptlc_spend_info, ptlc_success_script, ptlc_success_witness,
+    sign_ptlc_claim, AdaptorError, AdaptorSignature, PtlcOutput,
 };
+use crate::ch1_intro_htlcs::splice::{
+    build_splice_in_transaction, build_splice_out_transaction, sign_splice_funding_input, splice_funding_witness,
+    FundingScope, SpliceError, SpliceState,
+};
+use crate::ch1_intro_htlcs::taproot::{
+    self, build_funding_transaction as build_taproot_funding_transaction, funding_output_script, htlc_spend_info,
+    key_spend_sighash, leaf_control_block, offered_htlc_timeout_script, sign_tapscript, spend_info_output_script,
+    tapscript_witness, to_local_delay_script, to_local_spend_info, FundingSigner,
+};
+use crate::internal::messages::ClosingSignedFeeRange;
+use crate::internal::musig2::{KeyAggContext, MusigError, PubNonce};
+use crate::internal::script_disasm::{disassemble, identify_template, parse_asm, pretty_print, AsmError, ScriptTemplate};
+use crate::internal::script_interpreter::{verify_p2wsh_input, ScriptError};
+use crate::interactive::refund::{sign_funding_transaction, KeyManager as RefundKeyManager};
+use bitcoin::consensus::deserialize;
+use bitcoin::ecdsa::Signature as BitcoinSignature;
+use bitcoin::hashes::ripemd160::Hash as Ripemd160;
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::Message;
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::Amount;
+use rand::{thread_rng, Rng};
+use lightning::ln::chan_utils::{
+    build_htlc_transaction, get_anchor_redeemscript, get_htlc_redeemscript,
+    get_to_countersignatory_with_anchors_redeemscript, HTLCOutputInCommitment, TxCreationKeys,
+};
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+use lightning::ln::channel_keys::{DelayedPaymentKey, HtlcKey, RevocationKey};
+use lightning::types::features::ChannelTypeFeatures;
+use lightning::types::payment::PaymentHash;
 use bitcoin::hash_types::Txid;
 use bitcoin::script::ScriptBuf;
-use bitcoin::secp256k1::PublicKey;
+use bitcoin::secp256k1::{PublicKey, XOnlyPublicKey};
 use bitcoin::secp256k1::{self, Secp256k1};
 use bitcoin::PublicKey as BitcoinPublicKey;
+use bitcoin::locktime::absolute::LockTime;
+use bitcoin::transaction::Version;
 use bitcoin::{OutPoint, Sequence, Transaction, TxIn, Witness};
 
 /// hash160 of the empty string
@@ -278,3 +339,1857 @@ fn test_build_htlc_timeout_transaction() {
 
     assert!(acceptable_solutions.contains(&their_solution));
 }
+
+fn ldk_tx_creation_keys(
+    revocation_pubkey: PublicKey,
+    local_htlc_pubkey: PublicKey,
+    remote_htlc_pubkey: PublicKey,
+    to_local_delayed_pubkey: PublicKey,
+) -> TxCreationKeys {
+    TxCreationKeys {
+        per_commitment_point: pubkey_from_private_key(&[0x09; 32]),
+        revocation_key: RevocationKey(revocation_pubkey),
+        broadcaster_htlc_key: HtlcKey(local_htlc_pubkey),
+        countersignatory_htlc_key: HtlcKey(remote_htlc_pubkey),
+        broadcaster_delayed_payment_key: DelayedPaymentKey(to_local_delayed_pubkey),
+    }
+}
+
+#[test]
+fn test_build_htlc_receiver_witness_script() {
+    let revocation_pubkey = pubkey_from_private_key(&[0x01; 32]);
+    let remote_htlc_pubkey = pubkey_from_private_key(&[0x02; 32]);
+    let local_htlc_pubkey = pubkey_from_private_key(&[0x03; 32]);
+    let payment_hash = Sha256::hash(&[0x42; 32]).to_byte_array();
+    let payment_hash160 = Ripemd160::hash(&payment_hash).to_byte_array();
+    let cltv_expiry: u32 = 500_000;
+
+    let result = build_htlc_receiver_witness_script(
+        &revocation_pubkey,
+        &remote_htlc_pubkey,
+        &local_htlc_pubkey,
+        &payment_hash160,
+        cltv_expiry,
+    );
+
+    let ldk_htlc = HTLCOutputInCommitment {
+        offered: false,
+        amount_msat: 100_000_000,
+        cltv_expiry,
+        payment_hash: PaymentHash(payment_hash),
+        transaction_output_index: None,
+    };
+    let keys = ldk_tx_creation_keys(
+        revocation_pubkey,
+        local_htlc_pubkey,
+        remote_htlc_pubkey,
+        revocation_pubkey,
+    );
+    let expected = get_htlc_redeemscript(&ldk_htlc, &ChannelTypeFeatures::only_static_remote_key(), &keys);
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn test_build_htlc_success_transaction() {
+    let commitment_txid = "d9334caed6503ebc710d13a5f663f03bec531026d2bc786befdfdb8ef5aad721"
+        .parse::<Txid>()
+        .unwrap();
+
+    let txin = TxIn {
+        previous_output: OutPoint::new(commitment_txid, 2),
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence::ZERO,
+        witness: Witness::new(),
+    };
+
+    let revocation_pubkey = pubkey_from_private_key(&[0x01; 32]);
+    let to_local_delayed_pubkey = pubkey_from_private_key(&[0x02; 32]);
+    let to_self_delay: i64 = 144;
+    let htlc_amount: u64 = 100_000;
+
+    let transaction = build_htlc_success_transaction(
+        txin,
+        &revocation_pubkey,
+        &to_local_delayed_pubkey,
+        to_self_delay,
+        htlc_amount,
+    );
+
+    let ldk_htlc = HTLCOutputInCommitment {
+        offered: false,
+        amount_msat: htlc_amount * 1000,
+        cltv_expiry: 500_000,
+        payment_hash: PaymentHash([0x42; 32]),
+        transaction_output_index: Some(2),
+    };
+    // a zero feerate leaves the full HTLC amount in the second-stage output
+    let expected = build_htlc_transaction(
+        &commitment_txid,
+        0,
+        to_self_delay as u16,
+        &ldk_htlc,
+        &ChannelTypeFeatures::only_static_remote_key(),
+        &DelayedPaymentKey(to_local_delayed_pubkey),
+        &RevocationKey(revocation_pubkey),
+    );
+
+    assert_eq!(transaction, expected);
+}
+
+#[test]
+fn test_build_htlc_success_witness() {
+    let secp = Secp256k1::new();
+    let remote_htlc_private_key = secp256k1_private_key(&[0x02; 32]);
+    let local_htlc_private_key = secp256k1_private_key(&[0x03; 32]);
+    let msg = Message::from_digest([0x11; 32]);
+    let remote_signature =
+        BitcoinSignature::sighash_all(secp.sign_ecdsa(&msg, &remote_htlc_private_key));
+    let local_signature =
+        BitcoinSignature::sighash_all(secp.sign_ecdsa(&msg, &local_htlc_private_key));
+    let preimage = [0x42; 32];
+    let witness_script = ScriptBuf::from_bytes(vec![0x51]);
+
+    let witness =
+        build_htlc_success_witness(&remote_signature, &local_signature, &preimage, &witness_script);
+
+    let elements: Vec<&[u8]> = witness.iter().collect();
+    assert_eq!(elements.len(), 5);
+    assert!(elements[0].is_empty());
+    assert_eq!(elements[1], remote_signature.to_vec().as_slice());
+    assert_eq!(elements[2], local_signature.to_vec().as_slice());
+    assert_eq!(elements[3], &preimage[..]);
+    assert_eq!(elements[4], witness_script.as_bytes());
+}
+
+#[test]
+fn test_build_htlcs_commitment_transaction() {
+    let outpoint = OutPoint::new(
+        "d9334caed6503ebc710d13a5f663f03bec531026d2bc786befdfdb8ef5aad721"
+            .parse::<Txid>()
+            .unwrap(),
+        1,
+    );
+
+    let txin = TxIn {
+        previous_output: outpoint,
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence::MAX,
+        witness: Witness::new(),
+    };
+
+    let revocation_pubkey = pubkey_from_private_key(&[0x01; 32]);
+    let remote_htlc_pubkey = pubkey_from_private_key(&[0x02; 32]);
+    let local_htlc_pubkey = pubkey_from_private_key(&[0x03; 32]);
+    let to_local_delayed_pubkey = pubkey_from_private_key(&[0x04; 32]);
+    let remote_pubkey = pubkey_from_private_key(&[0x05; 32]);
+
+    let offered_payment_hash = Sha256::hash(&[0x01; 32]).to_byte_array();
+    let received_payment_hash = Sha256::hash(&[0x02; 32]).to_byte_array();
+
+    let htlcs = vec![
+        HtlcOutput {
+            offered: true,
+            amount: 10_000,
+            cltv_expiry: 500,
+            payment_hash160: Ripemd160::hash(&offered_payment_hash).to_byte_array(),
+        },
+        HtlcOutput {
+            offered: false,
+            amount: 20_000,
+            cltv_expiry: 501,
+            payment_hash160: Ripemd160::hash(&received_payment_hash).to_byte_array(),
+        },
+    ];
+    let payment_hashes = [offered_payment_hash, received_payment_hash];
+
+    let transaction = build_htlcs_commitment_transaction(
+        txin,
+        &revocation_pubkey,
+        &remote_htlc_pubkey,
+        &local_htlc_pubkey,
+        &to_local_delayed_pubkey,
+        remote_pubkey,
+        144,
+        &htlcs,
+        100_000,
+        70_000,
+    );
+
+    let keys = ldk_tx_creation_keys(
+        revocation_pubkey,
+        local_htlc_pubkey,
+        remote_htlc_pubkey,
+        to_local_delayed_pubkey,
+    );
+
+    assert_eq!(transaction.output.len(), 4);
+    for ((htlc, payment_hash), output) in htlcs
+        .iter()
+        .zip(payment_hashes.iter())
+        .zip(transaction.output[2..].iter())
+    {
+        let ldk_htlc = HTLCOutputInCommitment {
+            offered: htlc.offered,
+            amount_msat: htlc.amount * 1000,
+            cltv_expiry: htlc.cltv_expiry,
+            payment_hash: PaymentHash(*payment_hash),
+            transaction_output_index: None,
+        };
+        let expected_script =
+            get_htlc_redeemscript(&ldk_htlc, &ChannelTypeFeatures::only_static_remote_key(), &keys);
+
+        assert_eq!(output.script_pubkey, expected_script.to_p2wsh());
+        assert_eq!(output.value.to_sat(), htlc.amount);
+    }
+}
+
+fn assert_no_bolt3_failures(results: &[VectorResult]) {
+    let failures: Vec<String> = results
+        .iter()
+        .filter(|result| matches!(result.outcome, Outcome::Fail(_)))
+        .map(|result| result.to_string())
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn test_bolt3_key_derivation() {
+    let vectors = load_vectors();
+    let results = check_key_derivation(&vectors.key_derivation);
+
+    assert_no_bolt3_failures(&results);
+}
+
+#[test]
+fn test_bolt3_channel() {
+    let vectors = load_vectors();
+    let results = check_channel(&vectors.channel);
+
+    assert_no_bolt3_failures(&results);
+}
+
+#[test]
+fn test_bolt3_commitment_transactions() {
+    let vectors = load_vectors();
+    let results: Vec<VectorResult> = vectors
+        .commitments
+        .iter()
+        .map(|commitment| check_commitment(&vectors, commitment))
+        .collect();
+
+    assert_no_bolt3_failures(&results);
+    assert!(results.iter().any(|result| result.outcome == Outcome::Pass));
+}
+
+#[test]
+fn test_bolt3_htlc_transactions() {
+    let vectors = load_vectors();
+    let results: Vec<VectorResult> = vectors
+        .commitments
+        .iter()
+        .flat_map(|commitment| check_htlc_transactions(&vectors, commitment))
+        .collect();
+
+    assert_no_bolt3_failures(&results);
+    assert!(results.iter().any(|result| result.outcome == Outcome::Pass));
+}
+
+#[test]
+fn test_byte_diff() {
+    assert_eq!(byte_diff(&[1, 2, 3], &[1, 2, 3]), None);
+
+    let diff = byte_diff(&[1, 2, 3], &[1, 2, 4]).unwrap();
+    assert!(diff.starts_with("first difference at byte 2"));
+
+    let diff = byte_diff(&[1, 2, 3], &[1, 2]).unwrap();
+    assert!(diff.starts_with("first difference at byte 2"));
+}
+
+#[test]
+fn test_commitment_number_obscure_factor() {
+    // BOLT 3 Appendix C: local is the channel opener.
+    let local_payment_basepoint: PublicKey =
+        "034f355bdcb7cc0af728ef3cceb9615d90684bb5b2ca5f859ab0f0b704075871aa".parse().unwrap();
+    let remote_payment_basepoint: PublicKey =
+        "032c0b7cf95324a07d05398b240174dc0c2be444d96b159aa6c7f7b1e668680991".parse().unwrap();
+
+    let factor = commitment_number_obscure_factor(&local_payment_basepoint, &remote_payment_basepoint);
+
+    assert_eq!(factor, 0x2bb038521914);
+}
+
+#[test]
+fn test_obscured_commitment_locktime_and_sequence() {
+    let (locktime, sequence) = obscured_commitment_locktime_and_sequence(42, 0x2bb038521914);
+
+    assert_eq!(locktime.to_consensus_u32(), 0x2052193e);
+    assert_eq!(sequence.0, 0x802bb038);
+
+    let tx = build_transaction(
+        Version::TWO,
+        locktime,
+        vec![TxIn { sequence, ..Default::default() }],
+        vec![],
+    );
+    assert_eq!(commitment_number_from_transaction(&tx, 0x2bb038521914), 42);
+}
+
+#[test]
+fn test_sort_commitment_outputs() {
+    let script_a = ScriptBuf::from_bytes(vec![0x00, 0x01]);
+    let script_b = ScriptBuf::from_bytes(vec![0x00, 0x02]);
+
+    let mut outputs = vec![
+        (build_output(2_000, script_a.clone()), Some(506)),
+        (build_output(2_000, script_b.clone()), None),
+        (build_output(2_000, script_a.clone()), Some(505)),
+        (build_output(1_000, script_b.clone()), None),
+    ];
+
+    sort_commitment_outputs(&mut outputs);
+
+    let ordered: Vec<(u64, ScriptBuf, Option<u32>)> = outputs
+        .into_iter()
+        .map(|(output, cltv)| (output.value.to_sat(), output.script_pubkey, cltv))
+        .collect();
+
+    assert_eq!(
+        ordered,
+        vec![
+            (1_000, script_b.clone(), None),
+            (2_000, script_a.clone(), Some(505)),
+            (2_000, script_a, Some(506)),
+            (2_000, script_b, None),
+        ]
+    );
+}
+
+#[test]
+fn test_build_fee_aware_commitment_transaction() {
+    let local_payment_basepoint = pubkey_from_private_key(&[0x01; 32]);
+    let remote_payment_basepoint = pubkey_from_private_key(&[0x02; 32]);
+    let revocation_pubkey = pubkey_from_private_key(&[0x03; 32]);
+    let remote_htlc_pubkey = pubkey_from_private_key(&[0x04; 32]);
+    let local_htlc_pubkey = pubkey_from_private_key(&[0x05; 32]);
+    let to_local_delayed_pubkey = pubkey_from_private_key(&[0x06; 32]);
+    let local_funding_pubkey = pubkey_from_private_key(&[0x07; 32]);
+    let remote_funding_pubkey = pubkey_from_private_key(&[0x08; 32]);
+
+    // At 1000 sat/kw with a 546 sat dust limit, an offered HTLC needs at least
+    // 546 + 663 sat and a received HTLC at least 546 + 703 sat.
+    let offered = HtlcOutput {
+        offered: true,
+        amount: 1_208,
+        cltv_expiry: 500,
+        payment_hash160: HASH160_DUMMY,
+    };
+    let received = HtlcOutput {
+        offered: false,
+        amount: 1_249,
+        cltv_expiry: 501,
+        payment_hash160: HASH160_DUMMY,
+    };
+
+    let (tx, breakdown) = build_fee_aware_commitment_transaction(
+        TxIn::default(),
+        7,
+        &local_payment_basepoint,
+        &remote_payment_basepoint,
+        true,
+        ChannelType::StaticRemoteKey,
+        &local_funding_pubkey,
+        &remote_funding_pubkey,
+        &revocation_pubkey,
+        &remote_htlc_pubkey,
+        &local_htlc_pubkey,
+        &to_local_delayed_pubkey,
+        144,
+        1_000,
+        546,
+        &[offered.clone(), received.clone()],
+        1_400_000,
+        2_000_000_000,
+    );
+
+    // The opener's 1400 sat pays the 896 sat fee and the rest is dust.
+    assert_eq!(breakdown.weight, 724 + 172);
+    assert_eq!(breakdown.fee, 896);
+    assert_eq!(breakdown.to_local, None);
+    assert_eq!(breakdown.to_remote, Some(2_000_000));
+    assert!(!breakdown.local_anchor && !breakdown.remote_anchor);
+    assert_eq!(breakdown.untrimmed_htlcs, vec![received]);
+    assert_eq!(
+        breakdown.trimmed,
+        vec![
+            TrimmedOutput::Htlc { htlc: offered, threshold: 1_209 },
+            TrimmedOutput::ToLocal { amount: 504, dust_limit: 546 },
+        ]
+    );
+
+    let amounts: Vec<u64> = tx.output.iter().map(|output| output.value.to_sat()).collect();
+    assert_eq!(amounts, vec![1_249, 2_000_000]);
+}
+
+#[test]
+fn test_anchor_scripts() {
+    let funding_pubkey = pubkey_from_private_key(&[0x01; 32]);
+    let remote_payment_basepoint = pubkey_from_private_key(&[0x02; 32]);
+
+    assert_eq!(build_anchor_witness_script(&funding_pubkey), get_anchor_redeemscript(&funding_pubkey));
+    assert_eq!(
+        to_remote_output_script(ChannelType::AnchorsZeroFeeHtlcTx, &remote_payment_basepoint),
+        get_to_countersignatory_with_anchors_redeemscript(&remote_payment_basepoint).to_p2wsh()
+    );
+
+    let revocation_pubkey = pubkey_from_private_key(&[0x03; 32]);
+    let remote_htlc_pubkey = pubkey_from_private_key(&[0x04; 32]);
+    let local_htlc_pubkey = pubkey_from_private_key(&[0x05; 32]);
+    let payment_hash = Sha256::hash(&[0x42; 32]).to_byte_array();
+    let keys = ldk_tx_creation_keys(revocation_pubkey, local_htlc_pubkey, remote_htlc_pubkey, revocation_pubkey);
+
+    for offered in [true, false] {
+        let htlc = HtlcOutput {
+            offered,
+            amount: 10_000,
+            cltv_expiry: 500_000,
+            payment_hash160: Ripemd160::hash(&payment_hash).to_byte_array(),
+        };
+        let ldk_htlc = HTLCOutputInCommitment {
+            offered,
+            amount_msat: 10_000_000,
+            cltv_expiry: 500_000,
+            payment_hash: PaymentHash(payment_hash),
+            transaction_output_index: None,
+        };
+
+        let script = htlc_witness_script(
+            ChannelType::AnchorsZeroFeeHtlcTx,
+            &htlc,
+            &revocation_pubkey,
+            &remote_htlc_pubkey,
+            &local_htlc_pubkey,
+        );
+        let expected =
+            get_htlc_redeemscript(&ldk_htlc, &ChannelTypeFeatures::anchors_zero_htlc_fee_and_dependencies(), &keys);
+
+        assert_eq!(script, expected);
+    }
+}
+
+#[test]
+fn test_sign_htlc_transaction_single_anyonecanpay() {
+    let secp = Secp256k1::new();
+    let remote_htlc_private_key = secp256k1_private_key(&[0x04; 32]);
+    let remote_htlc_pubkey = pubkey_from_private_key(&[0x04; 32]);
+    let witness_script = build_anchor_witness_script(&remote_htlc_pubkey);
+    let htlc_amount = 10_000;
+
+    let mut htlc_tx = build_transaction(
+        Version::TWO,
+        LockTime::ZERO,
+        vec![TxIn { sequence: ChannelType::AnchorsZeroFeeHtlcTx.htlc_input_sequence(), ..Default::default() }],
+        vec![build_output(htlc_amount, witness_script.to_p2wsh())],
+    );
+
+    let signature = sign_htlc_transaction(
+        &htlc_tx,
+        &witness_script,
+        htlc_amount,
+        ChannelType::AnchorsZeroFeeHtlcTx.counterparty_htlc_sighash_type(),
+        &remote_htlc_private_key,
+    );
+    assert_eq!(signature.sighash_type, EcdsaSighashType::SinglePlusAnyoneCanPay);
+
+    // Whoever broadcasts can add a fee-paying input and change output without
+    // invalidating the counterparty's signature.
+    htlc_tx.input.push(TxIn::default());
+    htlc_tx.output.push(build_output(5_000, witness_script.to_p2wsh()));
+
+    let sighash = SighashCache::new(&htlc_tx)
+        .p2wsh_signature_hash(0, &witness_script, Amount::from_sat(htlc_amount), signature.sighash_type)
+        .unwrap();
+    let message = Message::from_digest(sighash.to_byte_array());
+
+    assert!(secp.verify_ecdsa(&message, &signature.signature, &remote_htlc_pubkey).is_ok());
+}
+
+struct FixedFeeEstimator(u32);
+
+impl FeeEstimator for FixedFeeEstimator {
+    fn get_est_sat_per_1000_weight(&self, _confirmation_target: ConfirmationTarget) -> u32 {
+        self.0
+    }
+}
+
+#[test]
+fn test_derive_private_revocation_key() {
+    let secp = Secp256k1::new();
+    let per_commitment_secret = secp256k1_private_key(&[0x31; 32]);
+    let revocation_basepoint_secret = secp256k1_private_key(&[0x32; 32]);
+
+    let revocation_private_key =
+        derive_private_revocation_key(&per_commitment_secret, &revocation_basepoint_secret);
+
+    let expected_pubkey = generate_revocation_pubkey_solution(
+        pubkey_from_private_key(&[0x32; 32]),
+        pubkey_from_private_key(&[0x31; 32]),
+    );
+    assert_eq!(PublicKey::from_secret_key(&secp, &revocation_private_key), expected_pubkey);
+    assert_eq!(
+        revocation_private_key,
+        lightning::ln::chan_utils::derive_private_revocation_key(
+            &secp,
+            &per_commitment_secret,
+            &revocation_basepoint_secret
+        )
+    );
+}
+
+#[test]
+fn test_build_justice_transaction() {
+    let secp = Secp256k1::new();
+
+    // The cheater's per-commitment secret for the revoked state, and our
+    // revocation basepoint secret.
+    let per_commitment_secret = secp256k1_private_key(&[0x31; 32]);
+    let revocation_basepoint_secret = secp256k1_private_key(&[0x32; 32]);
+    let revocation_pubkey = generate_revocation_pubkey_solution(
+        pubkey_from_private_key(&[0x32; 32]),
+        pubkey_from_private_key(&[0x31; 32]),
+    );
+
+    let local_payment_basepoint = pubkey_from_private_key(&[0x01; 32]);
+    let remote_payment_basepoint = pubkey_from_private_key(&[0x02; 32]);
+    let remote_htlc_pubkey = pubkey_from_private_key(&[0x04; 32]);
+    let local_htlc_pubkey = pubkey_from_private_key(&[0x05; 32]);
+    let to_local_delayed_pubkey = pubkey_from_private_key(&[0x06; 32]);
+    let local_funding_pubkey = pubkey_from_private_key(&[0x07; 32]);
+    let remote_funding_pubkey = pubkey_from_private_key(&[0x08; 32]);
+    let destination_script = p2wpkh_output_script(pubkey_from_private_key(&[0x09; 32]));
+
+    let htlcs = vec![
+        HtlcOutput { offered: true, amount: 20_000, cltv_expiry: 500, payment_hash160: HASH160_DUMMY },
+        HtlcOutput { offered: false, amount: 30_000, cltv_expiry: 501, payment_hash160: HASH160_DUMMY },
+    ];
+
+    let (revoked_commitment_tx, breakdown) = build_fee_aware_commitment_transaction(
+        TxIn::default(),
+        3,
+        &local_payment_basepoint,
+        &remote_payment_basepoint,
+        true,
+        ChannelType::StaticRemoteKey,
+        &local_funding_pubkey,
+        &remote_funding_pubkey,
+        &revocation_pubkey,
+        &remote_htlc_pubkey,
+        &local_htlc_pubkey,
+        &to_local_delayed_pubkey,
+        144,
+        253,
+        546,
+        &htlcs,
+        3_000_000_000,
+        2_000_000_000,
+    );
+
+    let justice_tx = build_justice_transaction(
+        &revoked_commitment_tx,
+        ChannelType::StaticRemoteKey,
+        &per_commitment_secret,
+        &revocation_basepoint_secret,
+        &to_local_delayed_pubkey,
+        144,
+        &remote_htlc_pubkey,
+        &local_htlc_pubkey,
+        &htlcs,
+        destination_script.clone(),
+        &FixedFeeEstimator(2_500),
+    )
+    .unwrap();
+
+    // to_local and both HTLCs are swept, to_remote is the honest party's.
+    assert_eq!(justice_tx.input.len(), 3);
+    assert_eq!(justice_tx.output.len(), 1);
+    assert_eq!(justice_tx.output[0].script_pubkey, destination_script);
+
+    let swept: u64 = breakdown.to_local.unwrap() + 20_000 + 30_000;
+    let fee = swept - justice_tx.output[0].value.to_sat();
+    let weight = justice_tx.weight().to_wu();
+    assert!(fee >= 2_500 * weight / 1000);
+    assert!(fee <= 2_500 * (weight + 3 * 3) / 1000 + 1);
+
+    for (input_idx, input) in justice_tx.input.iter().enumerate() {
+        let spent_output = &revoked_commitment_tx.output[input.previous_output.vout as usize];
+        let witness: Vec<&[u8]> = input.witness.iter().collect();
+        let witness_script = ScriptBuf::from_bytes(witness[2].to_vec());
+        assert_eq!(witness_script.to_p2wsh(), spent_output.script_pubkey);
+
+        let signature = BitcoinSignature::from_slice(witness[0]).unwrap();
+        let sighash = SighashCache::new(&justice_tx)
+            .p2wsh_signature_hash(input_idx, &witness_script, spent_output.value, EcdsaSighashType::All)
+            .unwrap();
+        let message = Message::from_digest(sighash.to_byte_array());
+        assert!(secp.verify_ecdsa(&message, &signature.signature, &revocation_pubkey).is_ok());
+    }
+
+    // nothing to claim, or not enough to pay for claiming it
+    let mut nothing_to_claim = revoked_commitment_tx.clone();
+    nothing_to_claim.output.clear();
+    let justice = |revoked_tx: &Transaction, feerate_per_kw: u32| {
+        build_justice_transaction(
+            revoked_tx,
+            ChannelType::StaticRemoteKey,
+            &per_commitment_secret,
+            &revocation_basepoint_secret,
+            &to_local_delayed_pubkey,
+            144,
+            &remote_htlc_pubkey,
+            &local_htlc_pubkey,
+            &htlcs,
+            destination_script.clone(),
+            &FixedFeeEstimator(feerate_per_kw),
+        )
+    };
+    assert_eq!(justice(&nothing_to_claim, 2_500), None);
+    assert_eq!(justice(&revoked_commitment_tx, 100_000_000), None);
+}
+
+#[test]
+fn test_derive_private_key_matches_derive_public_key() {
+    let secp = Secp256k1::new();
+    let mut rng = thread_rng();
+
+    for _ in 0..64 {
+        let basepoint_secret = secp256k1_private_key(&rng.gen::<[u8; 32]>());
+        let per_commitment_secret = secp256k1_private_key(&rng.gen::<[u8; 32]>());
+        let basepoint = PublicKey::from_secret_key(&secp, &basepoint_secret);
+        let per_commitment_point = PublicKey::from_secret_key(&secp, &per_commitment_secret);
+
+        let private_key = derive_private_key(&basepoint_secret, &per_commitment_point);
+        assert_eq!(
+            PublicKey::from_secret_key(&secp, &private_key),
+            derive_public_key(&basepoint, &per_commitment_point)
+        );
+
+        let revocation_private_key =
+            derive_private_revocation_key(&per_commitment_secret, &basepoint_secret);
+        assert_eq!(
+            PublicKey::from_secret_key(&secp, &revocation_private_key),
+            generate_revocation_pubkey_solution(basepoint, per_commitment_point)
+        );
+    }
+}
+
+#[test]
+fn test_build_closing_transaction() {
+    let local_script = p2wpkh_output_script(pubkey_from_private_key(&[0x01; 32]));
+    let remote_script = p2wpkh_output_script(pubkey_from_private_key(&[0x02; 32]));
+    let funding_txin = TxIn {
+        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+        ..TxIn::default()
+    };
+
+    // We opened the channel, so the fee comes out of our output. Amounts are
+    // rounded down to whole satoshis.
+    let tx = build_closing_transaction(
+        funding_txin.clone(),
+        local_script.clone(),
+        remote_script.clone(),
+        7_000_000_999,
+        3_000_000_000,
+        true,
+        546,
+        1_500,
+    );
+
+    assert_eq!(tx.version, Version::TWO);
+    assert_eq!(tx.lock_time, LockTime::ZERO);
+    assert_eq!(tx.input.len(), 1);
+    assert_eq!(tx.input[0].sequence, Sequence::MAX);
+    assert_eq!(tx.output.len(), 2);
+
+    // BIP69: the smaller output comes first.
+    assert_eq!(tx.output[0].script_pubkey, remote_script);
+    assert_eq!(tx.output[0].value, Amount::from_sat(3_000_000));
+    assert_eq!(tx.output[1].script_pubkey, local_script);
+    assert_eq!(tx.output[1].value, Amount::from_sat(7_000_000 - 1_500));
+
+    // If they opened the channel, they pay the fee; our dust output is omitted.
+    let tx = build_closing_transaction(
+        funding_txin,
+        local_script,
+        remote_script.clone(),
+        545_000,
+        9_000_000_000,
+        false,
+        546,
+        1_500,
+    );
+
+    assert_eq!(tx.output.len(), 1);
+    assert_eq!(tx.output[0].script_pubkey, remote_script);
+    assert_eq!(tx.output[0].value, Amount::from_sat(9_000_000 - 1_500));
+}
+
+#[test]
+fn test_respond_to_closing_signed() {
+    let ours = ClosingSignedFeeRange { min_fee_satoshis: 1_000, max_fee_satoshis: 5_000 };
+    let theirs = ClosingSignedFeeRange { min_fee_satoshis: 3_000, max_fee_satoshis: 8_000 };
+
+    // Their fee is acceptable to both of us.
+    assert_eq!(
+        respond_to_closing_signed(&ours, 2_000, 4_000, &theirs),
+        ClosingFeeResponse::Accept(4_000)
+    );
+
+    // Their fee is above our maximum: counter with the closest fee we both accept.
+    assert_eq!(
+        respond_to_closing_signed(&ours, 2_000, 7_000, &theirs),
+        ClosingFeeResponse::CounterPropose(3_000)
+    );
+
+    // No fee works for both of us.
+    let disjoint = ClosingSignedFeeRange { min_fee_satoshis: 6_000, max_fee_satoshis: 8_000 };
+    assert_eq!(
+        respond_to_closing_signed(&ours, 2_000, 7_000, &disjoint),
+        ClosingFeeResponse::Fail
+    );
+}
+
+#[test]
+fn test_negotiate_closing_fee() {
+    let opener = ClosingSignedFeeRange { min_fee_satoshis: 2_000, max_fee_satoshis: 6_000 };
+    let accepter = ClosingSignedFeeRange { min_fee_satoshis: 500, max_fee_satoshis: 3_000 };
+
+    // The opener's preferred fee is acceptable to the accepter.
+    assert_eq!(negotiate_closing_fee(2_500, &opener, 1_000, &accepter), Some(2_500));
+
+    // The opener wants more than the accepter will pay: settle on the fee in
+    // both ranges closest to what the accepter wants.
+    let fee = negotiate_closing_fee(5_000, &opener, 1_000, &accepter).unwrap();
+    assert_eq!(fee, 2_000);
+    assert!(fee >= opener.min_fee_satoshis && fee <= opener.max_fee_satoshis);
+    assert!(fee >= accepter.min_fee_satoshis && fee <= accepter.max_fee_satoshis);
+
+    let disjoint = ClosingSignedFeeRange { min_fee_satoshis: 100, max_fee_satoshis: 1_000 };
+    assert_eq!(negotiate_closing_fee(5_000, &opener, 500, &disjoint), None);
+
+    // A peer can send a fee_range with its minimum above its maximum.
+    let inverted = ClosingSignedFeeRange { min_fee_satoshis: 3_000, max_fee_satoshis: 2_000 };
+    assert_eq!(negotiate_closing_fee(2_500, &inverted, 1_000, &accepter), None);
+    assert_eq!(negotiate_closing_fee(2_500, &opener, 1_000, &inverted), None);
+}
+
+fn refund_key_manager(key_byte: u8) -> RefundKeyManager {
+    RefundKeyManager {
+        funding_private_key: secp256k1_private_key(&[key_byte; 32]),
+        funding_public_key: pubkey_from_private_key(&[key_byte; 32]),
+        commitment_pubkey: pubkey_from_private_key(&[key_byte + 0x10; 32]),
+    }
+}
+
+/// A refund transaction signed the way the `refund` demo signs it, spending a
+/// 5,000,000 sat funding output locked to keys 0x01 and 0x02.
+fn signed_refund_transaction() -> (Transaction, ScriptBuf) {
+    let our_keys = refund_key_manager(0x01);
+    let their_keys = refund_key_manager(0x02);
+    let funding_script =
+        two_of_two_multisig_witness_script(&our_keys.funding_public_key, &their_keys.funding_public_key);
+
+    let tx = build_transaction(
+        Version::TWO,
+        LockTime::ZERO,
+        vec![TxIn::default()],
+        vec![build_output(4_999_000, p2wpkh_output_script(our_keys.commitment_pubkey))],
+    );
+
+    (sign_funding_transaction(tx, our_keys, their_keys), funding_script)
+}
+
+#[test]
+fn test_verify_refund_witness() {
+    let (tx, funding_script) = signed_refund_transaction();
+
+    let execution = verify_p2wsh_input(&tx, 0, 5_000_000, &funding_script);
+
+    assert!(execution.is_valid(), "{}", execution);
+    let last_step = execution.steps.last().unwrap();
+    assert_eq!(last_step.opcode, "OP_CHECKMULTISIG");
+    // dummy, two signatures, 2, two keys, 2
+    assert_eq!(last_step.stack_before.len(), 7);
+    assert_eq!(last_step.stack_after, vec![vec![1u8]]);
+}
+
+#[test]
+fn test_verify_witness_reports_signature_order() {
+    let (mut tx, funding_script) = signed_refund_transaction();
+
+    let mut elements: Vec<Vec<u8>> = tx.input[0].witness.iter().map(|e| e.to_vec()).collect();
+    elements.swap(1, 2);
+    tx.input[0].witness = Witness::from_slice(&elements);
+
+    let execution = verify_p2wsh_input(&tx, 0, 5_000_000, &funding_script);
+    let failure = execution.result.unwrap_err();
+    assert_eq!(failure.opcode.as_deref(), Some("OP_CHECKMULTISIG"));
+    assert_eq!(failure.error, ScriptError::MultisigOrder);
+
+    // A non-empty dummy element is rejected before any signature is checked.
+    elements.swap(1, 2);
+    elements[0] = vec![0x00];
+    tx.input[0].witness = Witness::from_slice(&elements);
+    let execution = verify_p2wsh_input(&tx, 0, 5_000_000, &funding_script);
+    assert_eq!(execution.result.unwrap_err().error, ScriptError::NullDummy);
+
+    // The signatures commit to the prevout value.
+    let (tx, funding_script) = signed_refund_transaction();
+    let execution = verify_p2wsh_input(&tx, 0, 4_000_000, &funding_script);
+    assert!(matches!(execution.result.unwrap_err().error, ScriptError::UnknownSignature(_)));
+}
+
+#[test]
+fn test_verify_witness_reports_unsatisfied_csv() {
+    let revocation_pubkey = pubkey_from_private_key(&[0x01; 32]);
+    let delayed_private_key = secp256k1_private_key(&[0x02; 32]);
+    let delayed_pubkey = pubkey_from_private_key(&[0x02; 32]);
+    let to_self_delay = 144;
+    let to_local_script = to_local_solution(&revocation_pubkey, &delayed_pubkey, to_self_delay);
+
+    let sweep = |sequence: u32| {
+        let txin = TxIn { sequence: Sequence(sequence), ..TxIn::default() };
+        let mut tx = build_transaction(
+            Version::TWO,
+            LockTime::ZERO,
+            vec![txin],
+            vec![build_output(90_000, p2wpkh_output_script(delayed_pubkey))],
+        );
+        let signature =
+            sign_htlc_transaction(&tx, &to_local_script, 100_000, EcdsaSighashType::All, &delayed_private_key);
+        // <local_delayedsig> <> selects the delayed branch.
+        tx.input[0].witness = Witness::from_slice(&[
+            signature.to_vec(),
+            Vec::new(),
+            to_local_script.to_bytes(),
+        ]);
+        verify_p2wsh_input(&tx, 0, 100_000, &to_local_script)
+    };
+
+    let execution = sweep(144);
+    assert!(execution.is_valid(), "{}", execution);
+    // OP_IF was taken on the false branch: the revocation key push is skipped.
+    assert!(!execution.steps[2].executed);
+
+    let failure = sweep(143).result.unwrap_err();
+    assert_eq!(failure.opcode.as_deref(), Some("OP_CSV"));
+    assert_eq!(
+        failure.error,
+        ScriptError::UnsatisfiedSequence { required: 144, sequence: 143, version: 2 }
+    );
+}
+
+#[test]
+fn test_verify_witness_reports_bad_preimage() {
+    let secp = Secp256k1::new();
+    let revocation_pubkey = pubkey_from_private_key(&[0x01; 32]);
+    let remote_htlc_private_key = secp256k1_private_key(&[0x02; 32]);
+    let local_htlc_private_key = secp256k1_private_key(&[0x03; 32]);
+    let remote_htlc_pubkey = pubkey_from_private_key(&[0x02; 32]);
+    let local_htlc_pubkey = pubkey_from_private_key(&[0x03; 32]);
+
+    let preimage = [0x42; 32];
+    let payment_hash160 = Ripemd160::hash(&Sha256::hash(&preimage).to_byte_array()).to_byte_array();
+    let htlc = HtlcOutput { offered: false, amount: 50_000, cltv_expiry: 500, payment_hash160 };
+    let htlc_script = htlc_witness_script(
+        ChannelType::StaticRemoteKey,
+        &htlc,
+        &revocation_pubkey,
+        &remote_htlc_pubkey,
+        &local_htlc_pubkey,
+    );
+
+    let mut htlc_success_tx = build_htlc_success_transaction(
+        TxIn { sequence: Sequence::ZERO, ..TxIn::default() },
+        &revocation_pubkey,
+        &pubkey_from_private_key(&[0x04; 32]),
+        144,
+        45_000,
+    );
+    let remote_signature =
+        sign_htlc_transaction(&htlc_success_tx, &htlc_script, 50_000, EcdsaSighashType::All, &remote_htlc_private_key);
+    let local_signature =
+        sign_htlc_transaction(&htlc_success_tx, &htlc_script, 50_000, EcdsaSighashType::All, &local_htlc_private_key);
+
+    htlc_success_tx.input[0].witness =
+        build_htlc_success_witness(&remote_signature, &local_signature, &preimage, &htlc_script);
+    let execution = verify_p2wsh_input(&htlc_success_tx, 0, 50_000, &htlc_script);
+    assert!(execution.is_valid(), "{}", execution);
+
+    htlc_success_tx.input[0].witness =
+        build_htlc_success_witness(&remote_signature, &local_signature, &[0x43; 32], &htlc_script);
+    let failure = verify_p2wsh_input(&htlc_success_tx, 0, 50_000, &htlc_script)
+        .result
+        .unwrap_err();
+    assert_eq!(failure.opcode.as_deref(), Some("OP_EQUALVERIFY"));
+    match failure.error {
+        ScriptError::EqualVerify { right, .. } => assert_eq!(right, payment_hash160.to_vec()),
+        error => panic!("unexpected error: {}", error),
+    }
+
+    // The witness must end with the script being spent.
+    let failure = verify_p2wsh_input(&htlc_success_tx, 0, 50_000, &to_local_solution(&revocation_pubkey, &local_htlc_pubkey, 144))
+        .result
+        .unwrap_err();
+    assert_eq!(failure.error, ScriptError::WitnessScriptMismatch);
+}
+
+#[test]
+fn test_verify_bolt3_witnesses() {
+    // Every signed commitment and HTLC transaction in the BOLT 3 vectors
+    // must pass the interpreter.
+    let vectors = load_vectors();
+    let funding_script =
+        ScriptBuf::from_bytes(hex::decode(&vectors.channel.funding_witness_script).unwrap());
+
+    for commitment in vectors.commitments.iter() {
+        let commitment_tx: Transaction =
+            deserialize(&hex::decode(&commitment.tx_hex).unwrap()).unwrap();
+        let execution = verify_p2wsh_input(
+            &commitment_tx,
+            0,
+            vectors.channel.funding_amount_satoshis,
+            &funding_script,
+        );
+        assert!(execution.is_valid(), "{}: {}", commitment.name, execution);
+
+        for htlc_tx in commitment.htlc_txs.iter() {
+            let htlc_tx: Transaction = deserialize(&hex::decode(&htlc_tx.tx_hex).unwrap()).unwrap();
+            let htlc_script =
+                ScriptBuf::from_bytes(htlc_tx.input[0].witness.last().unwrap().to_vec());
+            let htlc_output = &commitment_tx.output[htlc_tx.input[0].previous_output.vout as usize];
+            assert_eq!(htlc_output.script_pubkey, htlc_script.to_p2wsh());
+
+            let execution =
+                verify_p2wsh_input(&htlc_tx, 0, htlc_output.value.to_sat(), &htlc_script);
+            assert!(execution.is_valid(), "{}: {}", commitment.name, execution);
+        }
+    }
+}
+
+/// Checks that both renderings of `script` parse back to the same bytes.
+fn check_asm_round_trip(script: &ScriptBuf) {
+    assert_eq!(parse_asm(&disassemble(script)).unwrap(), *script, "{}", disassemble(script));
+    assert_eq!(parse_asm(&pretty_print(script)).unwrap(), *script, "{}", pretty_print(script));
+}
+
+#[test]
+fn test_disassemble_labels_templates() {
+    let pubkey1 = pubkey_from_private_key(&[0x01; 32]);
+    let pubkey2 = pubkey_from_private_key(&[0x02; 32]);
+    let pubkey3 = pubkey_from_private_key(&[0x03; 32]);
+    let payment_hash160 = Ripemd160::hash(&[0x42; 32]).to_byte_array();
+
+    let multisig = two_of_two_multisig_witness_script_solution(&pubkey1, &pubkey2);
+    assert_eq!(
+        identify_template(&multisig),
+        Some(ScriptTemplate::TwoOfTwoMultisig { pubkey1, pubkey2 })
+    );
+    assert_eq!(
+        disassemble(&multisig),
+        format!("OP_2 {} {} OP_2 OP_CHECKMULTISIG", pubkey1, pubkey2)
+    );
+    check_asm_round_trip(&multisig);
+
+    let to_local_script = to_local_solution(&pubkey1, &pubkey2, 144);
+    assert_eq!(
+        identify_template(&to_local_script),
+        Some(ScriptTemplate::ToLocal { revocation_pubkey: pubkey1, to_self_delay: 144, delayed_pubkey: pubkey2 })
+    );
+    let pretty = pretty_print(&to_local_script);
+    assert!(pretty.starts_with("# to_local\nOP_IF\n"), "{}", pretty);
+    assert!(pretty.contains("\n    9000  # to_self_delay = 144\n"), "{}", pretty);
+    check_asm_round_trip(&to_local_script);
+
+    for channel_type in [ChannelType::StaticRemoteKey, ChannelType::AnchorsZeroFeeHtlcTx] {
+        let anchors = channel_type == ChannelType::AnchorsZeroFeeHtlcTx;
+        for offered in [true, false] {
+            let htlc = HtlcOutput { offered, amount: 5_000, cltv_expiry: 500_000, payment_hash160 };
+            let script = htlc_witness_script(channel_type, &htlc, &pubkey1, &pubkey2, &pubkey3);
+            let revocation_pubkey_hash = bitcoin::PubkeyHash::hash(&pubkey1.serialize()).to_byte_array();
+            let expected = if offered {
+                ScriptTemplate::OfferedHtlc {
+                    revocation_pubkey_hash,
+                    remote_htlc_pubkey: pubkey2,
+                    local_htlc_pubkey: pubkey3,
+                    payment_hash160,
+                    anchors,
+                }
+            } else {
+                ScriptTemplate::ReceivedHtlc {
+                    revocation_pubkey_hash,
+                    remote_htlc_pubkey: pubkey2,
+                    local_htlc_pubkey: pubkey3,
+                    payment_hash160,
+                    cltv_expiry: 500_000,
+                    anchors,
+                }
+            };
+            assert_eq!(identify_template(&script), Some(expected));
+            check_asm_round_trip(&script);
+        }
+    }
+
+    let hash_locked = parse_asm(&format!(
+        "OP_IF OP_HASH160 {} OP_EQUAL OP_ELSE c800 OP_CHECKLOCKTIMEVERIFY OP_DROP {} OP_CHECKSIG OP_ENDIF",
+        hex::encode(payment_hash160),
+        pubkey1
+    ))
+    .unwrap();
+    assert_eq!(
+        identify_template(&hash_locked),
+        Some(ScriptTemplate::HashLocked { payment_hash160, cltv_expiry: 200, pubkey: pubkey1 })
+    );
+    check_asm_round_trip(&hash_locked);
+
+    let timelocked = timelocked_p2pkh(&pubkey1, 6);
+    assert!(matches!(
+        identify_template(&timelocked),
+        Some(ScriptTemplate::TimelockedP2pkh { relative_locktime: 6, .. })
+    ));
+    check_asm_round_trip(&timelocked);
+
+    let anchor = build_anchor_witness_script(&pubkey1);
+    assert_eq!(identify_template(&anchor), Some(ScriptTemplate::Anchor { funding_pubkey: pubkey1 }));
+    let to_remote = build_anchor_to_remote_witness_script(&pubkey2);
+    assert_eq!(identify_template(&to_remote), Some(ScriptTemplate::ToRemoteAnchor { remote_pubkey: pubkey2 }));
+
+    let p2wpkh = p2wpkh_output_script(pubkey1);
+    assert!(matches!(identify_template(&p2wpkh), Some(ScriptTemplate::P2wpkh { .. })));
+    let p2wsh = multisig.to_p2wsh();
+    assert_eq!(
+        identify_template(&p2wsh),
+        Some(ScriptTemplate::P2wsh { script_hash: multisig.wscript_hash().to_byte_array() })
+    );
+    check_asm_round_trip(&p2wsh);
+
+    let unknown = parse_asm("OP_DUP OP_DROP").unwrap();
+    assert_eq!(identify_template(&unknown), None);
+    assert!(pretty_print(&unknown).starts_with("# unrecognised script\n"));
+}
+
+#[test]
+fn test_parse_asm() {
+    // rust-bitcoin's ASM, with explicit push opcodes, parses too
+    let to_local_script = to_local_solution(
+        &pubkey_from_private_key(&[0x01; 32]),
+        &pubkey_from_private_key(&[0x02; 32]),
+        144,
+    );
+    assert_eq!(parse_asm(&to_local_script.to_asm_string()).unwrap(), to_local_script);
+
+    // Bitcoin Core's opcode names and lower case are accepted
+    assert_eq!(
+        parse_asm("op_true OP_CHECKSEQUENCEVERIFY OP_NOP2 OP_FALSE OP_1NEGATE OP_16").unwrap().as_bytes(),
+        &[0x51, 0xb2, 0xb1, 0x00, 0x4f, 0x60]
+    );
+
+    // a non-minimal push keeps its opcode through a round trip
+    let non_minimal = ScriptBuf::from_bytes(vec![0x4c, 0x02, 0x01, 0xff]);
+    assert_eq!(disassemble(&non_minimal), "OP_PUSHDATA1 01ff");
+    check_asm_round_trip(&non_minimal);
+    assert_eq!(parse_asm("01ff").unwrap().as_bytes(), &[0x02, 0x01, 0xff]);
+
+    assert_eq!(parse_asm("OP_DUP OP_FOO"), Err(AsmError::UnknownToken("OP_FOO".to_string())));
+    assert_eq!(parse_asm("abc"), Err(AsmError::UnknownToken("abc".to_string())));
+    assert_eq!(
+        parse_asm("OP_PUSHBYTES_2"),
+        Err(AsmError::MissingPushData("OP_PUSHBYTES_2".to_string()))
+    );
+    assert_eq!(
+        parse_asm("OP_PUSHBYTES_2 ab"),
+        Err(AsmError::PushLength { opcode: "OP_PUSHBYTES_2".to_string(), length: 1 })
+    );
+
+    // a push that runs past the end of the script is reported, not panicked on
+    let truncated = ScriptBuf::from_bytes(vec![0x76, 0x4c, 0x05]);
+    assert!(disassemble(&truncated).starts_with("OP_DUP [error: "));
+}
+
+#[test]
+fn test_classify_bolt3_transactions() {
+    let vectors = load_vectors();
+    let channel = &vectors.channel;
+    let key = |hex_str: &str| PublicKey::from_slice(&hex::decode(hex_str).unwrap()).unwrap();
+
+    for commitment in vectors.commitments.iter() {
+        let context = CommitmentContext {
+            channel_type: commitment.channel_type(),
+            local_funding_pubkey: key(&channel.local_funding_pubkey),
+            remote_funding_pubkey: key(&channel.remote_funding_pubkey),
+            revocation_pubkey: key(&channel.local_revocation_pubkey),
+            local_delayed_pubkey: key(&channel.local_delayed_pubkey),
+            to_self_delay: channel.to_self_delay as i64,
+            remote_payment_basepoint: key(&channel.remote_payment_basepoint),
+            local_htlc_pubkey: key(&channel.local_htlc_pubkey),
+            remote_htlc_pubkey: key(&channel.remote_htlc_pubkey),
+            htlcs: vectors.htlcs.iter().map(htlc_output).collect(),
+        };
+        let commitment_tx: Transaction = deserialize(&hex::decode(&commitment.tx_hex).unwrap()).unwrap();
+        let classification = classify_transaction(&commitment_tx, Some(&context));
+
+        assert_eq!(classification.inputs[0].path, Some(SpendPath::Multisig), "{}", commitment.name);
+        let count = |wanted: fn(&OutputRole) -> bool| classification.outputs.iter().filter(|role| wanted(role)).count();
+        assert_eq!(count(|role| *role == OutputRole::ToLocal), (commitment.to_local_sat > 0) as usize);
+        assert_eq!(count(|role| *role == OutputRole::ToRemote), (commitment.to_remote_sat > 0) as usize);
+        assert_eq!(
+            count(|role| matches!(role, OutputRole::OfferedHtlc(_) | OutputRole::ReceivedHtlc(_))),
+            commitment.untrimmed_htlcs.len(),
+            "{}\n{}",
+            commitment.name,
+            classification
+        );
+        let has_htlcs = !commitment.untrimmed_htlcs.is_empty();
+        assert_eq!(
+            count(|role| *role == OutputRole::LocalAnchor),
+            (commitment.anchors && (commitment.to_local_sat > 0 || has_htlcs)) as usize
+        );
+        assert_eq!(
+            count(|role| *role == OutputRole::RemoteAnchor),
+            (commitment.anchors && (commitment.to_remote_sat > 0 || has_htlcs)) as usize
+        );
+        assert!(!classification.outputs.contains(&OutputRole::UnknownP2wsh), "{}", commitment.name);
+
+        for htlc_tx in commitment.htlc_txs.iter() {
+            let htlc_tx: Transaction = deserialize(&hex::decode(&htlc_tx.tx_hex).unwrap()).unwrap();
+            let spent = &classification.outputs[htlc_tx.input[0].previous_output.vout as usize];
+            let input = classify_input(&htlc_tx.input[0]);
+            match spent {
+                OutputRole::OfferedHtlc(_) => {
+                    assert!(matches!(input.template, Some(ScriptTemplate::OfferedHtlc { .. })));
+                    assert_eq!(input.path, Some(SpendPath::Timeout));
+                }
+                OutputRole::ReceivedHtlc(htlc) => {
+                    assert!(matches!(input.template, Some(ScriptTemplate::ReceivedHtlc { .. })));
+                    let preimage = match input.path {
+                        Some(SpendPath::Success { preimage }) => preimage,
+                        path => panic!("{}: expected a success path, got {:?}", commitment.name, path),
+                    };
+                    let payment_hash = Sha256::hash(&preimage).to_byte_array();
+                    assert_eq!(Ripemd160::hash(&payment_hash).to_byte_array(), htlc.payment_hash160);
+                }
+                role => panic!("{}: HTLC transaction spends {}", commitment.name, role),
+            }
+            assert_eq!(classify_outputs(&htlc_tx, Some(&context)), vec![OutputRole::ToLocal]);
+        }
+    }
+}
+
+#[test]
+fn test_classify_spend_paths() {
+    let revocation_pubkey = pubkey_from_private_key(&[0x01; 32]);
+    let delayed_pubkey = pubkey_from_private_key(&[0x02; 32]);
+    let signature = vec![0x30; 72];
+
+    let to_local_script = to_local_solution(&revocation_pubkey, &delayed_pubkey, 144);
+    let spend = |elements: Vec<Vec<u8>>| TxIn { witness: Witness::from_slice(&elements), ..TxIn::default() };
+
+    let revocation = classify_input(&spend(vec![signature.clone(), vec![1], to_local_script.to_bytes()]));
+    assert_eq!(revocation.path, Some(SpendPath::Revocation));
+    assert_eq!(revocation.to_string(), "to_local via revocation");
+    let delayed = classify_input(&spend(vec![signature.clone(), vec![], to_local_script.to_bytes()]));
+    assert_eq!(delayed.path, Some(SpendPath::Delayed));
+    let bad_selector = classify_input(&spend(vec![signature.clone(), vec![2], to_local_script.to_bytes()]));
+    assert!(matches!(bad_selector.template, Some(ScriptTemplate::ToLocal { .. })));
+    assert_eq!(bad_selector.path, None);
+
+    let anchor_script = build_anchor_witness_script(&revocation_pubkey);
+    assert_eq!(classify_input(&spend(vec![vec![], anchor_script.to_bytes()])).path, Some(SpendPath::AnchorSweep));
+    assert_eq!(
+        classify_input(&spend(vec![signature.clone(), anchor_script.to_bytes()])).path,
+        Some(SpendPath::KeySpend)
+    );
+
+    let p2wpkh = classify_input(&spend(vec![signature.clone(), delayed_pubkey.serialize().to_vec()]));
+    assert!(matches!(p2wpkh.template, Some(ScriptTemplate::P2wpkh { .. })));
+    assert_eq!(p2wpkh.path, Some(SpendPath::KeySpend));
+
+    assert_eq!(classify_input(&TxIn::default()), InputClassification { template: None, path: None });
+
+    // without a context, outputs are only told apart by type
+    let tx = build_transaction(
+        Version::TWO,
+        LockTime::ZERO,
+        vec![],
+        vec![
+            build_output(1_000, p2wpkh_output_script(delayed_pubkey)),
+            build_output(2_000, to_local_script.to_p2wsh()),
+            build_output(3_000, ScriptBuf::new_op_return([0x42])),
+        ],
+    );
+    assert_eq!(
+        classify_outputs(&tx, None),
+        vec![OutputRole::P2wpkh, OutputRole::UnknownP2wsh, OutputRole::Unknown]
+    );
+}
+
+#[test]
+fn test_classify_justice_transaction() {
+    let per_commitment_secret = secp256k1_private_key(&[0x31; 32]);
+    let revocation_basepoint_secret = secp256k1_private_key(&[0x32; 32]);
+    let revocation_pubkey = generate_revocation_pubkey_solution(
+        pubkey_from_private_key(&[0x32; 32]),
+        pubkey_from_private_key(&[0x31; 32]),
+    );
+    let context = CommitmentContext {
+        channel_type: ChannelType::StaticRemoteKey,
+        local_funding_pubkey: pubkey_from_private_key(&[0x07; 32]),
+        remote_funding_pubkey: pubkey_from_private_key(&[0x08; 32]),
+        revocation_pubkey,
+        local_delayed_pubkey: pubkey_from_private_key(&[0x06; 32]),
+        to_self_delay: 144,
+        remote_payment_basepoint: pubkey_from_private_key(&[0x02; 32]),
+        local_htlc_pubkey: pubkey_from_private_key(&[0x05; 32]),
+        remote_htlc_pubkey: pubkey_from_private_key(&[0x04; 32]),
+        htlcs: vec![
+            HtlcOutput { offered: true, amount: 20_000, cltv_expiry: 500, payment_hash160: HASH160_DUMMY },
+            HtlcOutput { offered: false, amount: 30_000, cltv_expiry: 501, payment_hash160: HASH160_DUMMY },
+        ],
+    };
+
+    let (revoked_commitment_tx, _) = build_fee_aware_commitment_transaction(
+        TxIn::default(),
+        3,
+        &pubkey_from_private_key(&[0x01; 32]),
+        &context.remote_payment_basepoint,
+        true,
+        context.channel_type,
+        &context.local_funding_pubkey,
+        &context.remote_funding_pubkey,
+        &context.revocation_pubkey,
+        &context.remote_htlc_pubkey,
+        &context.local_htlc_pubkey,
+        &context.local_delayed_pubkey,
+        context.to_self_delay,
+        253,
+        546,
+        &context.htlcs,
+        3_000_000_000,
+        2_000_000_000,
+    );
+    let mut roles = classify_outputs(&revoked_commitment_tx, Some(&context));
+    roles.sort_by_key(|role| role.to_string());
+    assert_eq!(
+        roles,
+        vec![
+            OutputRole::OfferedHtlc(context.htlcs[0].clone()),
+            OutputRole::ReceivedHtlc(context.htlcs[1].clone()),
+            OutputRole::ToLocal,
+            OutputRole::ToRemote,
+        ]
+    );
+
+    let justice_tx = build_justice_transaction(
+        &revoked_commitment_tx,
+        context.channel_type,
+        &per_commitment_secret,
+        &revocation_basepoint_secret,
+        &context.local_delayed_pubkey,
+        context.to_self_delay,
+        &context.remote_htlc_pubkey,
+        &context.local_htlc_pubkey,
+        &context.htlcs,
+        p2wpkh_output_script(pubkey_from_private_key(&[0x09; 32])),
+        &FixedFeeEstimator(2_500),
+    )
+    .unwrap();
+    let classification = classify_transaction(&justice_tx, Some(&context));
+    assert_eq!(classification.inputs.len(), 3);
+    for input in classification.inputs.iter() {
+        assert_eq!(input.path, Some(SpendPath::Revocation), "{}", classification);
+    }
+    assert_eq!(classification.outputs, vec![OutputRole::P2wpkh]);
+}
+
+#[test]
+fn test_estimate_signed_weight_bolt3() {
+    // Estimates assume 73-byte signatures, so they may only overshoot by the
+    // bytes a shorter DER encoding saves.
+    let vectors = load_vectors();
+    let funding_script =
+        ScriptBuf::from_bytes(hex::decode(&vectors.channel.funding_witness_script).unwrap());
+
+    for commitment in vectors.commitments.iter() {
+        let commitment_tx: Transaction = deserialize(&hex::decode(&commitment.tx_hex).unwrap()).unwrap();
+        let estimate =
+            estimate_signed_weight(&commitment_tx, &[InputSatisfaction::Multisig(funding_script.clone())]);
+        let actual = commitment_tx.weight().to_wu();
+        assert!(estimate >= actual && estimate <= actual + 4, "{}: {} vs {}", commitment.name, estimate, actual);
+
+        for htlc_tx in commitment.htlc_txs.iter() {
+            let htlc_tx: Transaction = deserialize(&hex::decode(&htlc_tx.tx_hex).unwrap()).unwrap();
+            let htlc_script = ScriptBuf::from_bytes(htlc_tx.input[0].witness.last().unwrap().to_vec());
+            let satisfaction = match identify_template(&htlc_script) {
+                Some(ScriptTemplate::OfferedHtlc { .. }) => InputSatisfaction::HtlcTimeout(htlc_script),
+                _ => InputSatisfaction::HtlcSuccess(htlc_script),
+            };
+            let estimate = estimate_signed_weight(&htlc_tx, &[satisfaction]);
+            let actual = htlc_tx.weight().to_wu();
+            assert!(estimate >= actual && estimate <= actual + 4, "{}: {} vs {}", commitment.name, estimate, actual);
+        }
+    }
+}
+
+#[test]
+fn test_set_output_amount_for_feerate() {
+    let alice = pubkey_from_private_key(&[0x01; 32]);
+    let bob = pubkey_from_private_key(&[0x02; 32]);
+    let funding_script = two_of_two_multisig_witness_script_solution(&alice, &bob);
+    let mut tx = build_transaction(
+        Version::TWO,
+        LockTime::ZERO,
+        vec![TxIn::default()],
+        vec![build_output(0, p2wpkh_output_script(alice)), build_output(1_000_000, p2wpkh_output_script(bob))],
+    );
+    let satisfactions = [InputSatisfaction::Multisig(funding_script.clone())];
+
+    let fee = set_output_amount_for_feerate(&mut tx, 0, 5_000_000, &satisfactions, 1_000).unwrap();
+    assert_eq!(fee, fee_for_weight(1_000, estimate_signed_weight(&tx, &satisfactions)));
+    assert_eq!(tx.output[0].value.to_sat(), 5_000_000 - 1_000_000 - fee);
+    assert_eq!(tx.output[1].value.to_sat(), 1_000_000);
+
+    // the output's amount has no effect on the estimate, so signing lands on the feerate
+    let signed_tx = sign_funding_transaction(tx.clone(), refund_key_manager(0x01), refund_key_manager(0x02));
+    assert!(signed_tx.weight().to_wu() <= estimate_signed_weight(&tx, &satisfactions));
+
+    let before = tx.clone();
+    assert_eq!(set_output_amount_for_feerate(&mut tx, 0, 1_000_000, &satisfactions, 1_000), None);
+    assert_eq!(tx, before);
+}
+
+#[test]
+fn test_musig2_key_aggregation_vectors() {
+    // BIP 327 key_agg_vectors.json
+    let keys: Vec<PublicKey> = [
+        "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+        "03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+        "023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66",
+    ]
+    .iter()
+    .map(|key| PublicKey::from_slice(&hex::decode(key).unwrap()).unwrap())
+    .collect();
+    let cases = [
+        (vec![0, 1, 2], "90539EEDE565F5D054F32CC0C220126889ED1E5D193BAF15AEF344FE59D4610C"),
+        (vec![2, 1, 0], "6204DE8B083426DC6EAF9502D27024D53FC826BF7D2012148A0575435DF54B2B"),
+        (vec![0, 0, 0], "B436E3BAD62B8CD409969A224731C193D051162D8C5AE8B109306127DA3AA935"),
+        (vec![0, 0, 1, 1], "69BC22BFA5D106306E48A20679DE1D7389386124D07571D0D872686028C26A3E"),
+    ];
+    for (indices, expected) in cases {
+        let pubkeys: Vec<PublicKey> = indices.iter().map(|i| keys[*i]).collect();
+        let aggregate = KeyAggContext::new(&pubkeys).x_only_public_key();
+        assert_eq!(hex::encode_upper(aggregate.serialize()), expected, "{:?}", indices);
+    }
+}
+
+#[test]
+fn test_taproot_funding_musig2_signing() {
+    let secp = Secp256k1::new();
+    let alice_key = secp256k1_private_key(&[0x01; 32]);
+    let bob_key = secp256k1_private_key(&[0x02; 32]);
+    let alice = pubkey_from_private_key(&[0x01; 32]);
+    let bob = pubkey_from_private_key(&[0x02; 32]);
+
+    // both peers derive the same output whatever the key order
+    assert_eq!(funding_output_script(&alice, &bob), funding_output_script(&bob, &alice));
+    let funding_tx = build_taproot_funding_transaction(vec![TxIn::default()], &alice, &bob, 1_000_000);
+    assert!(funding_tx.output[0].script_pubkey.is_p2tr());
+
+    let funding_txin = TxIn {
+        previous_output: OutPoint { txid: funding_tx.compute_txid(), vout: 0 },
+        ..Default::default()
+    };
+    let mut spend_tx = build_transaction(
+        Version::TWO,
+        LockTime::ZERO,
+        vec![funding_txin],
+        vec![build_output(999_000, p2wpkh_output_script(alice))],
+    );
+    let sighash = key_spend_sighash(&spend_tx, 0, &funding_tx.output);
+
+    let mut alice_signer = FundingSigner::new(alice_key, &bob);
+    let mut bob_signer = FundingSigner::new(bob_key, &alice);
+    assert_eq!(alice_signer.funding_output_key(), bob_signer.funding_output_key());
+    let (alice_nonce, bob_nonce) = (alice_signer.public_nonce(), bob_signer.public_nonce());
+
+    let alice_partial = alice_signer.partial_sign(&bob_nonce, sighash).unwrap();
+    let bob_partial = bob_signer.partial_sign(&alice_nonce, sighash).unwrap();
+
+    // a nonce signs once
+    assert_eq!(alice_signer.partial_sign(&bob_nonce, sighash), Err(MusigError::MissingNonce));
+
+    // a tampered partial signature is caught before aggregation
+    let mut tampered = bob_partial;
+    tampered.0[31] ^= 1;
+    assert!(matches!(
+        alice_signer.aggregate(&bob, &tampered),
+        Err(MusigError::InvalidPartialSignature { .. })
+    ));
+
+    let signature = alice_signer.aggregate(&bob, &bob_partial).unwrap();
+    assert_eq!(signature, bob_signer.aggregate(&alice, &alice_partial).unwrap());
+    secp.verify_schnorr(&signature, &Message::from_digest(sighash), &alice_signer.funding_output_key())
+        .unwrap();
+
+    spend_tx.input[0].witness = taproot::key_spend_witness(&signature);
+    assert_eq!(spend_tx.input[0].witness.len(), 1);
+
+    // a fresh nonce allows signing again
+    let first_nonce = alice_signer.public_nonce();
+    assert_ne!(alice_signer.next_nonce(), first_nonce);
+    assert!(alice_signer.partial_sign(&bob_nonce, sighash).is_ok());
+
+    // a peer nonce cancelling ours out still gives a signature, not a panic
+    let mut carol_signer = FundingSigner::new(alice_key, &bob);
+    let carol_nonce = carol_signer.public_nonce();
+    let cancelling = PubNonce { r1: carol_nonce.r1.negate(&secp), r2: carol_nonce.r2.negate(&secp) };
+    assert!(carol_signer.partial_sign(&cancelling, sighash).is_ok());
+}
+
+#[test]
+fn test_taproot_commitment_transaction() {
+    let secp = Secp256k1::new();
+    let revocation = pubkey_from_private_key(&[0x11; 32]);
+    let delayed_key = secp256k1_private_key(&[0x12; 32]);
+    let delayed = pubkey_from_private_key(&[0x12; 32]);
+    let remote = pubkey_from_private_key(&[0x13; 32]);
+    let local_htlc = pubkey_from_private_key(&[0x14; 32]);
+    let remote_htlc = pubkey_from_private_key(&[0x15; 32]);
+    let htlcs = [
+        HtlcOutput { offered: true, amount: 20_000, cltv_expiry: 500, payment_hash160: HASH160_DUMMY },
+        HtlcOutput { offered: false, amount: 30_000, cltv_expiry: 501, payment_hash160: HASH160_DUMMY },
+    ];
+
+    let commitment_tx = taproot::build_commitment_transaction(
+        TxIn::default(),
+        &revocation,
+        &delayed,
+        &remote,
+        144,
+        &local_htlc,
+        &remote_htlc,
+        &htlcs,
+        600_000,
+        350_000,
+    );
+    assert_eq!(commitment_tx.output.len(), 4);
+    assert!(commitment_tx.output.iter().all(|output| output.script_pubkey.is_p2tr()));
+
+    let to_local_info = to_local_spend_info(&revocation, &delayed, 144);
+    assert_eq!(commitment_tx.output[0].script_pubkey, spend_info_output_script(&to_local_info));
+    assert_eq!(to_local_info.internal_key(), taproot::nums_internal_key());
+
+    // HTLC outputs are key-path spendable with the revocation key
+    let offered_info = htlc_spend_info(&htlcs[0], &revocation, &remote_htlc, &local_htlc);
+    assert_eq!(commitment_tx.output[2].script_pubkey, spend_info_output_script(&offered_info));
+    assert_eq!(offered_info.internal_key(), revocation.x_only_public_key().0);
+    let timeout_leaf = offered_htlc_timeout_script(&local_htlc, &remote_htlc);
+    let control_block = leaf_control_block(&offered_info, &timeout_leaf);
+    let output_key = XOnlyPublicKey::from(offered_info.output_key());
+    assert!(control_block.verify_taproot_commitment(&secp, output_key, &timeout_leaf));
+
+    // sweep to_local through its delay leaf
+    let delay_leaf = to_local_delay_script(&delayed, 144);
+    let control_block = leaf_control_block(&to_local_info, &delay_leaf);
+    let output_key = XOnlyPublicKey::from(to_local_info.output_key());
+    assert!(control_block.verify_taproot_commitment(&secp, output_key, &delay_leaf));
+
+    let sweep_txin = TxIn {
+        previous_output: OutPoint { txid: commitment_tx.compute_txid(), vout: 0 },
+        sequence: Sequence::from_height(144),
+        ..Default::default()
+    };
+    let mut sweep_tx = build_transaction(
+        Version::TWO,
+        LockTime::ZERO,
+        vec![sweep_txin],
+        vec![build_output(599_000, p2wpkh_output_script(delayed))],
+    );
+    let prevouts = [commitment_tx.output[0].clone()];
+    let signature = sign_tapscript(&sweep_tx, 0, &prevouts, &delay_leaf, &delayed_key);
+    let sighash = taproot::script_spend_sighash(&sweep_tx, 0, &prevouts, &delay_leaf);
+    secp.verify_schnorr(&signature, &Message::from_digest(sighash), &delayed.x_only_public_key().0).unwrap();
+
+    sweep_tx.input[0].witness = tapscript_witness(vec![signature.as_ref().to_vec()], &delay_leaf, &control_block);
+    assert_eq!(sweep_tx.input[0].witness.len(), 3);
+    assert_eq!(sweep_tx.input[0].witness.second_to_last(), Some(delay_leaf.as_bytes()));
+}
+
+#[test]
+fn test_adaptor_signatures() {
+    let secp = Secp256k1::new();
+    let msg = Sha256::hash(b"ptlc").to_byte_array();
+    // enough keys and secrets to hit both parities of the key and the nonce
+    for i in 1..=8u8 {
+        let secret_key = secp256k1_private_key(&[i; 32]);
+        let pubkey = secret_key.x_only_public_key(&secp).0;
+        let adaptor_secret = secp256k1_private_key(&[0x40 + i; 32]);
+        let adaptor_point = payment_point(&adaptor_secret);
+
+        let adaptor_signature = adaptor_sign(&secret_key, msg, &adaptor_point, [i; 32]);
+        assert_eq!(AdaptorSignature::from_slice(&adaptor_signature.serialize()), Ok(adaptor_signature));
+        assert!(adaptor_verify(&pubkey, msg, &adaptor_point, &adaptor_signature));
+        let other_point = payment_point(&secp256k1_private_key(&[0x60 + i; 32]));
+        assert!(!adaptor_verify(&pubkey, msg, &other_point, &adaptor_signature));
+
+        // the adaptor signature alone is not a valid signature
+        let mut bare = [0u8; 64];
+        bare[..32].copy_from_slice(&adaptor_signature.nonce.x_only_public_key().0.serialize());
+        bare[32..].copy_from_slice(&adaptor_signature.s);
+        let bare = secp256k1::schnorr::Signature::from_slice(&bare).unwrap();
+        assert!(secp.verify_schnorr(&bare, &Message::from_digest(msg), &pubkey).is_err());
+
+        let signature = complete_adaptor_signature(&adaptor_signature, &adaptor_secret).unwrap();
+        secp.verify_schnorr(&signature, &Message::from_digest(msg), &pubkey).unwrap();
+        assert_eq!(extract_adaptor_secret(&adaptor_signature, &signature, &adaptor_point), Ok(adaptor_secret));
+
+        let unrelated = adaptor_sign(&secret_key, msg, &other_point, [i; 32]);
+        assert_eq!(
+            extract_adaptor_secret(&unrelated, &signature, &other_point),
+            Err(AdaptorError::NonceMismatch)
+        );
+
+        // a peer's nonce of -T is rejected, not a panic
+        let cancelling = AdaptorSignature { nonce: adaptor_point.negate(&secp), s: adaptor_signature.s };
+        assert!(!adaptor_verify(&pubkey, msg, &adaptor_point, &cancelling));
+        assert_eq!(complete_adaptor_signature(&cancelling, &adaptor_secret), Err(AdaptorError::InfiniteNonce));
+        assert_eq!(
+            extract_adaptor_secret(&cancelling, &signature, &adaptor_point),
+            Err(AdaptorError::InfiniteNonce)
+        );
+    }
+}
+
+#[test]
+fn test_ptlc_claim_reveals_payment_secret() {
+    let secp = Secp256k1::new();
+    let revocation = pubkey_from_private_key(&[0x11; 32]);
+    let delayed = pubkey_from_private_key(&[0x12; 32]);
+    let remote = pubkey_from_private_key(&[0x13; 32]);
+    let local_htlc_key = secp256k1_private_key(&[0x14; 32]);
+    let local_htlc = pubkey_from_private_key(&[0x14; 32]);
+    let remote_htlc_key = secp256k1_private_key(&[0x15; 32]);
+    let remote_htlc = pubkey_from_private_key(&[0x15; 32]);
+    let payment_secret = secp256k1_private_key(&[0x16; 32]);
+    let ptlc = PtlcOutput { offered: true, amount: 20_000, cltv_expiry: 500, payment_point: payment_point(&payment_secret) };
+    let htlc = HtlcOutput { offered: true, amount: 20_000, cltv_expiry: 500, payment_hash160: HASH160_DUMMY };
+
+    let commitment_tx = build_ptlc_commitment_transaction(
+        TxIn::default(),
+        &revocation,
+        &delayed,
+        &remote,
+        144,
+        &local_htlc,
+        &remote_htlc,
+        &[ptlc],
+        600_000,
+        350_000,
+    );
+    let spend_info = ptlc_spend_info(&ptlc, &revocation, &remote_htlc, &local_htlc);
+    assert_eq!(commitment_tx.output[2].script_pubkey, spend_info_output_script(&spend_info));
+    assert_eq!(spend_info.internal_key(), revocation.x_only_public_key().0);
+
+    // same tree shape as the HTLC, so only the output key tells them apart
+    let htlc_commitment_tx = taproot::build_commitment_transaction(
+        TxIn::default(),
+        &revocation,
+        &delayed,
+        &remote,
+        144,
+        &local_htlc,
+        &remote_htlc,
+        &[htlc],
+        600_000,
+        350_000,
+    );
+    assert_eq!(commitment_tx.output[..2], htlc_commitment_tx.output[..2]);
+    assert_ne!(commitment_tx.output[2], htlc_commitment_tx.output[2]);
+
+    // we hand the payee an adaptor signature for their claim
+    let claim_txin = TxIn {
+        previous_output: OutPoint { txid: commitment_tx.compute_txid(), vout: 2 },
+        sequence: Sequence::from_height(1),
+        ..Default::default()
+    };
+    let mut claim_tx = build_transaction(
+        Version::TWO,
+        LockTime::ZERO,
+        vec![claim_txin],
+        vec![build_output(19_000, p2wpkh_output_script(remote_htlc))],
+    );
+    let prevouts = [commitment_tx.output[2].clone()];
+    let success_script = ptlc_success_script(&ptlc, &local_htlc, &remote_htlc);
+    let adaptor_signature =
+        sign_ptlc_claim(&claim_tx, 0, &prevouts, &success_script, &local_htlc_key, &ptlc, [0x17; 32]);
+    let sighash = taproot::script_spend_sighash(&claim_tx, 0, &prevouts, &success_script);
+    assert!(adaptor_verify(&local_htlc.x_only_public_key().0, sighash, &ptlc.payment_point, &adaptor_signature));
+
+    // the payee completes it with the payment secret and broadcasts
+    let local_signature = complete_adaptor_signature(&adaptor_signature, &payment_secret).unwrap();
+    let remote_signature = sign_tapscript(&claim_tx, 0, &prevouts, &success_script, &remote_htlc_key);
+    secp.verify_schnorr(&local_signature, &Message::from_digest(sighash), &local_htlc.x_only_public_key().0)
+        .unwrap();
+    claim_tx.input[0].witness = ptlc_success_witness(&spend_info, &success_script, &local_signature, &remote_signature);
+
+    // unlike an HTLC claim, the witness carries no preimage, yet we learn the secret from it
+    let witness = &claim_tx.input[0].witness;
+    assert_eq!(witness.len(), 4);
+    assert!(witness.iter().all(|element| element != payment_secret.secret_bytes()));
+    let onchain_signature = secp256k1::schnorr::Signature::from_slice(&witness[1]).unwrap();
+    assert_eq!(
+        extract_adaptor_secret(&adaptor_signature, &onchain_signature, &ptlc.payment_point),
+        Ok(payment_secret)
+    );
+}
+
+#[test]
+fn test_build_funding_psbt_combines_contributions() {
+    let secp = Secp256k1::new();
+    let alice = pubkey_from_private_key(&[0x01; 32]);
+    let bob = pubkey_from_private_key(&[0x02; 32]);
+    let alice_wallet = bitcoin::PrivateKey::new(secp256k1_private_key(&[0x21; 32]), bitcoin::Network::Regtest);
+    let bob_wallet = bitcoin::PrivateKey::new(secp256k1_private_key(&[0x22; 32]), bitcoin::Network::Regtest);
+
+    let contribution = |txid_byte: u8, wallet: &bitcoin::PrivateKey| {
+        let txin = TxIn {
+            previous_output: OutPoint { txid: Txid::from_byte_array([txid_byte; 32]), vout: 0 },
+            sequence: Sequence::MAX,
+            ..Default::default()
+        };
+        let utxo = build_output(3_000_000, p2wpkh_output_script(wallet.public_key(&secp).inner));
+        (txin, utxo)
+    };
+    let (alice_txin, alice_utxo) = contribution(0xaa, &alice_wallet);
+    let (bob_txin, bob_utxo) = contribution(0xbb, &bob_wallet);
+
+    // each funder only knows the outputs it spends
+    let alice_psbt = build_funding_psbt(
+        vec![(alice_txin.clone(), Some(alice_utxo.clone())), (bob_txin.clone(), None)],
+        &alice,
+        &bob,
+        5_990_000,
+    );
+    let bob_psbt = build_funding_psbt(
+        vec![(alice_txin.clone(), None), (bob_txin.clone(), Some(bob_utxo.clone()))],
+        &alice,
+        &bob,
+        5_990_000,
+    );
+    assert_eq!(alice_psbt.outputs[0].witness_script, Some(two_of_two_multisig_witness_script_solution(&alice, &bob)));
+    assert_eq!(alice_psbt.unsigned_tx.output[0].script_pubkey, alice_psbt.outputs[0].witness_script.as_ref().unwrap().to_p2wsh());
+
+    let sign = |mut psbt: bitcoin::psbt::Psbt, wallet: &bitcoin::PrivateKey| {
+        let pubkey = wallet.public_key(&secp);
+        let own_script = p2wpkh_output_script(pubkey.inner);
+        for input in psbt.inputs.iter_mut() {
+            if input.witness_utxo.as_ref().map(|utxo| &utxo.script_pubkey) == Some(&own_script) {
+                input.bip32_derivation.insert(pubkey.inner, Default::default());
+            }
+        }
+        let keys: std::collections::BTreeMap<_, _> = [(pubkey, *wallet)].into_iter().collect();
+        psbt.sign(&keys, &secp).unwrap();
+        psbt
+    };
+    let combined = combine_psbts(vec![alice_psbt.clone(), bob_psbt.clone()]).unwrap();
+    assert_eq!(combined.inputs[0].witness_utxo, Some(alice_utxo));
+    assert_eq!(combined.inputs[1].witness_utxo, Some(bob_utxo));
+
+    // both sign the complete PSBT independently, then combine the signatures
+    let mut signed = combine_psbts(vec![sign(combined.clone(), &alice_wallet), sign(combined, &bob_wallet)]).unwrap();
+    assert_eq!(signed.fee().unwrap(), Amount::from_sat(10_000));
+    for input in signed.inputs.iter_mut() {
+        assert_eq!(input.partial_sigs.len(), 1);
+        let (pubkey, signature) = input.partial_sigs.pop_first().unwrap();
+        input.final_script_witness = Some(Witness::from_slice(&[signature.to_vec(), pubkey.to_bytes()]));
+    }
+    let tx = signed.extract_tx().unwrap();
+    assert_eq!(tx.input[0].previous_output, alice_txin.previous_output);
+    assert_eq!(tx.input[1].witness.len(), 2);
+
+    // PSBTs for different transactions do not combine
+    let other = build_funding_psbt(vec![(alice_txin, None)], &alice, &bob, 2_990_000);
+    assert!(matches!(combine_psbts(vec![alice_psbt, other]), Err(CombinePsbtsError::Psbt(_))));
+    assert!(matches!(combine_psbts(vec![]), Err(CombinePsbtsError::NoPsbts)));
+}
+
+/// Input 0 of `tx` spending the 5_000_000 sat funding output of
+/// `two_of_two_multisig_witness_script(alice, bob)`, signed by both.
+fn sign_splice(tx: &mut Transaction, alice_key: &[u8; 32], bob_key: &[u8; 32]) -> ScriptBuf {
+    let alice_pubkey = pubkey_from_private_key(alice_key);
+    let bob_pubkey = pubkey_from_private_key(bob_key);
+    let witness_script = two_of_two_multisig_witness_script_solution(&alice_pubkey, &bob_pubkey);
+
+    let alice_signature = sign_splice_funding_input(tx, 5_000_000, &witness_script, &secp256k1_private_key(alice_key));
+    let bob_signature = sign_splice_funding_input(tx, 5_000_000, &witness_script, &secp256k1_private_key(bob_key));
+    tx.input[0].witness = splice_funding_witness(&alice_signature, &bob_signature, &witness_script);
+    witness_script
+}
+
+fn splice_funding_txin() -> TxIn {
+    TxIn {
+        previous_output: OutPoint { txid: Txid::from_byte_array([0x43; 32]), vout: 0 },
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence::MAX,
+        witness: Witness::new(),
+    }
+}
+
+#[test]
+fn test_splice_in_transaction() {
+    let alice_pubkey = pubkey_from_private_key(&[0x01; 32]);
+    let bob_pubkey = pubkey_from_private_key(&[0x02; 32]);
+    let wallet_script = p2wpkh_output_script(pubkey_from_private_key(&[0x03; 32]));
+    let wallet_input = TxIn {
+        previous_output: OutPoint { txid: Txid::from_byte_array([0x44; 32]), vout: 1 },
+        ..splice_funding_txin()
+    };
+    let wallet_utxo = build_output(3_000_000, wallet_script.clone());
+
+    let (mut tx, fee) = build_splice_in_transaction(
+        splice_funding_txin(),
+        5_000_000,
+        vec![(wallet_input.clone(), wallet_utxo.clone())],
+        2_000_000,
+        wallet_script.clone(),
+        &alice_pubkey,
+        &bob_pubkey,
+        1_000,
+    )
+    .unwrap();
+
+    // the old funding output and the wallet input pay for the new funding
+    //   output, the change and the fee
+    let witness_script = sign_splice(&mut tx, &[0x01; 32], &[0x02; 32]);
+    assert_eq!(tx.input[0].previous_output, splice_funding_txin().previous_output);
+    assert_eq!(tx.input[1].previous_output, wallet_input.previous_output);
+    assert_eq!(tx.output[0].script_pubkey, witness_script.to_p2wsh());
+    assert_eq!(tx.output[0].value.to_sat(), 7_000_000);
+    assert_eq!(tx.output[1].value.to_sat(), 1_000_000 - fee);
+    let satisfactions = [InputSatisfaction::Multisig(witness_script.clone()), InputSatisfaction::P2wpkh];
+    assert_eq!(fee, fee_for_weight(1_000, estimate_signed_weight(&tx, &satisfactions)));
+
+    let execution = verify_p2wsh_input(&tx, 0, 5_000_000, &witness_script);
+    assert!(execution.is_valid(), "{}", execution);
+
+    // change that would be dust goes to the fee instead
+    let (tx, fee) = build_splice_in_transaction(
+        splice_funding_txin(),
+        5_000_000,
+        vec![(wallet_input.clone(), wallet_utxo.clone())],
+        2_999_000,
+        wallet_script.clone(),
+        &alice_pubkey,
+        &bob_pubkey,
+        1_000,
+    )
+    .unwrap();
+    assert_eq!(tx.output.len(), 1);
+    assert_eq!(fee, 1_000);
+
+    assert!(matches!(
+        build_splice_in_transaction(
+            splice_funding_txin(),
+            5_000_000,
+            vec![(wallet_input, wallet_utxo)],
+            3_000_000,
+            wallet_script,
+            &alice_pubkey,
+            &bob_pubkey,
+            1_000,
+        ),
+        Err(SpliceError::InsufficientFunds { available: 3_000_000, required }) if required > 3_000_000
+    ));
+}
+
+#[test]
+fn test_splice_out_and_interim_state() {
+    let alice_pubkey = pubkey_from_private_key(&[0x01; 32]);
+    let bob_pubkey = pubkey_from_private_key(&[0x02; 32]);
+    let witness_script = two_of_two_multisig_witness_script_solution(&alice_pubkey, &bob_pubkey);
+    let destination = build_output(1_000_000, p2wpkh_output_script(pubkey_from_private_key(&[0x03; 32])));
+
+    // alice has 4_000_000 sats in the channel and takes 1_000_000 out
+    let (mut tx, fee) = build_splice_out_transaction(
+        splice_funding_txin(),
+        5_000_000,
+        4_000_000_000,
+        destination.clone(),
+        &alice_pubkey,
+        &bob_pubkey,
+        1_000,
+    )
+    .unwrap();
+    sign_splice(&mut tx, &[0x01; 32], &[0x02; 32]);
+    assert_eq!(tx.output[0].value.to_sat(), 4_000_000 - fee);
+    assert_eq!(tx.output[1], destination);
+    let execution = verify_p2wsh_input(&tx, 0, 5_000_000, &witness_script);
+    assert!(execution.is_valid(), "{}", execution);
+
+    assert!(matches!(
+        build_splice_out_transaction(
+            splice_funding_txin(),
+            5_000_000,
+            1_000_000_000,
+            destination,
+            &alice_pubkey,
+            &bob_pubkey,
+            1_000,
+        ),
+        Err(SpliceError::InsufficientBalance { balance_msat: 1_000_000_000, .. })
+    ));
+
+    // until the splice confirms, the channel lives on both funding outputs
+    let mut state = SpliceState::new(FundingScope {
+        outpoint: splice_funding_txin().previous_output,
+        value: 5_000_000,
+        witness_script: witness_script.clone(),
+        to_local_msat: 4_000_000_000,
+        to_remote_msat: 1_000_000_000,
+    });
+    assert!(!state.is_splicing());
+    let scope = state.add_splice(&tx, true).unwrap().clone();
+    assert_eq!(scope.outpoint, OutPoint { txid: tx.compute_txid(), vout: 0 });
+    assert_eq!(scope.to_local_msat, (3_000_000 - fee) * 1000);
+    assert_eq!(scope.to_remote_msat, 1_000_000_000);
+    assert!(state.is_splicing());
+
+    // a payment must fit in both, and moves balances in both
+    assert_eq!(
+        state.apply_payment(3_500_000_000, true),
+        Err(SpliceError::InsufficientBalance { balance_msat: scope.to_local_msat, required_msat: 3_500_000_000 })
+    );
+    state.apply_payment(500_000_000, true).unwrap();
+    assert!(state.funding_scopes().all(|scope| scope.to_remote_msat == 1_500_000_000));
+
+    // one commitment transaction per funding output
+    let commitments = state.build_commitments(|scope| {
+        build_transaction(
+            Version::TWO,
+            LockTime::ZERO,
+            vec![scope.funding_txin()],
+            vec![build_output(scope.to_local_msat / 1000, ScriptBuf::new())],
+        )
+    });
+    assert_eq!(commitments.len(), 2);
+    assert_eq!(commitments[0].input[0].previous_output, splice_funding_txin().previous_output);
+    assert_eq!(commitments[1].input[0].previous_output, scope.outpoint);
+
+    // a transaction that does not spend the funding output is no splice
+    assert_eq!(state.add_splice(&commitments[1], true), Err(SpliceError::NotSpendingFunding));
+    let unknown = Txid::from_byte_array([0x45; 32]);
+    assert_eq!(state.splice_locked(unknown), Err(SpliceError::UnknownSplice(unknown)));
+
+    state.splice_locked(tx.compute_txid()).unwrap();
+    assert!(!state.is_splicing());
+    assert_eq!(state.current.outpoint, scope.outpoint);
+    assert_eq!(state.current.to_remote_msat, 1_500_000_000);
+}
@@ -19,10 +19,7 @@ use bitcoin::hashes::Hash;
 //
 //Channel Monitor
 //
-#[derive(Clone)]
-pub struct CounterpartyCommitmentSecrets {
-  old_secrets: [([u8; 32], u64); 49],
-}
+pub use crate::ch2_setup::channel_exercises_v2::CounterpartyCommitmentSecrets;
 
 #[derive(Hash, Copy, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub struct Preimage(pub [u8; 32]);
@@ -36,7 +33,7 @@ pub struct ChannelMonitor {
   channel_value_sats: u64,
   current_commitment_tx: Option<Transaction>,
   best_block: BestBlock,
-  commitment_secrets: Vec<[u8; 32]>,
+  commitment_secrets: CounterpartyCommitmentSecrets,
   preimages: Vec<Preimage>,
   outputs_to_watch: HashMap<Txid, Vec<(u32, ScriptBuf)>>,
 }
@@ -119,7 +116,8 @@ impl ChannelMonitor{
 
 
 impl ChannelMonitor {
-  pub fn update_monitor(&mut self, update: ChannelMonitorUpdate) {
+  #[allow(clippy::result_unit_err)]
+  pub fn update_monitor(&mut self, update: ChannelMonitorUpdate) -> Result<(), ()> {
     match update {
       ChannelMonitorUpdate::LatestHolderCommitmentTXInfo {commitment_tx} => {
       self.current_commitment_tx = Some(commitment_tx);
@@ -128,9 +126,11 @@ impl ChannelMonitor {
       self.preimages.push(payment_preimage);
       },
       ChannelMonitorUpdate::CommitmentSecret {secret} => {
-      self.commitment_secrets.push(secret);
+      let idx = self.commitment_secrets.get_min_seen_secret() - 1;
+      self.commitment_secrets.provide_secret(idx, secret)?;
       },
     }
+    Ok(())
   }
 
   pub fn new() -> Self {
@@ -143,7 +143,7 @@ impl ChannelMonitor {
           channel_value_sats: 0,
           current_commitment_tx: None,
           best_block: BestBlock::from_network(Network::Regtest),
-          commitment_secrets: Vec::new(),
+          commitment_secrets: CounterpartyCommitmentSecrets::new(),
           preimages: Vec::new(),
           outputs_to_watch: HashMap::new(),
       }
@@ -176,10 +176,11 @@ impl ChainMonitor {
     Ok(result)
     }
 
-  fn update_channel(&mut self, funding_outpoint: OutPoint, update: ChannelMonitorUpdate) {
+  fn update_channel(&mut self, funding_outpoint: OutPoint, update: ChannelMonitorUpdate) -> Result<(), ()> {
     let channel_monitor = self.monitors.get_mut(&funding_outpoint).unwrap();
-    channel_monitor.update_monitor(update);
+    channel_monitor.update_monitor(update)?;
     self.persister.persist_channel(funding_outpoint, channel_monitor.clone());
+    Ok(())
   }
 
   fn transactions_confirmed(&mut self,
@@ -14,12 +14,23 @@ use internal::bitcoind_client::BitcoindClient;
 use lightning::chain::chaininterface::BroadcasterInterface;
 use bitcoin::{Network};
 use bitcoin::hashes::Hash;
+use bitcoin::hashes::sha256;
 use rand::Rng;
 use lightning::ln::msgs;
 use internal::events::{MessageSendEvent, Event};
 use internal::messages::{OpenChannel, AcceptChannel,
                                             FundingCreated, FundingSigned,
-                                            ChannelReady};
+                                            ChannelReady, OpenChannelV2, AcceptChannelV2,
+                                            TxAddInput, TxAddOutput, TxRemoveInput, TxRemoveOutput,
+                                            TxComplete, TxSignatures};
+use crate::ch1_intro_htlcs::solutions::two_of_two_multisig_witness_script;
+use crate::ch2_setup::channel_id::{random_temporary_channel_id, v1_channel_id, v2_channel_id, v2_temporary_channel_id};
+use crate::ch2_setup::interactive_tx::{
+  plan_contribution, FundingContribution, InteractiveTxConstructor, InteractiveTxError, Role,
+};
+use bitcoin::locktime::absolute::LockTime;
+use bitcoin::{TxOut, Witness};
+use std::collections::VecDeque;
 use crate::ch3_keys::exercises::{
     SimpleKeysManager,
 };
@@ -82,11 +93,113 @@ impl MockFileStore {
 //
 //Channel Monitor
 //
-#[derive(Clone)]
+
+/// The first per-commitment secret has index 2^48 - 1; each new commitment
+/// counts down by one.
+pub const INITIAL_COMMITMENT_NUMBER: u64 = (1 << 48) - 1;
+
+/// Derives the per-commitment secret for `index` from `seed` (BOLT 3 "Per-commitment
+/// Secret Requirements"). For each of the 48 index bits, from most to least
+/// significant, that is set: flip the same bit in the running value and hash it.
+pub fn generate_from_seed(seed: &[u8; 32], index: u64) -> [u8; 32] {
+  derive_secret(*seed, 48, index)
+}
+
+/// Applies the shachain derivation from `secret` over the low `bits` bits of `index`.
+fn derive_secret(secret: [u8; 32], bits: u8, index: u64) -> [u8; 32] {
+  let mut res = secret;
+  for i in (0..bits).rev() {
+    if (index >> i) & 1 == 1 {
+      res[(i / 8) as usize] ^= 1 << (i & 7);
+      res = sha256::Hash::hash(&res).to_byte_array();
+    }
+  }
+  res
+}
+
+/// Compact storage for the per-commitment secrets our counterparty reveals.
+///
+/// A secret whose index ends in `n` zero bits can derive every secret that
+/// shares its upper bits, so we only keep one secret per trailing-zero count:
+/// 49 slots cover all 2^48 commitments.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct CounterpartyCommitmentSecrets {
   old_secrets: [([u8; 32], u64); 49],
 }
 
+impl CounterpartyCommitmentSecrets {
+  pub fn new() -> Self {
+    Self { old_secrets: [([0; 32], 1 << 48); 49] }
+  }
+
+  /// The slot a secret is stored in: the number of trailing zeros in its index.
+  fn place_secret(idx: u64) -> u8 {
+    for i in 0..48 {
+      if idx & (1 << i) == (1 << i) {
+        return i;
+      }
+    }
+    48
+  }
+
+  /// The lowest index we have received, or 2^48 if we have received nothing.
+  pub fn get_min_seen_secret(&self) -> u64 {
+    let mut min = 1 << 48;
+    for &(_, idx) in self.old_secrets.iter() {
+      if idx < min {
+        min = idx;
+      }
+    }
+    min
+  }
+
+  /// How many secrets we have received so far.
+  pub fn len(&self) -> usize {
+    ((1 << 48) - self.get_min_seen_secret()) as usize
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Stores the secret for `idx`, checking that it derives every secret we
+  /// already hold in the slots below it. Fails if the secret is inconsistent,
+  /// leaving the storage unchanged.
+  #[allow(clippy::result_unit_err)]
+  pub fn provide_secret(&mut self, idx: u64, secret: [u8; 32]) -> Result<(), ()> {
+    let pos = Self::place_secret(idx);
+    for i in 0..pos {
+      let (old_secret, old_idx) = self.old_secrets[i as usize];
+      if derive_secret(secret, pos, old_idx) != old_secret {
+        return Err(());
+      }
+    }
+    if self.get_min_seen_secret() <= idx {
+      return Ok(());
+    }
+    self.old_secrets[pos as usize] = (secret, idx);
+    Ok(())
+  }
+
+  /// Returns the secret for `idx` if we have received it (directly or by
+  /// deriving it from a secret we hold).
+  pub fn get_secret(&self, idx: u64) -> Option<[u8; 32]> {
+    for i in 0..self.old_secrets.len() {
+      if (idx & (!((1 << i) - 1))) == self.old_secrets[i].1 {
+        return Some(derive_secret(self.old_secrets[i].0, i as u8, idx));
+      }
+    }
+    assert!(idx < self.get_min_seen_secret());
+    None
+  }
+}
+
+impl Default for CounterpartyCommitmentSecrets {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
 #[derive(Debug, Hash, Copy, Clone, PartialEq, Eq, Ord, PartialOrd)]
 pub struct Preimage(pub [u8; 32]);
 
@@ -105,7 +218,7 @@ pub struct ChannelMonitor {
   pub channel_value_sats: u64,
   pub current_commitment_tx: Option<Transaction>,
   pub best_block: BestBlock,
-  pub commitment_secrets: Vec<[u8; 32]>,
+  pub commitment_secrets: CounterpartyCommitmentSecrets,
   pub preimages: Vec<Preimage>,
   pub outputs_to_watch: HashMap<Txid, Vec<(u32, ScriptBuf)>>,
 }
@@ -188,7 +301,8 @@ impl ChannelMonitor{
 
 
 impl ChannelMonitor {
-  pub fn update_monitor(&mut self, update: ChannelMonitorUpdate) {
+  #[allow(clippy::result_unit_err)]
+  pub fn update_monitor(&mut self, update: ChannelMonitorUpdate) -> Result<(), ()> {
     match update {
       ChannelMonitorUpdate::LatestHolderCommitmentTXInfo {commitment_tx} => {
       self.current_commitment_tx = Some(commitment_tx);
@@ -197,9 +311,13 @@ impl ChannelMonitor {
       self.preimages.push(payment_preimage);
       },
       ChannelMonitorUpdate::CommitmentSecret {secret} => {
-      self.commitment_secrets.push(secret);
+      // Secrets are revealed in order, so this one is for the commitment
+      // just below the oldest one we have seen.
+      let idx = self.commitment_secrets.get_min_seen_secret() - 1;
+      self.commitment_secrets.provide_secret(idx, secret)?;
       },
     }
+    Ok(())
   }
 
   pub fn new() -> Self {
@@ -212,7 +330,7 @@ impl ChannelMonitor {
           channel_value_sats: 0,
           current_commitment_tx: None,
           best_block: BestBlock::from_network(Network::Regtest),
-          commitment_secrets: Vec::new(),
+          commitment_secrets: CounterpartyCommitmentSecrets::new(),
           preimages: Vec::new(),
           outputs_to_watch: HashMap::new(),
       }
@@ -245,10 +363,12 @@ impl ChainMonitor {
     Ok(result)
     }
 
-  pub fn update_channel(&mut self, funding_outpoint: OutPoint, update: ChannelMonitorUpdate) {
+  #[allow(clippy::result_unit_err)]
+  pub fn update_channel(&mut self, funding_outpoint: OutPoint, update: ChannelMonitorUpdate) -> Result<(), ()> {
     let channel_monitor = self.monitors.get_mut(&funding_outpoint).unwrap();
-    channel_monitor.update_monitor(update);
+    channel_monitor.update_monitor(update)?;
     self.persister.persist_channel(funding_outpoint, channel_monitor.clone());
+    Ok(())
   }
 
   pub fn transactions_confirmed(&mut self,
@@ -273,9 +393,74 @@ impl ChainMonitor {
 pub struct Channel {
   their_network_key: PublicKey,
   temporary_channel_id: ChannelId,
+  /// The ID derived once the funding is known; until then the channel goes
+  /// by `temporary_channel_id`.
+  channel_id: Option<ChannelId>,
   channel_value_satoshis: u64,
   output_script: ScriptBuf,
-  funding_outpoint: OutPoint,
+  funding_outpoint: Option<OutPoint>,
+  dual_funding: Option<DualFundingState>,
+}
+
+/// Progress of a dual-funded open, from open_channel2 until both peers have
+/// exchanged tx_signatures.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct DualFundingState {
+  role: Role,
+  funding_feerate_perkw: u32,
+  locktime: u32,
+  local_funding_pubkey: PublicKey,
+  local_revocation_basepoint: PublicKey,
+  local_funding_satoshis: u64,
+  /// Our inputs and outputs not yet sent, one per turn.
+  pending_inputs: VecDeque<(Transaction, u32)>,
+  pending_outputs: VecDeque<TxOut>,
+  interactive_tx: Option<InteractiveTxConstructor>,
+  local_witnesses: Option<Vec<Witness>>,
+  remote_witnesses: Option<Vec<Witness>>,
+  sent_tx_signatures: bool,
+}
+
+impl DualFundingState {
+  #[allow(clippy::too_many_arguments)]
+  fn new(
+    role: Role,
+    funding_feerate_perkw: u32,
+    locktime: u32,
+    local_funding_pubkey: PublicKey,
+    local_revocation_basepoint: PublicKey,
+    local_funding_satoshis: u64,
+    contribution: FundingContribution,
+  ) -> Self {
+    Self {
+      role,
+      funding_feerate_perkw,
+      locktime,
+      local_funding_pubkey,
+      local_revocation_basepoint,
+      local_funding_satoshis,
+      pending_inputs: contribution.inputs.into(),
+      pending_outputs: contribution.change.into_iter().collect(),
+      interactive_tx: None,
+      local_witnesses: None,
+      remote_witnesses: None,
+      sent_tx_signatures: false,
+    }
+  }
+
+  fn interactive_tx(&mut self) -> Result<&mut InteractiveTxConstructor, InteractiveTxError> {
+    self.interactive_tx.as_mut().ok_or(InteractiveTxError::NegotiationInProgress)
+  }
+}
+
+/// The P2WSH funding output script, with the funding keys in BOLT 3 order.
+fn funding_witness_script(local_funding_pubkey: &PublicKey, remote_funding_pubkey: &PublicKey) -> ScriptBuf {
+  let (first, second) = if local_funding_pubkey.serialize() < remote_funding_pubkey.serialize() {
+    (local_funding_pubkey, remote_funding_pubkey)
+  } else {
+    (remote_funding_pubkey, local_funding_pubkey)
+  };
+  two_of_two_multisig_witness_script(first, second)
 }
 
 enum ChannelOpenStatus {
@@ -290,17 +475,68 @@ impl Channel{
   pub fn new(their_network_key: PublicKey, channel_value_satoshis: u64) -> Self {
     Self {
       their_network_key,
-      temporary_channel_id: ChannelId::new_zero(),
+      temporary_channel_id: random_temporary_channel_id(),
+      channel_id: None,
       channel_value_satoshis,
       output_script: ScriptBuf::new(),
-      funding_outpoint: OutPoint { txid: Txid::from_slice(&[43; 32]).unwrap(), index: 0 }
+      funding_outpoint: None,
+      dual_funding: None,
     }
   }
 
+  /// The ID peers know the channel by: the derived channel ID once there is
+  /// one, the temporary one before.
+  pub fn channel_id(&self) -> ChannelId {
+    self.channel_id.unwrap_or(self.temporary_channel_id)
+  }
+
+  fn dual_funding(&mut self) -> Result<&mut DualFundingState, InteractiveTxError> {
+    self.dual_funding.as_mut().ok_or(InteractiveTxError::NegotiationInProgress)
+  }
+
+  /// Starts building the funding transaction once both contributions are
+  /// known. The initiator adds the funding output ahead of its change.
+  ///
+  /// Both revocation basepoints are known now, so the channel switches to
+  /// its v2 channel ID, which the tx_* messages already use.
+  fn start_interactive_tx(
+    &mut self,
+    remote_funding_satoshis: u64,
+    remote_funding_pubkey: &PublicKey,
+    remote_revocation_basepoint: &PublicKey,
+  ) {
+    let dual_funding = self.dual_funding.as_mut().expect("dual-funded channel");
+    let channel_id = v2_channel_id(&dual_funding.local_revocation_basepoint, remote_revocation_basepoint);
+    let witness_script = funding_witness_script(&dual_funding.local_funding_pubkey, remote_funding_pubkey);
+    let channel_value_satoshis = dual_funding.local_funding_satoshis + remote_funding_satoshis;
+
+    if dual_funding.role == Role::Initiator {
+      dual_funding.pending_outputs.push_front(TxOut {
+        value: bitcoin::Amount::from_sat(channel_value_satoshis),
+        script_pubkey: witness_script.to_p2wsh(),
+      });
+    }
+    dual_funding.interactive_tx = Some(InteractiveTxConstructor::new(
+      channel_id,
+      dual_funding.role,
+      dual_funding.funding_feerate_perkw,
+      LockTime::from_consensus(dual_funding.locktime),
+      witness_script.to_p2wsh(),
+      dual_funding.local_funding_satoshis,
+      remote_funding_satoshis,
+    ));
+    self.channel_value_satoshis = channel_value_satoshis;
+    self.output_script = witness_script.to_p2wsh();
+    self.channel_id = Some(channel_id);
+  }
+
   pub fn open_channel(&mut self) -> ChannelOpenStatus {
-    ChannelOpenStatus::Success {
-      funding_outpoint: OutPoint { txid: Txid::from_slice(&[43; 32]).unwrap(), index: 0 },
-      channel_monitor: ChannelMonitor::new()
+    match self.funding_outpoint {
+      Some(funding_outpoint) => ChannelOpenStatus::Success {
+        funding_outpoint,
+        channel_monitor: self.into_monitor()
+      },
+      None => ChannelOpenStatus::Failure,
     }
   }
 
@@ -312,17 +548,25 @@ impl Channel{
     }
   }
 
-  pub fn funding_created_msg(&mut self, temporary_channel_id: ChannelId, transaction_id: Txid) -> FundingCreated {
+  pub fn funding_created_msg(&mut self, temporary_channel_id: ChannelId, transaction_id: Txid,
+                             funding_output_index: u16) -> FundingCreated {
     FundingCreated{
 
       temporary_channel_id: temporary_channel_id,
-      transaction_id: transaction_id
+      transaction_id,
+      funding_output_index
 
     }
   }
 
   pub fn into_monitor(&mut self)-> ChannelMonitor {
-    ChannelMonitor::new()
+    let mut channel_monitor = ChannelMonitor::new();
+    channel_monitor.channel_id = self.channel_id();
+    if let Some(funding_outpoint) = self.funding_outpoint {
+      channel_monitor.funding_outpoint = funding_outpoint;
+    }
+    channel_monitor.channel_value_sats = self.channel_value_satoshis;
+    channel_monitor
   }
 
 }
@@ -363,7 +607,7 @@ impl ChannelManager {
     
     self.pending_user_events.push(
       Event::FundingGenerationReady {
-        temporary_channel_id: msg.temporary_channel_id,
+        temporary_channel_id: temp_channel_id,
         counterparty_node_id: *counterparty_node_id,
         channel_value_satoshis: channel_value_satoshis,
         output_script: output_script,
@@ -371,24 +615,53 @@ impl ChannelManager {
     );
   }
 
-  pub fn handle_funding_signed(&mut self, counterparty_node_id: &PublicKey, msg: FundingSigned){
-    let channel = self.peers.get_mut(&counterparty_node_id).unwrap();
+  /// The counterparty signed our first commitment transaction, under the
+  /// channel ID derived from the funding outpoint we sent them.
+  #[allow(clippy::result_unit_err)]
+  pub fn handle_funding_signed(&mut self, counterparty_node_id: &PublicKey, msg: FundingSigned) -> Result<(), ()> {
+    let channel = self.peers.get_mut(counterparty_node_id).ok_or(())?;
 
-    let funding_outpoint = channel.funding_outpoint;
+    let funding_outpoint = channel.funding_outpoint.ok_or(())?;
+    if msg.channel_id != channel.channel_id() {
+      return Err(());
+    }
     let channel_monitor = channel.into_monitor();
     
     self.chain_monitor.watch_channel(funding_outpoint, channel_monitor)
     .expect("Add to watch channel");
+
+    self.pending_user_events.push(Event::ChannelPending {
+      channel_id: channel.channel_id(),
+      former_temporary_channel_id: channel.temporary_channel_id,
+      counterparty_node_id: *counterparty_node_id,
+      funding_txo: funding_outpoint.into_bitcoin_outpoint(),
+    });
+    Ok(())
   }
 
+  /// Takes the funding transaction built for `FundingGenerationReady`. From
+  /// here on the channel goes by the channel ID derived from its funding
+  /// outpoint.
+  #[allow(clippy::result_unit_err)]
   pub fn funding_transaction_generated(&mut self, temp_channel_id :ChannelId , their_network_key: PublicKey,
-                                   transaction: Transaction) {
+                                   transaction: Transaction) -> Result<(), ()> {
+
+    let channel = self.peers.get_mut(&their_network_key).ok_or(())?;
+    if channel.temporary_channel_id != temp_channel_id || channel.funding_outpoint.is_some() {
+      return Err(());
+    }
 
-    let channel = self.peers.get_mut(&their_network_key).unwrap();
+    // find the output we asked for
+    let funding_output_index = transaction.output.iter().position(|txout| {
+      txout.script_pubkey == channel.output_script && txout.value.to_sat() == channel.channel_value_satoshis
+    }).ok_or(())?;
 
     let txid = transaction.compute_txid();
+    let funding_outpoint = OutPoint { txid, index: funding_output_index as u16 };
+    channel.funding_outpoint = Some(funding_outpoint);
+    channel.channel_id = Some(v1_channel_id(&funding_outpoint));
     
-    let msg = channel.funding_created_msg(temp_channel_id, txid);
+    let msg = channel.funding_created_msg(temp_channel_id, txid, funding_output_index as u16);
 
     self.pending_peer_events.push(
       MessageSendEvent::SendFundingCreated {
@@ -396,6 +669,255 @@ impl ChannelManager {
         msg
       }
     );
-  
+    Ok(())
+  }
+
+  /// Opens a channel both peers fund, contributing `funding_satoshis` from
+  /// `inputs` and returning any change to `change_script`.
+  #[allow(clippy::too_many_arguments)]
+  pub fn create_dual_funded_channel(
+    &mut self,
+    their_network_key: PublicKey,
+    funding_satoshis: u64,
+    funding_feerate_perkw: u32,
+    funding_pubkey: PublicKey,
+    revocation_basepoint: PublicKey,
+    inputs: Vec<(Transaction, u32)>,
+    change_script: ScriptBuf,
+  ) -> Result<(), InteractiveTxError> {
+    let contribution = plan_contribution(Role::Initiator, funding_feerate_perkw, funding_satoshis, inputs, change_script)?;
+
+    let mut channel = Channel::new(their_network_key, funding_satoshis);
+    channel.temporary_channel_id = v2_temporary_channel_id(&revocation_basepoint);
+    channel.dual_funding = Some(DualFundingState::new(
+      Role::Initiator,
+      funding_feerate_perkw,
+      0,
+      funding_pubkey,
+      revocation_basepoint,
+      funding_satoshis,
+      contribution,
+    ));
+    self.peers.insert(their_network_key, channel.clone());
+
+    self.pending_peer_events.push(MessageSendEvent::SendOpenChannelV2 {
+      node_id: their_network_key,
+      msg: OpenChannelV2 {
+        temporary_channel_id: channel.temporary_channel_id,
+        funding_satoshis,
+        funding_feerate_perkw,
+        locktime: 0,
+        funding_pubkey,
+        revocation_basepoint,
+      },
+    });
+    Ok(())
+  }
+
+  /// Accepts a peer's open_channel2, contributing `funding_satoshis` (which
+  /// may be zero, with no inputs) to the channel.
+  #[allow(clippy::too_many_arguments)]
+  pub fn accept_dual_funded_channel(
+    &mut self,
+    counterparty_node_id: &PublicKey,
+    msg: OpenChannelV2,
+    funding_satoshis: u64,
+    funding_pubkey: PublicKey,
+    revocation_basepoint: PublicKey,
+    inputs: Vec<(Transaction, u32)>,
+    change_script: ScriptBuf,
+  ) -> Result<(), InteractiveTxError> {
+    let contribution =
+      plan_contribution(Role::NonInitiator, msg.funding_feerate_perkw, funding_satoshis, inputs, change_script)?;
+
+    let mut channel = Channel::new(*counterparty_node_id, funding_satoshis);
+    channel.temporary_channel_id = msg.temporary_channel_id;
+    channel.dual_funding = Some(DualFundingState::new(
+      Role::NonInitiator,
+      msg.funding_feerate_perkw,
+      msg.locktime,
+      funding_pubkey,
+      revocation_basepoint,
+      funding_satoshis,
+      contribution,
+    ));
+    channel.start_interactive_tx(msg.funding_satoshis, &msg.funding_pubkey, &msg.revocation_basepoint);
+    self.peers.insert(*counterparty_node_id, channel);
+
+    // the initiator sends the first tx_add message
+    self.pending_peer_events.push(MessageSendEvent::SendAcceptChannelV2 {
+      node_id: *counterparty_node_id,
+      msg: AcceptChannelV2 {
+        temporary_channel_id: msg.temporary_channel_id,
+        funding_satoshis,
+        funding_pubkey,
+        revocation_basepoint,
+      },
+    });
+    Ok(())
+  }
+
+  pub fn handle_accept_channel_v2(&mut self, counterparty_node_id: &PublicKey, msg: AcceptChannelV2) -> Result<(), InteractiveTxError> {
+    let channel = self.peers.get_mut(counterparty_node_id).ok_or(InteractiveTxError::UnknownPeer(*counterparty_node_id))?;
+    channel.start_interactive_tx(msg.funding_satoshis, &msg.funding_pubkey, &msg.revocation_basepoint);
+    self.send_next_interactive_tx_message(counterparty_node_id)
+  }
+
+  pub fn handle_tx_add_input(&mut self, counterparty_node_id: &PublicKey, msg: TxAddInput) -> Result<(), InteractiveTxError> {
+    let channel = self.peers.get_mut(counterparty_node_id).ok_or(InteractiveTxError::UnknownPeer(*counterparty_node_id))?;
+    channel.dual_funding()?.interactive_tx()?.handle_tx_add_input(&msg)?;
+    self.send_next_interactive_tx_message(counterparty_node_id)
+  }
+
+  pub fn handle_tx_add_output(&mut self, counterparty_node_id: &PublicKey, msg: TxAddOutput) -> Result<(), InteractiveTxError> {
+    let channel = self.peers.get_mut(counterparty_node_id).ok_or(InteractiveTxError::UnknownPeer(*counterparty_node_id))?;
+    channel.dual_funding()?.interactive_tx()?.handle_tx_add_output(&msg)?;
+    self.send_next_interactive_tx_message(counterparty_node_id)
+  }
+
+  pub fn handle_tx_remove_input(&mut self, counterparty_node_id: &PublicKey, msg: TxRemoveInput) -> Result<(), InteractiveTxError> {
+    let channel = self.peers.get_mut(counterparty_node_id).ok_or(InteractiveTxError::UnknownPeer(*counterparty_node_id))?;
+    channel.dual_funding()?.interactive_tx()?.handle_tx_remove_input(&msg)?;
+    self.send_next_interactive_tx_message(counterparty_node_id)
+  }
+
+  pub fn handle_tx_remove_output(&mut self, counterparty_node_id: &PublicKey, msg: TxRemoveOutput) -> Result<(), InteractiveTxError> {
+    let channel = self.peers.get_mut(counterparty_node_id).ok_or(InteractiveTxError::UnknownPeer(*counterparty_node_id))?;
+    channel.dual_funding()?.interactive_tx()?.handle_tx_remove_output(&msg)?;
+    self.send_next_interactive_tx_message(counterparty_node_id)
+  }
+
+  pub fn handle_tx_complete(&mut self, counterparty_node_id: &PublicKey, msg: TxComplete) -> Result<(), InteractiveTxError> {
+    let channel = self.peers.get_mut(counterparty_node_id).ok_or(InteractiveTxError::UnknownPeer(*counterparty_node_id))?;
+    if channel.dual_funding()?.interactive_tx()?.handle_tx_complete(&msg)? {
+      return self.interactive_tx_completed(counterparty_node_id);
+    }
+    self.send_next_interactive_tx_message(counterparty_node_id)
+  }
+
+  /// Our turn in the negotiation: add our next input or output, or send
+  /// tx_complete once everything is added.
+  fn send_next_interactive_tx_message(&mut self, counterparty_node_id: &PublicKey) -> Result<(), InteractiveTxError> {
+    let node_id = *counterparty_node_id;
+    let channel = self.peers.get_mut(counterparty_node_id).ok_or(InteractiveTxError::UnknownPeer(*counterparty_node_id))?;
+    let dual_funding = channel.dual_funding()?;
+
+    let event = if let Some((prevtx, prevtx_out)) = dual_funding.pending_inputs.pop_front() {
+      let msg = dual_funding.interactive_tx()?.add_local_input(prevtx, prevtx_out)?;
+      MessageSendEvent::SendTxAddInput { node_id, msg }
+    } else if let Some(output) = dual_funding.pending_outputs.pop_front() {
+      let msg = dual_funding.interactive_tx()?.add_local_output(output.value.to_sat(), output.script_pubkey)?;
+      MessageSendEvent::SendTxAddOutput { node_id, msg }
+    } else {
+      let interactive_tx = dual_funding.interactive_tx()?;
+      let msg = interactive_tx.local_tx_complete()?;
+      let complete = interactive_tx.is_complete();
+      self.pending_peer_events.push(MessageSendEvent::SendTxComplete { node_id, msg });
+      if complete {
+        return self.interactive_tx_completed(counterparty_node_id);
+      }
+      return Ok(());
+    };
+    self.pending_peer_events.push(event);
+    Ok(())
+  }
+
+  /// Both peers sent tx_complete: fix the funding outpoint and ask the user
+  /// to sign our inputs.
+  fn interactive_tx_completed(&mut self, counterparty_node_id: &PublicKey) -> Result<(), InteractiveTxError> {
+    let channel = self.peers.get_mut(counterparty_node_id).ok_or(InteractiveTxError::UnknownPeer(*counterparty_node_id))?;
+    let channel_id = channel.channel_id();
+    let interactive_tx = channel.dual_funding()?.interactive_tx()?.clone();
+    let unsigned_transaction = interactive_tx.build_transaction()?;
+
+    channel.funding_outpoint = Some(OutPoint {
+      txid: unsigned_transaction.compute_txid(),
+      index: interactive_tx.shared_output_index().expect("validated") as u16,
+    });
+    self.pending_user_events.push(Event::FundingTransactionReadyForSigning {
+      channel_id,
+      counterparty_node_id: *counterparty_node_id,
+      unsigned_transaction,
+      prevouts: interactive_tx.prevouts(),
+      local_input_indices: interactive_tx.local_input_indices(),
+    });
+    Ok(())
+  }
+
+  /// Takes the witnesses for our inputs of the funding transaction, in input
+  /// order, and sends them when it is our turn.
+  pub fn funding_transaction_signed(&mut self, counterparty_node_id: &PublicKey, witnesses: Vec<Witness>) -> Result<(), InteractiveTxError> {
+    let our_node_id = self.signer_provider.node_id;
+    let channel = self.peers.get_mut(counterparty_node_id).ok_or(InteractiveTxError::UnknownPeer(*counterparty_node_id))?;
+    let dual_funding = channel.dual_funding()?;
+    let interactive_tx = dual_funding.interactive_tx()?;
+    let expected = interactive_tx.input_count(true);
+    if witnesses.len() != expected {
+      return Err(InteractiveTxError::WitnessCountMismatch { expected, actual: witnesses.len() });
+    }
+    let sends_first = interactive_tx.holder_sends_tx_signatures_first(&our_node_id, counterparty_node_id);
+    dual_funding.local_witnesses = Some(witnesses);
+
+    if sends_first || dual_funding.remote_witnesses.is_some() {
+      self.send_tx_signatures(counterparty_node_id)?;
+    }
+    self.maybe_broadcast_funding(counterparty_node_id)
+  }
+
+  pub fn handle_tx_signatures(&mut self, counterparty_node_id: &PublicKey, msg: TxSignatures) -> Result<(), InteractiveTxError> {
+    let channel = self.peers.get_mut(counterparty_node_id).ok_or(InteractiveTxError::UnknownPeer(*counterparty_node_id))?;
+    let expected = channel.funding_outpoint.ok_or(InteractiveTxError::NegotiationInProgress)?.txid;
+    if msg.tx_hash != expected {
+      return Err(InteractiveTxError::TxHashMismatch { expected, actual: msg.tx_hash });
+    }
+    let dual_funding = channel.dual_funding()?;
+    dual_funding.interactive_tx()?.verify_remote_witnesses(&msg.witnesses)?;
+    dual_funding.remote_witnesses = Some(msg.witnesses);
+
+    // we went second and have already signed
+    if dual_funding.local_witnesses.is_some() && !dual_funding.sent_tx_signatures {
+      self.send_tx_signatures(counterparty_node_id)?;
+    }
+    self.maybe_broadcast_funding(counterparty_node_id)
+  }
+
+  fn send_tx_signatures(&mut self, counterparty_node_id: &PublicKey) -> Result<(), InteractiveTxError> {
+    let channel = self.peers.get_mut(counterparty_node_id).ok_or(InteractiveTxError::UnknownPeer(*counterparty_node_id))?;
+    let channel_id = channel.channel_id();
+    let tx_hash = channel.funding_outpoint.expect("negotiated").txid;
+    let dual_funding = channel.dual_funding()?;
+    dual_funding.sent_tx_signatures = true;
+
+    let witnesses = dual_funding.local_witnesses.clone().expect("signed");
+    self.pending_peer_events.push(MessageSendEvent::SendTxSignatures {
+      node_id: *counterparty_node_id,
+      msg: TxSignatures { channel_id, tx_hash, witnesses },
+    });
+    Ok(())
+  }
+
+  /// Once both sides' signatures are in, broadcast the funding transaction
+  /// and start watching the channel.
+  fn maybe_broadcast_funding(&mut self, counterparty_node_id: &PublicKey) -> Result<(), InteractiveTxError> {
+    let channel = self.peers.get_mut(counterparty_node_id).ok_or(InteractiveTxError::UnknownPeer(*counterparty_node_id))?;
+    let funding_outpoint = channel.funding_outpoint.expect("negotiated");
+    let dual_funding = channel.dual_funding()?;
+    let (local_witnesses, remote_witnesses) = match (&dual_funding.local_witnesses, &dual_funding.remote_witnesses) {
+      (Some(local), Some(remote)) if dual_funding.sent_tx_signatures => (local.clone(), remote.clone()),
+      _ => return Ok(()),
+    };
+    let funding_tx = dual_funding.interactive_tx()?.finalize(local_witnesses, remote_witnesses)?;
+
+    self.chain_monitor.broadcaster.broadcast_transactions(&[&funding_tx]);
+    let channel_monitor = channel.into_monitor();
+    self.chain_monitor.watch_channel(funding_outpoint, channel_monitor).expect("Add to watch channel");
+
+    self.pending_user_events.push(Event::ChannelPending {
+      channel_id: channel.channel_id(),
+      former_temporary_channel_id: channel.temporary_channel_id,
+      counterparty_node_id: *counterparty_node_id,
+      funding_txo: funding_outpoint.into_bitcoin_outpoint(),
+    });
+    Ok(())
   }
 }
\ No newline at end of file
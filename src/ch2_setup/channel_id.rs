@@ -0,0 +1,40 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! Channel IDs (BOLT 2 "Definition of channel_id").
+//!
+//! Until the funding transaction is known, peers refer to a channel by a
+//! temporary ID picked by the opener. Afterwards the channel ID is derived
+//! from the funding outpoint, or for dual-funded channels from both peers'
+//! revocation basepoints.
+use bitcoin::secp256k1::PublicKey;
+use lightning::chain::transaction::OutPoint;
+use lightning::ln::channel_keys::RevocationBasepoint;
+use lightning::ln::types::ChannelId;
+use rand::Rng;
+
+/// A random temporary channel ID for a channel we open.
+pub fn random_temporary_channel_id() -> ChannelId {
+  ChannelId::from_bytes(rand::thread_rng().gen())
+}
+
+/// The channel ID of a channel funded by a single peer: the funding txid,
+/// in its serialized byte order, with the funding output index XORed into
+/// its last two bytes.
+pub fn v1_channel_id(funding_outpoint: &OutPoint) -> ChannelId {
+  ChannelId::v1_from_funding_outpoint(*funding_outpoint)
+}
+
+/// The channel ID of a dual-funded channel: the SHA256 of both peers'
+/// revocation basepoints, the lesser one first.
+pub fn v2_channel_id(local_revocation_basepoint: &PublicKey, remote_revocation_basepoint: &PublicKey) -> ChannelId {
+  ChannelId::v2_from_revocation_basepoints(
+    &RevocationBasepoint::from(*local_revocation_basepoint),
+    &RevocationBasepoint::from(*remote_revocation_basepoint),
+  )
+}
+
+/// The temporary channel ID of a dual-funded channel we open: a v2 channel
+/// ID with the acceptor's revocation basepoint, which we do not know yet,
+/// zeroed out.
+pub fn v2_temporary_channel_id(local_revocation_basepoint: &PublicKey) -> ChannelId {
+  ChannelId::temporary_v2_from_revocation_basepoint(&RevocationBasepoint::from(*local_revocation_basepoint))
+}
@@ -0,0 +1,655 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! BOLT 2 interactive transaction construction, used to build the funding
+//! transaction of a dual-funded channel.
+//!
+//! Both peers take turns sending `tx_add_*`/`tx_remove_*` messages, or
+//! `tx_complete` when they have nothing more to add, until both have sent
+//! `tx_complete` in a row. Every input and output carries a serial ID whose
+//! parity tells who added it (even for the initiator, odd for the
+//! non-initiator), and the final transaction orders them by serial ID.
+use crate::ch1_intro_htlcs::solutions::{fee_for_weight, InputSatisfaction};
+use crate::internal::messages::{
+  TxAddInput, TxAddOutput, TxComplete, TxRemoveInput, TxRemoveOutput,
+};
+use bitcoin::locktime::absolute::LockTime;
+use bitcoin::script::ScriptBuf;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, XOnlyPublicKey};
+use bitcoin::sighash::{Prevouts, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::hashes::Hash;
+use bitcoin::{ecdsa, taproot};
+use bitcoin::{Amount, CompressedPublicKey, OutPoint, Sequence, Transaction, TxIn, TxOut, Txid, WScriptHash, Witness};
+use lightning::ln::types::ChannelId;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// The most inputs or outputs a constructed transaction may have.
+pub const MAX_INPUTS_OUTPUTS_COUNT: usize = 252;
+
+/// The most `tx_add_input` (and, separately, `tx_add_output`) messages we
+/// accept from a peer in one negotiation.
+pub const MAX_RECEIVED_TX_ADD_COUNT: u16 = 4096;
+
+/// Weight of the fields every transaction has: version, locktime, the input
+/// and output counts, and the segwit marker and flag. The initiator pays it.
+const COMMON_FIELDS_WEIGHT: u64 = (4 + 4 + 1 + 1) * 4 + 2;
+
+/// Weight of an input without its witness: outpoint, empty scriptSig and
+/// sequence.
+const INPUT_BASE_WEIGHT: u64 = (32 + 4 + 1 + 4) * 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+  Initiator,
+  NonInitiator,
+}
+
+impl Role {
+  fn serial_id_parity(&self) -> u64 {
+    match self {
+      Role::Initiator => 0,
+      Role::NonInitiator => 1,
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InteractiveTxError {
+  /// The serial ID has the other peer's parity.
+  WrongSerialIdParity(u64),
+  DuplicateSerialId(u64),
+  /// A removal named a serial ID that was never added, or added by the other
+  /// peer.
+  UnknownSerialId(u64),
+  /// The same outpoint was added twice.
+  DuplicateInput(OutPoint),
+  /// `prevtx` has no output `prevtx_out`.
+  PrevoutMissing,
+  /// Inputs must spend segwit outputs whose witness we can estimate, so that
+  /// the txid cannot change and fees can be checked.
+  UnsupportedInput(ScriptBuf),
+  /// Sequences must signal replaceability.
+  InvalidSequence(u32),
+  DustOutput { serial_id: u64, sats: u64 },
+  NonStandardOutput(ScriptBuf),
+  TooManyAdds,
+  TooManyInputsOrOutputs,
+  /// Messages arrived after both peers sent `tx_complete`.
+  NegotiationComplete,
+  /// The transaction is not complete yet.
+  NegotiationInProgress,
+  /// The funding output is missing or does not hold both contributions.
+  SharedOutputMismatch,
+  /// A side's inputs do not pay for its contribution and its share of the fee.
+  InsufficientFees { local: bool },
+  /// `tx_signatures` did not carry one witness per input of its sender.
+  WitnessCountMismatch { expected: usize, actual: usize },
+  /// The witness for input `input_index` does not spend its prevout.
+  InvalidWitness { input_index: usize },
+  /// `tx_signatures` named a different transaction than the one negotiated.
+  TxHashMismatch { expected: Txid, actual: Txid },
+  /// The message came from a peer we have no channel with.
+  UnknownPeer(PublicKey),
+}
+
+impl fmt::Display for InteractiveTxError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      InteractiveTxError::WrongSerialIdParity(serial_id) => write!(f, "serial id {} has the wrong parity", serial_id),
+      InteractiveTxError::DuplicateSerialId(serial_id) => write!(f, "serial id {} is already used", serial_id),
+      InteractiveTxError::UnknownSerialId(serial_id) => write!(f, "serial id {} was not added by the sender", serial_id),
+      InteractiveTxError::DuplicateInput(outpoint) => write!(f, "input {} is already added", outpoint),
+      InteractiveTxError::PrevoutMissing => write!(f, "prevtx has no such output"),
+      InteractiveTxError::UnsupportedInput(script) => write!(f, "cannot spend {} interactively", script),
+      InteractiveTxError::InvalidSequence(sequence) => write!(f, "sequence {:#x} is not replaceable", sequence),
+      InteractiveTxError::DustOutput { serial_id, sats } => write!(f, "output {} of {} sats is dust", serial_id, sats),
+      InteractiveTxError::NonStandardOutput(script) => write!(f, "output script {} is not standard", script),
+      InteractiveTxError::TooManyAdds => write!(f, "too many tx_add messages"),
+      InteractiveTxError::TooManyInputsOrOutputs => write!(f, "too many inputs or outputs"),
+      InteractiveTxError::NegotiationComplete => write!(f, "negotiation is already complete"),
+      InteractiveTxError::NegotiationInProgress => write!(f, "negotiation is still in progress"),
+      InteractiveTxError::SharedOutputMismatch => write!(f, "funding output does not match the contributions"),
+      InteractiveTxError::InsufficientFees { local: true } => write!(f, "our inputs do not cover our fee"),
+      InteractiveTxError::InsufficientFees { local: false } => write!(f, "their inputs do not cover their fee"),
+      InteractiveTxError::WitnessCountMismatch { expected, actual } => {
+        write!(f, "expected {} witnesses, got {}", expected, actual)
+      }
+      InteractiveTxError::InvalidWitness { input_index } => write!(f, "witness for input {} is invalid", input_index),
+      InteractiveTxError::TxHashMismatch { expected, actual } => {
+        write!(f, "tx_signatures is for {}, not the negotiated {}", actual, expected)
+      }
+      InteractiveTxError::UnknownPeer(node_id) => write!(f, "no channel with peer {}", node_id),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InteractiveInput {
+  pub prevtx: Transaction,
+  pub prevtx_out: u32,
+  pub sequence: u32,
+}
+
+impl InteractiveInput {
+  pub fn outpoint(&self) -> OutPoint {
+    OutPoint { txid: self.prevtx.compute_txid(), vout: self.prevtx_out }
+  }
+
+  pub fn prevout(&self) -> &TxOut {
+    &self.prevtx.output[self.prevtx_out as usize]
+  }
+}
+
+fn input_satisfaction(script_pubkey: &ScriptBuf) -> Option<InputSatisfaction> {
+  if script_pubkey.is_p2wpkh() {
+    Some(InputSatisfaction::P2wpkh)
+  } else if script_pubkey.is_p2tr() {
+    Some(InputSatisfaction::TaprootKeySpend)
+  } else {
+    None
+  }
+}
+
+/// Whether `witness` is a valid key-path spend of `prevouts[index]` by `tx`.
+/// Only the P2WPKH and taproot inputs `input_satisfaction` allows can pass.
+fn verify_witness(tx: &Transaction, prevouts: &[TxOut], index: usize, witness: &Witness) -> bool {
+  let secp = Secp256k1::verification_only();
+  let prevout = &prevouts[index];
+  let mut cache = SighashCache::new(tx);
+  if prevout.script_pubkey.is_p2wpkh() {
+    if witness.len() != 2 {
+      return false;
+    }
+    let (signature, pubkey) = match (ecdsa::Signature::from_slice(&witness[0]), CompressedPublicKey::from_slice(&witness[1])) {
+      (Ok(signature), Ok(pubkey)) => (signature, pubkey),
+      _ => return false,
+    };
+    if ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash()) != prevout.script_pubkey {
+      return false;
+    }
+    let sighash = match cache.p2wpkh_signature_hash(index, &prevout.script_pubkey, prevout.value, signature.sighash_type) {
+      Ok(sighash) => sighash,
+      Err(_) => return false,
+    };
+    secp.verify_ecdsa(&Message::from_digest(sighash.to_byte_array()), &signature.signature, &pubkey.0).is_ok()
+  } else if prevout.script_pubkey.is_p2tr() {
+    if witness.len() != 1 {
+      return false;
+    }
+    let (signature, output_key) = match (
+      taproot::Signature::from_slice(&witness[0]),
+      XOnlyPublicKey::from_slice(&prevout.script_pubkey.as_bytes()[2..]),
+    ) {
+      (Ok(signature), Ok(output_key)) => (signature, output_key),
+      _ => return false,
+    };
+    let sighash = match cache.taproot_key_spend_signature_hash(index, &Prevouts::All(prevouts), signature.sighash_type) {
+      Ok(sighash) => sighash,
+      Err(_) => return false,
+    };
+    secp.verify_schnorr(&signature.signature, &Message::from_digest(sighash.to_byte_array()), &output_key).is_ok()
+  } else {
+    false
+  }
+}
+
+fn output_weight(txout: &TxOut) -> u64 {
+  (txout.size() * 4) as u64
+}
+
+/// Weight one side adds to the transaction: the inputs spending `spent`
+/// once signed, `outputs`, and the common fields for the initiator.
+pub fn contribution_weight(initiator: bool, spent: &[TxOut], outputs: &[TxOut]) -> u64 {
+  let mut weight = if initiator { COMMON_FIELDS_WEIGHT } else { 0 };
+  for prevout in spent {
+    let satisfaction = input_satisfaction(&prevout.script_pubkey).expect("inputs are checked when added");
+    weight += INPUT_BASE_WEIGHT + satisfaction.witness_weight();
+  }
+  weight + outputs.iter().map(output_weight).sum::<u64>()
+}
+
+/// What we put into a dual-funded transaction: the inputs we add and, when
+/// it is worth having, a change output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FundingContribution {
+  pub inputs: Vec<(Transaction, u32)>,
+  pub change: Option<TxOut>,
+}
+
+/// Picks the change for funding `funding_satoshis` from `inputs` at
+/// `feerate_per_kw`. The initiator also pays for the shared output, which is
+/// always a P2WSH output of the same size.
+pub fn plan_contribution(
+  role: Role,
+  feerate_per_kw: u32,
+  funding_satoshis: u64,
+  inputs: Vec<(Transaction, u32)>,
+  change_script: ScriptBuf,
+) -> Result<FundingContribution, InteractiveTxError> {
+  let mut spent = Vec::new();
+  for (prevtx, prevtx_out) in inputs.iter() {
+    let prevout = prevtx.output.get(*prevtx_out as usize).ok_or(InteractiveTxError::PrevoutMissing)?;
+    if input_satisfaction(&prevout.script_pubkey).is_none() {
+      return Err(InteractiveTxError::UnsupportedInput(prevout.script_pubkey.clone()));
+    }
+    spent.push(prevout.clone());
+  }
+  let input_value: u64 = spent.iter().map(|prevout| prevout.value.to_sat()).sum();
+
+  let initiator = role == Role::Initiator;
+  let mut outputs = Vec::new();
+  if initiator {
+    outputs.push(TxOut { value: Amount::from_sat(funding_satoshis), script_pubkey: ScriptBuf::new_p2wsh(&WScriptHash::all_zeros()) });
+  }
+  let fee_without_change = fee_for_weight(feerate_per_kw, contribution_weight(initiator, &spent, &outputs));
+  let available = input_value
+    .checked_sub(funding_satoshis + fee_without_change)
+    .ok_or(InteractiveTxError::InsufficientFees { local: true })?;
+
+  let change = TxOut { value: Amount::ZERO, script_pubkey: change_script };
+  let change_fee = fee_for_weight(feerate_per_kw, output_weight(&change));
+  let change = match available.checked_sub(change_fee) {
+    Some(value) if Amount::from_sat(value) >= change.script_pubkey.minimal_non_dust() => {
+      Some(TxOut { value: Amount::from_sat(value), ..change })
+    }
+    // too little left over for change, so it goes to the fee
+    _ => None,
+  };
+  Ok(FundingContribution { inputs, change })
+}
+
+/// One side's view of an interactive transaction construction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InteractiveTxConstructor {
+  channel_id: ChannelId,
+  role: Role,
+  feerate_per_kw: u32,
+  locktime: LockTime,
+  /// The funding output both sides pay into.
+  shared_output_script: ScriptBuf,
+  local_funding_satoshis: u64,
+  remote_funding_satoshis: u64,
+  inputs: BTreeMap<u64, InteractiveInput>,
+  outputs: BTreeMap<u64, TxOut>,
+  next_serial_id: u64,
+  received_input_count: u16,
+  received_output_count: u16,
+  sent_tx_complete: bool,
+  received_tx_complete: bool,
+}
+
+impl InteractiveTxConstructor {
+  pub fn new(
+    channel_id: ChannelId,
+    role: Role,
+    feerate_per_kw: u32,
+    locktime: LockTime,
+    shared_output_script: ScriptBuf,
+    local_funding_satoshis: u64,
+    remote_funding_satoshis: u64,
+  ) -> Self {
+    Self {
+      channel_id,
+      role,
+      feerate_per_kw,
+      locktime,
+      shared_output_script,
+      local_funding_satoshis,
+      remote_funding_satoshis,
+      inputs: BTreeMap::new(),
+      outputs: BTreeMap::new(),
+      next_serial_id: role.serial_id_parity(),
+      received_input_count: 0,
+      received_output_count: 0,
+      sent_tx_complete: false,
+      received_tx_complete: false,
+    }
+  }
+
+  pub fn channel_id(&self) -> ChannelId {
+    self.channel_id
+  }
+
+  pub fn role(&self) -> Role {
+    self.role
+  }
+
+  /// Both peers sent `tx_complete` since the last change.
+  pub fn is_complete(&self) -> bool {
+    self.sent_tx_complete && self.received_tx_complete
+  }
+
+  fn is_local(&self, serial_id: u64) -> bool {
+    serial_id % 2 == self.role.serial_id_parity()
+  }
+
+  fn ensure_negotiating(&self) -> Result<(), InteractiveTxError> {
+    if self.is_complete() {
+      return Err(InteractiveTxError::NegotiationComplete);
+    }
+    Ok(())
+  }
+
+  /// Any change means both peers have to send `tx_complete` again.
+  fn changed(&mut self) {
+    self.sent_tx_complete = false;
+    self.received_tx_complete = false;
+  }
+
+  fn take_serial_id(&mut self) -> u64 {
+    let serial_id = self.next_serial_id;
+    self.next_serial_id += 2;
+    serial_id
+  }
+
+  fn check_input(&self, serial_id: u64, input: &InteractiveInput) -> Result<(), InteractiveTxError> {
+    if self.inputs.contains_key(&serial_id) {
+      return Err(InteractiveTxError::DuplicateSerialId(serial_id));
+    }
+    let prevout = input
+      .prevtx
+      .output
+      .get(input.prevtx_out as usize)
+      .ok_or(InteractiveTxError::PrevoutMissing)?;
+    if input_satisfaction(&prevout.script_pubkey).is_none() {
+      return Err(InteractiveTxError::UnsupportedInput(prevout.script_pubkey.clone()));
+    }
+    if input.sequence >= Sequence::ENABLE_LOCKTIME_NO_RBF.0 {
+      return Err(InteractiveTxError::InvalidSequence(input.sequence));
+    }
+    let outpoint = input.outpoint();
+    if self.inputs.values().any(|existing| existing.outpoint() == outpoint) {
+      return Err(InteractiveTxError::DuplicateInput(outpoint));
+    }
+    if self.inputs.len() >= MAX_INPUTS_OUTPUTS_COUNT {
+      return Err(InteractiveTxError::TooManyInputsOrOutputs);
+    }
+    Ok(())
+  }
+
+  fn check_output(&self, serial_id: u64, output: &TxOut) -> Result<(), InteractiveTxError> {
+    if self.outputs.contains_key(&serial_id) {
+      return Err(InteractiveTxError::DuplicateSerialId(serial_id));
+    }
+    if !output.script_pubkey.is_witness_program() {
+      return Err(InteractiveTxError::NonStandardOutput(output.script_pubkey.clone()));
+    }
+    if output.value < output.script_pubkey.minimal_non_dust() {
+      return Err(InteractiveTxError::DustOutput { serial_id, sats: output.value.to_sat() });
+    }
+    if self.outputs.len() >= MAX_INPUTS_OUTPUTS_COUNT {
+      return Err(InteractiveTxError::TooManyInputsOrOutputs);
+    }
+    Ok(())
+  }
+
+  /// Adds one of our inputs, spending `prevtx`'s output `prevtx_out`.
+  pub fn add_local_input(&mut self, prevtx: Transaction, prevtx_out: u32) -> Result<TxAddInput, InteractiveTxError> {
+    self.ensure_negotiating()?;
+    let input = InteractiveInput { prevtx, prevtx_out, sequence: Sequence::ENABLE_RBF_NO_LOCKTIME.0 };
+    let serial_id = self.next_serial_id;
+    self.check_input(serial_id, &input)?;
+    self.take_serial_id();
+
+    let msg = TxAddInput {
+      channel_id: self.channel_id,
+      serial_id,
+      prevtx: input.prevtx.clone(),
+      prevtx_out: input.prevtx_out,
+      sequence: input.sequence,
+    };
+    self.inputs.insert(serial_id, input);
+    self.changed();
+    Ok(msg)
+  }
+
+  /// Adds one of our outputs. The initiator adds the shared funding output
+  /// this way too.
+  pub fn add_local_output(&mut self, sats: u64, script: ScriptBuf) -> Result<TxAddOutput, InteractiveTxError> {
+    self.ensure_negotiating()?;
+    let output = TxOut { value: Amount::from_sat(sats), script_pubkey: script };
+    let serial_id = self.next_serial_id;
+    self.check_output(serial_id, &output)?;
+    self.take_serial_id();
+
+    let msg = TxAddOutput { channel_id: self.channel_id, serial_id, sats, script: output.script_pubkey.clone() };
+    self.outputs.insert(serial_id, output);
+    self.changed();
+    Ok(msg)
+  }
+
+  pub fn remove_local_input(&mut self, serial_id: u64) -> Result<TxRemoveInput, InteractiveTxError> {
+    self.ensure_negotiating()?;
+    if !self.is_local(serial_id) || self.inputs.remove(&serial_id).is_none() {
+      return Err(InteractiveTxError::UnknownSerialId(serial_id));
+    }
+    self.changed();
+    Ok(TxRemoveInput { channel_id: self.channel_id, serial_id })
+  }
+
+  pub fn remove_local_output(&mut self, serial_id: u64) -> Result<TxRemoveOutput, InteractiveTxError> {
+    self.ensure_negotiating()?;
+    if !self.is_local(serial_id) || self.outputs.remove(&serial_id).is_none() {
+      return Err(InteractiveTxError::UnknownSerialId(serial_id));
+    }
+    self.changed();
+    Ok(TxRemoveOutput { channel_id: self.channel_id, serial_id })
+  }
+
+  /// We have nothing more to add.
+  pub fn local_tx_complete(&mut self) -> Result<TxComplete, InteractiveTxError> {
+    self.ensure_negotiating()?;
+    self.sent_tx_complete = true;
+    Ok(TxComplete { channel_id: self.channel_id })
+  }
+
+  fn check_remote_serial_id(&self, serial_id: u64) -> Result<(), InteractiveTxError> {
+    if self.is_local(serial_id) {
+      return Err(InteractiveTxError::WrongSerialIdParity(serial_id));
+    }
+    Ok(())
+  }
+
+  pub fn handle_tx_add_input(&mut self, msg: &TxAddInput) -> Result<(), InteractiveTxError> {
+    self.ensure_negotiating()?;
+    self.check_remote_serial_id(msg.serial_id)?;
+    if self.received_input_count >= MAX_RECEIVED_TX_ADD_COUNT {
+      return Err(InteractiveTxError::TooManyAdds);
+    }
+    let input = InteractiveInput { prevtx: msg.prevtx.clone(), prevtx_out: msg.prevtx_out, sequence: msg.sequence };
+    self.check_input(msg.serial_id, &input)?;
+
+    self.received_input_count += 1;
+    self.inputs.insert(msg.serial_id, input);
+    self.changed();
+    Ok(())
+  }
+
+  pub fn handle_tx_add_output(&mut self, msg: &TxAddOutput) -> Result<(), InteractiveTxError> {
+    self.ensure_negotiating()?;
+    self.check_remote_serial_id(msg.serial_id)?;
+    if self.received_output_count >= MAX_RECEIVED_TX_ADD_COUNT {
+      return Err(InteractiveTxError::TooManyAdds);
+    }
+    let output = TxOut { value: Amount::from_sat(msg.sats), script_pubkey: msg.script.clone() };
+    self.check_output(msg.serial_id, &output)?;
+
+    self.received_output_count += 1;
+    self.outputs.insert(msg.serial_id, output);
+    self.changed();
+    Ok(())
+  }
+
+  pub fn handle_tx_remove_input(&mut self, msg: &TxRemoveInput) -> Result<(), InteractiveTxError> {
+    self.ensure_negotiating()?;
+    self.check_remote_serial_id(msg.serial_id)?;
+    self.inputs.remove(&msg.serial_id).ok_or(InteractiveTxError::UnknownSerialId(msg.serial_id))?;
+    self.changed();
+    Ok(())
+  }
+
+  pub fn handle_tx_remove_output(&mut self, msg: &TxRemoveOutput) -> Result<(), InteractiveTxError> {
+    self.ensure_negotiating()?;
+    self.check_remote_serial_id(msg.serial_id)?;
+    self.outputs.remove(&msg.serial_id).ok_or(InteractiveTxError::UnknownSerialId(msg.serial_id))?;
+    self.changed();
+    Ok(())
+  }
+
+  /// Returns whether negotiation is now complete. If it is not, we still owe
+  /// the peer a message (at least our own `tx_complete`).
+  pub fn handle_tx_complete(&mut self, msg: &TxComplete) -> Result<bool, InteractiveTxError> {
+    self.ensure_negotiating()?;
+    self.received_tx_complete = true;
+    Ok(self.is_complete())
+  }
+
+  fn is_shared_output(&self, txout: &TxOut) -> bool {
+    txout.script_pubkey == self.shared_output_script
+  }
+
+  /// Sum of the inputs added by us (`local`) or by the peer.
+  pub fn input_value(&self, local: bool) -> u64 {
+    self
+      .inputs
+      .iter()
+      .filter(|(serial_id, _)| self.is_local(**serial_id) == local)
+      .map(|(_, input)| input.prevout().value.to_sat())
+      .sum()
+  }
+
+  /// The fee a side's inputs must pay for: the weight it added, plus the
+  /// common fields and the shared output for the initiator.
+  pub fn required_fee(&self, local: bool) -> u64 {
+    let added_by = |serial_id: u64| self.is_local(serial_id) == local;
+    let initiator = (self.role == Role::Initiator) == local;
+
+    let spent: Vec<TxOut> =
+      self.inputs.iter().filter(|(id, _)| added_by(**id)).map(|(_, input)| input.prevout().clone()).collect();
+    let outputs: Vec<TxOut> = self
+      .outputs
+      .iter()
+      .filter(|(id, output)| if self.is_shared_output(output) { initiator } else { added_by(**id) })
+      .map(|(_, output)| output.clone())
+      .collect();
+    fee_for_weight(self.feerate_per_kw, contribution_weight(initiator, &spent, &outputs))
+  }
+
+  /// Checks the completed transaction: one funding output holding both
+  /// contributions, and each side paying its own fee.
+  pub fn validate(&self) -> Result<(), InteractiveTxError> {
+    if !self.is_complete() {
+      return Err(InteractiveTxError::NegotiationInProgress);
+    }
+    let shared: Vec<&TxOut> = self.outputs.values().filter(|output| self.is_shared_output(output)).collect();
+    if shared.len() != 1 || shared[0].value.to_sat() != self.local_funding_satoshis + self.remote_funding_satoshis {
+      return Err(InteractiveTxError::SharedOutputMismatch);
+    }
+
+    for local in [true, false] {
+      let funding = if local { self.local_funding_satoshis } else { self.remote_funding_satoshis };
+      let other_outputs: u64 = self
+        .outputs
+        .iter()
+        .filter(|(serial_id, output)| self.is_local(**serial_id) == local && !self.is_shared_output(output))
+        .map(|(_, output)| output.value.to_sat())
+        .sum();
+      let spent = funding + other_outputs + self.required_fee(local);
+      if self.input_value(local) < spent {
+        return Err(InteractiveTxError::InsufficientFees { local });
+      }
+    }
+    Ok(())
+  }
+
+  /// The unsigned transaction, inputs and outputs in serial ID order.
+  pub fn build_transaction(&self) -> Result<Transaction, InteractiveTxError> {
+    self.validate()?;
+    let input = self
+      .inputs
+      .values()
+      .map(|input| TxIn {
+        previous_output: input.outpoint(),
+        sequence: Sequence(input.sequence),
+        ..Default::default()
+      })
+      .collect();
+    Ok(Transaction { version: Version::TWO, lock_time: self.locktime, input, output: self.outputs.values().cloned().collect() })
+  }
+
+  /// Index of the funding output in the built transaction.
+  pub fn shared_output_index(&self) -> Option<u32> {
+    self.outputs.values().position(|output| self.is_shared_output(output)).map(|index| index as u32)
+  }
+
+  /// The outputs spent by the built transaction, in input order, as needed
+  /// to sign it.
+  pub fn prevouts(&self) -> Vec<TxOut> {
+    self.inputs.values().map(|input| input.prevout().clone()).collect()
+  }
+
+  /// Indices, in the built transaction, of the inputs we added and sign.
+  pub fn local_input_indices(&self) -> Vec<usize> {
+    self.inputs.keys().enumerate().filter(|(_, serial_id)| self.is_local(**serial_id)).map(|(index, _)| index).collect()
+  }
+
+  /// Number of inputs added by us (`local`) or by the peer, i.e. how many
+  /// witnesses that side's `tx_signatures` carries.
+  pub fn input_count(&self, local: bool) -> usize {
+    self.inputs.keys().filter(|serial_id| self.is_local(**serial_id) == local).count()
+  }
+
+  /// Whether we send `tx_signatures` first: the side that contributed less
+  /// to the inputs does, or the one with the lower node ID on a tie. This
+  /// keeps the side with more at stake from signing blind.
+  pub fn holder_sends_tx_signatures_first(&self, our_node_id: &PublicKey, their_node_id: &PublicKey) -> bool {
+    let (ours, theirs) = (self.input_value(true), self.input_value(false));
+    if ours == theirs {
+      our_node_id.serialize() < their_node_id.serialize()
+    } else {
+      ours < theirs
+    }
+  }
+
+  /// Checks that the peer's witnesses, in serial ID order, validly spend
+  /// their inputs of the built transaction.
+  pub fn verify_remote_witnesses(&self, witnesses: &[Witness]) -> Result<(), InteractiveTxError> {
+    let tx = self.build_transaction()?;
+    let expected = self.input_count(false);
+    if witnesses.len() != expected {
+      return Err(InteractiveTxError::WitnessCountMismatch { expected, actual: witnesses.len() });
+    }
+    let prevouts = self.prevouts();
+    let remote_indices = self.inputs.keys().enumerate().filter(|(_, serial_id)| !self.is_local(**serial_id)).map(|(index, _)| index);
+    for (input_index, witness) in remote_indices.zip(witnesses) {
+      if !verify_witness(&tx, &prevouts, input_index, witness) {
+        return Err(InteractiveTxError::InvalidWitness { input_index });
+      }
+    }
+    Ok(())
+  }
+
+  /// The signed transaction, placing each side's witnesses on its inputs in
+  /// serial ID order.
+  pub fn finalize(
+    &self,
+    local_witnesses: Vec<Witness>,
+    remote_witnesses: Vec<Witness>,
+  ) -> Result<Transaction, InteractiveTxError> {
+    let mut tx = self.build_transaction()?;
+    for (witnesses, local) in [(&local_witnesses, true), (&remote_witnesses, false)] {
+      let expected = self.input_count(local);
+      if witnesses.len() != expected {
+        return Err(InteractiveTxError::WitnessCountMismatch { expected, actual: witnesses.len() });
+      }
+    }
+
+    let mut local_witnesses = local_witnesses.into_iter();
+    let mut remote_witnesses = remote_witnesses.into_iter();
+    for (txin, serial_id) in tx.input.iter_mut().zip(self.inputs.keys()) {
+      let witness = if self.is_local(*serial_id) { local_witnesses.next() } else { remote_witnesses.next() };
+      txin.witness = witness.expect("counted above");
+    }
+    Ok(tx)
+  }
+}
@@ -1,10 +1,12 @@
 pub mod bitcoin_client;
 pub mod bitcoin_client_solutions;
 pub mod channel_exercises;
+pub mod channel_id;
 pub mod events;
 pub mod exercises;
 pub mod fee_estimator_exercise;
 pub mod helpers;
+pub mod interactive_tx;
 //pub mod payment_exercise;
 pub mod peer_listener_exercise;
 pub mod persist_exercise;
@@ -4,6 +4,10 @@ use std::sync::{Arc, Mutex};
 use tokio::net::TcpListener;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use rand::Rng;
+use std::str::FromStr;
+use bitcoin::Txid;
+use lightning::chain::transaction::OutPoint;
+use crate::ch2_setup::channel_id::v1_channel_id;
 
 /// Represents a Lightning channel's state
 #[derive(Debug, Clone)]
@@ -148,11 +152,11 @@ Available Commands:
             let funding_tx = parts[2].to_string();
             let signature = parts[3].to_string();
 
-            if is_valid_txid(&funding_tx) != true {
-                return format!(
-                    "ERROR: MUST provide funding output in following format:  TxID:OutputIndex\n\n"
-                );
-            }
+            let funding_outpoint = match parse_funding_outpoint(&funding_tx) {
+                Some(funding_outpoint) => funding_outpoint,
+                None => return
+                    "ERROR: MUST provide funding output in following format:  TxID:OutputIndex\n\n".to_string(),
+            };
             
             let mut channels_lock = channels.lock().unwrap();
             if let Some(channel) = channels_lock.get_mut(&channel_id) {
@@ -163,7 +167,7 @@ Available Commands:
                     );
                 }
                 channel.state = "funding_signed".to_string();
-                channel.channel_id = Some(generate_random_channel_id());
+                channel.channel_id = Some(v1_channel_id(&funding_outpoint).to_string());
                 return format!("FUNDING_SIGNED: channel_id={}, signature=[avb1adx4]\n\n", channel.channel_id.as_ref().unwrap());
             }
             "ERROR: Channel not found\n".to_string()
@@ -208,31 +212,11 @@ Available Commands:
     }
 }
 
-fn is_valid_txid(s: &str) -> bool {
-    let parts: Vec<&str> = s.split(':').collect();
-    if parts.len() != 2 { // Must have exactly two parts (before and after ":")
-        return false;
-    }
-    let before_colon = parts[0];
-    let after_colon = parts[1];
-
-    // Before colon: any text (non-empty in this case, adjust as needed)
-    if before_colon.is_empty() {
-        return false;
-    }
-
-    // After colon: must be a valid number (integer)
-    after_colon.parse::<i64>().is_ok()
+/// Parses a funding output given as `TxID:OutputIndex`.
+fn parse_funding_outpoint(s: &str) -> Option<OutPoint> {
+    let (txid, index) = s.split_once(':')?;
+    Some(OutPoint {
+        txid: Txid::from_str(txid).ok()?,
+        index: index.parse().ok()?,
+    })
 }
-
-// Generate a random 3-letter channel ID
-fn generate_random_channel_id() -> String {
-    let letters = "abcdefghijklmnopqrstuvwxyz";
-    let mut rng = rand::thread_rng();
-    let mut id = String::with_capacity(3);
-    for _ in 0..3 {
-        let idx = rng.gen_range(0, letters.len()); // Two arguments: low, high
-        id.push(letters.chars().nth(idx).unwrap());
-    }
-    id
-}
\ No newline at end of file
@@ -56,7 +56,9 @@ use crate::internal::helper::{
 };
 use crate::ch2_setup::channel_exercises_v2::{ChannelMonitor, MockBroadcaster, MockFileStore,
                                             ChainMonitor, Header as HeaderExercise, TransactionData,
-                                            ChannelManager, ChannelMonitorUpdate, Preimage};
+                                            ChannelManager, ChannelMonitorUpdate, Preimage,
+                                            CounterpartyCommitmentSecrets, generate_from_seed,
+                                            INITIAL_COMMITMENT_NUMBER};
 use lightning::chain::transaction::OutPoint;
 use bitcoin::Transaction;
 use bitcoin::consensus::{deserialize, serialize};
@@ -70,6 +72,17 @@ use internal::messages::{OpenChannel, AcceptChannel,
     FundingCreated, FundingSigned,
     ChannelReady};
 use bitcoin::secp256k1::{ecdsa::Signature};
+use crate::ch2_setup::channel_id::{v1_channel_id, v2_channel_id, v2_temporary_channel_id};
+use crate::ch2_setup::interactive_tx::{
+    plan_contribution, InteractiveTxConstructor, InteractiveTxError, Role,
+};
+use crate::internal::events::{Event, MessageSendEvent};
+use crate::internal::messages::{OpenChannelV2, TxAddInput, TxRemoveInput, TxSignatures};
+use crate::internal::helper::{build_output, p2wpkh_output_script};
+use bitcoin::locktime::absolute::LockTime;
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{Amount, TxIn, TxOut, Witness};
 use bitcoin::secp256k1::ffi::Signature as FFISignature;
 
 #[tokio::test]
@@ -719,10 +732,6 @@ async fn test_funding_signed() {
 
     let pubkey = pubkey_from_private_key(&[0x01; 32]);
     let channel_balance = 100_000_000;
-    let msg = FundingSigned{
-        channel_id: ChannelId::new_zero(),
-        signature: Signature::from(unsafe { FFISignature::new() })
-    };
 
     let mut channel_manager = ChannelManager { 
         chain_monitor: chain_monitor,
@@ -733,8 +742,14 @@ async fn test_funding_signed() {
     };
 
     channel_manager.create_channel(pubkey, channel_balance);
+    let funding_tx = generate_funding_transaction(&mut channel_manager, &pubkey);
 
-    channel_manager.handle_funding_signed(&pubkey, msg);
+    // the counterparty signs under the channel ID derived from the funding outpoint
+    let msg = FundingSigned{
+        channel_id: v1_channel_id(&OutPoint { txid: funding_tx.compute_txid(), index: 0 }),
+        signature: Signature::from(unsafe { FFISignature::new() })
+    };
+    channel_manager.handle_funding_signed(&pubkey, msg).unwrap();
 
     println!("channel_manager.chain_monitor.monitors.len(): {:?}\n\n", channel_manager.chain_monitor.monitors.len());
 
@@ -760,4 +775,581 @@ async fn test_read_event() {
 
     peer_manager.read_event(socket_descriptor, data_open_channel)
 
-}
\ No newline at end of file
+}
+
+fn secret_from_hex(hex: &str) -> [u8; 32] {
+    <[u8; 32]>::from_hex(hex).unwrap()
+}
+
+#[test]
+fn test_generate_from_seed() {
+    // BOLT 3 Appendix D: generation tests
+    let vectors = [
+        ([0x00; 32], INITIAL_COMMITMENT_NUMBER, "02a40c85b6f28da08dfdbe0926c53fab2de6d28c10301f8f7c4073d5e42e3148"),
+        ([0xFF; 32], INITIAL_COMMITMENT_NUMBER, "7cc854b54e3e0dcdb010d7a3fee464a9687be6e8db3be6854c475621e007a5dc"),
+        ([0xFF; 32], 0xaaaaaaaaaaa, "56f4008fb007ca9acf0e15b054d5c9fd12ee06cea347914ddbaed70d1c13a528"),
+        ([0xFF; 32], 0x555555555555, "9015daaeb06dba4ccc05b91b2f73bd54405f2be9f217fbacd3c5ac2e62327d31"),
+        ([0x01; 32], 1, "915c75942a26bb3a433a8ce2cb0427c29ec6c1775cfc78328b57f6ba7bfeaa9c"),
+    ];
+
+    for (seed, index, expected) in vectors {
+        assert_eq!(generate_from_seed(&seed, index), secret_from_hex(expected));
+        assert_eq!(
+            generate_from_seed(&seed, index),
+            lightning::ln::chan_utils::build_commitment_secret(&seed, index)
+        );
+    }
+}
+
+/// Feeds `secrets` to a fresh `CounterpartyCommitmentSecrets` starting at the
+/// first commitment index. Every secret is expected to be accepted, except the
+/// last one if `last_is_invalid` is set. After each accepted secret, every
+/// secret seen so far must be retrievable and nothing older than that.
+fn check_secret_storage(secrets: &[&str], last_is_invalid: bool) {
+    let mut storage = CounterpartyCommitmentSecrets::new();
+    let mut idx = INITIAL_COMMITMENT_NUMBER;
+
+    for (i, secret) in secrets.iter().enumerate() {
+        let secret = secret_from_hex(secret);
+        if last_is_invalid && i == secrets.len() - 1 {
+            let before = storage.clone();
+            assert!(storage.provide_secret(idx, secret).is_err());
+            assert_eq!(storage, before);
+            return;
+        }
+        storage.provide_secret(idx, secret).unwrap();
+
+        for (j, seen) in secrets[..=i].iter().enumerate() {
+            assert_eq!(
+                storage.get_secret(INITIAL_COMMITMENT_NUMBER - j as u64),
+                Some(secret_from_hex(seen))
+            );
+        }
+        assert_eq!(storage.get_min_seen_secret(), idx);
+        assert_eq!(storage.len(), i + 1);
+        assert!(storage.get_secret(idx - 1).is_none());
+        idx -= 1;
+    }
+}
+
+const SHACHAIN_CORRECT: [&str; 8] = [
+    "7cc854b54e3e0dcdb010d7a3fee464a9687be6e8db3be6854c475621e007a5dc",
+    "c7518c8ae4660ed02894df8976fa1a3659c1a8b4b5bec0c4b872abeba4cb8964",
+    "2273e227a5b7449b6e70f1fb4652864038b1cbf9cd7c043a7d6456b7fc275ad8",
+    "27cddaa5624534cb6cb9d7da077cf2b22ab21e9b506fd4998a51d54502e99116",
+    "c65716add7aa98ba7acb236352d665cab17345fe45b55fb879ff80e6bd0c41dd",
+    "969660042a28f32d9be17344e09374b379962d03db1574df5a8a5a47e19ce3f2",
+    "a5a64476122ca0925fb344bdc1854c1c0a59fc614298e50a33e331980a220f32",
+    "05cde6323d949933f7f7b78776bcc1ea6d9b31447732e3802e1f7ac44b650e17",
+];
+
+#[test]
+fn test_commitment_secrets_correct_sequence() {
+    // BOLT 3 Appendix D: insert_secret correct sequence
+    check_secret_storage(&SHACHAIN_CORRECT, false);
+}
+
+#[test]
+fn test_commitment_secrets_reject_incorrect() {
+    // BOLT 3 Appendix D: insert_secret #1..#8 incorrect
+    let c = SHACHAIN_CORRECT;
+    let bad_0 = "02a40c85b6f28da08dfdbe0926c53fab2de6d28c10301f8f7c4073d5e42e3148";
+    let bad_1 = "dddc3a8d14fddf2b68fa8c7fbad2748274937479dd0f8930d5ebb4ab6bd866a3";
+    let bad_2 = "c51a18b13e8527e579ec56365482c62f180b7d5760b46e9477dae59e87ed423a";
+    let bad_3 = "ba65d7b0ef55a3ba300d4e87af29868f394f8f138d78a7011669c79b37b936f4";
+    let bad_4 = "631373ad5f9ef654bb3dade742d09504c567edd24320d2fcd68e3cc47e2ff6a6";
+    let bad_5 = "b7e76a83668bde38b373970155c868a653304308f9896692f904a23731224bb1";
+    let bad_6 = "e7971de736e01da8ed58b94c2fc216cb1dca9e326f3a96e7194fe8ea8af6c0a3";
+    let bad_7 = "a7efbc61aac46d34f77778bac22c8a20c6a46ca460addc49009bda875ec88fa4";
+
+    let cases: [Vec<&str>; 8] = [
+        vec![bad_0, c[1]],
+        vec![bad_0, bad_1, c[2], c[3]],
+        vec![c[0], c[1], bad_2, c[3]],
+        vec![bad_0, bad_1, bad_2, bad_3, c[4], c[5], c[6], c[7]],
+        vec![c[0], c[1], c[2], c[3], bad_4, c[5]],
+        vec![c[0], c[1], c[2], c[3], bad_4, bad_5, c[6], c[7]],
+        vec![c[0], c[1], c[2], c[3], c[4], c[5], bad_6, c[7]],
+        vec![c[0], c[1], c[2], c[3], c[4], c[5], c[6], bad_7],
+    ];
+
+    for case in cases.iter() {
+        check_secret_storage(case, true);
+    }
+}
+
+#[test]
+fn test_update_monitor_rejects_invalid_secret() {
+    let mut channel_monitor = ChannelMonitor::new();
+    let seed = [0xFF; 32];
+
+    for idx in (INITIAL_COMMITMENT_NUMBER - 6..=INITIAL_COMMITMENT_NUMBER).rev() {
+        let secret = generate_from_seed(&seed, idx);
+        assert!(channel_monitor
+            .update_monitor(ChannelMonitorUpdate::CommitmentSecret { secret })
+            .is_ok());
+    }
+
+    // The next index ends in three zero bits, so its secret must derive the
+    // secrets we hold for the three slots below it.
+    let bad_update = ChannelMonitorUpdate::CommitmentSecret { secret: [10; 32] };
+    assert!(channel_monitor.update_monitor(bad_update).is_err());
+    assert_eq!(7, channel_monitor.commitment_secrets.len());
+    assert_eq!(
+        channel_monitor.commitment_secrets.get_secret(INITIAL_COMMITMENT_NUMBER - 2),
+        Some(generate_from_seed(&seed, INITIAL_COMMITMENT_NUMBER - 2))
+    );
+}
+
+/// A confirmed transaction paying `amount` to the P2WPKH of `key`, to spend
+/// as a funding input.
+fn wallet_utxo(key: &[u8; 32], amount: u64) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn::default()],
+        output: vec![build_output(amount, p2wpkh_output_script(pubkey_from_private_key(key)))],
+    }
+}
+
+fn sign_wallet_inputs(tx: &Transaction, prevouts: &[TxOut], input_indices: &[usize], key: &[u8; 32]) -> Vec<Witness> {
+    let secp = Secp256k1::new();
+    let secret_key = secp256k1_private_key(key);
+    let pubkey = pubkey_from_private_key(key);
+    input_indices
+        .iter()
+        .map(|index| {
+            let sighash = SighashCache::new(tx)
+                .p2wpkh_signature_hash(*index, &prevouts[*index].script_pubkey, prevouts[*index].value, EcdsaSighashType::All)
+                .unwrap();
+            let signature = secp.sign_ecdsa(&secp256k1::Message::from_digest(sighash.to_byte_array()), &secret_key);
+            let signature = bitcoin::ecdsa::Signature::sighash_all(signature);
+            Witness::p2wpkh(&signature, &pubkey)
+        })
+        .collect()
+}
+
+#[test]
+fn test_interactive_tx_construction() {
+    let funding_script = ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::hash(b"funding"));
+    let mut initiator = InteractiveTxConstructor::new(
+        ChannelId::new_zero(), Role::Initiator, 1_000, LockTime::ZERO, funding_script.clone(), 600_000, 400_000,
+    );
+    let mut acceptor = InteractiveTxConstructor::new(
+        ChannelId::new_zero(), Role::NonInitiator, 1_000, LockTime::ZERO, funding_script.clone(), 400_000, 600_000,
+    );
+    let alice_utxo = wallet_utxo(&[0x21; 32], 700_000);
+    let bob_utxo = wallet_utxo(&[0x22; 32], 500_000);
+
+    // turns alternate, each side's serial ids carry its parity
+    let msg = initiator.add_local_output(1_000_000, funding_script.clone()).unwrap();
+    assert_eq!(msg.serial_id % 2, 0);
+    acceptor.handle_tx_add_output(&msg).unwrap();
+    let msg = acceptor.add_local_input(bob_utxo.clone(), 0).unwrap();
+    assert_eq!(msg.serial_id % 2, 1);
+    initiator.handle_tx_add_input(&msg).unwrap();
+
+    // a peer may not use our parity, reuse a serial id or spend an input twice
+    let mut bad = msg.clone();
+    bad.serial_id = 4;
+    assert_eq!(initiator.handle_tx_add_input(&bad), Err(InteractiveTxError::WrongSerialIdParity(4)));
+    assert_eq!(initiator.handle_tx_add_input(&msg), Err(InteractiveTxError::DuplicateSerialId(msg.serial_id)));
+    bad.serial_id = 7;
+    assert_eq!(
+        initiator.handle_tx_add_input(&bad),
+        Err(InteractiveTxError::DuplicateInput(bitcoin::OutPoint { txid: bob_utxo.compute_txid(), vout: 0 }))
+    );
+
+    // a spare input is added, then removed again
+    let spare = acceptor.add_local_input(wallet_utxo(&[0x22; 32], 10_000), 0).unwrap();
+    initiator.handle_tx_add_input(&spare).unwrap();
+    let remove = acceptor.remove_local_input(spare.serial_id).unwrap();
+    assert_eq!(
+        initiator.handle_tx_remove_input(&TxRemoveInput { serial_id: 0, ..remove.clone() }),
+        Err(InteractiveTxError::WrongSerialIdParity(0))
+    );
+    initiator.handle_tx_remove_input(&remove).unwrap();
+
+    let msg = initiator.add_local_input(alice_utxo.clone(), 0).unwrap();
+    acceptor.handle_tx_add_input(&msg).unwrap();
+
+    // the acceptor is done, but the initiator still owes its change
+    let complete = acceptor.local_tx_complete().unwrap();
+    assert!(!initiator.handle_tx_complete(&complete).unwrap());
+    assert_eq!(initiator.build_transaction(), Err(InteractiveTxError::NegotiationInProgress));
+    let change_script = p2wpkh_output_script(pubkey_from_private_key(&[0x21; 32]));
+    let change = initiator.add_local_output(99_000, change_script.clone()).unwrap();
+    acceptor.handle_tx_add_output(&change).unwrap();
+    assert!(!acceptor.is_complete());
+    assert!(!initiator.handle_tx_complete(&acceptor.local_tx_complete().unwrap()).unwrap());
+    assert!(acceptor.handle_tx_complete(&initiator.local_tx_complete().unwrap()).unwrap());
+    assert!(initiator.is_complete());
+    assert_eq!(acceptor.handle_tx_add_input(&msg), Err(InteractiveTxError::NegotiationComplete));
+
+    // both build the same transaction, in serial id order
+    let tx = initiator.build_transaction().unwrap();
+    assert_eq!(tx, acceptor.build_transaction().unwrap());
+    assert_eq!(tx.input[0].previous_output.txid, bob_utxo.compute_txid());
+    assert_eq!(tx.input[1].previous_output.txid, alice_utxo.compute_txid());
+    assert_eq!(tx.output[0].script_pubkey, funding_script);
+    assert_eq!(initiator.shared_output_index(), Some(0));
+
+    // each side pays for what it added, and the initiator for the rest
+    let fee = 700_000 + 500_000 - 1_000_000 - 99_000;
+    assert!(initiator.required_fee(true) + initiator.required_fee(false) <= fee);
+    assert_eq!(initiator.required_fee(true), acceptor.required_fee(false));
+
+    // the acceptor has less at stake, so it signs first
+    let alice_id = pubkey_from_private_key(&[0x01; 32]);
+    let bob_id = pubkey_from_private_key(&[0x02; 32]);
+    assert!(acceptor.holder_sends_tx_signatures_first(&bob_id, &alice_id));
+    assert!(!initiator.holder_sends_tx_signatures_first(&alice_id, &bob_id));
+
+    let prevouts = initiator.prevouts();
+    let alice_witnesses = sign_wallet_inputs(&tx, &prevouts, &initiator.local_input_indices(), &[0x21; 32]);
+    let bob_witnesses = sign_wallet_inputs(&tx, &prevouts, &acceptor.local_input_indices(), &[0x22; 32]);
+    assert_eq!(
+        initiator.finalize(alice_witnesses.clone(), vec![]),
+        Err(InteractiveTxError::WitnessCountMismatch { expected: 1, actual: 0 })
+    );
+    let signed = initiator.finalize(alice_witnesses.clone(), bob_witnesses.clone()).unwrap();
+    assert_eq!(signed, acceptor.finalize(bob_witnesses, alice_witnesses).unwrap());
+    assert_eq!(signed.compute_txid(), tx.compute_txid());
+}
+
+#[test]
+fn test_interactive_tx_rejects_underpaying_side() {
+    let funding_script = ScriptBuf::new_p2wsh(&bitcoin::WScriptHash::hash(b"funding"));
+    let mut initiator = InteractiveTxConstructor::new(
+        ChannelId::new_zero(), Role::Initiator, 1_000, LockTime::ZERO, funding_script.clone(), 600_000, 400_000,
+    );
+    let mut acceptor = InteractiveTxConstructor::new(
+        ChannelId::new_zero(), Role::NonInitiator, 1_000, LockTime::ZERO, funding_script.clone(), 400_000, 600_000,
+    );
+
+    // the acceptor's input covers its contribution but none of its fee
+    acceptor.handle_tx_add_output(&initiator.add_local_output(1_000_000, funding_script).unwrap()).unwrap();
+    initiator.handle_tx_add_input(&acceptor.add_local_input(wallet_utxo(&[0x22; 32], 400_000), 0).unwrap()).unwrap();
+    acceptor.handle_tx_add_input(&initiator.add_local_input(wallet_utxo(&[0x21; 32], 700_000), 0).unwrap()).unwrap();
+    initiator.handle_tx_complete(&acceptor.local_tx_complete().unwrap()).unwrap();
+    acceptor.handle_tx_complete(&initiator.local_tx_complete().unwrap()).unwrap();
+
+    assert_eq!(initiator.build_transaction(), Err(InteractiveTxError::InsufficientFees { local: false }));
+    assert_eq!(acceptor.build_transaction(), Err(InteractiveTxError::InsufficientFees { local: true }));
+
+    // and a contribution that cannot cover its own fee is refused up front
+    assert!(matches!(
+        plan_contribution(Role::Initiator, 1_000, 600_000, vec![(wallet_utxo(&[0x21; 32], 600_100), 0)], ScriptBuf::new()),
+        Err(InteractiveTxError::InsufficientFees { local: true })
+    ));
+}
+
+fn test_channel_manager(seed: u8) -> ChannelManager {
+    ChannelManager {
+        chain_monitor: ChainMonitor {
+            monitors: HashMap::new(),
+            persister: MockFileStore::new(),
+            broadcaster: MockBroadcaster::new(),
+        },
+        pending_peer_events: Vec::new(),
+        pending_user_events: Vec::new(),
+        peers: HashMap::new(),
+        signer_provider: SimpleKeysManager::new([seed; 32]),
+    }
+}
+
+/// Hands every message `from` queued for `to` over to `to`. Returns whether
+/// there was anything to deliver.
+fn deliver_messages(from: &mut ChannelManager, to: &mut ChannelManager) -> bool {
+    let from_id = from.signer_provider.node_id;
+    let events: Vec<MessageSendEvent> = from.pending_peer_events.drain(..).collect();
+    let delivered = !events.is_empty();
+    for event in events {
+        match event {
+            MessageSendEvent::SendAcceptChannelV2 { msg, .. } => to.handle_accept_channel_v2(&from_id, msg).unwrap(),
+            MessageSendEvent::SendTxAddInput { msg, .. } => {
+                assert_eq!(msg.channel_id, from.peers[&to.signer_provider.node_id].channel_id());
+                to.handle_tx_add_input(&from_id, msg).unwrap()
+            }
+            MessageSendEvent::SendTxAddOutput { msg, .. } => to.handle_tx_add_output(&from_id, msg).unwrap(),
+            MessageSendEvent::SendTxComplete { msg, .. } => to.handle_tx_complete(&from_id, msg).unwrap(),
+            MessageSendEvent::SendTxSignatures { msg, .. } => to.handle_tx_signatures(&from_id, msg).unwrap(),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+    delivered
+}
+
+/// Signs our funding inputs when the manager asks for it, leaving any other
+/// event in place.
+fn sign_funding_inputs(manager: &mut ChannelManager, counterparty_node_id: &bitcoin::secp256k1::PublicKey, wallet_key: &[u8; 32]) {
+    let events: Vec<Event> = manager.pending_user_events.drain(..).collect();
+    for event in events {
+        match event {
+            Event::FundingTransactionReadyForSigning { unsigned_transaction, prevouts, local_input_indices, .. } => {
+                let witnesses = sign_wallet_inputs(&unsigned_transaction, &prevouts, &local_input_indices, wallet_key);
+                manager.funding_transaction_signed(counterparty_node_id, witnesses).unwrap();
+            }
+            other => manager.pending_user_events.push(other),
+        }
+    }
+}
+
+/// Sends open_channel2 from `alice` and accepts it at `bob`, both funding
+/// from their wallet keys. Returns the temporary channel ID.
+fn start_dual_funded_open(alice: &mut ChannelManager, bob: &mut ChannelManager) -> ChannelId {
+    let alice_id = alice.signer_provider.node_id;
+    let bob_id = bob.signer_provider.node_id;
+    let alice_change = p2wpkh_output_script(pubkey_from_private_key(&[0x21; 32]));
+    let bob_change = p2wpkh_output_script(pubkey_from_private_key(&[0x22; 32]));
+
+    alice
+        .create_dual_funded_channel(
+            bob_id,
+            600_000,
+            1_000,
+            pubkey_from_private_key(&[0x31; 32]),
+            pubkey_from_private_key(&[0x41; 32]),
+            vec![(wallet_utxo(&[0x21; 32], 300_000), 0), (wallet_utxo(&[0x21; 32], 400_000), 0)],
+            alice_change,
+        )
+        .unwrap();
+    let open_channel = match alice.pending_peer_events.pop() {
+        Some(MessageSendEvent::SendOpenChannelV2 { msg, .. }) => msg,
+        other => panic!("expected open_channel2, got {:?}", other),
+    };
+    let temporary_channel_id = open_channel.temporary_channel_id;
+    bob.accept_dual_funded_channel(
+        &alice_id,
+        open_channel,
+        400_000,
+        pubkey_from_private_key(&[0x32; 32]),
+        pubkey_from_private_key(&[0x42; 32]),
+        vec![(wallet_utxo(&[0x22; 32], 500_000), 0)],
+        bob_change,
+    )
+    .unwrap();
+    temporary_channel_id
+}
+
+#[test]
+fn test_dual_funded_channel_open() {
+    let mut alice = test_channel_manager(0x01);
+    let mut bob = test_channel_manager(0x02);
+    let alice_id = alice.signer_provider.node_id;
+    let bob_id = bob.signer_provider.node_id;
+    let alice_revocation_basepoint = pubkey_from_private_key(&[0x41; 32]);
+    let bob_revocation_basepoint = pubkey_from_private_key(&[0x42; 32]);
+
+    let temporary_channel_id = start_dual_funded_open(&mut alice, &mut bob);
+    assert_eq!(temporary_channel_id, v2_temporary_channel_id(&alice_revocation_basepoint));
+    let channel_id = v2_channel_id(&alice_revocation_basepoint, &bob_revocation_basepoint);
+
+    // negotiate until both sides go quiet, signing whenever asked
+    loop {
+        let bob_sent = deliver_messages(&mut bob, &mut alice);
+        sign_funding_inputs(&mut alice, &bob_id, &[0x21; 32]);
+        let alice_sent = deliver_messages(&mut alice, &mut bob);
+        sign_funding_inputs(&mut bob, &alice_id, &[0x22; 32]);
+        if !alice_sent && !bob_sent {
+            break;
+        }
+    }
+
+    let funding_tx = &alice.chain_monitor.broadcaster.broadcasted_txs[0];
+    assert_eq!(funding_tx, &bob.chain_monitor.broadcaster.broadcasted_txs[0]);
+    assert_eq!(funding_tx.input.len(), 3);
+    assert!(funding_tx.input.iter().all(|txin| txin.witness.len() == 2));
+
+    // one funding output with both contributions, plus each side's change
+    let funding_output = funding_tx.output.iter().find(|txout| txout.value == Amount::from_sat(1_000_000)).unwrap();
+    assert!(funding_output.script_pubkey.is_p2wsh());
+    assert_eq!(funding_tx.output.len(), 3);
+    let fee = 1_200_000 - funding_tx.output.iter().map(|txout| txout.value.to_sat()).sum::<u64>();
+    // at 1000 sat/kw, every weight unit costs a satoshi
+    assert!(fee >= funding_tx.weight().to_wu());
+
+    assert_eq!(alice.chain_monitor.monitors.len(), 1);
+    assert_eq!(bob.chain_monitor.monitors.len(), 1);
+
+    // both sides moved to the v2 channel ID once basepoints were exchanged
+    assert_eq!(alice.peers[&bob_id].channel_id(), channel_id);
+    assert_eq!(bob.peers[&alice_id].channel_id(), channel_id);
+    for manager in [&alice, &bob] {
+        match manager.pending_user_events.last() {
+            Some(Event::ChannelPending { channel_id: pending_id, former_temporary_channel_id, .. }) => {
+                assert_eq!(*pending_id, channel_id);
+                assert_eq!(*former_temporary_channel_id, temporary_channel_id);
+            }
+            _ => panic!("expected ChannelPending"),
+        }
+    }
+}
+
+/// Answers the `FundingGenerationReady` event of a channel we opened with a
+/// funding transaction paying the requested output.
+fn generate_funding_transaction(channel_manager: &mut ChannelManager, counterparty_node_id: &bitcoin::secp256k1::PublicKey) -> Transaction {
+    channel_manager.handle_accept_channel(counterparty_node_id, AcceptChannel {
+        channel_value_satoshis: 100_000_000,
+        temporary_channel_id: ChannelId::new_zero(),
+    });
+    let (temporary_channel_id, channel_value_satoshis, output_script) = match channel_manager.pending_user_events.pop() {
+        Some(Event::FundingGenerationReady { temporary_channel_id, channel_value_satoshis, output_script, .. }) => {
+            (temporary_channel_id, channel_value_satoshis, output_script)
+        }
+        _ => panic!("expected FundingGenerationReady"),
+    };
+
+    let funding_tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn::default()],
+        output: vec![build_output(channel_value_satoshis, output_script)],
+    };
+    channel_manager
+        .funding_transaction_generated(temporary_channel_id, *counterparty_node_id, funding_tx.clone())
+        .unwrap();
+    funding_tx
+}
+
+#[test]
+fn test_channel_id_derivation() {
+    // BOLT 2: the output index is XORed into the last two bytes, big-endian
+    let funding_txid = Txid::from_byte_array([0x42; 32]);
+    let channel_id = v1_channel_id(&OutPoint { txid: funding_txid, index: 0x0102 });
+    assert_eq!(channel_id.0[..30], [0x42; 30]);
+    assert_eq!(channel_id.0[30..], [0x42 ^ 0x01, 0x42 ^ 0x02]);
+    assert_eq!(v1_channel_id(&OutPoint { txid: funding_txid, index: 0 }).0, [0x42; 32]);
+
+    // v2: SHA256 of both revocation basepoints, the lesser one first
+    let alice_basepoint = pubkey_from_private_key(&[0x11; 32]);
+    let bob_basepoint = pubkey_from_private_key(&[0x12; 32]);
+    let (lesser, greater) = if alice_basepoint.serialize() < bob_basepoint.serialize() {
+        (alice_basepoint, bob_basepoint)
+    } else {
+        (bob_basepoint, alice_basepoint)
+    };
+    let preimage = [lesser.serialize(), greater.serialize()].concat();
+    assert_eq!(v2_channel_id(&alice_basepoint, &bob_basepoint).0, Sha256::hash(&preimage).to_byte_array());
+    assert_eq!(v2_channel_id(&alice_basepoint, &bob_basepoint), v2_channel_id(&bob_basepoint, &alice_basepoint));
+
+    // the temporary v2 ID zeroes the acceptor's basepoint, which sorts first
+    let preimage = [[0; 33], alice_basepoint.serialize()].concat();
+    assert_eq!(v2_temporary_channel_id(&alice_basepoint).0, Sha256::hash(&preimage).to_byte_array());
+}
+
+#[test]
+fn test_funding_transaction_generated_derives_channel_id() {
+    let mut channel_manager = test_channel_manager(0x01);
+    let counterparty_node_id = pubkey_from_private_key(&[0x02; 32]);
+    channel_manager.create_channel(counterparty_node_id, 100_000_000);
+    channel_manager.pending_peer_events.clear();
+
+    // a transaction without the requested output is refused
+    let temporary_channel_id = channel_manager.peers[&counterparty_node_id].channel_id();
+    let wrong_tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn::default()],
+        output: vec![build_output(99_999_999, ScriptBuf::new())],
+    };
+    assert_eq!(
+        channel_manager.funding_transaction_generated(temporary_channel_id, counterparty_node_id, wrong_tx),
+        Err(())
+    );
+    assert_eq!(channel_manager.peers[&counterparty_node_id].channel_id(), temporary_channel_id);
+
+    let funding_tx = generate_funding_transaction(&mut channel_manager, &counterparty_node_id);
+    let funding_outpoint = OutPoint { txid: funding_tx.compute_txid(), index: 0 };
+    let channel_id = v1_channel_id(&funding_outpoint);
+
+    // funding_created still uses the temporary ID, everything after the derived one
+    match channel_manager.pending_peer_events.pop() {
+        Some(MessageSendEvent::SendFundingCreated { msg, .. }) => {
+            assert_eq!(msg.temporary_channel_id, temporary_channel_id);
+            assert_eq!(msg.transaction_id, funding_outpoint.txid);
+            assert_eq!(msg.funding_output_index, 0);
+        }
+        other => panic!("expected funding_created, got {:?}", other),
+    }
+    assert_eq!(channel_manager.peers[&counterparty_node_id].channel_id(), channel_id);
+    assert_eq!(
+        channel_manager.funding_transaction_generated(temporary_channel_id, counterparty_node_id, funding_tx.clone()),
+        Err(())
+    );
+
+    let funding_signed = |channel_id| FundingSigned {
+        channel_id,
+        signature: Signature::from(unsafe { FFISignature::new() }),
+    };
+    assert_eq!(channel_manager.handle_funding_signed(&counterparty_node_id, funding_signed(temporary_channel_id)), Err(()));
+    channel_manager.handle_funding_signed(&counterparty_node_id, funding_signed(channel_id)).unwrap();
+
+    assert_eq!(channel_manager.chain_monitor.monitors[&funding_outpoint].channel_id, channel_id);
+    match channel_manager.pending_user_events.pop() {
+        Some(Event::ChannelPending { channel_id: pending_id, former_temporary_channel_id, funding_txo, .. }) => {
+            assert_eq!(pending_id, channel_id);
+            assert_eq!(former_temporary_channel_id, temporary_channel_id);
+            assert_eq!(funding_txo, funding_outpoint.into_bitcoin_outpoint());
+        }
+        _ => panic!("expected ChannelPending"),
+    }
+}
+
+#[test]
+fn test_dual_funded_channel_rejects_bad_tx_signatures() {
+    let mut alice = test_channel_manager(0x01);
+    let mut bob = test_channel_manager(0x02);
+    let alice_id = alice.signer_provider.node_id;
+    let bob_id = bob.signer_provider.node_id;
+    start_dual_funded_open(&mut alice, &mut bob);
+
+    // bob has less at stake and signs first: hold his tx_signatures back
+    let mut bob_signing_request = None;
+    let tx_signatures = loop {
+        let position = bob.pending_peer_events.iter().position(|event| matches!(event, MessageSendEvent::SendTxSignatures { .. }));
+        if let Some(position) = position {
+            match bob.pending_peer_events.remove(position) {
+                MessageSendEvent::SendTxSignatures { msg, .. } => break msg,
+                _ => unreachable!(),
+            }
+        }
+        deliver_messages(&mut bob, &mut alice);
+        sign_funding_inputs(&mut alice, &bob_id, &[0x21; 32]);
+        deliver_messages(&mut alice, &mut bob);
+        for event in &bob.pending_user_events {
+            if let Event::FundingTransactionReadyForSigning { unsigned_transaction, prevouts, local_input_indices, .. } = event {
+                bob_signing_request = Some((unsigned_transaction.clone(), prevouts.clone(), local_input_indices.clone()));
+            }
+        }
+        sign_funding_inputs(&mut bob, &alice_id, &[0x22; 32]);
+    };
+    let (unsigned_tx, prevouts, bob_input_indices) = bob_signing_request.unwrap();
+    let funding_txid = tx_signatures.tx_hash;
+    assert_eq!(funding_txid, unsigned_tx.compute_txid());
+
+    let stranger = pubkey_from_private_key(&[0x09; 32]);
+    assert_eq!(
+        alice.handle_tx_signatures(&stranger, tx_signatures.clone()),
+        Err(InteractiveTxError::UnknownPeer(stranger))
+    );
+
+    let wrong_txid = bitcoin::Txid::all_zeros();
+    assert_eq!(
+        alice.handle_tx_signatures(&bob_id, TxSignatures { tx_hash: wrong_txid, ..tx_signatures.clone() }),
+        Err(InteractiveTxError::TxHashMismatch { expected: funding_txid, actual: wrong_txid })
+    );
+
+    // a signature by another key, and one over another transaction
+    let invalid_witness = Err(InteractiveTxError::InvalidWitness { input_index: bob_input_indices[0] });
+    let witnesses = sign_wallet_inputs(&unsigned_tx, &prevouts, &bob_input_indices, &[0x23; 32]);
+    assert_eq!(alice.handle_tx_signatures(&bob_id, TxSignatures { witnesses, ..tx_signatures.clone() }), invalid_witness);
+    let mut other_tx = unsigned_tx.clone();
+    other_tx.lock_time = LockTime::from_consensus(1);
+    let witnesses = sign_wallet_inputs(&other_tx, &prevouts, &bob_input_indices, &[0x22; 32]);
+    assert_eq!(alice.handle_tx_signatures(&bob_id, TxSignatures { witnesses, ..tx_signatures.clone() }), invalid_witness);
+    assert!(alice.chain_monitor.broadcaster.broadcasted_txs.is_empty());
+
+    alice.handle_tx_signatures(&bob_id, tx_signatures).unwrap();
+    assert_eq!(alice.chain_monitor.broadcaster.broadcasted_txs[0].compute_txid(), funding_txid);
+}
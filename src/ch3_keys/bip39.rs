@@ -0,0 +1,197 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! BIP39 mnemonic codes.
+//!
+//! A mnemonic encodes 128 to 256 bits of entropy, plus a short SHA256
+//! checksum, as 12 to 24 words from a fixed list of 2048. The words (and an
+//! optional passphrase) are then stretched with PBKDF2-HMAC-SHA512 into the
+//! 64-byte seed our keys are derived from.
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::sha256::Hash as Sha256;
+use bitcoin::hashes::sha512::Hash as Sha512;
+use bitcoin::hashes::{Hash, HashEngine};
+use rand::{thread_rng, Rng};
+use std::fmt;
+use std::str::FromStr;
+use unicode_normalization::UnicodeNormalization;
+
+const PBKDF2_ROUNDS: u32 = 2048;
+
+fn english_wordlist() -> Vec<&'static str> {
+    include_str!("english.txt").lines().collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Bip39Error {
+    /// Entropy must be 16 to 32 bytes, in steps of 4.
+    BadEntropyLength(usize),
+    /// A mnemonic has 12, 15, 18, 21 or 24 words.
+    BadWordCount(usize),
+    /// The word is not in the English wordlist.
+    UnknownWord(String),
+    /// The words do not end with the checksum of their entropy.
+    InvalidChecksum,
+}
+
+impl fmt::Display for Bip39Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Bip39Error::BadEntropyLength(len) => write!(f, "entropy of {} bytes, expected 16 to 32 in steps of 4", len),
+            Bip39Error::BadWordCount(count) => write!(f, "{} words, expected 12, 15, 18, 21 or 24", count),
+            Bip39Error::UnknownWord(word) => write!(f, "unknown word \"{}\"", word),
+            Bip39Error::InvalidChecksum => write!(f, "invalid checksum"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mnemonic {
+    words: Vec<&'static str>,
+}
+
+impl Mnemonic {
+    /// Encodes `entropy` as words: each word holds 11 bits of the entropy
+    /// followed by the first `entropy.len() / 4` bits of its SHA256.
+    pub fn from_entropy(entropy: &[u8]) -> Result<Mnemonic, Bip39Error> {
+        if !matches!(entropy.len(), 16 | 20 | 24 | 28 | 32) {
+            return Err(Bip39Error::BadEntropyLength(entropy.len()));
+        }
+
+        let checksum = Sha256::hash(entropy).to_byte_array();
+        let mut bits = bytes_to_bits(entropy);
+        bits.extend(bytes_to_bits(&checksum).into_iter().take(entropy.len() / 4));
+
+        let wordlist = english_wordlist();
+        let words = bits
+            .chunks(11)
+            .map(|chunk| wordlist[bits_to_index(chunk)])
+            .collect();
+
+        Ok(Mnemonic { words })
+    }
+
+    /// A new mnemonic of `word_count` words from fresh random entropy.
+    pub fn generate(word_count: usize) -> Result<Mnemonic, Bip39Error> {
+        if !valid_word_count(word_count) {
+            return Err(Bip39Error::BadWordCount(word_count));
+        }
+
+        let mut entropy = vec![0; word_count / 3 * 4];
+        thread_rng().fill_bytes(&mut entropy);
+        Mnemonic::from_entropy(&entropy)
+    }
+
+    /// Parses a space separated mnemonic, checking every word and the
+    /// checksum.
+    pub fn parse(phrase: &str) -> Result<Mnemonic, Bip39Error> {
+        let wordlist = english_wordlist();
+        let input: Vec<&str> = phrase.split_whitespace().collect();
+        if !valid_word_count(input.len()) {
+            return Err(Bip39Error::BadWordCount(input.len()));
+        }
+
+        let mut words = Vec::with_capacity(input.len());
+        let mut bits = Vec::with_capacity(input.len() * 11);
+        for word in input {
+            let lowercase = word.to_lowercase();
+            let index = wordlist
+                .binary_search(&lowercase.as_str())
+                .map_err(|_| Bip39Error::UnknownWord(word.to_string()))?;
+            words.push(wordlist[index]);
+            bits.extend((0..11).rev().map(|i| (index >> i) & 1 == 1));
+        }
+
+        let checksum_len = bits.len() / 33;
+        let entropy = bits_to_bytes(&bits[..bits.len() - checksum_len]);
+        let checksum = bytes_to_bits(&Sha256::hash(&entropy).to_byte_array());
+        if bits[bits.len() - checksum_len..] != checksum[..checksum_len] {
+            return Err(Bip39Error::InvalidChecksum);
+        }
+
+        Ok(Mnemonic { words })
+    }
+
+    pub fn words(&self) -> &[&'static str] {
+        &self.words
+    }
+
+    /// The entropy the words encode, without the checksum.
+    pub fn entropy(&self) -> Vec<u8> {
+        let wordlist = english_wordlist();
+        let bits: Vec<bool> = self
+            .words
+            .iter()
+            .flat_map(|word| {
+                let index = wordlist.binary_search(word).unwrap();
+                (0..11).rev().map(move |i| (index >> i) & 1 == 1)
+            })
+            .collect();
+        bits_to_bytes(&bits[..bits.len() - bits.len() / 33])
+    }
+
+    /// The 64-byte seed: PBKDF2-HMAC-SHA512 of the words, salted with
+    /// "mnemonic" followed by the passphrase. Any passphrase gives a valid
+    /// seed, so a mistyped one silently leads to a different wallet.
+    ///
+    /// Both are NFKD normalized first, so a passphrase typed with composed
+    /// or decomposed accents gives the same seed.
+    pub fn to_seed(&self, passphrase: &str) -> [u8; 64] {
+        let password: String = self.to_string().nfkd().collect();
+        let salt: String = format!("mnemonic{}", passphrase).nfkd().collect();
+        pbkdf2_hmac_sha512(password.as_bytes(), salt.as_bytes(), PBKDF2_ROUNDS)
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.words.join(" "))
+    }
+}
+
+impl FromStr for Mnemonic {
+    type Err = Bip39Error;
+
+    fn from_str(s: &str) -> Result<Mnemonic, Bip39Error> {
+        Mnemonic::parse(s)
+    }
+}
+
+/// PBKDF2 with a single block of output, which is all SHA512 needs to fill
+/// a 64-byte seed.
+fn pbkdf2_hmac_sha512(password: &[u8], salt: &[u8], rounds: u32) -> [u8; 64] {
+    let mut engine = HmacEngine::<Sha512>::new(password);
+    engine.input(salt);
+    engine.input(&1u32.to_be_bytes());
+    let mut block = Hmac::<Sha512>::from_engine(engine).to_byte_array();
+
+    let mut output = block;
+    for _ in 1..rounds {
+        let mut engine = HmacEngine::<Sha512>::new(password);
+        engine.input(&block);
+        block = Hmac::<Sha512>::from_engine(engine).to_byte_array();
+        for (out, byte) in output.iter_mut().zip(block.iter()) {
+            *out ^= byte;
+        }
+    }
+    output
+}
+
+fn valid_word_count(word_count: usize) -> bool {
+    matches!(word_count, 12 | 15 | 18 | 21 | 24)
+}
+
+fn bytes_to_bits(bytes: &[u8]) -> Vec<bool> {
+    bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1 == 1))
+        .collect()
+}
+
+fn bits_to_bytes(bits: &[bool]) -> Vec<u8> {
+    bits.chunks(8)
+        .map(|chunk| chunk.iter().fold(0, |byte, bit| (byte << 1) | *bit as u8))
+        .collect()
+}
+
+fn bits_to_index(bits: &[bool]) -> usize {
+    bits.iter().fold(0, |index, bit| (index << 1) | *bit as usize)
+}
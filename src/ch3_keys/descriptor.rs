@@ -0,0 +1,268 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! Output descriptors (BIP380) for watching our funds from bitcoind.
+//!
+//! We only need the small subset bitcoind's `importdescriptors` takes for a
+//! watch-only wallet: `wpkh(...)` and key-path-only `tr(...)` over an xpub
+//! with its key origin, either for the xpub's own key or for a ranged
+//! `/<chain>/*` branch of it.
+use crate::internal::helper::p2wpkh_output_script;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Fingerprint, Xpriv, Xpub};
+use bitcoin::network::{Network, NetworkKind};
+use bitcoin::script::ScriptBuf;
+use bitcoin::secp256k1::Secp256k1;
+use std::fmt;
+use std::str::FromStr;
+
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DescriptorError {
+    /// Only `wpkh(...)` and `tr(...)` descriptors are supported.
+    UnsupportedDescriptor(String),
+    /// The checksum after `#` does not match the descriptor.
+    InvalidChecksum,
+    /// The key does not start with a `[fingerprint/path]` origin.
+    InvalidKeyOrigin,
+    /// The key is not an extended public key.
+    InvalidXpub,
+    /// Only a `/<chain>/*` range, with an unhardened chain, may follow the
+    /// xpub.
+    InvalidDerivation,
+    /// The xpub is for a different network than the wallet.
+    WrongNetwork,
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DescriptorError::UnsupportedDescriptor(descriptor) => write!(f, "unsupported descriptor {}", descriptor),
+            DescriptorError::InvalidChecksum => write!(f, "invalid checksum"),
+            DescriptorError::InvalidKeyOrigin => write!(f, "missing or invalid key origin"),
+            DescriptorError::InvalidXpub => write!(f, "invalid xpub"),
+            DescriptorError::InvalidDerivation => write!(f, "invalid derivation after the xpub"),
+            DescriptorError::WrongNetwork => write!(f, "xpub is for a different network"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DescriptorType {
+    /// Pay to witness public key hash.
+    Wpkh,
+    /// Pay to taproot, spent with the (tweaked) key only.
+    Tr,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyDescriptor {
+    pub descriptor_type: DescriptorType,
+    pub master_fingerprint: Fingerprint,
+    /// Path from the master key to `xpub`.
+    pub origin: DerivationPath,
+    pub xpub: Xpub,
+    /// `Some(chain)` for the ranged descriptor of the xpub's `chain/*`
+    /// children, `None` for the xpub's own key.
+    pub chain: Option<u32>,
+}
+
+impl KeyDescriptor {
+    pub fn new(descriptor_type: DescriptorType, master_key: &Xpriv, origin: DerivationPath,
+               chain: Option<u32>) -> KeyDescriptor {
+        let secp_ctx = Secp256k1::new();
+        let xpriv = master_key
+            .derive_priv(&secp_ctx, &origin)
+            .expect("Your RNG is busted");
+
+        KeyDescriptor {
+            descriptor_type,
+            master_fingerprint: master_key.fingerprint(&secp_ctx),
+            origin,
+            xpub: Xpub::from_priv(&secp_ctx, &xpriv),
+            chain,
+        }
+    }
+
+    /// The script of the `index`th key of a ranged descriptor, or of the
+    /// single key of any other descriptor. An xpub only derives unhardened
+    /// children, so the chain and `index` must be below 2^31.
+    pub fn script_pubkey(&self, index: u32) -> Result<ScriptBuf, DescriptorError> {
+        let secp_ctx = Secp256k1::new();
+        let public_key = match self.chain {
+            Some(chain) => {
+                let path = [
+                    ChildNumber::from_normal_idx(chain).map_err(|_| DescriptorError::InvalidDerivation)?,
+                    ChildNumber::from_normal_idx(index).map_err(|_| DescriptorError::InvalidDerivation)?,
+                ];
+                self.xpub.derive_pub(&secp_ctx, &path).map_err(|_| DescriptorError::InvalidDerivation)?.public_key
+            }
+            None => self.xpub.public_key,
+        };
+
+        Ok(match self.descriptor_type {
+            DescriptorType::Wpkh => p2wpkh_output_script(public_key),
+            DescriptorType::Tr => ScriptBuf::new_p2tr(&secp_ctx, public_key.x_only_public_key().0, None),
+        })
+    }
+
+    pub fn is_ranged(&self) -> bool {
+        self.chain.is_some()
+    }
+}
+
+/// Descriptors are printed with their checksum, and hardened steps as `h`
+/// so they can be pasted into a shell.
+impl fmt::Display for KeyDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut key = format!("[{}", self.master_fingerprint);
+        for child in &self.origin {
+            key.push_str(&format!("/{:#}", child));
+        }
+        key.push_str(&format!("]{}", self.xpub));
+        if let Some(chain) = self.chain {
+            key.push_str(&format!("/{}/*", chain));
+        }
+
+        let descriptor = match self.descriptor_type {
+            DescriptorType::Wpkh => format!("wpkh({})", key),
+            DescriptorType::Tr => format!("tr({})", key),
+        };
+        let checksum = descriptor_checksum(&descriptor).expect("descriptor characters are valid");
+        write!(f, "{}#{}", descriptor, checksum)
+    }
+}
+
+impl FromStr for KeyDescriptor {
+    type Err = DescriptorError;
+
+    /// Parses a descriptor, checking its checksum if it has one.
+    fn from_str(s: &str) -> Result<KeyDescriptor, DescriptorError> {
+        let descriptor = match s.split_once('#') {
+            Some((descriptor, checksum)) => {
+                if descriptor_checksum(descriptor).as_deref() != Some(checksum) {
+                    return Err(DescriptorError::InvalidChecksum);
+                }
+                descriptor
+            }
+            None => s,
+        };
+
+        let (descriptor_type, key) = if let Some(key) = descriptor.strip_prefix("wpkh(") {
+            (DescriptorType::Wpkh, key)
+        } else if let Some(key) = descriptor.strip_prefix("tr(") {
+            (DescriptorType::Tr, key)
+        } else {
+            return Err(DescriptorError::UnsupportedDescriptor(descriptor.to_string()));
+        };
+        let key = key
+            .strip_suffix(')')
+            .ok_or_else(|| DescriptorError::UnsupportedDescriptor(descriptor.to_string()))?;
+
+        let (origin, key) = key
+            .strip_prefix('[')
+            .and_then(|key| key.split_once(']'))
+            .ok_or(DescriptorError::InvalidKeyOrigin)?;
+        let (fingerprint, path) = origin.split_once('/').unwrap_or((origin, ""));
+        let master_fingerprint = Fingerprint::from_str(fingerprint).map_err(|_| DescriptorError::InvalidKeyOrigin)?;
+        let origin = DerivationPath::from_str(path).map_err(|_| DescriptorError::InvalidKeyOrigin)?;
+
+        let mut steps = key.split('/');
+        let xpub = steps
+            .next()
+            .and_then(|xpub| Xpub::from_str(xpub).ok())
+            .ok_or(DescriptorError::InvalidXpub)?;
+        let chain = match (steps.next(), steps.next(), steps.next()) {
+            (None, _, _) => None,
+            (Some(chain), Some("*"), None) => Some(chain.parse().map_err(|_| DescriptorError::InvalidDerivation)?),
+            _ => return Err(DescriptorError::InvalidDerivation),
+        };
+        if chain.is_some_and(|chain| ChildNumber::from_normal_idx(chain).is_err()) {
+            return Err(DescriptorError::InvalidDerivation);
+        }
+
+        Ok(KeyDescriptor { descriptor_type, master_fingerprint, origin, xpub, chain })
+    }
+}
+
+/// A watch-only view of a wallet, rebuilt from its exported descriptors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchOnlyWallet {
+    pub network: Network,
+    pub descriptors: Vec<KeyDescriptor>,
+}
+
+impl WatchOnlyWallet {
+    pub fn new(network: Network) -> WatchOnlyWallet {
+        WatchOnlyWallet { network, descriptors: Vec::new() }
+    }
+
+    pub fn import_descriptor(&mut self, descriptor: &str) -> Result<(), DescriptorError> {
+        let descriptor = KeyDescriptor::from_str(descriptor)?;
+        if descriptor.xpub.network != NetworkKind::from(self.network) {
+            return Err(DescriptorError::WrongNetwork);
+        }
+        self.descriptors.push(descriptor);
+        Ok(())
+    }
+
+    /// Every script we watch: the first `lookahead` of each ranged
+    /// descriptor, and each single key.
+    pub fn script_pubkeys(&self, lookahead: u32) -> Vec<ScriptBuf> {
+        self.descriptors
+            .iter()
+            .flat_map(|descriptor| {
+                let count = if descriptor.is_ranged() { lookahead } else { 1 };
+                (0..count).map_while(move |index| descriptor.script_pubkey(index).ok())
+            })
+            .collect()
+    }
+
+    pub fn is_mine(&self, script_pubkey: &ScriptBuf, lookahead: u32) -> bool {
+        self.script_pubkeys(lookahead).contains(script_pubkey)
+    }
+}
+
+/// The BIP380 descriptor checksum, or `None` if the descriptor has a
+/// character descriptors can't contain.
+pub fn descriptor_checksum(descriptor: &str) -> Option<String> {
+    let mut symbols = Vec::new();
+    let mut groups = Vec::new();
+    for c in descriptor.chars() {
+        let value = INPUT_CHARSET.find(c)? as u64;
+        symbols.push(value & 31);
+        groups.push(value >> 5);
+        if groups.len() == 3 {
+            symbols.push(groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+    match groups.len() {
+        1 => symbols.push(groups[0]),
+        2 => symbols.push(groups[0] * 3 + groups[1]),
+        _ => {}
+    }
+    symbols.extend([0; 8]);
+
+    let checksum = polymod(&symbols) ^ 1;
+    Some(
+        (0..8)
+            .map(|i| CHECKSUM_CHARSET[((checksum >> (5 * (7 - i))) & 31) as usize] as char)
+            .collect(),
+    )
+}
+
+fn polymod(symbols: &[u64]) -> u64 {
+    const GENERATOR: [u64; 5] = [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+
+    let mut checksum = 1;
+    for value in symbols {
+        let top = checksum >> 35;
+        checksum = ((checksum & 0x7ffffffff) << 5) ^ value;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
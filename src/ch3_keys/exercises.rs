@@ -1,15 +1,20 @@
 #![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
 use crate::internal;
+use crate::ch3_keys::bip39::Mnemonic;
+use crate::ch3_keys::descriptor::{DescriptorType, KeyDescriptor};
 
 use bitcoin::amount::Amount;
-use bitcoin::bip32::{ChildNumber, Xpriv, Xpub};
+use bitcoin::bip32::{self, ChildNumber, DerivationPath, Xpriv, Xpub};
 use bitcoin::blockdata::opcodes::all as opcodes;
 use bitcoin::hashes::ripemd160::Hash as Ripemd160;
 use bitcoin::locktime::absolute::LockTime;
 use bitcoin::network::Network;
 use bitcoin::script::{ScriptBuf, ScriptHash};
 use bitcoin::secp256k1;
-use bitcoin::secp256k1::ecdsa::Signature;
+use bitcoin::secp256k1::ecdh::SharedSecret;
+use bitcoin::secp256k1::ecdsa::{RecoverableSignature, Signature};
+use bitcoin::secp256k1::schnorr;
+use bitcoin::secp256k1::Keypair;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::secp256k1::Scalar;
 use bitcoin::secp256k1::Secp256k1;
@@ -20,14 +25,25 @@ use bitcoin::{Block, OutPoint, Transaction, TxIn, TxOut};
 use core::sync::atomic::{AtomicUsize, Ordering};
 use internal::bitcoind_client::BitcoindClient;
 use internal::builder::Builder;
-use lightning::sign::KeysManager;
+use internal::helper::p2wpkh_output_script;
+use lightning::io::Cursor;
+use lightning::ln::msgs::{DecodeError, UnsignedGossipMessage};
+use lightning::ln::script::ShutdownScript;
+use lightning::offers::invoice::UnsignedBolt12Invoice;
+use lightning::offers::invoice_request::UnsignedInvoiceRequest;
+use lightning::sign::{EntropySource, InMemorySigner, KeyMaterial, KeysManager, NodeSigner, Recipient, SignerProvider};
+use lightning::util::ser::{ReadableArgs, Writeable};
+use lightning_invoice::RawBolt11Invoice;
+use rand::Rng;
+use std::collections::HashSet;
+use std::sync::{Mutex, MutexGuard, PoisonError};
 use std::time::{Duration, SystemTime};
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::sha256d::Hash as Sha256dHash;
 use bitcoin::secp256k1::Message;
 use bitcoin::hashes::{Hash, HashEngine};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug)]
 pub struct SimpleKeysManager {
     pub secp_ctx: Secp256k1<secp256k1::All>,
     pub node_secret: SecretKey,
@@ -36,16 +52,100 @@ pub struct SimpleKeysManager {
     pub coop_close_pubkey: PublicKey,
     pub channel_master_key: Xpriv,
     pub inbound_payment_key: SecretKey,
+    /// The child of `channel_master_key` the next channel's keys come from.
+    /// It starts over at 0, so after a restart `restore_channel_keys_id`
+    /// must move it past every earlier channel, or new channels reuse their
+    /// keys (and revocation secrets).
+    pub channel_child_index: AtomicUsize,
+    /// The channel keys IDs we derived keys for or restored, the only ones
+    /// we give out keys for.
+    pub issued_channel_keys_ids: Mutex<HashSet<[u8; 32]>>,
+    pub seed: Vec<u8>,
+    pub network: Network,
+}
+
+/// The keys for a single channel. Everything in our side of the channel's
+/// scripts is either one of these keys or derived from one of these
+/// basepoints and a per-commitment point, whose secrets come from the
+/// commitment seed.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ChannelKeys {
+    pub funding_key: SecretKey,
+    pub revocation_base_key: SecretKey,
+    pub payment_key: SecretKey,
+    pub delayed_payment_base_key: SecretKey,
+    pub htlc_base_key: SecretKey,
+    pub commitment_seed: [u8; 32],
+    pub channel_value_satoshis: u64,
+    pub user_channel_id: u128,
+    /// The child of `channel_master_key` these keys were derived from, which
+    /// is all we need to derive them again.
     pub channel_child_index: u32,
-    pub seed: [u8; 32],
+}
+
+impl ChannelKeys {
+    pub fn funding_pubkey(&self) -> PublicKey {
+        get_public_key(self.funding_key)
+    }
+
+    pub fn revocation_basepoint(&self) -> PublicKey {
+        get_public_key(self.revocation_base_key)
+    }
+
+    pub fn payment_point(&self) -> PublicKey {
+        get_public_key(self.payment_key)
+    }
+
+    pub fn delayed_payment_basepoint(&self) -> PublicKey {
+        get_public_key(self.delayed_payment_base_key)
+    }
+
+    pub fn htlc_basepoint(&self) -> PublicKey {
+        get_public_key(self.htlc_base_key)
+    }
+
+    /// How LDK refers to the channel's signer: the child index the keys were
+    /// derived from, followed by the user channel ID.
+    pub fn channel_keys_id(&self) -> [u8; 32] {
+        let mut channel_keys_id = [0; 32];
+        channel_keys_id[..4].copy_from_slice(&self.channel_child_index.to_be_bytes());
+        channel_keys_id[4..20].copy_from_slice(&self.user_channel_id.to_be_bytes());
+        channel_keys_id
+    }
+
+    /// An LDK channel signer holding these keys.
+    pub fn to_signer(&self, rand_bytes_unique_start: [u8; 32]) -> InMemorySigner {
+        InMemorySigner::new(
+            &Secp256k1::new(),
+            self.funding_key,
+            self.revocation_base_key,
+            self.payment_key,
+            self.delayed_payment_base_key,
+            self.htlc_base_key,
+            self.commitment_seed,
+            self.channel_value_satoshis,
+            self.channel_keys_id(),
+            rand_bytes_unique_start,
+        )
+    }
 }
 
 impl SimpleKeysManager {
     pub(crate) fn new(seed: [u8; 32]) -> SimpleKeysManager {
+        SimpleKeysManager::new_for_network(seed, Network::Regtest)
+    }
+
+    pub fn new_for_network(seed: [u8; 32], network: Network) -> SimpleKeysManager {
+        SimpleKeysManager::from_seed(&seed, network)
+    }
+
+    /// A keys manager whose BIP32 master key is derived from `seed`, which
+    /// may be anywhere from 16 to 64 bytes long.
+    pub fn from_seed(seed: &[u8], network: Network) -> SimpleKeysManager {
 
         let secp_ctx = Secp256k1::new();
         
-        let master_key = get_master_key(seed);
+        let master_key = get_master_key(seed, network);
 
         let node_secret = get_hardened_child_private_key(master_key, 0);
 
@@ -55,7 +155,7 @@ impl SimpleKeysManager {
         let unilateral_close_pubkey = get_public_key(unilateral_close_private_key);
 
         let coop_close_private_key = get_hardened_child_private_key(master_key, 2);
-        let coop_close_pubkey = get_public_key(unilateral_close_private_key);
+        let coop_close_pubkey = get_public_key(coop_close_private_key);
 
         let channel_master_key = get_hardened_extended_child_private_key(master_key, 3);
 
@@ -69,11 +169,96 @@ impl SimpleKeysManager {
             coop_close_pubkey: coop_close_pubkey,
             channel_master_key: channel_master_key,
             inbound_payment_key: inbound_payment_key,
-            channel_child_index: 0,
-            seed: seed,
+            channel_child_index: AtomicUsize::new(0),
+            issued_channel_keys_ids: Mutex::new(HashSet::new()),
+            seed: seed.to_vec(),
+            network,
+        }
+    }
+
+    /// Restores a keys manager from a BIP39 mnemonic, using the full 64-byte
+    /// BIP39 seed as the BIP32 seed like any other wallet does.
+    pub fn from_mnemonic(mnemonic: &Mnemonic, passphrase: &str, network: Network) -> SimpleKeysManager {
+        SimpleKeysManager::from_seed(&mnemonic.to_seed(passphrase), network)
+    }
+
+    /// Derives the keys for a new channel from the next child of
+    /// `channel_master_key`, so no two channels share keys.
+    pub fn derive_channel_keys(&self, channel_value_satoshis: u64, user_channel_id: u128) -> ChannelKeys {
+        let channel_child_index = self.channel_child_index.fetch_add(1, Ordering::SeqCst);
+        let channel_keys = self
+            .channel_keys_at_index(channel_child_index as u32, channel_value_satoshis, user_channel_id)
+            .expect("fewer than 2^31 channels");
+        self.issued_channel_keys_ids().insert(channel_keys.channel_keys_id());
+        channel_keys
+    }
+
+    /// The keys of the channel derived from child `channel_child_index`,
+    /// which has to be below 2^31 to be hardened.
+    pub fn channel_keys_at_index(&self, channel_child_index: u32, channel_value_satoshis: u64,
+                                 user_channel_id: u128) -> Result<ChannelKeys, bip32::Error> {
+        let child_number = ChildNumber::from_hardened_idx(channel_child_index)?;
+        let channel_key = self.channel_master_key.derive_priv(&self.secp_ctx, &child_number)?;
+
+        Ok(ChannelKeys {
+            funding_key: get_hardened_child_private_key(channel_key, 0),
+            revocation_base_key: get_hardened_child_private_key(channel_key, 1),
+            payment_key: get_hardened_child_private_key(channel_key, 2),
+            delayed_payment_base_key: get_hardened_child_private_key(channel_key, 3),
+            htlc_base_key: get_hardened_child_private_key(channel_key, 4),
+            commitment_seed: get_hardened_child_private_key(channel_key, 5).secret_bytes(),
+            channel_value_satoshis,
+            user_channel_id,
+            channel_child_index,
+        })
+    }
+
+    /// The keys of the channel with the given `ChannelKeys::channel_keys_id`,
+    /// e.g. to sign for a channel again after a restart. `None` for an ID we
+    /// never derived keys for or restored.
+    pub fn channel_keys_from_id(&self, channel_value_satoshis: u64, channel_keys_id: [u8; 32]) -> Option<ChannelKeys> {
+        if !self.issued_channel_keys_ids().contains(&channel_keys_id) {
+            return None;
+        }
+        let (channel_child_index, user_channel_id) = split_channel_keys_id(channel_keys_id);
+        self.channel_keys_at_index(channel_child_index, channel_value_satoshis, user_channel_id).ok()
+    }
+
+    /// Tells a restarted keys manager about a channel it derived keys for
+    /// before, so it gives out the channel's keys again and
+    /// `derive_channel_keys` carries on after it. Restore every channel we
+    /// have, closed ones too, before opening new ones.
+    pub fn restore_channel_keys_id(&self, channel_keys_id: [u8; 32]) {
+        let (channel_child_index, _) = split_channel_keys_id(channel_keys_id);
+        if channel_child_index < 1 << 31 {
+            self.channel_child_index.fetch_max(channel_child_index as usize + 1, Ordering::SeqCst);
+            self.issued_channel_keys_ids().insert(channel_keys_id);
         }
     }
 
+    /// Inserting an ID can't leave the set half updated, so a panic while
+    /// it was locked does no harm.
+    fn issued_channel_keys_ids(&self) -> MutexGuard<'_, HashSet<[u8; 32]>> {
+        self.issued_channel_keys_ids.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Watch-only descriptors for everything we hold on-chain: our on-chain
+    /// wallet, and the keys our channel balances are paid to on a mutual
+    /// close (`shutdown`) or swept to after a force close.
+    pub fn watch_only_descriptors(&self) -> Vec<KeyDescriptor> {
+        let master_key = get_master_key(&self.seed, self.network);
+
+        let mut descriptors = onchain_wallet_descriptors(&master_key, self.network);
+        descriptors.push(KeyDescriptor::new(DescriptorType::Wpkh, &master_key, hardened_path(&[2]), None));
+        descriptors.push(KeyDescriptor::new(DescriptorType::Wpkh, &master_key, hardened_path(&[1]), None));
+        descriptors
+    }
+
+    /// The script our funds are paid to on a mutual close, as sent in `shutdown`.
+    pub fn get_shutdown_scriptpubkey(&self) -> ScriptBuf {
+        p2wpkh_output_script(self.coop_close_pubkey)
+    }
+
     pub fn sign_gossip_message(&self, msg: &[u8]) -> Signature {
         let double_sha256_hash = Sha256dHash::hash(msg);
         let msg_hash = hash_to_message(double_sha256_hash);
@@ -81,18 +266,159 @@ impl SimpleKeysManager {
     }
 }
 
+// The child index is atomic, and the issued IDs behind a mutex, so LDK can
+// derive channel keys through a shared reference, which rules out deriving
+// these.
+impl Clone for SimpleKeysManager {
+    fn clone(&self) -> SimpleKeysManager {
+        SimpleKeysManager {
+            secp_ctx: self.secp_ctx.clone(),
+            node_secret: self.node_secret,
+            node_id: self.node_id,
+            unilateral_close_pubkey: self.unilateral_close_pubkey,
+            coop_close_pubkey: self.coop_close_pubkey,
+            channel_master_key: self.channel_master_key,
+            inbound_payment_key: self.inbound_payment_key,
+            channel_child_index: AtomicUsize::new(self.channel_child_index.load(Ordering::SeqCst)),
+            issued_channel_keys_ids: Mutex::new(self.issued_channel_keys_ids().clone()),
+            seed: self.seed.clone(),
+            network: self.network,
+        }
+    }
+}
+
+impl PartialEq for SimpleKeysManager {
+    fn eq(&self, other: &SimpleKeysManager) -> bool {
+        // every key is derived from the seed
+        self.seed == other.seed
+            && self.network == other.network
+            && self.channel_child_index.load(Ordering::SeqCst) == other.channel_child_index.load(Ordering::SeqCst)
+            && *self.issued_channel_keys_ids() == *other.issued_channel_keys_ids()
+    }
+}
+
+impl Eq for SimpleKeysManager {}
+
+impl EntropySource for SimpleKeysManager {
+    fn get_secure_random_bytes(&self) -> [u8; 32] {
+        rand::thread_rng().gen()
+    }
+}
+
+impl NodeSigner for SimpleKeysManager {
+    fn get_inbound_payment_key_material(&self) -> KeyMaterial {
+        KeyMaterial(self.inbound_payment_key.secret_bytes())
+    }
+
+    fn get_node_id(&self, recipient: Recipient) -> Result<PublicKey, ()> {
+        match recipient {
+            Recipient::Node => Ok(self.node_id),
+            Recipient::PhantomNode => Err(()),
+        }
+    }
+
+    /// The shared secret of a BOLT 8 handshake or an onion packet.
+    fn ecdh(&self, recipient: Recipient, other_key: &PublicKey, tweak: Option<&Scalar>) -> Result<SharedSecret, ()> {
+        let mut node_secret = match recipient {
+            Recipient::Node => self.node_secret,
+            Recipient::PhantomNode => return Err(()),
+        };
+        if let Some(tweak) = tweak {
+            node_secret = node_secret.mul_tweak(tweak).map_err(|_| ())?;
+        }
+        Ok(SharedSecret::new(other_key, &node_secret))
+    }
+
+    fn sign_invoice(&self, invoice: &RawBolt11Invoice, recipient: Recipient) -> Result<RecoverableSignature, ()> {
+        if let Recipient::PhantomNode = recipient {
+            return Err(());
+        }
+        let msg_hash = Message::from_digest(invoice.signable_hash());
+        Ok(self.secp_ctx.sign_ecdsa_recoverable(&msg_hash, &self.node_secret))
+    }
+
+    fn sign_bolt12_invoice_request(&self, invoice_request: &UnsignedInvoiceRequest) -> Result<schnorr::Signature, ()> {
+        let keys = Keypair::from_secret_key(&self.secp_ctx, &self.node_secret);
+        let aux_rand = self.get_secure_random_bytes();
+        Ok(self.secp_ctx.sign_schnorr_with_aux_rand(invoice_request.tagged_hash().as_digest(), &keys, &aux_rand))
+    }
+
+    fn sign_bolt12_invoice(&self, invoice: &UnsignedBolt12Invoice) -> Result<schnorr::Signature, ()> {
+        let keys = Keypair::from_secret_key(&self.secp_ctx, &self.node_secret);
+        let aux_rand = self.get_secure_random_bytes();
+        Ok(self.secp_ctx.sign_schnorr_with_aux_rand(invoice.tagged_hash().as_digest(), &keys, &aux_rand))
+    }
+
+    fn sign_gossip_message(&self, msg: UnsignedGossipMessage) -> Result<Signature, ()> {
+        Ok(SimpleKeysManager::sign_gossip_message(self, &msg.encode()))
+    }
+}
+
+impl SignerProvider for SimpleKeysManager {
+    type EcdsaSigner = InMemorySigner;
+
+    fn generate_channel_keys_id(&self, inbound: bool, channel_value_satoshis: u64, user_channel_id: u128) -> [u8; 32] {
+        self.derive_channel_keys(channel_value_satoshis, user_channel_id).channel_keys_id()
+    }
+
+    /// LDK asks for the signer of every channel it reads back after a
+    /// restart, so this restores them too.
+    fn derive_channel_signer(&self, channel_value_satoshis: u64, channel_keys_id: [u8; 32]) -> InMemorySigner {
+        self.restore_channel_keys_id(channel_keys_id);
+        self.channel_keys_from_id(channel_value_satoshis, channel_keys_id)
+            .expect("LDK only asks for channel keys IDs we generated")
+            .to_signer(self.get_secure_random_bytes())
+    }
+
+    fn read_chan_signer(&self, reader: &[u8]) -> Result<InMemorySigner, DecodeError> {
+        InMemorySigner::read(&mut Cursor::new(reader), self)
+    }
+
+    /// Where we sweep our outputs of a force close to.
+    fn get_destination_script(&self, channel_keys_id: [u8; 32]) -> Result<ScriptBuf, ()> {
+        Ok(p2wpkh_output_script(self.unilateral_close_pubkey))
+    }
+
+    fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> {
+        ShutdownScript::try_from(SimpleKeysManager::get_shutdown_scriptpubkey(self)).map_err(|_| ())
+    }
+}
+
 fn hash_to_message(hash: Sha256dHash) -> Message {
     Message::from_digest_slice(&hash[..]).unwrap()
 }
 
-fn get_master_key(seed: [u8; 32]) -> Xpriv {
-    let master_key = match Xpriv::new_master(Network::Regtest, &seed) {
+fn get_master_key(seed: &[u8], network: Network) -> Xpriv {
+    let master_key = match Xpriv::new_master(network, seed) {
         Ok(key) => key,
         Err(_) => panic!("Your RNG is busted"),
     };
     master_key
 }
 
+/// The BIP84 (wpkh) and BIP86 (tr) accounts of an on-chain wallet, each
+/// with a receive (0) and change (1) chain.
+fn onchain_wallet_descriptors(master_key: &Xpriv, network: Network) -> Vec<KeyDescriptor> {
+    let coin_type = if network == Network::Bitcoin { 0 } else { 1 };
+
+    let mut descriptors = Vec::new();
+    for (descriptor_type, purpose) in [(DescriptorType::Wpkh, 84), (DescriptorType::Tr, 86)] {
+        for chain in [0, 1] {
+            let origin = hardened_path(&[purpose, coin_type, 0]);
+            descriptors.push(KeyDescriptor::new(descriptor_type, master_key, origin, Some(chain)));
+        }
+    }
+    descriptors
+}
+
+fn hardened_path(indexes: &[u32]) -> DerivationPath {
+    indexes
+        .iter()
+        .map(|idx| ChildNumber::from_hardened_idx(*idx).unwrap())
+        .collect::<Vec<_>>()
+        .into()
+}
+
 fn get_hardened_child_private_key(master_key: Xpriv, idx: u32) -> SecretKey {
     let secp_ctx = Secp256k1::new();
     let hardened_child = master_key
@@ -110,47 +436,28 @@ fn get_hardened_extended_child_private_key(master_key: Xpriv, idx: u32) -> Xpriv
     hardened_extended_child
 }
 
+/// The child index and user channel ID a `ChannelKeys::channel_keys_id`
+/// is made of.
+fn split_channel_keys_id(channel_keys_id: [u8; 32]) -> (u32, u128) {
+    let mut channel_child_index = [0; 4];
+    channel_child_index.copy_from_slice(&channel_keys_id[..4]);
+    let mut user_channel_id = [0; 16];
+    user_channel_id.copy_from_slice(&channel_keys_id[4..20]);
+    (u32::from_be_bytes(channel_child_index), u128::from_be_bytes(user_channel_id))
+}
+
 fn get_public_key(private_key: SecretKey) -> PublicKey {
     let secp_ctx = Secp256k1::new();
     let public_key = PublicKey::from_secret_key(&secp_ctx, &private_key);
     public_key
 }
 
-pub fn new_simple_key_manager(seed: [u8; 32]) -> SimpleKeysManager {
-    let secp_ctx = Secp256k1::new();
-    
-    let master_key = get_master_key(seed);
-
-    let node_secret = get_hardened_child_private_key(master_key, 0);
-
-    let node_id = get_public_key(node_secret);
-
-    let unilateral_close_private_key = get_hardened_child_private_key(master_key, 1);
-    let unilateral_close_pubkey = get_public_key(unilateral_close_private_key);
-
-    let coop_close_private_key = get_hardened_child_private_key(master_key, 2);
-    let coop_close_pubkey = get_public_key(unilateral_close_private_key);
-
-    let channel_master_key = get_hardened_extended_child_private_key(master_key, 3);
-
-    let inbound_payment_key = get_hardened_child_private_key(master_key, 5);
-
-    SimpleKeysManager {
-        secp_ctx: secp_ctx,
-        node_secret: node_secret,
-        node_id: node_id,
-        unilateral_close_pubkey: unilateral_close_pubkey,
-        coop_close_pubkey: coop_close_pubkey,
-        channel_master_key: channel_master_key,
-        inbound_payment_key: inbound_payment_key,
-        channel_child_index: 0,
-        seed: seed,
-    }
+pub fn new_simple_key_manager(seed: [u8; 32], network: Network) -> SimpleKeysManager {
+    SimpleKeysManager::new_for_network(seed, network)
 }
 
-pub fn unified_onchain_offchain_wallet(seed: [u8; 64]) -> KeysManager {
-    // Other supported networks include mainnet (Bitcoin), Regtest, Signet
-    let master_xprv = Xpriv::new_master(Network::Testnet, &seed).unwrap();
+pub fn unified_onchain_offchain_wallet(seed: [u8; 64], network: Network) -> KeysManager {
+    let master_xprv = get_master_key(&seed, network);
     let secp = Secp256k1::new();
     let xprv: Xpriv = master_xprv
         .derive_priv(&secp, &ChildNumber::from_hardened_idx(535).unwrap())
@@ -164,3 +471,16 @@ pub fn unified_onchain_offchain_wallet(seed: [u8; 64]) -> KeysManager {
     let keys_manager = KeysManager::new(&ldk_seed, cur.as_secs(), cur.subsec_nanos());
     keys_manager
 }
+
+/// The unified wallet for a BIP39 mnemonic, seeded with its full 64-byte
+/// seed like any other BIP32 wallet.
+pub fn unified_onchain_offchain_wallet_from_mnemonic(mnemonic: &Mnemonic, passphrase: &str,
+                                                     network: Network) -> KeysManager {
+    unified_onchain_offchain_wallet(mnemonic.to_seed(passphrase), network)
+}
+
+/// Watch-only descriptors for the on-chain side of the unified wallet,
+/// which lives next to LDK's keys at m/535h.
+pub fn unified_onchain_wallet_descriptors(seed: [u8; 64], network: Network) -> Vec<KeyDescriptor> {
+    onchain_wallet_descriptors(&get_master_key(&seed, network), network)
+}
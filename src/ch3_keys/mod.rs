@@ -1,3 +1,7 @@
+pub mod bip39;
+pub mod descriptor;
 pub mod exercises;
+pub mod policy;
+pub mod remote_signer;
 pub mod solutions;
 pub mod test;
\ No newline at end of file
@@ -0,0 +1,570 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! A signing policy: checks every request before it reaches our keys.
+//!
+//! A signer that signs whatever it is given is only as safe as the node
+//! asking. The policy refuses the signatures that could lose us funds:
+//!
+//! * a commitment the counterparty already revoked, or a second, different
+//!   commitment with a number we already signed. The number is the one
+//!   obscured in the transaction itself, not one the node tells us
+//! * a mutual close paying anywhere but our own scripts and the shutdown
+//!   script the counterparty gave us
+//! * a justice transaction paying anywhere but our own scripts
+//! * an HTLC transaction whose locktime does not match the HTLC's CLTV
+//! * any transaction paying more than a maximum fee rate, or whose fee we
+//!   can't tell
+//! * anything signed with our node secret but gossip
+//!
+//! What it learns about each channel is written to a JSON file before the
+//! signature is handed out, so a restart does not make old states signable.
+use crate::ch1_intro_htlcs::solutions::{
+    commitment_number_from_transaction, commitment_number_obscure_factor, estimate_signed_weight, InputSatisfaction,
+};
+use crate::ch3_keys::exercises::SimpleKeysManager;
+use crate::ch3_keys::remote_signer::{gossip_signed_region, Signer, SignerError};
+use crate::internal::helper::p2wpkh_output_script;
+use crate::internal::script_disasm::{identify_template, ScriptTemplate};
+use bitcoin::script::ScriptBuf;
+use bitcoin::secp256k1::ecdh::SharedSecret;
+use bitcoin::secp256k1::ecdsa::Signature;
+use bitcoin::secp256k1::{PublicKey, Scalar, SecretKey};
+use bitcoin::Transaction;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// 50 sat/vbyte.
+pub const DEFAULT_MAX_FEERATE_PER_KW: u64 = 12_500;
+
+/// Commitment numbers are obscured into 48 bits.
+const MAX_COMMITMENT_NUMBER: u64 = (1 << 48) - 1;
+
+/// Why the policy refused to sign.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PolicyDenial {
+    /// The counterparty revoked this commitment, or a later one.
+    RevokedCommitment { commitment_number: u64, latest_revoked: u64 },
+    /// We already signed a different transaction as this commitment.
+    ConflictingCommitment { commitment_number: u64, signed_txid: String },
+    /// A revocation for a commitment older than one already revoked.
+    StaleRevocation { commitment_number: u64, latest_revoked: u64 },
+    /// A commitment number that does not fit in 48 bits.
+    InvalidCommitmentNumber(u64),
+    /// The transaction's locktime and sequence do not carry an obscured
+    /// commitment number.
+    NotACommitment,
+    /// We were asked to sign a commitment before `ready_channel`.
+    ChannelNotReady,
+    /// `ready_channel` was called again with different parameters.
+    ChannelParametersChanged,
+    /// A commitment or mutual close that does not spend the funding output
+    /// alone.
+    UnexpectedInputCount(usize),
+    /// An output pays to a script that is neither ours nor the
+    /// counterparty's shutdown script.
+    UnknownScript(String),
+    /// A mutual close before the counterparty gave us their shutdown
+    /// script.
+    NoShutdownScript,
+    /// The counterparty's shutdown script differs from the one they gave
+    /// us before.
+    ShutdownScriptChanged(String),
+    /// The HTLC transaction's locktime (or the HTLC script's CLTV) is not
+    /// the HTLC's `cltv_expiry`.
+    CltvMismatch { expected: u32, actual: u32 },
+    /// The HTLC transaction does not spend a known HTLC script.
+    UnknownHtlcScript,
+    /// The transaction pays a higher fee rate than we allow.
+    FeeRateTooHigh { feerate_per_kw: u64, max_feerate_per_kw: u64 },
+    /// The transaction spends inputs we don't know the amounts of.
+    UnknownFee,
+    /// A message to sign with our node secret that is not a
+    /// `channel_announcement`, `node_announcement` or `channel_update`.
+    NotGossip,
+}
+
+impl fmt::Display for PolicyDenial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolicyDenial::RevokedCommitment { commitment_number, latest_revoked } => write!(
+                f,
+                "commitment {} is revoked, the latest revoked commitment is {}",
+                commitment_number, latest_revoked
+            ),
+            PolicyDenial::ConflictingCommitment { commitment_number, signed_txid } => write!(
+                f,
+                "commitment {} was already signed as {}",
+                commitment_number, signed_txid
+            ),
+            PolicyDenial::StaleRevocation { commitment_number, latest_revoked } => write!(
+                f,
+                "revocation of commitment {} after commitment {} was revoked",
+                commitment_number, latest_revoked
+            ),
+            PolicyDenial::InvalidCommitmentNumber(commitment_number) => {
+                write!(f, "commitment number {} does not fit in 48 bits", commitment_number)
+            }
+            PolicyDenial::NotACommitment => write!(f, "not a commitment transaction"),
+            PolicyDenial::ChannelNotReady => write!(f, "channel is not ready"),
+            PolicyDenial::ChannelParametersChanged => write!(f, "channel parameters changed"),
+            PolicyDenial::UnexpectedInputCount(count) => {
+                write!(f, "expected the funding input alone, got {} inputs", count)
+            }
+            PolicyDenial::UnknownScript(script) => write!(f, "output pays to unknown script {}", script),
+            PolicyDenial::NoShutdownScript => write!(f, "no shutdown script from the counterparty"),
+            PolicyDenial::ShutdownScriptChanged(script) => write!(f, "shutdown script changed to {}", script),
+            PolicyDenial::CltvMismatch { expected, actual } => {
+                write!(f, "CLTV of {} does not match the HTLC's {}", actual, expected)
+            }
+            PolicyDenial::UnknownHtlcScript => write!(f, "not an HTLC script"),
+            PolicyDenial::FeeRateTooHigh { feerate_per_kw, max_feerate_per_kw } => write!(
+                f,
+                "fee rate of {} sat/kw is above the maximum of {} sat/kw",
+                feerate_per_kw, max_feerate_per_kw
+            ),
+            PolicyDenial::UnknownFee => write!(f, "can't tell the transaction's fee"),
+            PolicyDenial::NotGossip => write!(f, "not a gossip message"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolicyConfig {
+    pub max_feerate_per_kw: u64,
+    /// Scripts of ours a mutual close or justice transaction may pay to.
+    pub allowed_scripts: Vec<ScriptBuf>,
+}
+
+impl PolicyConfig {
+    /// The default ceiling, with our closes paying to our shutdown script
+    /// or the script our force closes are swept to.
+    pub fn for_keys_manager(keys_manager: &SimpleKeysManager) -> PolicyConfig {
+        PolicyConfig {
+            max_feerate_per_kw: DEFAULT_MAX_FEERATE_PER_KW,
+            allowed_scripts: vec![
+                keys_manager.get_shutdown_scriptpubkey(),
+                p2wpkh_output_script(keys_manager.unilateral_close_pubkey),
+            ],
+        }
+    }
+}
+
+/// What the policy remembers about a channel.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelPolicyState {
+    /// What the channel's commitment numbers are obscured with, once
+    /// `ready_channel` told us.
+    pub commitment_number_obscure_factor: Option<u64>,
+    /// The highest commitment number the counterparty revoked.
+    pub latest_revoked: Option<u64>,
+    /// Txids of the commitments we signed and that are not revoked yet, by
+    /// commitment number.
+    pub signed_commitments: BTreeMap<u64, String>,
+    /// The counterparty's shutdown script, hex encoded, once they gave it
+    /// to us.
+    pub counterparty_shutdown_script: Option<String>,
+}
+
+/// The policy's state for every channel, keyed by hex channel keys id.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyState {
+    pub channels: HashMap<String, ChannelPolicyState>,
+}
+
+impl PolicyState {
+    /// Reads the state written by `save`, or a fresh state if there is no
+    /// file yet.
+    pub fn load(path: &Path) -> Result<PolicyState, SignerError> {
+        if !path.exists() {
+            return Ok(PolicyState::default());
+        }
+        let json = fs::read_to_string(path).map_err(|e| SignerError::Unavailable(e.to_string()))?;
+        serde_json::from_str(&json).map_err(|e| SignerError::Unavailable(e.to_string()))
+    }
+
+    /// The channel keys IDs of every channel the policy has seen.
+    pub fn channel_keys_ids(&self) -> Vec<[u8; 32]> {
+        self.channels
+            .keys()
+            .filter_map(|channel_keys_id| hex::decode(channel_keys_id).ok()?.try_into().ok())
+            .collect()
+    }
+
+    /// Writes the state to a temporary file and moves it over `path`, so a
+    /// crash leaves either the old or the new state behind.
+    pub fn save(&self, path: &Path) -> Result<(), SignerError> {
+        let json = serde_json::to_string(self).expect("policy state serializes");
+        let tmp_path = path.with_extension("tmp");
+        // The state has to be on disk, not just in the page cache, before we
+        // hand out the signature, and so does the rename.
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::create(&tmp_path)
+            .and_then(|mut file| {
+                file.write_all(json.as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp_path, path))
+            .and_then(|_| File::open(dir)?.sync_all())
+            .map_err(|e| SignerError::Unavailable(format!("can't save policy state: {}", e)))
+    }
+}
+
+/// A `Signer` that only passes on the requests its policy allows.
+pub struct PolicySigner<S: Signer> {
+    signer: S,
+    config: PolicyConfig,
+    state: Mutex<PolicyState>,
+    state_path: Option<PathBuf>,
+}
+
+impl<S: Signer> PolicySigner<S> {
+    /// A policy that only keeps its state in memory.
+    pub fn new(signer: S, config: PolicyConfig) -> PolicySigner<S> {
+        PolicySigner { signer, config, state: Mutex::new(PolicyState::default()), state_path: None }
+    }
+
+    /// A policy that picks up the state saved at `state_path` and saves
+    /// every change to it.
+    pub fn with_state_file(signer: S, config: PolicyConfig, state_path: PathBuf) -> Result<PolicySigner<S>, SignerError> {
+        let state = PolicyState::load(&state_path)?;
+        Ok(PolicySigner { signer, config, state: Mutex::new(state), state_path: Some(state_path) })
+    }
+
+    /// The signer the policy passes requests on to.
+    pub fn inner(&self) -> &S {
+        &self.signer
+    }
+
+    pub fn state(&self) -> PolicyState {
+        self.lock_state().clone()
+    }
+
+    /// The state is only replaced whole, once a check and its signature
+    /// went through, so a panic while it was locked can't have left it half
+    /// updated.
+    fn lock_state(&self) -> MutexGuard<'_, PolicyState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Runs `check` against a copy of the channel's state. If it passes,
+    /// signs with `sign` and only then saves the updated state.
+    fn with_channel_state<T, C, F>(&self, channel_keys_id: [u8; 32], check: C, sign: F) -> Result<T, SignerError>
+    where
+        C: FnOnce(&mut ChannelPolicyState) -> Result<(), PolicyDenial>,
+        F: FnOnce() -> Result<T, SignerError>,
+    {
+        let mut state = self.lock_state();
+        let mut new_state = state.clone();
+        let channel = new_state.channels.entry(hex::encode(channel_keys_id)).or_default();
+        check(channel).map_err(SignerError::PolicyDenied)?;
+
+        let result = sign()?;
+        if *state != new_state {
+            if let Some(path) = &self.state_path {
+                new_state.save(path)?;
+            }
+            *state = new_state;
+        }
+        Ok(result)
+    }
+
+    /// `inputs` holds the amount and the satisfaction of each of `tx`'s
+    /// inputs, in order.
+    fn check_feerate(&self, tx: &Transaction, inputs: &[(u64, InputSatisfaction)]) -> Result<(), PolicyDenial> {
+        if inputs.len() != tx.input.len() {
+            return Err(PolicyDenial::UnknownFee);
+        }
+        let input_amount = inputs
+            .iter()
+            .try_fold(0u64, |total, (amount, _)| total.checked_add(*amount))
+            .ok_or(PolicyDenial::UnknownFee)?;
+        let output_amount = tx
+            .output
+            .iter()
+            .try_fold(0u64, |total, output| total.checked_add(output.value.to_sat()))
+            .ok_or(PolicyDenial::UnknownFee)?;
+        let fee = input_amount.saturating_sub(output_amount);
+        let satisfactions: Vec<InputSatisfaction> =
+            inputs.iter().map(|(_, satisfaction)| satisfaction.clone()).collect();
+        // A fee too large to scale is too high for any ceiling.
+        let feerate_per_kw = fee
+            .checked_mul(1000)
+            .map_or(u64::MAX, |fee| fee / estimate_signed_weight(tx, &satisfactions));
+        if feerate_per_kw > self.config.max_feerate_per_kw {
+            return Err(PolicyDenial::FeeRateTooHigh {
+                feerate_per_kw,
+                max_feerate_per_kw: self.config.max_feerate_per_kw,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn check_funding_input(tx: &Transaction) -> Result<(), PolicyDenial> {
+    if tx.input.len() != 1 {
+        return Err(PolicyDenial::UnexpectedInputCount(tx.input.len()));
+    }
+    Ok(())
+}
+
+/// The commitment number obscured in a commitment transaction, whose
+/// locktime and sequence BOLT 3 marks with an upper byte of 0x20 and 0x80.
+fn commitment_number(tx: &Transaction, obscure_factor: u64) -> Result<u64, PolicyDenial> {
+    check_funding_input(tx)?;
+    if tx.lock_time.to_consensus_u32() >> 24 != 0x20 || tx.input[0].sequence.0 >> 24 != 0x80 {
+        return Err(PolicyDenial::NotACommitment);
+    }
+    Ok(commitment_number_from_transaction(tx, obscure_factor))
+}
+
+fn check_scripts_allowed(tx: &Transaction, allowed_scripts: &[ScriptBuf]) -> Result<(), PolicyDenial> {
+    for output in &tx.output {
+        if !allowed_scripts.contains(&output.script_pubkey) {
+            return Err(PolicyDenial::UnknownScript(output.script_pubkey.to_hex_string()));
+        }
+    }
+    Ok(())
+}
+
+impl<S: Signer> Signer for PolicySigner<S> {
+    fn node_id(&self) -> Result<PublicKey, SignerError> {
+        self.signer.node_id()
+    }
+
+    fn ecdh(&self, other_key: &PublicKey, tweak: Option<&Scalar>) -> Result<SharedSecret, SignerError> {
+        self.signer.ecdh(other_key, tweak)
+    }
+
+    /// Gossip only announces what is public anyway, so it is always signed,
+    /// but nothing else is signed with our node secret.
+    fn sign_gossip(&self, msg: &[u8]) -> Result<Signature, SignerError> {
+        gossip_signed_region(msg).ok_or(SignerError::PolicyDenied(PolicyDenial::NotGossip))?;
+        self.signer.sign_gossip(msg)
+    }
+
+    /// Keeps a record of the new channel, so the signer can restore it after
+    /// a restart.
+    fn new_channel_keys_id(&self, channel_value_satoshis: u64, user_channel_id: u128)
+                           -> Result<[u8; 32], SignerError> {
+        let channel_keys_id = self.signer.new_channel_keys_id(channel_value_satoshis, user_channel_id)?;
+        self.with_channel_state(channel_keys_id, |_| Ok(()), || Ok(channel_keys_id))
+    }
+
+    fn payment_basepoint(&self, channel_keys_id: [u8; 32]) -> Result<PublicKey, SignerError> {
+        self.signer.payment_basepoint(channel_keys_id)
+    }
+
+    fn ready_channel(&self, channel_keys_id: [u8; 32], holder_is_opener: bool,
+                     counterparty_payment_basepoint: &PublicKey) -> Result<(), SignerError> {
+        let holder_payment_basepoint = self.signer.payment_basepoint(channel_keys_id)?;
+        let obscure_factor = if holder_is_opener {
+            commitment_number_obscure_factor(&holder_payment_basepoint, counterparty_payment_basepoint)
+        } else {
+            commitment_number_obscure_factor(counterparty_payment_basepoint, &holder_payment_basepoint)
+        };
+        self.with_channel_state(
+            channel_keys_id,
+            |channel| {
+                match channel.commitment_number_obscure_factor {
+                    Some(factor) if factor != obscure_factor => return Err(PolicyDenial::ChannelParametersChanged),
+                    _ => {}
+                }
+
+                channel.commitment_number_obscure_factor = Some(obscure_factor);
+                Ok(())
+            },
+            || self.signer.ready_channel(channel_keys_id, holder_is_opener, counterparty_payment_basepoint),
+        )
+    }
+
+    fn sign_commitment(&self, channel_keys_id: [u8; 32], commitment_tx: &Transaction,
+                       funding_redeem_script: &ScriptBuf, channel_value_satoshis: u64)
+                       -> Result<Signature, SignerError> {
+        let txid = commitment_tx.compute_txid().to_string();
+        self.with_channel_state(
+            channel_keys_id,
+            |channel| {
+                let obscure_factor = channel.commitment_number_obscure_factor.ok_or(PolicyDenial::ChannelNotReady)?;
+                let commitment_number = commitment_number(commitment_tx, obscure_factor)?;
+                if let Some(latest_revoked) = channel.latest_revoked {
+                    if commitment_number <= latest_revoked {
+                        return Err(PolicyDenial::RevokedCommitment { commitment_number, latest_revoked });
+                    }
+                }
+                match channel.signed_commitments.get(&commitment_number) {
+                    Some(signed_txid) if *signed_txid != txid => {
+                        return Err(PolicyDenial::ConflictingCommitment {
+                            commitment_number,
+                            signed_txid: signed_txid.clone(),
+                        });
+                    }
+                    _ => {}
+                }
+                self.check_feerate(
+                    commitment_tx,
+                    &[(channel_value_satoshis, InputSatisfaction::Multisig(funding_redeem_script.clone()))],
+                )?;
+
+                channel.signed_commitments.insert(commitment_number, txid);
+                Ok(())
+            },
+            || {
+                self.signer.sign_commitment(
+                    channel_keys_id,
+                    commitment_tx,
+                    funding_redeem_script,
+                    channel_value_satoshis,
+                )
+            },
+        )
+    }
+
+    fn validate_counterparty_revocation(&self, channel_keys_id: [u8; 32], commitment_number: u64,
+                                        per_commitment_point: &PublicKey, per_commitment_secret: &SecretKey)
+                                        -> Result<(), SignerError> {
+        self.with_channel_state(
+            channel_keys_id,
+            |channel| {
+                let next_commitment_number = commitment_number
+                    .checked_add(1)
+                    .filter(|_| commitment_number <= MAX_COMMITMENT_NUMBER)
+                    .ok_or(PolicyDenial::InvalidCommitmentNumber(commitment_number))?;
+                if let Some(latest_revoked) = channel.latest_revoked {
+                    if commitment_number < latest_revoked {
+                        return Err(PolicyDenial::StaleRevocation { commitment_number, latest_revoked });
+                    }
+                }
+
+                channel.latest_revoked = Some(commitment_number);
+                // Revoked commitments can never be signed again, so there is
+                // nothing left to compare them against.
+                channel.signed_commitments = channel.signed_commitments.split_off(&next_commitment_number);
+                Ok(())
+            },
+            || {
+                self.signer.validate_counterparty_revocation(
+                    channel_keys_id,
+                    commitment_number,
+                    per_commitment_point,
+                    per_commitment_secret,
+                )
+            },
+        )
+    }
+
+    fn set_counterparty_shutdown_script(&self, channel_keys_id: [u8; 32], shutdown_script: &ScriptBuf)
+                                        -> Result<(), SignerError> {
+        self.with_channel_state(
+            channel_keys_id,
+            |channel| {
+                let shutdown_script = shutdown_script.to_hex_string();
+                match &channel.counterparty_shutdown_script {
+                    Some(script) if *script != shutdown_script => {
+                        return Err(PolicyDenial::ShutdownScriptChanged(shutdown_script));
+                    }
+                    _ => {}
+                }
+
+                channel.counterparty_shutdown_script = Some(shutdown_script);
+                Ok(())
+            },
+            || self.signer.set_counterparty_shutdown_script(channel_keys_id, shutdown_script),
+        )
+    }
+
+    fn sign_closing(&self, channel_keys_id: [u8; 32], closing_tx: &Transaction, funding_redeem_script: &ScriptBuf,
+                    channel_value_satoshis: u64) -> Result<Signature, SignerError> {
+        self.with_channel_state(
+            channel_keys_id,
+            |channel| {
+                check_funding_input(closing_tx)?;
+                let shutdown_script = channel.counterparty_shutdown_script.as_ref().ok_or(PolicyDenial::NoShutdownScript)?;
+                // Our output may be missing altogether: with nothing or dust
+                // left to us, the close only pays the counterparty.
+                for output in &closing_tx.output {
+                    if !self.config.allowed_scripts.contains(&output.script_pubkey)
+                        && output.script_pubkey.to_hex_string() != *shutdown_script
+                    {
+                        return Err(PolicyDenial::UnknownScript(output.script_pubkey.to_hex_string()));
+                    }
+                }
+                self.check_feerate(
+                    closing_tx,
+                    &[(channel_value_satoshis, InputSatisfaction::Multisig(funding_redeem_script.clone()))],
+                )
+            },
+            || self.signer.sign_closing(channel_keys_id, closing_tx, funding_redeem_script, channel_value_satoshis),
+        )
+    }
+
+    /// An HTLC-timeout transaction spends an offered HTLC and must not be
+    /// valid before the HTLC's `cltv_expiry`. An HTLC-success transaction
+    /// spends a received HTLC, whose script carries the `cltv_expiry`, and
+    /// has no locktime.
+    fn sign_htlc(&self, channel_keys_id: [u8; 32], htlc_tx: &Transaction, input_index: usize,
+                 htlc_redeem_script: &ScriptBuf, htlc_amount: u64, cltv_expiry: u32,
+                 per_commitment_point: &PublicKey) -> Result<Signature, SignerError> {
+        let lock_time = htlc_tx.lock_time.to_consensus_u32();
+        let satisfaction = match identify_template(htlc_redeem_script) {
+            Some(ScriptTemplate::OfferedHtlc { .. }) => {
+                if lock_time != cltv_expiry {
+                    return Err(SignerError::PolicyDenied(PolicyDenial::CltvMismatch {
+                        expected: cltv_expiry,
+                        actual: lock_time,
+                    }));
+                }
+                InputSatisfaction::HtlcTimeout(htlc_redeem_script.clone())
+            }
+            Some(ScriptTemplate::ReceivedHtlc { cltv_expiry: script_cltv_expiry, .. }) => {
+                if script_cltv_expiry != cltv_expiry as i64 {
+                    return Err(SignerError::PolicyDenied(PolicyDenial::CltvMismatch {
+                        expected: cltv_expiry,
+                        actual: script_cltv_expiry as u32,
+                    }));
+                }
+                if lock_time != 0 {
+                    return Err(SignerError::PolicyDenied(PolicyDenial::CltvMismatch { expected: 0, actual: lock_time }));
+                }
+                InputSatisfaction::HtlcSuccess(htlc_redeem_script.clone())
+            }
+            _ => return Err(SignerError::PolicyDenied(PolicyDenial::UnknownHtlcScript)),
+        };
+        self.check_feerate(htlc_tx, &[(htlc_amount, satisfaction)]).map_err(SignerError::PolicyDenied)?;
+
+        self.signer.sign_htlc(
+            channel_keys_id,
+            htlc_tx,
+            input_index,
+            htlc_redeem_script,
+            htlc_amount,
+            cltv_expiry,
+            per_commitment_point,
+        )
+    }
+
+    fn sign_justice(&self, channel_keys_id: [u8; 32], justice_tx: &Transaction, input_index: usize,
+                    redeem_scripts: &[ScriptBuf], amounts: &[u64], per_commitment_secret: &SecretKey)
+                    -> Result<Signature, SignerError> {
+        if redeem_scripts.len() != amounts.len() {
+            return Err(SignerError::PolicyDenied(PolicyDenial::UnknownFee));
+        }
+        let inputs: Vec<(u64, InputSatisfaction)> = amounts
+            .iter()
+            .zip(redeem_scripts)
+            .map(|(amount, redeem_script)| (*amount, InputSatisfaction::Revocation(redeem_script.clone())))
+            .collect();
+        check_scripts_allowed(justice_tx, &self.config.allowed_scripts)
+            .and_then(|_| self.check_feerate(justice_tx, &inputs))
+            .map_err(SignerError::PolicyDenied)?;
+
+        self.signer.sign_justice(channel_keys_id, justice_tx, input_index, redeem_scripts, amounts,
+                                 per_commitment_secret)
+    }
+}
@@ -0,0 +1,716 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! A remote signer: `SimpleKeysManager` running in its own process, so the
+//! node never holds our node secret or channel keys.
+//!
+//! The node itself doesn't use it yet: it still hands LDK an in-process
+//! `SimpleKeysManager`. That takes LDK's `NodeSigner`, `SignerProvider` and
+//! `EcdsaChannelSigner` implemented on top of `RemoteSigner`.
+//!
+//! The node and the signer talk over a local Unix socket. Every request is
+//! a line of JSON, answered by a line of JSON. Like bitcoind's RPC, keys,
+//! signatures, scripts and transactions travel hex encoded.
+use crate::ch1_intro_htlcs::solutions::{derive_private_key, derive_private_revocation_key};
+use crate::ch3_keys::exercises::{ChannelKeys, SimpleKeysManager};
+use crate::ch3_keys::policy::PolicyDenial;
+use crate::interactive::helper::generate_p2wsh_signature;
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::script::ScriptBuf;
+use bitcoin::secp256k1::ecdh::SharedSecret;
+use bitcoin::secp256k1::ecdsa::Signature;
+use bitcoin::secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey};
+use bitcoin::sighash::EcdsaSighashType;
+use bitcoin::Transaction;
+use lightning::sign::{NodeSigner, Recipient};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignerError {
+    /// We could not reach the signer, or it hung up on us.
+    Unavailable(String),
+    /// The request (or its response) could not be parsed.
+    InvalidMessage(String),
+    /// The signer understood the request but could not sign it.
+    Rejected(String),
+    /// The signer's policy refused to sign.
+    PolicyDenied(PolicyDenial),
+}
+
+impl fmt::Display for SignerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignerError::Unavailable(reason) => write!(f, "signer unavailable: {}", reason),
+            SignerError::InvalidMessage(reason) => write!(f, "invalid signer message: {}", reason),
+            SignerError::Rejected(reason) => write!(f, "signer rejected the request: {}", reason),
+            SignerError::PolicyDenied(denial) => write!(f, "signer policy denied the request: {}", denial),
+        }
+    }
+}
+
+/// Everything the node asks of its keys. `SimpleKeysManager` signs in
+/// process, `RemoteSigner` forwards each call to a `signer-daemon`.
+///
+/// Channel keys are picked by `channel_keys_id`, as in LDK's
+/// `SignerProvider`.
+pub trait Signer {
+    fn node_id(&self) -> Result<PublicKey, SignerError>;
+
+    /// The ECDH secret of our node secret (multiplied by `tweak`, if any)
+    /// and `other_key`, for BOLT 8 and onion packets.
+    fn ecdh(&self, other_key: &PublicKey, tweak: Option<&Scalar>) -> Result<SharedSecret, SignerError>;
+
+    /// Signs a `channel_announcement`, `node_announcement` or
+    /// `channel_update` with our node secret. `msg` is the whole message,
+    /// type and (placeholder) signatures included, and the signature covers
+    /// the double SHA256 of what follows the signatures.
+    fn sign_gossip(&self, msg: &[u8]) -> Result<Signature, SignerError>;
+
+    /// Derives the keys for a new channel, like LDK's
+    /// `generate_channel_keys_id`, and returns the ID that picks them in the
+    /// calls below.
+    fn new_channel_keys_id(&self, channel_value_satoshis: u64, user_channel_id: u128)
+                           -> Result<[u8; 32], SignerError>;
+
+    /// Our payment basepoint for a channel.
+    fn payment_basepoint(&self, channel_keys_id: [u8; 32]) -> Result<PublicKey, SignerError>;
+
+    /// Tells the signer who opened the channel and the counterparty's
+    /// payment basepoint from `open_channel` or `accept_channel`, which
+    /// together obscure the channel's commitment numbers. Comes before the
+    /// first `sign_commitment`.
+    fn ready_channel(&self, channel_keys_id: [u8; 32], holder_is_opener: bool,
+                     counterparty_payment_basepoint: &PublicKey) -> Result<(), SignerError>;
+
+    /// Our signature on the funding input of our counterparty's commitment
+    /// transaction. Its commitment number is the one obscured in its
+    /// locktime and sequence.
+    fn sign_commitment(&self, channel_keys_id: [u8; 32], commitment_tx: &Transaction,
+                       funding_redeem_script: &ScriptBuf, channel_value_satoshis: u64)
+                       -> Result<Signature, SignerError>;
+
+    /// Tells the signer our counterparty revoked their commitment number
+    /// `commitment_number`, handing us the `per_commitment_secret` of the
+    /// `per_commitment_point` they gave us for it.
+    fn validate_counterparty_revocation(&self, channel_keys_id: [u8; 32], commitment_number: u64,
+                                        per_commitment_point: &PublicKey, per_commitment_secret: &SecretKey)
+                                        -> Result<(), SignerError>;
+
+    /// Tells the signer the script from our counterparty's `shutdown`, the
+    /// only script of theirs a mutual close may pay to.
+    fn set_counterparty_shutdown_script(&self, channel_keys_id: [u8; 32], shutdown_script: &ScriptBuf)
+                                        -> Result<(), SignerError>;
+
+    /// Our signature on the funding input of a mutual close transaction.
+    fn sign_closing(&self, channel_keys_id: [u8; 32], closing_tx: &Transaction, funding_redeem_script: &ScriptBuf,
+                    channel_value_satoshis: u64) -> Result<Signature, SignerError>;
+
+    /// Our signature on an HTLC output of a commitment transaction, with our
+    /// HTLC key for the commitment's `per_commitment_point`. `cltv_expiry`
+    /// is the HTLC's, as agreed in `update_add_htlc`.
+    #[allow(clippy::too_many_arguments)]
+    fn sign_htlc(&self, channel_keys_id: [u8; 32], htlc_tx: &Transaction, input_index: usize,
+                 htlc_redeem_script: &ScriptBuf, htlc_amount: u64, cltv_expiry: u32,
+                 per_commitment_point: &PublicKey) -> Result<Signature, SignerError>;
+
+    /// Our signature on input `input_index` of a justice transaction, which
+    /// spends outputs of a revoked commitment transaction with the
+    /// revocation key its `per_commitment_secret` gives us.
+    /// `redeem_scripts` and `amounts` are those of every input, in order.
+    #[allow(clippy::too_many_arguments)]
+    fn sign_justice(&self, channel_keys_id: [u8; 32], justice_tx: &Transaction, input_index: usize,
+                    redeem_scripts: &[ScriptBuf], amounts: &[u64], per_commitment_secret: &SecretKey)
+                    -> Result<Signature, SignerError>;
+}
+
+impl Signer for SimpleKeysManager {
+    fn node_id(&self) -> Result<PublicKey, SignerError> {
+        Ok(self.node_id)
+    }
+
+    fn ecdh(&self, other_key: &PublicKey, tweak: Option<&Scalar>) -> Result<SharedSecret, SignerError> {
+        NodeSigner::ecdh(self, Recipient::Node, other_key, tweak)
+            .map_err(|_| SignerError::Rejected("invalid tweak".to_string()))
+    }
+
+    fn sign_gossip(&self, msg: &[u8]) -> Result<Signature, SignerError> {
+        let signed_region = gossip_signed_region(msg)
+            .ok_or_else(|| SignerError::Rejected("not a gossip message we sign".to_string()))?;
+        Ok(self.sign_gossip_message(signed_region))
+    }
+
+    fn new_channel_keys_id(&self, channel_value_satoshis: u64, user_channel_id: u128)
+                           -> Result<[u8; 32], SignerError> {
+        Ok(self.derive_channel_keys(channel_value_satoshis, user_channel_id).channel_keys_id())
+    }
+
+    fn payment_basepoint(&self, channel_keys_id: [u8; 32]) -> Result<PublicKey, SignerError> {
+        Ok(self.requested_channel_keys(0, channel_keys_id)?.payment_point())
+    }
+
+    fn ready_channel(&self, channel_keys_id: [u8; 32], holder_is_opener: bool,
+                     counterparty_payment_basepoint: &PublicKey) -> Result<(), SignerError> {
+        self.requested_channel_keys(0, channel_keys_id).map(|_| ())
+    }
+
+    fn sign_commitment(&self, channel_keys_id: [u8; 32], commitment_tx: &Transaction,
+                       funding_redeem_script: &ScriptBuf, channel_value_satoshis: u64)
+                       -> Result<Signature, SignerError> {
+        self.sign_funding_input(channel_keys_id, commitment_tx, funding_redeem_script, channel_value_satoshis)
+    }
+
+    fn validate_counterparty_revocation(&self, channel_keys_id: [u8; 32], commitment_number: u64,
+                                        per_commitment_point: &PublicKey, per_commitment_secret: &SecretKey)
+                                        -> Result<(), SignerError> {
+        self.requested_channel_keys(0, channel_keys_id)?;
+        if PublicKey::from_secret_key(&Secp256k1::new(), per_commitment_secret) != *per_commitment_point {
+            return Err(SignerError::Rejected(format!(
+                "per_commitment_secret of commitment {} does not match its per_commitment_point",
+                commitment_number
+            )));
+        }
+        Ok(())
+    }
+
+    fn set_counterparty_shutdown_script(&self, channel_keys_id: [u8; 32], shutdown_script: &ScriptBuf)
+                                        -> Result<(), SignerError> {
+        self.requested_channel_keys(0, channel_keys_id).map(|_| ())
+    }
+
+    fn sign_closing(&self, channel_keys_id: [u8; 32], closing_tx: &Transaction, funding_redeem_script: &ScriptBuf,
+                    channel_value_satoshis: u64) -> Result<Signature, SignerError> {
+        self.sign_funding_input(channel_keys_id, closing_tx, funding_redeem_script, channel_value_satoshis)
+    }
+
+    fn sign_htlc(&self, channel_keys_id: [u8; 32], htlc_tx: &Transaction, input_index: usize,
+                 htlc_redeem_script: &ScriptBuf, htlc_amount: u64, cltv_expiry: u32,
+                 per_commitment_point: &PublicKey) -> Result<Signature, SignerError> {
+        let channel_keys = self.requested_channel_keys(0, channel_keys_id)?;
+        check_input(htlc_tx, input_index)?;
+
+        let htlc_key = derive_private_key(&channel_keys.htlc_base_key, per_commitment_point);
+        Ok(generate_p2wsh_signature(
+            htlc_tx.clone(),
+            input_index,
+            htlc_redeem_script,
+            htlc_amount,
+            EcdsaSighashType::All,
+            htlc_key,
+        ))
+    }
+
+    fn sign_justice(&self, channel_keys_id: [u8; 32], justice_tx: &Transaction, input_index: usize,
+                    redeem_scripts: &[ScriptBuf], amounts: &[u64], per_commitment_secret: &SecretKey)
+                    -> Result<Signature, SignerError> {
+        let channel_keys = self.requested_channel_keys(0, channel_keys_id)?;
+        check_input(justice_tx, input_index)?;
+        let (redeem_script, amount) = match (redeem_scripts.get(input_index), amounts.get(input_index)) {
+            (Some(redeem_script), Some(amount)) => (redeem_script, *amount),
+            _ => return Err(SignerError::Rejected(format!("no redeem script and amount for input {}", input_index))),
+        };
+
+        let revocation_key = derive_private_revocation_key(per_commitment_secret, &channel_keys.revocation_base_key);
+        Ok(generate_p2wsh_signature(
+            justice_tx.clone(),
+            input_index,
+            redeem_script,
+            amount,
+            EcdsaSighashType::All,
+            revocation_key,
+        ))
+    }
+}
+
+impl SimpleKeysManager {
+    /// The keys for a channel keys ID from a request, which may not be one
+    /// we handed out.
+    fn requested_channel_keys(&self, channel_value_satoshis: u64,
+                              channel_keys_id: [u8; 32]) -> Result<ChannelKeys, SignerError> {
+        self.channel_keys_from_id(channel_value_satoshis, channel_keys_id)
+            .ok_or_else(|| SignerError::Rejected("unknown channel keys id".to_string()))
+    }
+
+    fn sign_funding_input(&self, channel_keys_id: [u8; 32], tx: &Transaction, funding_redeem_script: &ScriptBuf,
+                          channel_value_satoshis: u64) -> Result<Signature, SignerError> {
+        let channel_keys = self.requested_channel_keys(channel_value_satoshis, channel_keys_id)?;
+        check_input(tx, 0)?;
+
+        Ok(generate_p2wsh_signature(
+            tx.clone(),
+            0,
+            funding_redeem_script,
+            channel_value_satoshis,
+            EcdsaSighashType::All,
+            channel_keys.funding_key,
+        ))
+    }
+}
+
+/// The part of a `channel_announcement` (256), `node_announcement` (257) or
+/// `channel_update` (258) that its signatures cover: everything after the
+/// type and the signatures. `None` for any other message.
+pub fn gossip_signed_region(msg: &[u8]) -> Option<&[u8]> {
+    let msg_type = u16::from_be_bytes([*msg.first()?, *msg.get(1)?]);
+    let signature_count = match msg_type {
+        256 => 4,
+        257 | 258 => 1,
+        _ => return None,
+    };
+    msg.get(2 + 64 * signature_count..)
+}
+
+fn check_input(tx: &Transaction, input_index: usize) -> Result<(), SignerError> {
+    if input_index >= tx.input.len() {
+        return Err(SignerError::Rejected(format!("no input {}", input_index)));
+    }
+    Ok(())
+}
+
+/// A request on the wire.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum SignerRequest {
+    GetNodeId,
+    Ecdh {
+        other_key: String,
+        tweak: Option<String>,
+    },
+    SignGossip {
+        message: String,
+    },
+    NewChannelKeysId {
+        channel_value_satoshis: u64,
+        /// In decimal: tagged enums can't carry a u128.
+        user_channel_id: String,
+    },
+    PaymentBasepoint {
+        channel_keys_id: String,
+    },
+    ReadyChannel {
+        channel_keys_id: String,
+        holder_is_opener: bool,
+        counterparty_payment_basepoint: String,
+    },
+    SignCommitment {
+        channel_keys_id: String,
+        commitment_tx: String,
+        funding_redeem_script: String,
+        channel_value_satoshis: u64,
+    },
+    ValidateCounterpartyRevocation {
+        channel_keys_id: String,
+        commitment_number: u64,
+        per_commitment_point: String,
+        per_commitment_secret: String,
+    },
+    SetCounterpartyShutdownScript {
+        channel_keys_id: String,
+        shutdown_script: String,
+    },
+    SignClosing {
+        channel_keys_id: String,
+        closing_tx: String,
+        funding_redeem_script: String,
+        channel_value_satoshis: u64,
+    },
+    SignHtlc {
+        channel_keys_id: String,
+        htlc_tx: String,
+        input_index: usize,
+        htlc_redeem_script: String,
+        htlc_amount: u64,
+        cltv_expiry: u32,
+        per_commitment_point: String,
+    },
+    SignJustice {
+        channel_keys_id: String,
+        justice_tx: String,
+        input_index: usize,
+        redeem_scripts: Vec<String>,
+        amounts: Vec<u64>,
+        per_commitment_secret: String,
+    },
+}
+
+/// A response on the wire.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignerResponse {
+    NodeId(String),
+    ChannelKeysId(String),
+    PaymentBasepoint(String),
+    SharedSecret(String),
+    Signature(String),
+    /// The signer took note of a request that needs no signature.
+    Validated,
+    Error(SignerError),
+}
+
+/// Answers a request with `signer`. This is all the daemon does.
+pub fn handle_request<S: Signer>(signer: &S, request: SignerRequest) -> SignerResponse {
+    let response = match request {
+        SignerRequest::GetNodeId => signer.node_id().map(|node_id| SignerResponse::NodeId(node_id.to_string())),
+        SignerRequest::Ecdh { other_key, tweak } => parse_pubkey(&other_key)
+            .and_then(|other_key| {
+                let tweak = tweak.map(|tweak| parse_scalar(&tweak)).transpose()?;
+                signer.ecdh(&other_key, tweak.as_ref())
+            })
+            .map(|secret| SignerResponse::SharedSecret(hex::encode(secret.secret_bytes()))),
+        SignerRequest::SignGossip { message } => parse_bytes(&message)
+            .and_then(|message| signer.sign_gossip(&message))
+            .map(signature_response),
+        SignerRequest::NewChannelKeysId { channel_value_satoshis, user_channel_id } => u128::from_str(&user_channel_id)
+            .map_err(invalid)
+            .and_then(|user_channel_id| signer.new_channel_keys_id(channel_value_satoshis, user_channel_id))
+            .map(|channel_keys_id| SignerResponse::ChannelKeysId(hex::encode(channel_keys_id))),
+        SignerRequest::PaymentBasepoint { channel_keys_id } => parse_channel_keys_id(&channel_keys_id)
+            .and_then(|channel_keys_id| signer.payment_basepoint(channel_keys_id))
+            .map(|basepoint| SignerResponse::PaymentBasepoint(basepoint.to_string())),
+        SignerRequest::ReadyChannel { channel_keys_id, holder_is_opener, counterparty_payment_basepoint } => {
+            (|| {
+                signer.ready_channel(
+                    parse_channel_keys_id(&channel_keys_id)?,
+                    holder_is_opener,
+                    &parse_pubkey(&counterparty_payment_basepoint)?,
+                )
+            })()
+            .map(|_| SignerResponse::Validated)
+        }
+        SignerRequest::SignCommitment { channel_keys_id, commitment_tx, funding_redeem_script,
+                                        channel_value_satoshis } => {
+            (|| {
+                signer.sign_commitment(
+                    parse_channel_keys_id(&channel_keys_id)?,
+                    &parse_tx(&commitment_tx)?,
+                    &parse_script(&funding_redeem_script)?,
+                    channel_value_satoshis,
+                )
+            })()
+            .map(signature_response)
+        }
+        SignerRequest::ValidateCounterpartyRevocation { channel_keys_id, commitment_number, per_commitment_point,
+                                                        per_commitment_secret } => {
+            (|| {
+                signer.validate_counterparty_revocation(
+                    parse_channel_keys_id(&channel_keys_id)?,
+                    commitment_number,
+                    &parse_pubkey(&per_commitment_point)?,
+                    &SecretKey::from_str(&per_commitment_secret).map_err(invalid)?,
+                )
+            })()
+            .map(|_| SignerResponse::Validated)
+        }
+        SignerRequest::SetCounterpartyShutdownScript { channel_keys_id, shutdown_script } => {
+            (|| {
+                signer.set_counterparty_shutdown_script(
+                    parse_channel_keys_id(&channel_keys_id)?,
+                    &parse_script(&shutdown_script)?,
+                )
+            })()
+            .map(|_| SignerResponse::Validated)
+        }
+        SignerRequest::SignClosing { channel_keys_id, closing_tx, funding_redeem_script, channel_value_satoshis } => {
+            (|| {
+                signer.sign_closing(
+                    parse_channel_keys_id(&channel_keys_id)?,
+                    &parse_tx(&closing_tx)?,
+                    &parse_script(&funding_redeem_script)?,
+                    channel_value_satoshis,
+                )
+            })()
+            .map(signature_response)
+        }
+        SignerRequest::SignHtlc { channel_keys_id, htlc_tx, input_index, htlc_redeem_script, htlc_amount,
+                                  cltv_expiry, per_commitment_point } => {
+            (|| {
+                signer.sign_htlc(
+                    parse_channel_keys_id(&channel_keys_id)?,
+                    &parse_tx(&htlc_tx)?,
+                    input_index,
+                    &parse_script(&htlc_redeem_script)?,
+                    htlc_amount,
+                    cltv_expiry,
+                    &parse_pubkey(&per_commitment_point)?,
+                )
+            })()
+            .map(signature_response)
+        }
+        SignerRequest::SignJustice { channel_keys_id, justice_tx, input_index, redeem_scripts, amounts,
+                                     per_commitment_secret } => {
+            (|| {
+                signer.sign_justice(
+                    parse_channel_keys_id(&channel_keys_id)?,
+                    &parse_tx(&justice_tx)?,
+                    input_index,
+                    &redeem_scripts.iter().map(|script| parse_script(script)).collect::<Result<Vec<_>, _>>()?,
+                    &amounts,
+                    &SecretKey::from_str(&per_commitment_secret).map_err(invalid)?,
+                )
+            })()
+            .map(signature_response)
+        }
+    };
+
+    response.unwrap_or_else(SignerResponse::Error)
+}
+
+fn signature_response(signature: Signature) -> SignerResponse {
+    SignerResponse::Signature(hex::encode(signature.serialize_der()))
+}
+
+/// Answers every connection to `listener` with `signer`, one thread per
+/// connection. Only returns if the listener fails.
+pub fn serve<S: Signer + Send + Sync + 'static>(listener: UnixListener, signer: Arc<S>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => return eprintln!("Signer socket failed: {}", e),
+        };
+        let signer = signer.clone();
+        thread::spawn(move || handle_connection(stream, &*signer));
+    }
+}
+
+fn handle_connection<S: Signer>(stream: UnixStream, signer: &S) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        let response = match serde_json::from_str(&line) {
+            Ok(request) => handle_request(signer, request),
+            Err(e) => SignerResponse::Error(SignerError::InvalidMessage(e.to_string())),
+        };
+
+        let mut response = serde_json::to_string(&response).expect("responses serialize");
+        response.push('\n');
+        if writer.write_all(response.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+/// The node's side of the socket: signs by asking a `signer-daemon`.
+pub struct RemoteSigner {
+    connection: Mutex<BufReader<UnixStream>>,
+}
+
+impl RemoteSigner {
+    pub fn connect<P: AsRef<Path>>(socket_path: P) -> Result<RemoteSigner, SignerError> {
+        let stream = UnixStream::connect(socket_path).map_err(unavailable)?;
+        Ok(RemoteSigner { connection: Mutex::new(BufReader::new(stream)) })
+    }
+
+    fn request(&self, request: SignerRequest) -> Result<SignerResponse, SignerError> {
+        // A request that panicked half way may have left a partial line
+        // behind, so the connection is no use after that.
+        let mut connection = self
+            .connection
+            .lock()
+            .map_err(|_| SignerError::Unavailable("connection poisoned".to_string()))?;
+
+        let mut line = serde_json::to_string(&request).expect("requests serialize");
+        line.push('\n');
+        connection.get_mut().write_all(line.as_bytes()).map_err(unavailable)?;
+
+        let mut response = String::new();
+        if connection.read_line(&mut response).map_err(unavailable)? == 0 {
+            return Err(SignerError::Unavailable("connection closed".to_string()));
+        }
+        match serde_json::from_str(&response).map_err(invalid)? {
+            SignerResponse::Error(e) => Err(e),
+            response => Ok(response),
+        }
+    }
+
+    fn request_signature(&self, request: SignerRequest) -> Result<Signature, SignerError> {
+        match self.request(request)? {
+            SignerResponse::Signature(signature) => {
+                Signature::from_der(&parse_bytes(&signature)?).map_err(invalid)
+            }
+            response => Err(unexpected(response)),
+        }
+    }
+
+    fn request_validation(&self, request: SignerRequest) -> Result<(), SignerError> {
+        match self.request(request)? {
+            SignerResponse::Validated => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn node_id(&self) -> Result<PublicKey, SignerError> {
+        match self.request(SignerRequest::GetNodeId)? {
+            SignerResponse::NodeId(node_id) => parse_pubkey(&node_id),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    fn ecdh(&self, other_key: &PublicKey, tweak: Option<&Scalar>) -> Result<SharedSecret, SignerError> {
+        let request = SignerRequest::Ecdh {
+            other_key: other_key.to_string(),
+            tweak: tweak.map(|tweak| hex::encode(tweak.to_be_bytes())),
+        };
+        match self.request(request)? {
+            SignerResponse::SharedSecret(secret) => {
+                let secret: [u8; 32] = parse_bytes(&secret)?
+                    .try_into()
+                    .map_err(|_| SignerError::InvalidMessage("shared secret is not 32 bytes".to_string()))?;
+                Ok(SharedSecret::from_bytes(secret))
+            }
+            response => Err(unexpected(response)),
+        }
+    }
+
+    fn sign_gossip(&self, msg: &[u8]) -> Result<Signature, SignerError> {
+        self.request_signature(SignerRequest::SignGossip { message: hex::encode(msg) })
+    }
+
+    fn new_channel_keys_id(&self, channel_value_satoshis: u64, user_channel_id: u128)
+                           -> Result<[u8; 32], SignerError> {
+        let request = SignerRequest::NewChannelKeysId {
+            channel_value_satoshis,
+            user_channel_id: user_channel_id.to_string(),
+        };
+        match self.request(request)? {
+            SignerResponse::ChannelKeysId(channel_keys_id) => parse_channel_keys_id(&channel_keys_id),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    fn payment_basepoint(&self, channel_keys_id: [u8; 32]) -> Result<PublicKey, SignerError> {
+        match self.request(SignerRequest::PaymentBasepoint { channel_keys_id: hex::encode(channel_keys_id) })? {
+            SignerResponse::PaymentBasepoint(basepoint) => parse_pubkey(&basepoint),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    fn ready_channel(&self, channel_keys_id: [u8; 32], holder_is_opener: bool,
+                     counterparty_payment_basepoint: &PublicKey) -> Result<(), SignerError> {
+        self.request_validation(SignerRequest::ReadyChannel {
+            channel_keys_id: hex::encode(channel_keys_id),
+            holder_is_opener,
+            counterparty_payment_basepoint: counterparty_payment_basepoint.to_string(),
+        })
+    }
+
+    fn sign_commitment(&self, channel_keys_id: [u8; 32], commitment_tx: &Transaction,
+                       funding_redeem_script: &ScriptBuf, channel_value_satoshis: u64)
+                       -> Result<Signature, SignerError> {
+        self.request_signature(SignerRequest::SignCommitment {
+            channel_keys_id: hex::encode(channel_keys_id),
+            commitment_tx: serialize_hex(commitment_tx),
+            funding_redeem_script: funding_redeem_script.to_hex_string(),
+            channel_value_satoshis,
+        })
+    }
+
+    fn validate_counterparty_revocation(&self, channel_keys_id: [u8; 32], commitment_number: u64,
+                                        per_commitment_point: &PublicKey, per_commitment_secret: &SecretKey)
+                                        -> Result<(), SignerError> {
+        self.request_validation(SignerRequest::ValidateCounterpartyRevocation {
+            channel_keys_id: hex::encode(channel_keys_id),
+            commitment_number,
+            per_commitment_point: per_commitment_point.to_string(),
+            per_commitment_secret: hex::encode(per_commitment_secret.secret_bytes()),
+        })
+    }
+
+    fn set_counterparty_shutdown_script(&self, channel_keys_id: [u8; 32], shutdown_script: &ScriptBuf)
+                                        -> Result<(), SignerError> {
+        self.request_validation(SignerRequest::SetCounterpartyShutdownScript {
+            channel_keys_id: hex::encode(channel_keys_id),
+            shutdown_script: shutdown_script.to_hex_string(),
+        })
+    }
+
+    fn sign_closing(&self, channel_keys_id: [u8; 32], closing_tx: &Transaction, funding_redeem_script: &ScriptBuf,
+                    channel_value_satoshis: u64) -> Result<Signature, SignerError> {
+        self.request_signature(SignerRequest::SignClosing {
+            channel_keys_id: hex::encode(channel_keys_id),
+            closing_tx: serialize_hex(closing_tx),
+            funding_redeem_script: funding_redeem_script.to_hex_string(),
+            channel_value_satoshis,
+        })
+    }
+
+    fn sign_htlc(&self, channel_keys_id: [u8; 32], htlc_tx: &Transaction, input_index: usize,
+                 htlc_redeem_script: &ScriptBuf, htlc_amount: u64, cltv_expiry: u32,
+                 per_commitment_point: &PublicKey) -> Result<Signature, SignerError> {
+        self.request_signature(SignerRequest::SignHtlc {
+            channel_keys_id: hex::encode(channel_keys_id),
+            htlc_tx: serialize_hex(htlc_tx),
+            input_index,
+            htlc_redeem_script: htlc_redeem_script.to_hex_string(),
+            htlc_amount,
+            cltv_expiry,
+            per_commitment_point: per_commitment_point.to_string(),
+        })
+    }
+
+    fn sign_justice(&self, channel_keys_id: [u8; 32], justice_tx: &Transaction, input_index: usize,
+                    redeem_scripts: &[ScriptBuf], amounts: &[u64], per_commitment_secret: &SecretKey)
+                    -> Result<Signature, SignerError> {
+        self.request_signature(SignerRequest::SignJustice {
+            channel_keys_id: hex::encode(channel_keys_id),
+            justice_tx: serialize_hex(justice_tx),
+            input_index,
+            redeem_scripts: redeem_scripts.iter().map(|script| script.to_hex_string()).collect(),
+            amounts: amounts.to_vec(),
+            per_commitment_secret: hex::encode(per_commitment_secret.secret_bytes()),
+        })
+    }
+}
+
+fn unavailable<E: fmt::Display>(e: E) -> SignerError {
+    SignerError::Unavailable(e.to_string())
+}
+
+fn invalid<E: fmt::Display>(e: E) -> SignerError {
+    SignerError::InvalidMessage(e.to_string())
+}
+
+fn unexpected(response: SignerResponse) -> SignerError {
+    SignerError::InvalidMessage(format!("unexpected response {:?}", response))
+}
+
+fn parse_bytes(hex: &str) -> Result<Vec<u8>, SignerError> {
+    hex::decode(hex).map_err(invalid)
+}
+
+fn parse_pubkey(hex: &str) -> Result<PublicKey, SignerError> {
+    PublicKey::from_str(hex).map_err(invalid)
+}
+
+fn parse_scalar(hex: &str) -> Result<Scalar, SignerError> {
+    let bytes: [u8; 32] = parse_bytes(hex)?
+        .try_into()
+        .map_err(|_| SignerError::InvalidMessage("tweak is not 32 bytes".to_string()))?;
+    Scalar::from_be_bytes(bytes).map_err(invalid)
+}
+
+fn parse_channel_keys_id(hex: &str) -> Result<[u8; 32], SignerError> {
+    parse_bytes(hex)?
+        .try_into()
+        .map_err(|_| SignerError::InvalidMessage("channel keys id is not 32 bytes".to_string()))
+}
+
+fn parse_tx(hex: &str) -> Result<Transaction, SignerError> {
+    deserialize_hex(hex).map_err(invalid)
+}
+
+fn parse_script(hex: &str) -> Result<ScriptBuf, SignerError> {
+    ScriptBuf::from_hex(hex).map_err(invalid)
+}
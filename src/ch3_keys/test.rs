@@ -1,11 +1,30 @@
 #![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+use crate::ch3_keys::bip39::{Bip39Error, Mnemonic};
+use crate::ch3_keys::descriptor::{descriptor_checksum, DescriptorError, KeyDescriptor, WatchOnlyWallet};
 use crate::ch3_keys::exercises::{
-    new_simple_key_manager, unified_onchain_offchain_wallet, SimpleKeysManager,
+    new_simple_key_manager, unified_onchain_offchain_wallet,
+    unified_onchain_offchain_wallet_from_mnemonic, unified_onchain_wallet_descriptors, ChannelKeys,
+    SimpleKeysManager,
 };
+use crate::ch3_keys::policy::{PolicyConfig, PolicyDenial, PolicySigner, PolicyState, DEFAULT_MAX_FEERATE_PER_KW};
+use crate::ch3_keys::remote_signer::{self, RemoteSigner, SignerError, SignerRequest, SignerResponse};
+use crate::ch1_intro_htlcs::solutions::{
+    build_closing_transaction, build_fee_aware_commitment_transaction, build_htlc_success_transaction,
+    build_htlc_timeout_transaction, build_justice_transaction, commitment_number_obscure_factor, derive_public_key,
+    generate_revocation_pubkey, htlc_witness_script, obscured_commitment_locktime_and_sequence,
+    two_of_two_multisig_witness_script, ChannelType, HtlcOutput,
+};
+use crate::interactive::helper::RegtestFeeEstimator;
 use crate::internal::bitcoind_client::BitcoindClient;
+use crate::internal::helper::{build_output, p2wpkh_output_script, pubkey_from_private_key};
+use std::os::unix::net::UnixListener;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
 use std::time::{Duration, SystemTime};
 
 use bitcoin::amount::Amount;
+use bitcoin::bip32::ChildNumber;
 use bitcoin::hash_types::Txid;
 use bitcoin::hashes::hex::FromHex;
 use bitcoin::hashes::Hash;
@@ -15,11 +34,19 @@ use bitcoin::secp256k1::ecdsa::Signature;
 use bitcoin::secp256k1::PublicKey as Secp256k1PublicKey;
 use bitcoin::secp256k1::Scalar;
 use bitcoin::secp256k1::{self, Secp256k1};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
 use bitcoin::transaction::Version;
 use bitcoin::PubkeyHash;
-use bitcoin::{OutPoint, PublicKey, Sequence, Transaction, TxIn, Witness};
+use bitcoin::{Address, Network, OutPoint, PublicKey, Sequence, Transaction, TxIn, Witness};
 use core::sync::atomic::{AtomicUsize, Ordering};
 use rand::{thread_rng, Rng};
+use lightning::ln::features::NodeFeatures;
+use lightning::ln::msgs::{UnsignedGossipMessage, UnsignedNodeAnnouncement};
+use lightning::routing::gossip::{NodeAlias, NodeId};
+use lightning::sign::{ChannelSigner, NodeSigner, Recipient, SignerProvider};
+use lightning::util::ser::Writeable;
+use bitcoin::hashes::sha256d::Hash as Sha256dHash;
+use bitcoin::secp256k1::Message;
 
 #[test]
 fn test_new_simple_key_manager() {
@@ -47,7 +74,8 @@ fn test_new_simple_key_manager() {
     // check channel_child_index
     assert_eq!(
         keys_interface_impl
-            .channel_child_index,
+            .channel_child_index
+            .load(Ordering::SeqCst),
         child_index
     );
 }
@@ -55,7 +83,7 @@ fn test_new_simple_key_manager() {
 #[test]
 fn test_unifed_onchain_offchain_wallet() {
     let seed = [1_u8; 64];
-    let keys_interface_impl = unified_onchain_offchain_wallet(seed);
+    let keys_interface_impl = unified_onchain_offchain_wallet(seed, Network::Testnet);
     // check channel_master_key
     assert_eq!(
         keys_interface_impl
@@ -65,3 +93,990 @@ fn test_unifed_onchain_offchain_wallet() {
         "67cf3832ea5f1e0abab97340883623accc3776d9fd7b6cf763e1243d81704219".to_string()
     );
 }
+
+#[test]
+fn test_shutdown_scriptpubkey_uses_coop_close_pubkey() {
+    let keys_interface_impl = SimpleKeysManager::new([1_u8; 32]);
+
+    // The cooperative and unilateral close keys are different children of the master key.
+    assert_ne!(
+        keys_interface_impl.coop_close_pubkey,
+        keys_interface_impl.unilateral_close_pubkey
+    );
+    assert_eq!(
+        keys_interface_impl.coop_close_pubkey,
+        new_simple_key_manager([1_u8; 32], Network::Regtest).coop_close_pubkey
+    );
+
+    let shutdown_script = keys_interface_impl.get_shutdown_scriptpubkey();
+    assert_eq!(
+        shutdown_script,
+        p2wpkh_output_script(keys_interface_impl.coop_close_pubkey)
+    );
+
+    // Our shutdown script receives our balance on a mutual close.
+    let remote_script = p2wpkh_output_script(pubkey_from_private_key(&[0x02; 32]));
+    let closing_tx = build_closing_transaction(
+        TxIn::default(),
+        shutdown_script.clone(),
+        remote_script,
+        2_000_000_000,
+        1_000_000_000,
+        true,
+        546,
+        1_000,
+    );
+    assert!(closing_tx
+        .output
+        .iter()
+        .any(|output| output.script_pubkey == shutdown_script));
+}
+
+#[test]
+fn test_bip39_mnemonic() {
+    // test vectors from BIP39, all with the passphrase "TREZOR"
+    let vectors: [(&[u8], &str, &str); 4] = [
+        (
+            &[0x00; 16],
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+        ),
+        (
+            &[0x7f; 16],
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+            "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6fa457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607",
+        ),
+        (
+            &[0xff; 16],
+            "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo wrong",
+            "ac27495480225222079d7be181583751e86f571027b0497b5b5d11218e0a8a13332572917f0f8e5a589620c6f15b11c61dee327651a14c34e18231052e48c069",
+        ),
+        (
+            &[0x00; 32],
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art",
+            "bda85446c68413707090a52022edd26a1c9462295029f2e60cd7c4f2bbd3097170af7a4d73245cafa9c3cca8d561a7c3de6f5d4a10be8ed2a5e608d68f92fcc8",
+        ),
+    ];
+
+    for (entropy, words, seed) in vectors {
+        let mnemonic = Mnemonic::from_entropy(entropy).unwrap();
+        assert_eq!(mnemonic.to_string(), words);
+        assert_eq!(Mnemonic::parse(words).unwrap(), mnemonic);
+        assert_eq!(mnemonic.entropy(), entropy);
+        assert_eq!(hex::encode(mnemonic.to_seed("TREZOR")), seed);
+    }
+
+    // the passphrase changes the seed
+    let mnemonic = Mnemonic::from_entropy(&[0x00; 16]).unwrap();
+    assert_ne!(mnemonic.to_seed(""), mnemonic.to_seed("TREZOR"));
+    // but not how its accents are encoded
+    assert_eq!(mnemonic.to_seed("caf\u{e9}"), mnemonic.to_seed("cafe\u{301}"));
+
+    let generated = Mnemonic::generate(24).unwrap();
+    assert_eq!(generated.words().len(), 24);
+    assert_eq!(Mnemonic::parse(&generated.to_string()).unwrap(), generated);
+}
+
+#[test]
+fn test_bip39_rejects_invalid_mnemonics() {
+    assert_eq!(
+        Mnemonic::from_entropy(&[0x00; 15]),
+        Err(Bip39Error::BadEntropyLength(15))
+    );
+    assert_eq!(Mnemonic::generate(13), Err(Bip39Error::BadWordCount(13)));
+    assert_eq!(
+        Mnemonic::parse("abandon abandon about"),
+        Err(Bip39Error::BadWordCount(3))
+    );
+    assert_eq!(
+        Mnemonic::parse("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon bitcoin"),
+        Err(Bip39Error::UnknownWord("bitcoin".to_string()))
+    );
+    // "abandon" is all zero bits, but the checksum of zero entropy is not
+    assert_eq!(
+        Mnemonic::parse("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon"),
+        Err(Bip39Error::InvalidChecksum)
+    );
+}
+
+#[test]
+fn test_keys_manager_from_mnemonic() {
+    let mnemonic = Mnemonic::parse(
+        "legal winner thank year wave sausage worth useful legal winner thank yellow",
+    )
+    .unwrap();
+    let bip39_seed = mnemonic.to_seed("");
+
+    let keys_interface_impl = SimpleKeysManager::from_mnemonic(&mnemonic, "", Network::Regtest);
+    assert_eq!(keys_interface_impl.seed[..], bip39_seed[..]);
+    assert_eq!(
+        keys_interface_impl.node_id,
+        SimpleKeysManager::from_mnemonic(&mnemonic, "", Network::Regtest).node_id
+    );
+    assert_ne!(
+        keys_interface_impl.node_id,
+        SimpleKeysManager::from_mnemonic(&mnemonic, "TREZOR", Network::Regtest).node_id
+    );
+
+    // the same on-chain wallet as the unified wallet for the same words
+    let keys_interface_impl = SimpleKeysManager::from_mnemonic(&mnemonic, "", Network::Testnet);
+    let onchain_descriptors = unified_onchain_wallet_descriptors(bip39_seed, Network::Testnet);
+    assert_eq!(keys_interface_impl.watch_only_descriptors()[..onchain_descriptors.len()], onchain_descriptors[..]);
+
+    let keys_manager = unified_onchain_offchain_wallet_from_mnemonic(&mnemonic, "", Network::Testnet);
+    assert_eq!(
+        keys_manager.get_node_secret_key(),
+        unified_onchain_offchain_wallet(bip39_seed, Network::Testnet).get_node_secret_key()
+    );
+}
+
+#[test]
+fn test_derive_channel_keys() {
+    let keys_interface_impl = SimpleKeysManager::new([1_u8; 32]);
+
+    let first = keys_interface_impl.derive_channel_keys(5_000_000, 42);
+    let second = keys_interface_impl.derive_channel_keys(1_000_000, 43);
+    assert_eq!(keys_interface_impl.channel_child_index.load(Ordering::SeqCst), 2);
+    assert_eq!(first.channel_child_index, 0);
+    assert_eq!(second.channel_child_index, 1);
+    assert_eq!(first.channel_value_satoshis, 5_000_000);
+    assert_eq!(first.user_channel_id, 42);
+
+    // every key of a channel is different, and no two channels share keys
+    let secrets = |keys: &ChannelKeys| {
+        vec![
+            keys.funding_key.secret_bytes(),
+            keys.revocation_base_key.secret_bytes(),
+            keys.payment_key.secret_bytes(),
+            keys.delayed_payment_base_key.secret_bytes(),
+            keys.htlc_base_key.secret_bytes(),
+            keys.commitment_seed,
+        ]
+    };
+    let mut all_secrets = secrets(&first);
+    all_secrets.extend(secrets(&second));
+    let count = all_secrets.len();
+    all_secrets.sort();
+    all_secrets.dedup();
+    assert_eq!(all_secrets.len(), count);
+
+    // the funding key is m/3'/0'/0' of the node's master key
+    let secp_ctx = Secp256k1::new();
+    let path = [
+        ChildNumber::from_hardened_idx(0).unwrap(),
+        ChildNumber::from_hardened_idx(0).unwrap(),
+    ];
+    let funding_key = keys_interface_impl
+        .channel_master_key
+        .derive_priv(&secp_ctx, &path)
+        .unwrap()
+        .private_key;
+    assert_eq!(first.funding_key, funding_key);
+    assert_eq!(
+        first.funding_pubkey(),
+        secp256k1::PublicKey::from_secret_key(&secp_ctx, &funding_key)
+    );
+
+    // the same seed derives the same keys again, in the same order
+    let restored = SimpleKeysManager::new([1_u8; 32]);
+    assert_eq!(restored.derive_channel_keys(5_000_000, 42), first);
+    assert_eq!(restored.channel_keys_at_index(1, 1_000_000, 43).unwrap(), second);
+
+    // after a restart the index starts over, until the channels we have are
+    // restored
+    let restarted = SimpleKeysManager::new([1_u8; 32]);
+    restarted.restore_channel_keys_id(second.channel_keys_id());
+    restarted.restore_channel_keys_id(first.channel_keys_id());
+    assert_eq!(restarted.channel_child_index.load(Ordering::SeqCst), 2);
+    assert_eq!(restarted.derive_channel_keys(5_000_000, 44).channel_child_index, 2);
+    assert_eq!(restarted.channel_keys_from_id(1_000_000, second.channel_keys_id()).unwrap(), second);
+
+    // an ID with a child index that can't be hardened is not one of ours
+    assert!(restarted.channel_keys_from_id(1_000_000, [0x80; 32]).is_none());
+    restarted.restore_channel_keys_id([0x80; 32]);
+    assert_eq!(restarted.channel_child_index.load(Ordering::SeqCst), 3);
+
+    let other_node = SimpleKeysManager::new([2_u8; 32]);
+    assert_ne!(
+        other_node.derive_channel_keys(5_000_000, 42).funding_key,
+        first.funding_key
+    );
+}
+
+#[test]
+fn test_simple_keys_manager_node_signer() {
+    let alice = SimpleKeysManager::new([1_u8; 32]);
+    let bob = SimpleKeysManager::new([2_u8; 32]);
+
+    assert_eq!(alice.get_node_id(Recipient::Node), Ok(alice.node_id));
+    assert!(alice.get_node_id(Recipient::PhantomNode).is_err());
+
+    // both ends of a BOLT 8 handshake arrive at the same secret
+    assert_eq!(
+        alice.ecdh(Recipient::Node, &bob.node_id, None).unwrap(),
+        bob.ecdh(Recipient::Node, &alice.node_id, None).unwrap()
+    );
+
+    let announcement = UnsignedNodeAnnouncement {
+        features: NodeFeatures::empty(),
+        timestamp: 1,
+        node_id: NodeId::from_pubkey(&alice.node_id),
+        rgb: [0; 3],
+        alias: NodeAlias([0; 32]),
+        addresses: vec![],
+        excess_address_data: vec![],
+        excess_data: vec![],
+    };
+    let msg_hash = Message::from_digest(Sha256dHash::hash(&announcement.encode()).to_byte_array());
+    let signature = NodeSigner::sign_gossip_message(
+        &alice,
+        UnsignedGossipMessage::NodeAnnouncement(&announcement),
+    )
+    .unwrap();
+    assert!(alice
+        .secp_ctx
+        .verify_ecdsa(&msg_hash, &signature, &alice.node_id)
+        .is_ok());
+}
+
+#[test]
+fn test_simple_keys_manager_signer_provider() {
+    let keys_interface_impl = SimpleKeysManager::new([1_u8; 32]);
+
+    let first_id = keys_interface_impl.generate_channel_keys_id(false, 5_000_000, 42);
+    let second_id = keys_interface_impl.generate_channel_keys_id(true, 5_000_000, 42);
+    assert_ne!(first_id, second_id);
+
+    // the signer for a channel holds the keys derived for it
+    let channel_keys = keys_interface_impl.channel_keys_at_index(0, 5_000_000, 42).unwrap();
+    assert_eq!(channel_keys.channel_keys_id(), first_id);
+    let signer = keys_interface_impl.derive_channel_signer(5_000_000, first_id);
+    assert_eq!(signer.channel_keys_id(), first_id);
+    assert_eq!(signer.pubkeys().funding_pubkey, channel_keys.funding_pubkey());
+    assert_eq!(
+        signer.pubkeys().revocation_basepoint.to_public_key(),
+        channel_keys.revocation_basepoint()
+    );
+    assert_eq!(signer.pubkeys().payment_point, channel_keys.payment_point());
+    assert_eq!(
+        signer.pubkeys().delayed_payment_basepoint.to_public_key(),
+        channel_keys.delayed_payment_basepoint()
+    );
+    assert_eq!(
+        signer.pubkeys().htlc_basepoint.to_public_key(),
+        channel_keys.htlc_basepoint()
+    );
+
+    let read_signer = keys_interface_impl
+        .read_chan_signer(&signer.encode())
+        .unwrap();
+    assert_eq!(read_signer.pubkeys(), signer.pubkeys());
+
+    // LDK asking for the signers of its channels after a restart restores
+    // them
+    let restarted = SimpleKeysManager::new([1_u8; 32]);
+    restarted.derive_channel_signer(5_000_000, second_id);
+    assert_ne!(restarted.generate_channel_keys_id(false, 5_000_000, 42), first_id);
+    assert_eq!(restarted.channel_child_index.load(Ordering::SeqCst), 3);
+
+    assert_eq!(
+        SignerProvider::get_shutdown_scriptpubkey(&keys_interface_impl)
+            .unwrap()
+            .into_inner(),
+        p2wpkh_output_script(keys_interface_impl.coop_close_pubkey)
+    );
+    assert_eq!(
+        keys_interface_impl.get_destination_script(first_id),
+        Ok(p2wpkh_output_script(keys_interface_impl.unilateral_close_pubkey))
+    );
+}
+
+#[test]
+fn test_descriptor_checksum() {
+    // from BIP380
+    assert_eq!(descriptor_checksum("raw(deadbeef)"), Some("89f8spxm".to_string()));
+    assert_eq!(descriptor_checksum("raw(\u{e9})"), None);
+}
+
+#[test]
+fn test_onchain_wallet_descriptors() {
+    let mnemonic = Mnemonic::from_entropy(&[0x00; 16]).unwrap();
+    let descriptors = unified_onchain_wallet_descriptors(mnemonic.to_seed(""), Network::Bitcoin);
+    assert_eq!(descriptors.len(), 4);
+
+    // the first receive addresses of the BIP84 and BIP86 test vectors
+    let address = |descriptor: &KeyDescriptor| {
+        Address::from_script(&descriptor.script_pubkey(0).unwrap(), Network::Bitcoin)
+            .unwrap()
+            .to_string()
+    };
+    assert!(descriptors[0]
+        .to_string()
+        .starts_with("wpkh([73c5da0a/84h/0h/0h]xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V/0/*)#"));
+    assert_eq!(address(&descriptors[0]), "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+    assert_eq!(
+        address(&descriptors[2]),
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+    );
+
+    // the mainnet and test network wallets use different coin types
+    let testnet_descriptors = unified_onchain_wallet_descriptors(mnemonic.to_seed(""), Network::Testnet);
+    assert!(testnet_descriptors[0].to_string().starts_with("wpkh([73c5da0a/84h/1h/0h]tpub"));
+}
+
+#[test]
+fn test_watch_only_wallet_from_descriptors() {
+    let keys_interface_impl = SimpleKeysManager::new_for_network([1_u8; 32], Network::Regtest);
+    let descriptors = keys_interface_impl.watch_only_descriptors();
+    assert_eq!(descriptors.len(), 6);
+
+    let mut watch_only = WatchOnlyWallet::new(Network::Regtest);
+    for descriptor in &descriptors {
+        let exported = descriptor.to_string();
+        assert_eq!(&KeyDescriptor::from_str(&exported).unwrap(), descriptor);
+        watch_only.import_descriptor(&exported).unwrap();
+    }
+
+    // we see our shutdown and sweep scripts, and our on-chain wallet's
+    assert!(watch_only.is_mine(&keys_interface_impl.get_shutdown_scriptpubkey(), 10));
+    assert!(watch_only.is_mine(
+        &p2wpkh_output_script(keys_interface_impl.unilateral_close_pubkey),
+        10
+    ));
+    assert!(watch_only.is_mine(&descriptors[1].script_pubkey(9).unwrap(), 10));
+    assert!(!watch_only.is_mine(&descriptors[1].script_pubkey(10).unwrap(), 10));
+    assert_eq!(descriptors[1].script_pubkey(1 << 31), Err(DescriptorError::InvalidDerivation));
+    assert!(!watch_only.is_mine(
+        &p2wpkh_output_script(pubkey_from_private_key(&[0x02; 32])),
+        10
+    ));
+    assert_eq!(watch_only.script_pubkeys(10).len(), 4 * 10 + 2);
+
+    let exported = descriptors[0].to_string();
+    let mut mainnet = WatchOnlyWallet::new(Network::Bitcoin);
+    assert_eq!(
+        mainnet.import_descriptor(&exported),
+        Err(DescriptorError::WrongNetwork)
+    );
+
+    let (descriptor, checksum) = exported.split_once('#').unwrap();
+    assert_eq!(
+        KeyDescriptor::from_str(descriptor).unwrap(),
+        descriptors[0]
+    );
+    assert_eq!(
+        KeyDescriptor::from_str(&format!("{}#{}", descriptor.replace("/0/*", "/1/*"), checksum)),
+        Err(DescriptorError::InvalidChecksum)
+    );
+    // an xpub can't derive a hardened chain
+    assert_eq!(
+        KeyDescriptor::from_str(&descriptor.replace("/0/*", "/2147483648/*")),
+        Err(DescriptorError::InvalidDerivation)
+    );
+    assert_eq!(
+        KeyDescriptor::from_str("pkh(02deadbeef)"),
+        Err(DescriptorError::UnsupportedDescriptor("pkh(02deadbeef)".to_string()))
+    );
+    assert_eq!(
+        KeyDescriptor::from_str(&descriptor.replace("/0/*", "/0/1/*")),
+        Err(DescriptorError::InvalidDerivation)
+    );
+}
+
+#[test]
+fn test_remote_signer_matches_local_signer() {
+    let socket_path = std::env::temp_dir().join(format!("signer-test-{}.sock", thread_rng().gen::<u64>()));
+    let listener = UnixListener::bind(&socket_path).unwrap();
+    let keys_manager = Arc::new(SimpleKeysManager::new([1_u8; 32]));
+    let server_keys = keys_manager.clone();
+    thread::spawn(move || remote_signer::serve(listener, server_keys));
+
+    let local: &dyn remote_signer::Signer = &*keys_manager;
+    let remote = RemoteSigner::connect(&socket_path).unwrap();
+    let remote: &dyn remote_signer::Signer = &remote;
+
+    assert_eq!(remote.node_id(), Ok(keys_manager.node_id));
+
+    let bob = SimpleKeysManager::new([2_u8; 32]);
+    let tweak = Scalar::from_be_bytes([7; 32]).unwrap();
+    assert_eq!(remote.ecdh(&bob.node_id, None), local.ecdh(&bob.node_id, None));
+    assert_eq!(remote.ecdh(&bob.node_id, Some(&tweak)), local.ecdh(&bob.node_id, Some(&tweak)));
+
+    let mut gossip = vec![0x01, 0x02];
+    gossip.extend([0; 64]);
+    gossip.extend(b"channel_update");
+    assert_eq!(remote.sign_gossip(&gossip), local.sign_gossip(&gossip));
+
+    // the channel keys come from the signer, and are the ones the keys
+    // manager derives
+    let channel_keys_id = remote.new_channel_keys_id(5_000_000, 42).unwrap();
+    let channel_keys = keys_manager.channel_keys_from_id(5_000_000, channel_keys_id).unwrap();
+    assert_eq!(channel_keys, keys_manager.channel_keys_at_index(0, 5_000_000, 42).unwrap());
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint { txid: Txid::all_zeros(), vout: 0 },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: vec![],
+    };
+    let redeem_script = p2wpkh_output_script(channel_keys.funding_pubkey());
+    let secp_ctx = Secp256k1::new();
+    let per_commitment_secret = secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+    let per_commitment_point = secp256k1::PublicKey::from_secret_key(&secp_ctx, &per_commitment_secret);
+
+    let their_payment_basepoint = SimpleKeysManager::new([2_u8; 32]).derive_channel_keys(5_000_000, 42).payment_point();
+    assert_eq!(remote.payment_basepoint(channel_keys_id), Ok(channel_keys.payment_point()));
+    assert_eq!(remote.ready_channel(channel_keys_id, true, &their_payment_basepoint), Ok(()));
+
+    let commitment_sig = remote.sign_commitment(channel_keys_id, &tx, &redeem_script, 5_000_000);
+    assert_eq!(commitment_sig, local.sign_commitment(channel_keys_id, &tx, &redeem_script, 5_000_000));
+    assert_eq!(
+        remote.sign_htlc(channel_keys_id, &tx, 0, &redeem_script, 10_000, 0, &per_commitment_point),
+        local.sign_htlc(channel_keys_id, &tx, 0, &redeem_script, 10_000, 0, &per_commitment_point)
+    );
+    assert_eq!(
+        remote.sign_justice(channel_keys_id, &tx, 0, &[redeem_script.clone()], &[10_000], &per_commitment_secret),
+        local.sign_justice(channel_keys_id, &tx, 0, &[redeem_script.clone()], &[10_000], &per_commitment_secret)
+    );
+
+    let shutdown_script = keys_manager.get_shutdown_scriptpubkey();
+    assert_eq!(remote.set_counterparty_shutdown_script(channel_keys_id, &shutdown_script), Ok(()));
+    assert_eq!(
+        remote.sign_closing(channel_keys_id, &tx, &redeem_script, 5_000_000),
+        local.sign_closing(channel_keys_id, &tx, &redeem_script, 5_000_000)
+    );
+    assert_eq!(
+        remote.validate_counterparty_revocation(channel_keys_id, 0, &per_commitment_point, &per_commitment_secret),
+        Ok(())
+    );
+
+    // the revealed secret must be the one behind the point they gave us
+    assert!(matches!(
+        remote.validate_counterparty_revocation(channel_keys_id, 0, &their_payment_basepoint, &per_commitment_secret),
+        Err(SignerError::Rejected(_))
+    ));
+
+    // the commitment is signed with the channel's funding key
+    let sighash = SighashCache::new(&tx)
+        .p2wsh_signature_hash(0, &redeem_script, Amount::from_sat(5_000_000), EcdsaSighashType::All)
+        .unwrap();
+    let msg = Message::from_digest(sighash.to_byte_array());
+    assert!(secp_ctx
+        .verify_ecdsa(&msg, &commitment_sig.unwrap(), &channel_keys.funding_pubkey())
+        .is_ok());
+
+    // errors come back as errors, and the connection stays usable
+    assert!(matches!(
+        remote.sign_htlc(channel_keys_id, &tx, 1, &redeem_script, 10_000, 0, &per_commitment_point),
+        Err(SignerError::Rejected(_))
+    ));
+    assert_eq!(remote.node_id(), Ok(keys_manager.node_id));
+
+    let _ = std::fs::remove_file(&socket_path);
+}
+
+#[test]
+fn test_signer_rejects_invalid_requests() {
+    let keys_manager = SimpleKeysManager::new([1_u8; 32]);
+
+    let request = SignerRequest::Ecdh { other_key: "not a key".to_string(), tweak: None };
+    assert!(matches!(
+        remote_signer::handle_request(&keys_manager, request),
+        SignerResponse::Error(SignerError::InvalidMessage(_))
+    ));
+
+    let request: SignerRequest = serde_json::from_str(r#"{"method":"get_node_id"}"#).unwrap();
+    assert_eq!(
+        remote_signer::handle_request(&keys_manager, request),
+        SignerResponse::NodeId(keys_manager.node_id.to_string())
+    );
+
+    // channel keys IDs we didn't hand out, whether we could have or not
+    let signer: &dyn remote_signer::Signer = &keys_manager;
+    let per_commitment_secret = secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+    let sign_justice = |channel_keys_id: [u8; 32]| {
+        signer.sign_justice(channel_keys_id, &policy_test_tx(0, vec![]), 0, &[ScriptBuf::new()], &[1_000],
+                            &per_commitment_secret)
+    };
+    assert!(matches!(sign_justice([0x80; 32]), Err(SignerError::Rejected(_))));
+    assert!(matches!(sign_justice([0; 32]), Err(SignerError::Rejected(_))));
+
+    let channel_keys_id = keys_manager.derive_channel_keys(1_000_000, 7).channel_keys_id();
+    assert!(sign_justice(channel_keys_id).is_ok());
+    let mut made_up = channel_keys_id;
+    made_up[19] ^= 1;
+    assert!(matches!(sign_justice(made_up), Err(SignerError::Rejected(_))));
+}
+
+fn policy_test_tx(lock_time: u32, outputs: Vec<bitcoin::TxOut>) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::from_consensus(lock_time),
+        input: vec![TxIn {
+            previous_output: OutPoint { txid: Txid::all_zeros(), vout: 0 },
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        }],
+        output: outputs,
+    }
+}
+
+fn policy_denial<T: std::fmt::Debug>(result: Result<T, SignerError>) -> PolicyDenial {
+    match result {
+        Err(SignerError::PolicyDenied(denial)) => denial,
+        other => panic!("expected a policy denial, got {:?}", other),
+    }
+}
+
+/// Commitment `commitment_number` of a channel whose commitment numbers are
+/// obscured with `obscure_factor`.
+fn policy_test_commitment(commitment_number: u64, obscure_factor: u64, outputs: Vec<bitcoin::TxOut>) -> Transaction {
+    let (lock_time, sequence) = obscured_commitment_locktime_and_sequence(commitment_number, obscure_factor);
+    let mut tx = policy_test_tx(lock_time.to_consensus_u32(), outputs);
+    tx.input[0].sequence = sequence;
+    tx
+}
+
+/// A policy over our keys for a channel we opened with the keys manager of
+/// seed `[2; 32]`, and what the channel's commitment numbers are obscured
+/// with.
+fn ready_policy_signer(keys_manager: SimpleKeysManager, channel_keys_id: [u8; 32])
+                       -> (PolicySigner<SimpleKeysManager>, u64) {
+    let config = PolicyConfig::for_keys_manager(&keys_manager);
+    let ours = keys_manager.channel_keys_from_id(5_000_000, channel_keys_id).unwrap();
+    let theirs = SimpleKeysManager::new([2_u8; 32]).derive_channel_keys(5_000_000, 42);
+    let policy = PolicySigner::new(keys_manager, config);
+    let signer: &dyn remote_signer::Signer = &policy;
+    signer.ready_channel(channel_keys_id, true, &theirs.payment_point()).unwrap();
+    (policy, commitment_number_obscure_factor(&ours.payment_point(), &theirs.payment_point()))
+}
+
+#[test]
+fn test_policy_rejects_revoked_and_conflicting_commitments() {
+    let keys_manager = SimpleKeysManager::new([1_u8; 32]);
+    let channel_keys = keys_manager.derive_channel_keys(5_000_000, 42);
+    let channel_keys_id = channel_keys.channel_keys_id();
+    let funding_script = two_of_two_multisig_witness_script(
+        &channel_keys.funding_pubkey(),
+        &SimpleKeysManager::new([2_u8; 32]).derive_channel_keys(5_000_000, 42).funding_pubkey(),
+    );
+    let (policy, obscure_factor) = ready_policy_signer(keys_manager, channel_keys_id);
+    let policy: &dyn remote_signer::Signer = &policy;
+    let secp_ctx = Secp256k1::new();
+    let per_commitment_secret = secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+    let per_commitment_point = secp256k1::PublicKey::from_secret_key(&secp_ctx, &per_commitment_secret);
+
+    let commitment = |number: u64, to_remote: u64| {
+        policy_test_commitment(number, obscure_factor, vec![build_output(to_remote, ScriptBuf::new())])
+    };
+    let sign = |tx: &Transaction| policy.sign_commitment(channel_keys_id, tx, &funding_script, 5_000_000);
+    let revoke = |number: u64| {
+        policy.validate_counterparty_revocation(channel_keys_id, number, &per_commitment_point, &per_commitment_secret)
+    };
+
+    // signing the same commitment twice is fine, signing another as the same
+    // number is not
+    assert!(sign(&commitment(1, 4_999_000)).is_ok());
+    assert!(sign(&commitment(1, 4_999_000)).is_ok());
+    assert_eq!(
+        policy_denial(sign(&commitment(1, 4_998_000))),
+        PolicyDenial::ConflictingCommitment {
+            commitment_number: 1,
+            signed_txid: commitment(1, 4_999_000).compute_txid().to_string(),
+        }
+    );
+
+    assert!(revoke(1).is_ok());
+    assert_eq!(
+        policy_denial(sign(&commitment(1, 4_999_000))),
+        PolicyDenial::RevokedCommitment { commitment_number: 1, latest_revoked: 1 }
+    );
+    assert_eq!(
+        policy_denial(sign(&commitment(0, 4_999_000))),
+        PolicyDenial::RevokedCommitment { commitment_number: 0, latest_revoked: 1 }
+    );
+    assert_eq!(
+        policy_denial(revoke(0)),
+        PolicyDenial::StaleRevocation { commitment_number: 0, latest_revoked: 1 }
+    );
+    assert!(sign(&commitment(2, 4_998_000)).is_ok());
+
+    // a 100_000 sat fee is far above the default ceiling
+    assert!(matches!(
+        policy_denial(sign(&commitment(3, 4_900_000))),
+        PolicyDenial::FeeRateTooHigh { .. }
+    ));
+
+    // the number comes from the transaction: 3 obscured with another factor
+    // decodes to 2, which we signed already, and one that isn't obscured at
+    // all isn't a commitment
+    let mut other_channel = policy_test_commitment(3, obscure_factor ^ 1, vec![build_output(4_999_000, ScriptBuf::new())]);
+    assert_eq!(
+        policy_denial(sign(&other_channel)),
+        PolicyDenial::ConflictingCommitment {
+            commitment_number: 2,
+            signed_txid: commitment(2, 4_998_000).compute_txid().to_string(),
+        }
+    );
+    other_channel.input[0].sequence = Sequence::MAX;
+    assert_eq!(policy_denial(sign(&other_channel)), PolicyDenial::NotACommitment);
+
+    let mut two_inputs = commitment(4, 4_999_000);
+    two_inputs.input.push(two_inputs.input[0].clone());
+    assert_eq!(policy_denial(sign(&two_inputs)), PolicyDenial::UnexpectedInputCount(2));
+
+    // commitment numbers are 48 bits, and the next one has to exist
+    assert_eq!(policy_denial(revoke(u64::MAX)), PolicyDenial::InvalidCommitmentNumber(u64::MAX));
+    assert_eq!(policy_denial(revoke(1 << 48)), PolicyDenial::InvalidCommitmentNumber(1 << 48));
+    assert!(sign(&commitment(4, 4_999_000)).is_ok());
+}
+
+#[test]
+fn test_policy_needs_the_channel_parameters() {
+    let keys_manager = SimpleKeysManager::new([1_u8; 32]);
+    let config = PolicyConfig::for_keys_manager(&keys_manager);
+    let channel_keys_id = keys_manager.derive_channel_keys(5_000_000, 42).channel_keys_id();
+    let theirs = SimpleKeysManager::new([2_u8; 32]).derive_channel_keys(5_000_000, 42);
+    let policy: &dyn remote_signer::Signer = &PolicySigner::new(keys_manager, config);
+
+    let commitment = policy_test_commitment(1, 0, vec![build_output(4_999_000, ScriptBuf::new())]);
+    assert_eq!(
+        policy_denial(policy.sign_commitment(channel_keys_id, &commitment, &ScriptBuf::new(), 5_000_000)),
+        PolicyDenial::ChannelNotReady
+    );
+
+    assert!(policy.ready_channel(channel_keys_id, true, &theirs.payment_point()).is_ok());
+    assert!(policy.ready_channel(channel_keys_id, true, &theirs.payment_point()).is_ok());
+    assert_eq!(
+        policy_denial(policy.ready_channel(channel_keys_id, false, &theirs.payment_point())),
+        PolicyDenial::ChannelParametersChanged
+    );
+    assert!(matches!(
+        policy.ready_channel([0x80; 32], true, &theirs.payment_point()),
+        Err(SignerError::Rejected(_))
+    ));
+}
+
+#[test]
+fn test_policy_state_survives_restarts() {
+    let state_path = std::env::temp_dir().join(format!("signer-policy-{}.json", thread_rng().gen::<u64>()));
+    let keys_manager = SimpleKeysManager::new([1_u8; 32]);
+    let config = PolicyConfig::for_keys_manager(&keys_manager);
+    let their_payment_basepoint = SimpleKeysManager::new([2_u8; 32]).derive_channel_keys(5_000_000, 42).payment_point();
+    let secp_ctx = Secp256k1::new();
+    let per_commitment_secret = secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+    let per_commitment_point = secp256k1::PublicKey::from_secret_key(&secp_ctx, &per_commitment_secret);
+
+    let policy = PolicySigner::with_state_file(keys_manager, config.clone(), state_path.clone()).unwrap();
+    let signer: &dyn remote_signer::Signer = &policy;
+    let channel_keys_id = signer.new_channel_keys_id(5_000_000, 42).unwrap();
+    let channel_keys = policy.inner().channel_keys_from_id(5_000_000, channel_keys_id).unwrap();
+    let obscure_factor = commitment_number_obscure_factor(&channel_keys.payment_point(), &their_payment_basepoint);
+    assert!(signer.ready_channel(channel_keys_id, true, &their_payment_basepoint).is_ok());
+    assert!(signer
+        .validate_counterparty_revocation(channel_keys_id, 5, &per_commitment_point, &per_commitment_secret)
+        .is_ok());
+    assert_eq!(PolicyState::load(&state_path).unwrap(), policy.state());
+
+    // a restarted signer doesn't know the channel until it restores the
+    // channels in the policy's state, and still refuses the revoked
+    // commitment
+    let restarted = PolicySigner::with_state_file(SimpleKeysManager::new([1_u8; 32]), config, state_path.clone()).unwrap();
+    let restarted_signer: &dyn remote_signer::Signer = &restarted;
+    assert!(matches!(restarted_signer.payment_basepoint(channel_keys_id), Err(SignerError::Rejected(_))));
+    for channel_keys_id in restarted.state().channel_keys_ids() {
+        restarted.inner().restore_channel_keys_id(channel_keys_id);
+    }
+    assert_eq!(restarted_signer.payment_basepoint(channel_keys_id), Ok(channel_keys.payment_point()));
+
+    let commitment = policy_test_commitment(5, obscure_factor, vec![build_output(4_999_000, ScriptBuf::new())]);
+    assert_eq!(
+        policy_denial(restarted_signer.sign_commitment(channel_keys_id, &commitment, &ScriptBuf::new(), 5_000_000)),
+        PolicyDenial::RevokedCommitment { commitment_number: 5, latest_revoked: 5 }
+    );
+    assert_ne!(restarted_signer.new_channel_keys_id(5_000_000, 42), Ok(channel_keys_id));
+
+    let _ = std::fs::remove_file(&state_path);
+}
+
+#[test]
+fn test_policy_rejects_closing_to_unknown_scripts() {
+    let keys_manager = SimpleKeysManager::new([1_u8; 32]);
+    let config = PolicyConfig::for_keys_manager(&keys_manager);
+    let our_script = keys_manager.get_shutdown_scriptpubkey();
+    let channel_keys = keys_manager.derive_channel_keys(5_000_000, 42);
+    let channel_keys_id = channel_keys.channel_keys_id();
+    let funding_script = p2wpkh_output_script(channel_keys.funding_pubkey());
+    let policy: &dyn remote_signer::Signer = &PolicySigner::new(keys_manager, config);
+
+    let secp_ctx = Secp256k1::new();
+    let their_script = p2wpkh_output_script(SimpleKeysManager::new([2_u8; 32]).coop_close_pubkey);
+    let other_script = p2wpkh_output_script(secp256k1::PublicKey::from_secret_key(
+        &secp_ctx,
+        &secp256k1::SecretKey::from_slice(&[9; 32]).unwrap(),
+    ));
+    let closing = |to_local_script: &ScriptBuf, to_remote_script: &ScriptBuf| {
+        build_closing_transaction(
+            policy_test_tx(0, vec![]).input[0].clone(),
+            to_local_script.clone(),
+            to_remote_script.clone(),
+            3_000_000_000,
+            1_999_000_000,
+            true,
+            546,
+            1_000,
+        )
+    };
+    let sign = |tx: &Transaction| policy.sign_closing(channel_keys_id, tx, &funding_script, 5_000_000);
+
+    // their shutdown script comes first, and only once
+    assert_eq!(policy_denial(sign(&closing(&our_script, &their_script))), PolicyDenial::NoShutdownScript);
+    assert!(policy.set_counterparty_shutdown_script(channel_keys_id, &their_script).is_ok());
+    assert_eq!(
+        policy_denial(policy.set_counterparty_shutdown_script(channel_keys_id, &other_script)),
+        PolicyDenial::ShutdownScriptChanged(other_script.to_hex_string())
+    );
+
+    assert_eq!(
+        policy_denial(sign(&closing(&our_script, &other_script))),
+        PolicyDenial::UnknownScript(other_script.to_hex_string())
+    );
+    assert!(sign(&closing(&our_script, &their_script)).is_ok());
+
+    // as the accepter with a dust balance, the close pays them alone
+    let dust_to_us = build_closing_transaction(
+        policy_test_tx(0, vec![]).input[0].clone(),
+        our_script.clone(),
+        their_script.clone(),
+        500_000,
+        4_999_500_000,
+        false,
+        546,
+        1_000,
+    );
+    assert_eq!(dust_to_us.output.len(), 1);
+    assert!(sign(&dust_to_us).is_ok());
+
+    let mut two_inputs = closing(&our_script, &their_script);
+    two_inputs.input.push(two_inputs.input[0].clone());
+    assert_eq!(policy_denial(sign(&two_inputs)), PolicyDenial::UnexpectedInputCount(2));
+}
+
+#[test]
+fn test_policy_rejects_justice_to_unknown_scripts() {
+    let keys_manager = SimpleKeysManager::new([1_u8; 32]);
+    let config = PolicyConfig::for_keys_manager(&keys_manager);
+    let our_script = keys_manager.get_shutdown_scriptpubkey();
+    let channel_keys_id = keys_manager.derive_channel_keys(5_000_000, 42).channel_keys_id();
+    let their_script = p2wpkh_output_script(SimpleKeysManager::new([2_u8; 32]).coop_close_pubkey);
+    let policy: &dyn remote_signer::Signer = &PolicySigner::new(keys_manager, config);
+    let per_commitment_secret = secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+
+    let sign = |tx: &Transaction, amounts: &[u64]| {
+        let redeem_scripts = vec![ScriptBuf::new(); amounts.len()];
+        policy.sign_justice(channel_keys_id, tx, 0, &redeem_scripts, amounts, &per_commitment_secret)
+    };
+
+    assert!(sign(&policy_test_tx(0, vec![build_output(399_000, our_script.clone())]), &[400_000]).is_ok());
+    assert_eq!(
+        policy_denial(sign(&policy_test_tx(0, vec![build_output(399_000, their_script.clone())]), &[400_000])),
+        PolicyDenial::UnknownScript(their_script.to_hex_string())
+    );
+
+    // the fee is over every input, so we need the amount of each
+    let mut two_inputs = policy_test_tx(0, vec![build_output(799_000, our_script.clone())]);
+    two_inputs.input.push(two_inputs.input[0].clone());
+    assert_eq!(policy_denial(sign(&two_inputs, &[400_000])), PolicyDenial::UnknownFee);
+    assert!(sign(&two_inputs, &[400_000, 400_000]).is_ok());
+    assert_eq!(policy_denial(sign(&two_inputs, &[u64::MAX, 1])), PolicyDenial::UnknownFee);
+
+    // a fee too large to compute a fee rate from is refused, and leaves the
+    // signer working
+    assert_eq!(
+        policy_denial(sign(&policy_test_tx(0, vec![build_output(399_000, our_script.clone())]), &[u64::MAX])),
+        PolicyDenial::FeeRateTooHigh { feerate_per_kw: u64::MAX, max_feerate_per_kw: DEFAULT_MAX_FEERATE_PER_KW }
+    );
+    assert!(sign(&policy_test_tx(0, vec![build_output(399_000, our_script)]), &[400_000]).is_ok());
+}
+
+#[test]
+fn test_policy_signs_justice_transactions_sweeping_several_outputs() {
+    let keys_manager = SimpleKeysManager::new([1_u8; 32]);
+    let config = PolicyConfig::for_keys_manager(&keys_manager);
+    let our_script = keys_manager.get_shutdown_scriptpubkey();
+    let ours = keys_manager.derive_channel_keys(5_000_000, 42);
+    let theirs = SimpleKeysManager::new([2_u8; 32]).derive_channel_keys(5_000_000, 42);
+    let policy: &dyn remote_signer::Signer = &PolicySigner::new(keys_manager, config);
+
+    // they broadcast a commitment they revoked, with an HTLC they offered
+    let secp_ctx = Secp256k1::new();
+    let per_commitment_secret = secp256k1::SecretKey::from_slice(&[3; 32]).unwrap();
+    let per_commitment_point = secp256k1::PublicKey::from_secret_key(&secp_ctx, &per_commitment_secret);
+    let their_delayed_pubkey = derive_public_key(&theirs.delayed_payment_basepoint(), &per_commitment_point);
+    let their_htlc_pubkey = derive_public_key(&theirs.htlc_basepoint(), &per_commitment_point);
+    let our_htlc_pubkey = derive_public_key(&ours.htlc_basepoint(), &per_commitment_point);
+    let revocation_pubkey = generate_revocation_pubkey(ours.revocation_basepoint(), per_commitment_point);
+    let htlcs = vec![HtlcOutput { offered: true, amount: 400_000, cltv_expiry: 300, payment_hash160: [7; 20] }];
+    let (revoked_tx, _) = build_fee_aware_commitment_transaction(
+        policy_test_tx(0, vec![]).input[0].clone(),
+        1,
+        &theirs.payment_point(),
+        &ours.payment_point(),
+        true,
+        ChannelType::StaticRemoteKey,
+        &theirs.funding_pubkey(),
+        &ours.funding_pubkey(),
+        &revocation_pubkey,
+        &our_htlc_pubkey,
+        &their_htlc_pubkey,
+        &their_delayed_pubkey,
+        144,
+        1_000,
+        546,
+        &htlcs,
+        3_000_000_000,
+        1_600_000_000,
+    );
+
+    let justice_tx = build_justice_transaction(
+        &revoked_tx,
+        ChannelType::StaticRemoteKey,
+        &per_commitment_secret,
+        &ours.revocation_base_key,
+        &their_delayed_pubkey,
+        144,
+        &our_htlc_pubkey,
+        &their_htlc_pubkey,
+        &htlcs,
+        our_script,
+        &RegtestFeeEstimator,
+    )
+    .unwrap();
+    assert_eq!(justice_tx.input.len(), 2);
+
+    let redeem_scripts: Vec<ScriptBuf> = justice_tx
+        .input
+        .iter()
+        .map(|input| ScriptBuf::from_bytes(input.witness.last().unwrap().to_vec()))
+        .collect();
+    let amounts: Vec<u64> = justice_tx
+        .input
+        .iter()
+        .map(|input| revoked_tx.output[input.previous_output.vout as usize].value.to_sat())
+        .collect();
+
+    // the policy signs each input as the transaction itself was signed
+    for (input_index, input) in justice_tx.input.iter().enumerate() {
+        let signature = policy
+            .sign_justice(ours.channel_keys_id(), &justice_tx, input_index, &redeem_scripts, &amounts,
+                          &per_commitment_secret)
+            .unwrap();
+        let witness_signature = input.witness.nth(0).unwrap();
+        assert_eq!(signature.serialize_der().to_vec(), witness_signature[..witness_signature.len() - 1]);
+    }
+
+    // the amount of one input only doesn't tell the fee
+    assert_eq!(
+        policy_denial(policy.sign_justice(ours.channel_keys_id(), &justice_tx, 0, &redeem_scripts[..1],
+                                          &amounts[..1], &per_commitment_secret)),
+        PolicyDenial::UnknownFee
+    );
+}
+
+#[test]
+fn test_policy_only_signs_gossip_with_the_node_secret() {
+    let keys_manager = SimpleKeysManager::new([1_u8; 32]);
+    let node_id = keys_manager.node_id;
+    let config = PolicyConfig::for_keys_manager(&keys_manager);
+    let policy: &dyn remote_signer::Signer = &PolicySigner::new(keys_manager, config);
+
+    let announcement = UnsignedNodeAnnouncement {
+        features: NodeFeatures::empty(),
+        timestamp: 1,
+        node_id: NodeId::from_pubkey(&node_id),
+        rgb: [0; 3],
+        alias: NodeAlias([0; 32]),
+        addresses: vec![],
+        excess_address_data: vec![],
+        excess_data: vec![],
+    };
+    let mut node_announcement = vec![0x01, 0x01];
+    node_announcement.extend([0; 64]);
+    node_announcement.extend(announcement.encode());
+
+    // the signature covers what follows the type and the signature
+    let signature = policy.sign_gossip(&node_announcement).unwrap();
+    let msg_hash = Message::from_digest(Sha256dHash::hash(&announcement.encode()).to_byte_array());
+    assert!(Secp256k1::new().verify_ecdsa(&msg_hash, &signature, &node_id).is_ok());
+
+    // a channel_announcement carries four signatures
+    let mut channel_announcement = vec![0x01, 0x00];
+    channel_announcement.extend([0; 4 * 64]);
+    assert!(policy.sign_gossip(&channel_announcement).is_ok());
+    assert_eq!(policy_denial(policy.sign_gossip(&channel_announcement[..200])), PolicyDenial::NotGossip);
+
+    // nothing else gets signed with the node secret, like a transaction's
+    // sighash or a BOLT 11 invoice
+    let mut other = vec![0x01, 0x03];
+    other.extend([0; 64]);
+    assert_eq!(policy_denial(policy.sign_gossip(&other)), PolicyDenial::NotGossip);
+    assert_eq!(policy_denial(policy.sign_gossip(&[0x01])), PolicyDenial::NotGossip);
+    assert_eq!(policy_denial(policy.sign_gossip(&[0; 32])), PolicyDenial::NotGossip);
+}
+
+#[test]
+fn test_policy_rejects_htlc_transactions_with_mismatched_cltv() {
+    let keys_manager = SimpleKeysManager::new([1_u8; 32]);
+    let config = PolicyConfig::for_keys_manager(&keys_manager);
+    let ours = keys_manager.derive_channel_keys(5_000_000, 42);
+    let theirs = SimpleKeysManager::new([2_u8; 32]).derive_channel_keys(5_000_000, 42);
+    let policy: &dyn remote_signer::Signer = &PolicySigner::new(keys_manager, config);
+
+    let secp_ctx = Secp256k1::new();
+    let per_commitment_point = secp256k1::PublicKey::from_secret_key(
+        &secp_ctx,
+        &secp256k1::SecretKey::from_slice(&[3; 32]).unwrap(),
+    );
+    let htlc_script = |offered: bool| {
+        let htlc = HtlcOutput { offered, amount: 400_000, cltv_expiry: 500_000, payment_hash160: [7; 20] };
+        htlc_witness_script(
+            ChannelType::StaticRemoteKey,
+            &htlc,
+            &ours.revocation_basepoint(),
+            &theirs.htlc_basepoint(),
+            &ours.htlc_basepoint(),
+        )
+    };
+    let htlc_txin = policy_test_tx(0, vec![]).input[0].clone();
+    let timeout_tx = |cltv_expiry: u32| {
+        build_htlc_timeout_transaction(htlc_txin.clone(), &ours.revocation_basepoint(),
+                                       &ours.delayed_payment_basepoint(), 144, cltv_expiry, 399_000)
+    };
+    let success_tx = build_htlc_success_transaction(htlc_txin.clone(), &ours.revocation_basepoint(),
+                                                    &ours.delayed_payment_basepoint(), 144, 399_000);
+    let sign = |tx: &Transaction, script: &ScriptBuf, cltv_expiry: u32| {
+        policy.sign_htlc(ours.channel_keys_id(), tx, 0, script, 400_000, cltv_expiry, &per_commitment_point)
+    };
+
+    assert!(sign(&timeout_tx(500_000), &htlc_script(true), 500_000).is_ok());
+    assert_eq!(
+        policy_denial(sign(&timeout_tx(499_000), &htlc_script(true), 500_000)),
+        PolicyDenial::CltvMismatch { expected: 500_000, actual: 499_000 }
+    );
+
+    assert!(sign(&success_tx, &htlc_script(false), 500_000).is_ok());
+    assert_eq!(
+        policy_denial(sign(&success_tx, &htlc_script(false), 510_000)),
+        PolicyDenial::CltvMismatch { expected: 510_000, actual: 500_000 }
+    );
+
+    assert_eq!(
+        policy_denial(sign(&success_tx, &p2wpkh_output_script(ours.htlc_basepoint()), 500_000)),
+        PolicyDenial::UnknownHtlcScript
+    );
+}
@@ -39,7 +39,8 @@ use lightning_block_sync::rpc::RpcClient;
 use lightning_block_sync::SpvClient;
 use lightning_block_sync::{AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSource};
 use ch1_intro_htlcs::solutions::{
-    build_htlc_commitment_transaction, build_commitment_transaction, two_of_two_multisig_witness_script, build_refund_transaction
+    build_htlc_commitment_transaction, build_commitment_transaction, two_of_two_multisig_witness_script, build_refund_transaction,
+    derive_public_key, generate_revocation_pubkey,
 };
 use bitcoin::PublicKey as BitcoinPubKey;
 use internal::bitcoind_client;
@@ -56,7 +57,8 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
 use hex;
-use helper::{get_bitcoind_client, get_unspent_output, sign_raw_transaction, generate_p2wsh_signature, get_funding_input, get_arg};
+use helper::{get_bitcoind_client, get_unspent_output, sign_raw_transaction, generate_p2wsh_signature, get_funding_input, get_arg, get_per_commitment_point,
+             check_p2wsh_witness, our_channel_keys, counterparty_channel_keys};
 
 
 pub struct KeyManager{
@@ -88,6 +90,10 @@ pub async fn create_broadcast_funding_tx(bitcoind: BitcoindClient,
         our_balance,
         counterparty_balance);
 
+    let redeem_script = two_of_two_multisig_witness_script(
+        &our_key_manager.funding_public_key,
+        &counterparty_key_manager.funding_public_key);
+
     let signed_tx = sign_funding_transaction(tx, our_key_manager, counterparty_key_manager);
 
     println!("\n");
@@ -95,6 +101,8 @@ pub async fn create_broadcast_funding_tx(bitcoind: BitcoindClient,
     println!("\n");
     println!("Tx Hex: {}", serialize_hex(&signed_tx));
 
+    check_p2wsh_witness(&signed_tx, txid_index, &redeem_script, funding_amount);
+
 }
 
 
@@ -106,12 +114,17 @@ pub async fn run(funding_txid: String) {
     // get bitcoin client
     let bitcoind = get_bitcoind_client().await;
 
-    // Get our keys
-    let our_funding_private_key = secp256k1_private_key(&[0x01; 32]);
-    let our_funding_public_key = pubkey_from_private_key(&[0x01; 32]);
-    let our_commitment_pubkey = pubkey_from_private_key(&[0x11; 32]);
-    let our_revocation_pubkey = pubkey_from_private_key(&[0x12; 32]);
-    let our_delayed_pubkey = pubkey_from_private_key(&[0x13; 32]);
+    // Get our keys, and our counterparty's
+    let our_keys = our_channel_keys();
+    let counterparty_keys = counterparty_channel_keys();
+    let our_funding_private_key = our_keys.funding_key;
+    let our_funding_public_key = our_keys.funding_pubkey();
+    let per_commitment_point = get_per_commitment_point();
+    let our_commitment_pubkey = our_keys.payment_point();
+    let our_revocation_pubkey =
+        generate_revocation_pubkey(counterparty_keys.revocation_basepoint(), per_commitment_point);
+    let our_delayed_pubkey =
+        derive_public_key(&our_keys.delayed_payment_basepoint(), &per_commitment_point);
 
     let our_key_manager = KeyManager{
             funding_private_key: our_funding_private_key,
@@ -122,11 +135,11 @@ pub async fn run(funding_txid: String) {
         };
 
     // Get our Counterparty Pubkey
-    let counterparty_funding_private_key = secp256k1_private_key(&[0x02; 32]);
-    let counterparty_funding_public_key = pubkey_from_private_key(&[0x02; 32]);
-    let counterparty_commitment_pubkey = pubkey_from_private_key(&[0x21; 32]);
-    let counterparty_revocation_pubkey = pubkey_from_private_key(&[0x22; 32]);
-    let counterparty_delayed_pubkey = pubkey_from_private_key(&[0x23; 32]);
+    let counterparty_funding_private_key = counterparty_keys.funding_key;
+    let counterparty_funding_public_key = counterparty_keys.funding_pubkey();
+    let counterparty_commitment_pubkey = counterparty_keys.payment_point();
+    let counterparty_revocation_pubkey = counterparty_keys.revocation_basepoint();
+    let counterparty_delayed_pubkey = counterparty_keys.delayed_payment_basepoint();
 
     let counterparty_key_manager = KeyManager{
             funding_private_key: counterparty_funding_private_key,
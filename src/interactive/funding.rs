@@ -36,7 +36,7 @@ use lightning_block_sync::SpvClient;
 use lightning_block_sync::{AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSource};
 use internal::bitcoind_client;
 use internal::bitcoind_client::BitcoindClient;
-use ch1_intro_htlcs::solutions::build_funding_transaction;
+use ch1_intro_htlcs::solutions::{fill_funding_output, two_of_two_multisig_witness_script};
 use internal::helper::{pubkey_from_private_key, bitcoin_pubkey_from_private_key, secp256k1_private_key};
 use internal::convert;
 use internal::convert::{BlockchainInfo, ListUnspentUtxo, SignedTx};
@@ -49,28 +49,30 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
 use bitcoin::PublicKey;
-use helper::{get_bitcoind_client, get_unspent_output, sign_raw_transaction};
+use helper::{get_bitcoind_client, sign_psbt, our_channel_keys, counterparty_channel_keys};
 
-pub async fn build_funding_tx(bitcoind: BitcoindClient,
-                                        tx_input: TxIn,
-                                        tx_in_amount: u64) {
+pub async fn build_funding_tx(bitcoind: BitcoindClient, funding_amount: u64) {
 
     // we're locking to a 2-of-2 multisig, so we need two public keys
     // normally, we would generate our own public key
     //   and the counterparty would send us theirs
-    let our_public_key = pubkey_from_private_key(&[0x01; 32]);
-    let counterparty_pubkey = pubkey_from_private_key(&[0x02; 32]);
+    let our_public_key = our_channel_keys().funding_pubkey();
+    let counterparty_pubkey = counterparty_channel_keys().funding_pubkey();
 
-    // build funding transaction using the function we created
-    let tx = build_funding_transaction(
-            vec![tx_input],
-            &our_public_key,
-            &counterparty_pubkey,
-            tx_in_amount,
-        );
+    // the wallet picks the inputs, pays the fee and adds change
+    //   after the funding output, which stays at index 0
+    let funding_script = two_of_two_multisig_witness_script(&our_public_key, &counterparty_pubkey);
+    let funding_address = Address::p2wsh(&funding_script, Network::Regtest);
+    let funded = bitcoind.wallet_create_funded_psbt(&[(funding_address, funding_amount)]).await;
 
-    let signed_tx = sign_raw_transaction(bitcoind.clone(), tx).await;
+    // fill in the funding script, so the wallet sees what it funds
+    let mut psbt = funded.psbt;
+    fill_funding_output(&mut psbt, &our_public_key, &counterparty_pubkey);
 
+    let signed_tx = sign_psbt(bitcoind.clone(), psbt).await;
+
+    println!("\n");
+    println!("Fee: {} sats", funded.fee);
     println!("\n");
     println!("Tx ID: {}", signed_tx.compute_txid());
     println!("\n");
@@ -82,14 +84,10 @@ pub async fn run() {
     // get bitcoin client
     let bitcoind = get_bitcoind_client().await;
 
-    // get an unspent output for funding transaction
-    let tx_input = get_unspent_output(bitcoind.clone()).await;
+    let funding_amount = 5_000_000;
 
-    let tx_in_amount = 5_000_000;
-    
-        build_funding_tx(bitcoind, tx_input, tx_in_amount).await;
+    build_funding_tx(bitcoind, funding_amount).await;
 
     // Add a delay to allow the spawned task to complete
     sleep(Duration::from_secs(2)).await;
 }
-
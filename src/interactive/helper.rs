@@ -1,5 +1,6 @@
 #![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
 use crate::internal;
+use crate::ch3_keys::exercises::{ChannelKeys, SimpleKeysManager};
 use internal::bitcoind_client::BitcoindClient;
 use bitcoin::blockdata::transaction::Transaction;
 use bitcoin::consensus::encode::serialize_hex;
@@ -7,7 +8,8 @@ use internal::hex_utils;
 use bitcoin::{Network};
 use bitcoin::blockdata::script::ScriptBuf;
 use bitcoin::consensus::{encode};
-use bitcoin::{OutPoint, Sequence, TxIn, Witness};
+use bitcoin::{OutPoint, Sequence, TxIn, TxOut, Witness};
+use bitcoin::psbt::Psbt;
 use bitcoin::hash_types::Txid;
 use bitcoin::hashes::Hash;
 use std::env;
@@ -19,6 +21,23 @@ use bitcoin::sighash::SighashCache;
 use bitcoin::secp256k1::Scalar;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::amount::Amount;
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use internal::helper::secp256k1_private_key;
+use internal::script_interpreter::verify_p2wsh_input;
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+
+/// Regtest has no fee market, so we use a fixed feerate a bit above the
+/// minimum relay fee (1 sat/vbyte = 250 sat/kw).
+pub struct RegtestFeeEstimator;
+
+impl FeeEstimator for RegtestFeeEstimator {
+  fn get_est_sat_per_1000_weight(&self, confirmation_target: ConfirmationTarget) -> u32 {
+    match confirmation_target {
+      ConfirmationTarget::UrgentOnChainSweep => 2_500,
+      _ => 1_000,
+    }
+  }
+}
 
 pub async fn get_bitcoind_client() -> BitcoindClient {
   let bitcoind = BitcoindClient::new(
@@ -35,6 +54,12 @@ pub async fn get_bitcoind_client() -> BitcoindClient {
 }
 
 pub async fn get_unspent_output(bitcoind: BitcoindClient) -> TxIn {
+  get_unspent_utxo(bitcoind).await.0
+}
+
+/// Like `get_unspent_output`, along with the output being spent, which PSBT
+/// signers need to see.
+pub async fn get_unspent_utxo(bitcoind: BitcoindClient) -> (TxIn, TxOut) {
   let utxos = bitcoind.list_unspent().await;
   let utxo = utxos
       .0
@@ -52,7 +77,12 @@ pub async fn get_unspent_output(bitcoind: BitcoindClient) -> TxIn {
         witness: Witness::new(),
     };
 
-    tx_input
+    let tx_output = TxOut {
+        value: Amount::from_sat(utxo.amount),
+        script_pubkey: utxo.address.script_pubkey(),
+    };
+
+    (tx_input, tx_output)
 }
 
 pub async fn sign_raw_transaction(bitcoind: BitcoindClient,
@@ -72,6 +102,18 @@ pub async fn sign_raw_transaction(bitcoind: BitcoindClient,
   final_tx
 }
 
+pub async fn sign_psbt(bitcoind: BitcoindClient,
+                       psbt: Psbt) -> Transaction {
+
+  // the wallet signs the inputs it owns
+  let processed = bitcoind.wallet_process_psbt(&psbt).await;
+
+  // and builds the final witnesses once every input is signed
+  let finalized = bitcoind.finalize_psbt(&processed.psbt).await;
+
+  finalized.tx.expect("wallet could not sign every PSBT input")
+}
+
 pub fn get_funding_input(input_tx_id_str: String, vout: usize) -> TxIn {
 
     // Get an unspent output to spend
@@ -130,6 +172,32 @@ pub fn build_unsigned_input(txid: String, vout: u32, sequence: Sequence) -> TxIn
     }
 }
 
+/// Per-commitment secret of the commitment transactions built by the demos.
+/// Every per-commitment key (revocation, delayed, HTLC) is derived from its
+/// point and the parties' basepoints.
+pub fn get_per_commitment_secret() -> SecretKey {
+    secp256k1_private_key(&[0x31; 32])
+}
+
+pub fn get_per_commitment_point() -> PublicKey {
+    PublicKey::from_secret_key(&Secp256k1::new(), &get_per_commitment_secret())
+}
+
+/// The funding amount of the channel opened by the `funding` command.
+pub const DEMO_CHANNEL_VALUE: u64 = 5_000_000;
+
+/// Our keys for the demo channel: the first channel our keys manager
+/// derives keys for.
+pub fn our_channel_keys() -> ChannelKeys {
+  SimpleKeysManager::new([0x01; 32]).derive_channel_keys(DEMO_CHANNEL_VALUE, 0)
+}
+
+/// Our counterparty's keys for the demo channel. Normally we would only
+/// know their public keys, sent to us in `open_channel`/`accept_channel`.
+pub fn counterparty_channel_keys() -> ChannelKeys {
+  SimpleKeysManager::new([0x02; 32]).derive_channel_keys(DEMO_CHANNEL_VALUE, 0)
+}
+
 pub fn get_arg() -> String {
     // Collect command-line arguments
     let args: Vec<String> = env::args().collect();
@@ -152,6 +220,9 @@ pub fn get_arg() -> String {
     txid.to_string()
 }
 
+/// Signs input `input_idx` with a private key the caller holds. The demos
+/// keep their own regtest keys, so no signing policy applies here; the
+/// node's channel keys are only signed with through `remote_signer::Signer`.
 pub fn generate_p2wsh_signature(
     transaction: Transaction,
     input_idx: usize,
@@ -164,9 +235,7 @@ pub fn generate_p2wsh_signature(
 
     let message =
         generate_p2wsh_message(transaction, input_idx, witness_script, value, sighash_type);
-    let signature = secp.sign_ecdsa(&message, &private_key);
-
-    signature
+    secp.sign_ecdsa(&message, &private_key)
 }
 
 fn generate_p2wsh_message(
@@ -183,10 +252,26 @@ fn generate_p2wsh_message(
     let amount = Amount::from_sat(value);
 
     let sighash = cache
-        .p2wsh_signature_hash(input_idx, &witness_script, amount, sighash_type)
+        .p2wsh_signature_hash(input_idx, witness_script, amount, sighash_type)
         .unwrap();
 
-    let message = Message::from_digest_slice(&sighash[..]).unwrap();
+    Message::from_digest_slice(&sighash[..]).unwrap()
+}
+
+/// Runs the witness of `transaction`'s input through the script interpreter
+/// and prints the trace, so a malformed witness shows up here instead of as a
+/// rejection from bitcoind. Returns whether the witness is valid.
+pub fn check_p2wsh_witness(
+    transaction: &Transaction,
+    input_idx: usize,
+    witness_script: &ScriptBuf,
+    value: u64,
+) -> bool {
+    let execution = verify_p2wsh_input(transaction, input_idx, value, witness_script);
+
+    println!("\n");
+    println!("Witness Script Execution (input {}):", input_idx);
+    println!("{}", execution);
 
-    message
+    execution.is_valid()
 }
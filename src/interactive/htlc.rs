@@ -39,7 +39,8 @@ use lightning_block_sync::rpc::RpcClient;
 use lightning_block_sync::SpvClient;
 use lightning_block_sync::{AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSource};
 use ch1_intro_htlcs::solutions::{
-    build_htlc_commitment_transaction, two_of_two_multisig_witness_script
+    build_htlc_commitment_transaction, two_of_two_multisig_witness_script,
+    derive_public_key, generate_revocation_pubkey,
 };
 use bitcoin::PublicKey as BitcoinPubKey;
 use internal::bitcoind_client;
@@ -56,7 +57,8 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
 use hex;
-use helper::{get_bitcoind_client, get_unspent_output, sign_raw_transaction, get_funding_input, get_arg, generate_p2wsh_signature};
+use helper::{get_bitcoind_client, get_unspent_output, sign_raw_transaction, get_funding_input, get_arg, generate_p2wsh_signature, get_per_commitment_point,
+             check_p2wsh_witness, our_channel_keys, counterparty_channel_keys};
 
 /// hash160 of the empty string
 const HASH160_DUMMY: [u8; 20] = [
@@ -160,6 +162,7 @@ pub async fn create_broadcast_funding_tx(bitcoind: BitcoindClient,
     println!("\n");
     println!("Tx Hex: {}", serialize_hex(&signed_tx));
 
+    check_p2wsh_witness(&signed_tx, txid_index, &redeem_script, funding_amount);
 }
 
 pub async fn run(funding_txid: String) {
@@ -170,13 +173,19 @@ pub async fn run(funding_txid: String) {
     // Parse the argument as txid
     let txid = funding_txid;
 
-    // Get our keys
-    let our_funding_private_key = secp256k1_private_key(&[0x01; 32]);
-    let our_funding_public_key = pubkey_from_private_key(&[0x01; 32]);
-    let local_htlc_pubkey = pubkey_from_private_key(&[0x11; 32]);
-    let revocation_pubkey = pubkey_from_private_key(&[0x12; 32]);
-    let to_local_delayed_pubkey = pubkey_from_private_key(&[0x13; 32]);
-    let local_pubkey = pubkey_from_private_key(&[0x14; 32]);
+    // Get our keys, and our counterparty's
+    let our_keys = our_channel_keys();
+    let counterparty_keys = counterparty_channel_keys();
+    let our_funding_private_key = our_keys.funding_key;
+    let our_funding_public_key = our_keys.funding_pubkey();
+    let per_commitment_point = get_per_commitment_point();
+    let local_htlc_pubkey =
+        derive_public_key(&our_keys.htlc_basepoint(), &per_commitment_point);
+    let revocation_pubkey =
+        generate_revocation_pubkey(counterparty_keys.revocation_basepoint(), per_commitment_point);
+    let to_local_delayed_pubkey =
+        derive_public_key(&our_keys.delayed_payment_basepoint(), &per_commitment_point);
+    let local_pubkey = our_keys.payment_point();
 
     let our_key_manager = KeyManager{
             funding_private_key: our_funding_private_key,
@@ -188,12 +197,13 @@ pub async fn run(funding_txid: String) {
         };
 
     // Get our Counterparty Pubkey
-    let counterparty_funding_private_key = secp256k1_private_key(&[0x02; 32]);
-    let counterparty_funding_public_key = pubkey_from_private_key(&[0x02; 32]);
-    let counterparty_htlc_pubkey = pubkey_from_private_key(&[0x21; 32]);
-    let counterparty_pubkey = pubkey_from_private_key(&[0x22; 32]);
-    let counterparty_delayed_key = pubkey_from_private_key(&[0x23; 32]);
-    let counterparty_revocation_key = pubkey_from_private_key(&[0x24; 32]);
+    let counterparty_funding_private_key = counterparty_keys.funding_key;
+    let counterparty_funding_public_key = counterparty_keys.funding_pubkey();
+    let counterparty_htlc_pubkey =
+        derive_public_key(&counterparty_keys.htlc_basepoint(), &per_commitment_point);
+    let counterparty_pubkey = counterparty_keys.payment_point();
+    let counterparty_delayed_key = counterparty_keys.delayed_payment_basepoint();
+    let counterparty_revocation_key = counterparty_keys.revocation_basepoint();
 
     let counterparty_key_manager = KeyManager{
             funding_private_key: counterparty_funding_private_key,
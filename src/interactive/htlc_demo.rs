@@ -39,11 +39,12 @@ use lightning_block_sync::SpvClient;
 use lightning_block_sync::{AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSource};
 use internal::bitcoind_client;
 use internal::bitcoind_client::BitcoindClient;
-use ch1_intro_htlcs::solutions::{build_funding_transaction, to_local};
+use ch1_intro_htlcs::solutions::{build_funding_transaction, to_local, set_output_amount_for_feerate, InputSatisfaction};
 use internal::helper::{pubkey_from_private_key, build_output, build_transaction, bitcoin_pubkey_from_private_key, secp256k1_private_key, p2wpkh_output_script};
 use internal::convert;
 use internal::convert::{BlockchainInfo, ListUnspentUtxo, SignedTx};
 use internal::hex_utils;
+use internal::script_disasm::pretty_print;
 use serde_json;
 use std::collections::HashMap;
 use std::str::FromStr;
@@ -52,9 +53,12 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
 use bitcoin::secp256k1::PublicKey;
-use helper::{get_bitcoind_client, get_unspent_output, sign_raw_transaction};
+use helper::{get_bitcoind_client, get_unspent_output, sign_raw_transaction, RegtestFeeEstimator};
 use bitcoin::hashes::ripemd160::Hash as Ripemd160;
 
+/// Value of the hash-locked output, which `htlc-demo2` spends.
+pub const HTLC_AMOUNT: u64 = 400_000;
+
 pub async fn build_funding_tx(bitcoind: BitcoindClient,
                                         tx_input: TxIn,
                                         tx_in_amount: u64) {
@@ -80,21 +84,29 @@ pub async fn build_funding_tx(bitcoind: BitcoindClient,
 
     let remote_output_script = p2wpkh_output_script(counterparty_public_key);
 
-    let local_output = build_output(3_594_500, local_output_script.to_p2wsh());
+    // to_local gets the change, so it is set once we know the fee
+    let local_output = build_output(0, local_output_script.to_p2wsh());
     let remote_output = build_output(1_000_000, remote_output_script);
 
     // build funding transaction using the function we created
     let output_script = build_hash_locked_script(&our_public_key,
                                                 &payment_hash160);
     println!("Witness Script (hex): {}", output_script.to_hex_string());
+    println!("Witness Script (asm):\n{}", pretty_print(&output_script));
     
 
-    let htlc_output = build_output(405_000, output_script.to_p2wsh());
+    let htlc_output = build_output(HTLC_AMOUNT, output_script.to_p2wsh());
 
     let version = Version::TWO;
     let locktime = LockTime::ZERO;
 
-    let tx = build_transaction(version, locktime, vec![tx_input], vec![local_output, remote_output, htlc_output]);
+    let mut tx = build_transaction(version, locktime, vec![tx_input], vec![local_output, remote_output, htlc_output]);
+
+    // bitcoind signs our wallet input, which is P2WPKH
+    let feerate = RegtestFeeEstimator.get_est_sat_per_1000_weight(ConfirmationTarget::NonAnchorChannelFee);
+    let fee = set_output_amount_for_feerate(&mut tx, 0, tx_in_amount, &[InputSatisfaction::P2wpkh], feerate)
+        .expect("wallet input covers the outputs");
+    println!("Fee: {} sats", fee);
 
     let signed_tx = sign_raw_transaction(bitcoind.clone(), tx).await;
 
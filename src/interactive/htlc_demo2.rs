@@ -1,6 +1,7 @@
 #![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
 use base64;
 use crate::interactive::helper;
+use crate::interactive::htlc_demo::HTLC_AMOUNT;
 use internal::builder::Builder;
 use crate::internal;
 use crate::ch1_intro_htlcs;
@@ -41,7 +42,8 @@ use lightning_block_sync::rpc::RpcClient;
 use lightning_block_sync::SpvClient;
 use lightning_block_sync::{AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSource};
 use ch1_intro_htlcs::solutions::{
-    build_htlc_commitment_transaction, build_commitment_transaction, two_of_two_multisig_witness_script, build_refund_transaction
+    build_htlc_commitment_transaction, build_commitment_transaction, two_of_two_multisig_witness_script, build_refund_transaction,
+    set_output_amount_for_feerate, InputSatisfaction,
 };
 use bitcoin::PublicKey as BitcoinPubKey;
 use internal::bitcoind_client;
@@ -61,9 +63,18 @@ use hex;
 use bitcoin::hashes::ripemd160::Hash as Ripemd160;
 use internal::helper::{pubkey_from_private_key, secp256k1_private_key,
                       p2wpkh_output_script, build_output, build_transaction};
-use helper::{get_bitcoind_client, get_unspent_output, sign_raw_transaction, generate_p2wsh_signature, get_htlc_funding_input, get_arg};
+use helper::{get_bitcoind_client, get_unspent_output, sign_raw_transaction, generate_p2wsh_signature, get_htlc_funding_input, get_arg,
+             check_p2wsh_witness, RegtestFeeEstimator};
 
 
+/// The preimage of the hash-locked output built by `htlc-demo`.
+const SECRET: &str = "ProgrammingLightning";
+
+fn payment_hash160() -> [u8; 20] {
+    let payment_hash = Sha256::hash(SECRET.as_bytes()).to_byte_array();
+    Ripemd160::hash(&payment_hash).to_byte_array()
+}
+
 pub async fn create_broadcast_funding_tx(bitcoind: BitcoindClient,
                                         txid: String,
                                         funding_amount: u64) {
@@ -81,6 +92,10 @@ pub async fn create_broadcast_funding_tx(bitcoind: BitcoindClient,
     println!("\n");
     println!("Tx Hex: {}", serialize_hex(&signed_tx));
 
+    // `sign_transaction` pushes the hash-locked script as the last witness element.
+    let witness_script = ScriptBuf::from_bytes(signed_tx.input[0].witness.last().unwrap().to_vec());
+    check_p2wsh_witness(&signed_tx, 0, &witness_script, HTLC_AMOUNT);
+
 }
 
 
@@ -105,16 +120,14 @@ pub async fn run(funding_txid: String) {
 
 pub fn sign_transaction(tx: Transaction)-> Transaction {
 
-    let funding_amount = 405_000;
+    let funding_amount = HTLC_AMOUNT;
     let txid_index = 0;
     
     let our_public_key = pubkey_from_private_key(&[0x01; 32]);
     let our_private_key = secp256k1_private_key(&[0x01; 32]);
 
-    let secret = "ProgrammingLightning".to_string();
-    let secret_bytes = secret.as_bytes();
-    let payment_hash = Sha256::hash(secret_bytes).to_byte_array();
-    let payment_hash160 = Ripemd160::hash(&payment_hash).to_byte_array();
+    let secret_bytes = SECRET.as_bytes();
+    let payment_hash160 = payment_hash160();
 
     // build funding transaction using the function we created
     let redeem_script = build_hash_locked_script(&our_public_key,
@@ -162,15 +175,24 @@ pub fn sign_transaction(tx: Transaction)-> Transaction {
 
 fn build_p2wpkh_tx(txin: TxIn, pubkey: PublicKey) -> Transaction {
     let output_script = p2wpkh_output_script(pubkey);
-    let output = build_output(405_000, output_script);
+    let output = build_output(0, output_script);
     
     let version = Version::TWO;
     let locktime = LockTime::from_consensus(200);
 
-    let tx = build_transaction(version,
+    let mut tx = build_transaction(version,
                       locktime,
                       vec![txin],
                       vec![output]);
+
+    // we spend the preimage path: `<secret> 1 <hash-locked script>`
+    let satisfaction = InputSatisfaction::Custom {
+        element_sizes: vec![SECRET.len() as u64, 1],
+        witness_script: build_hash_locked_script(&pubkey, &payment_hash160()),
+    };
+    let feerate = RegtestFeeEstimator.get_est_sat_per_1000_weight(ConfirmationTarget::NonAnchorChannelFee);
+    set_output_amount_for_feerate(&mut tx, 0, HTLC_AMOUNT, &[satisfaction], feerate)
+        .expect("HTLC output covers the fee");
     tx
     
 }
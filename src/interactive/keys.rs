@@ -0,0 +1,94 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+use crate::ch3_keys;
+use ch3_keys::bip39::Mnemonic;
+use ch3_keys::exercises::SimpleKeysManager;
+use bitcoin::Network;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::process;
+
+/// Reads a mnemonic or passphrase from `path`, or its first line from stdin
+/// when `path` is "-", so it never shows up in the process list or shell
+/// history.
+pub fn read_secret(path: &Path) -> io::Result<String> {
+    let secret = if path == Path::new("-") {
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line)?;
+        line
+    } else {
+        fs::read_to_string(path)?
+    };
+    Ok(secret.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// The passphrase in `path`, or none without one. Exits on failure.
+fn read_passphrase(path: Option<&Path>) -> String {
+    let path = match path {
+        Some(path) => path,
+        None => return String::new(),
+    };
+    match read_secret(path) {
+        Ok(passphrase) => passphrase,
+        Err(e) => {
+            eprintln!("Can't read passphrase from {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    }
+}
+
+fn print_node(mnemonic: &Mnemonic, passphrase: &str, network: Network) {
+    let keys_manager = SimpleKeysManager::from_mnemonic(mnemonic, passphrase, network);
+
+    println!("\n");
+    println!("Node ID: {}", keys_manager.node_id);
+    println!("\n");
+    println!("Watch-only descriptors, for bitcoind's `importdescriptors`:");
+    for descriptor in keys_manager.watch_only_descriptors() {
+        println!("{}", descriptor);
+    }
+}
+
+pub async fn run_new(word_count: usize, passphrase_file: Option<&Path>, network: Network) {
+    let mnemonic = match Mnemonic::generate(word_count) {
+        Ok(mnemonic) => mnemonic,
+        Err(e) => {
+            eprintln!("Can't create mnemonic: {}", e);
+            process::exit(1);
+        }
+    };
+    let passphrase = read_passphrase(passphrase_file);
+
+    println!("\n");
+    println!("Write these words down, in order, and keep them somewhere safe:");
+    println!("\n");
+    for (i, word) in mnemonic.words().iter().enumerate() {
+        println!("{:>2}. {}", i + 1, word);
+    }
+    if !passphrase.is_empty() {
+        println!("\n");
+        println!("You'll need your passphrase too, the words alone restore a different node.");
+    }
+
+    print_node(&mnemonic, &passphrase, network);
+}
+
+pub async fn run_restore(mnemonic_file: &Path, passphrase_file: Option<&Path>, network: Network) {
+    let words = match read_secret(mnemonic_file) {
+        Ok(words) => words,
+        Err(e) => {
+            eprintln!("Can't read mnemonic from {}: {}", mnemonic_file.display(), e);
+            process::exit(1);
+        }
+    };
+    let mnemonic = match Mnemonic::parse(&words) {
+        Ok(mnemonic) => mnemonic,
+        Err(e) => {
+            eprintln!("Invalid mnemonic: {}", e);
+            process::exit(1);
+        }
+    };
+    let passphrase = read_passphrase(passphrase_file);
+
+    print_node(&mnemonic, &passphrase, network);
+}
@@ -5,5 +5,8 @@ pub mod htlc;
 pub mod htlc_demo;
 pub mod htlc_demo2;
 pub mod htlc_timeout;
+pub mod keys;
+pub mod penalty;
 pub mod refund;
+pub mod splice;
 pub mod mempool;
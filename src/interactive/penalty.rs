@@ -0,0 +1,143 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+use crate::interactive::commit;
+use crate::interactive::helper;
+use crate::internal;
+use crate::ch1_intro_htlcs;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::secp256k1::{PublicKey, SecretKey};
+use lightning::chain::chaininterface::{ConfirmationTarget, FeeEstimator};
+use ch1_intro_htlcs::solutions::{
+    build_fee_aware_commitment_transaction, build_justice_transaction, derive_public_key,
+    generate_revocation_pubkey, ChannelType, HtlcOutput,
+};
+use internal::bitcoind_client::BitcoindClient;
+use internal::helper::{pubkey_from_private_key, secp256k1_private_key};
+use std::time::Duration;
+use tokio::time::sleep;
+use helper::{get_bitcoind_client, get_funding_input, get_per_commitment_point, get_per_commitment_secret,
+             our_channel_keys, counterparty_channel_keys, RegtestFeeEstimator};
+
+/// hash160 of the empty string
+const HASH160_DUMMY: [u8; 20] = [
+    0xb4, 0x72, 0xa2, 0x66, 0xd0, 0xbd, 0x89, 0xc1, 0x37, 0x06, 0xa4, 0x13, 0x2c, 0xcf, 0xb1, 0x6f,
+    0x7c, 0x3b, 0x9f, 0xcb,
+];
+
+pub async fn create_revoked_commitment_and_justice_tx(bitcoind: BitcoindClient, txid: String) {
+
+    let txid_index = 0;
+    let to_self_delay: i64 = 144;
+    let funding_txin = get_funding_input(txid.to_string(), txid_index);
+    let fee_estimator = RegtestFeeEstimator;
+
+    let our_keys = our_channel_keys();
+    let counterparty_keys = counterparty_channel_keys();
+
+    // We are the cheater: we broadcast an old commitment transaction whose
+    // per-commitment secret we already gave to our counterparty.
+    let our_per_commitment_secret = get_per_commitment_secret();
+    let our_per_commitment_point = get_per_commitment_point();
+    let our_payment_basepoint = our_keys.payment_point();
+    let our_htlc_pubkey =
+        derive_public_key(&our_keys.htlc_basepoint(), &our_per_commitment_point);
+    let our_delayed_pubkey =
+        derive_public_key(&our_keys.delayed_payment_basepoint(), &our_per_commitment_point);
+
+    // Our counterparty holds the revocation basepoint secret for our commitments.
+    let counterparty_revocation_basepoint_secret = counterparty_keys.revocation_base_key;
+    let counterparty_revocation_basepoint = counterparty_keys.revocation_basepoint();
+    let counterparty_payment_basepoint = counterparty_keys.payment_point();
+    let counterparty_htlc_pubkey =
+        derive_public_key(&counterparty_keys.htlc_basepoint(), &our_per_commitment_point);
+
+    let revocation_pubkey =
+        generate_revocation_pubkey(counterparty_revocation_basepoint, our_per_commitment_point);
+
+    // The revoked state: we had offered a 400,000 sat HTLC.
+    let htlcs = vec![HtlcOutput {
+        offered: true,
+        amount: 400_000,
+        cltv_expiry: 300,
+        payment_hash160: HASH160_DUMMY,
+    }];
+
+    let (revoked_tx, breakdown) = build_fee_aware_commitment_transaction(
+        funding_txin,
+        1,
+        &our_payment_basepoint,
+        &counterparty_payment_basepoint,
+        true,
+        ChannelType::StaticRemoteKey,
+        &our_keys.funding_pubkey(),
+        &counterparty_keys.funding_pubkey(),
+        &revocation_pubkey,
+        &counterparty_htlc_pubkey,
+        &our_htlc_pubkey,
+        &our_delayed_pubkey,
+        to_self_delay,
+        fee_estimator.get_est_sat_per_1000_weight(ConfirmationTarget::NonAnchorChannelFee),
+        546,
+        &htlcs,
+        2_600_000_000,
+        2_000_000_000,
+    );
+
+    let signed_revoked_tx = commit::sign_funding_transaction(
+        revoked_tx,
+        funding_key_manager(0x01),
+        funding_key_manager(0x02));
+
+    println!("\n");
+    println!("Revoked Commitment Tx ID: {}", signed_revoked_tx.compute_txid());
+    println!("\n");
+    println!("Revoked Commitment Tx Hex: {}", serialize_hex(&signed_revoked_tx));
+
+    // Our counterparty sweeps our to_local and the HTLC to their wallet.
+    let destination_script = bitcoind.get_new_address().await.script_pubkey();
+
+    let justice_tx = match build_justice_transaction(
+        &signed_revoked_tx,
+        ChannelType::StaticRemoteKey,
+        &our_per_commitment_secret,
+        &counterparty_revocation_basepoint_secret,
+        &our_delayed_pubkey,
+        to_self_delay,
+        &counterparty_htlc_pubkey,
+        &our_htlc_pubkey,
+        &htlcs,
+        destination_script,
+        &fee_estimator,
+    ) {
+        Some(justice_tx) => justice_tx,
+        None => return println!("Nothing in the revoked commitment covers the justice transaction fee"),
+    };
+
+    println!("\n");
+    println!("Justice Tx ID: {}", justice_tx.compute_txid());
+    println!("\n");
+    println!("Justice Tx Hex: {}", serialize_hex(&justice_tx));
+}
+
+/// The funding keys used by the `funding` and `commit` demos.
+fn funding_key_manager(key_byte: u8) -> commit::KeyManager {
+    let pubkey = pubkey_from_private_key(&[key_byte; 32]);
+    commit::KeyManager {
+        funding_private_key: secp256k1_private_key(&[key_byte; 32]),
+        funding_public_key: pubkey,
+        delayed_pubkey: pubkey,
+        commitment_pubkey: pubkey,
+        revocation_pubkey: pubkey,
+    }
+}
+
+pub async fn run(funding_txid: String) {
+
+    // get bitcoin client
+    let bitcoind = get_bitcoind_client().await;
+
+    create_revoked_commitment_and_justice_tx(bitcoind, funding_txid).await;
+
+    // Add a delay to allow the spawned task to complete
+    sleep(Duration::from_secs(2)).await;
+}
@@ -39,7 +39,8 @@ use lightning_block_sync::rpc::RpcClient;
 use lightning_block_sync::SpvClient;
 use lightning_block_sync::{AsyncBlockSourceResult, BlockData, BlockHeaderData, BlockSource};
 use ch1_intro_htlcs::solutions::{
-    build_htlc_commitment_transaction, two_of_two_multisig_witness_script, build_refund_transaction
+    build_htlc_commitment_transaction, two_of_two_multisig_witness_script, build_refund_transaction,
+    set_output_amount_for_feerate, InputSatisfaction,
 };
 use bitcoin::PublicKey as BitcoinPubKey;
 use internal::bitcoind_client;
@@ -56,7 +57,8 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
 use hex;
-use helper::{get_bitcoind_client, get_unspent_output, generate_p2wsh_signature, sign_raw_transaction, get_funding_input, get_arg};
+use helper::{get_bitcoind_client, get_unspent_output, generate_p2wsh_signature, sign_raw_transaction, get_funding_input, get_arg,
+             check_p2wsh_witness, our_channel_keys, counterparty_channel_keys, RegtestFeeEstimator};
 
 
 pub struct KeyManager{
@@ -70,19 +72,33 @@ pub async fn create_broadcast_funding_tx(bitcoind: BitcoindClient,
                                         our_key_manager: KeyManager,
                                         counterparty_key_manager: KeyManager,
                                         funding_amount: u64,
-                                        our_balance: u64,
                                         counterparty_balance: u64) {
 
     let txid_index = 0;
     let funding_txin = get_funding_input(txid.to_string(), txid_index);
 
-    let tx = build_refund_transaction(
+    let mut tx = build_refund_transaction(
         funding_txin,
         our_key_manager.commitment_pubkey,
         counterparty_key_manager.commitment_pubkey,
-        our_balance,
+        0,
         counterparty_balance);
 
+    let redeem_script = two_of_two_multisig_witness_script(
+        &our_key_manager.funding_public_key,
+        &counterparty_key_manager.funding_public_key);
+
+    // we get whatever is left after the counterparty's balance and the fee
+    let feerate = RegtestFeeEstimator.get_est_sat_per_1000_weight(ConfirmationTarget::NonAnchorChannelFee);
+    let fee = set_output_amount_for_feerate(
+        &mut tx,
+        0,
+        funding_amount,
+        &[InputSatisfaction::Multisig(redeem_script.clone())],
+        feerate)
+        .expect("funding output covers the refund");
+    println!("Fee: {} sats", fee);
+
     let signed_tx = sign_funding_transaction(tx, our_key_manager, counterparty_key_manager);
 
     println!("\n");
@@ -90,6 +106,10 @@ pub async fn create_broadcast_funding_tx(bitcoind: BitcoindClient,
     println!("\n");
     println!("Tx Hex: {}", serialize_hex(&signed_tx));
 
+    if !check_p2wsh_witness(&signed_tx, 0, &redeem_script, funding_amount) {
+        return;
+    }
+
     // Broadcast it
     bitcoind.broadcast_transactions(&[&signed_tx]);
 }
@@ -103,10 +123,12 @@ pub async fn run(funding_txid: String) {
     // get bitcoin client
     let bitcoind = get_bitcoind_client().await;
 
-    // Get our keys
-    let our_funding_private_key = secp256k1_private_key(&[0x01; 32]);
-    let our_funding_public_key = pubkey_from_private_key(&[0x01; 32]);
-    let our_commitment_pubkey = pubkey_from_private_key(&[0x11; 32]);
+    // Get our keys, and our counterparty's
+    let our_keys = our_channel_keys();
+    let counterparty_keys = counterparty_channel_keys();
+    let our_funding_private_key = our_keys.funding_key;
+    let our_funding_public_key = our_keys.funding_pubkey();
+    let our_commitment_pubkey = our_keys.payment_point();
 
     let our_key_manager = KeyManager{
             funding_private_key: our_funding_private_key,
@@ -115,9 +137,9 @@ pub async fn run(funding_txid: String) {
         };
 
     // Get our Counterparty Pubkey
-    let counterparty_funding_private_key = secp256k1_private_key(&[0x02; 32]);
-    let counterparty_funding_public_key = pubkey_from_private_key(&[0x02; 32]);
-    let counterparty_commitment_pubkey = pubkey_from_private_key(&[0x21; 32]);
+    let counterparty_funding_private_key = counterparty_keys.funding_key;
+    let counterparty_funding_public_key = counterparty_keys.funding_pubkey();
+    let counterparty_commitment_pubkey = counterparty_keys.payment_point();
 
     let counterparty_key_manager = KeyManager{
             funding_private_key: counterparty_funding_private_key,
@@ -126,11 +148,10 @@ pub async fn run(funding_txid: String) {
         };
     
     let funding_amount = 5_000_000;
-    let our_balance = 4_999_500;
     let counterparty_balance = 500;
     
     create_broadcast_funding_tx(bitcoind, txid.clone(), our_key_manager, counterparty_key_manager, funding_amount,
-                               our_balance, counterparty_balance).await;
+                               counterparty_balance).await;
 
     // Add a delay to allow the spawned task to complete
     sleep(Duration::from_secs(2)).await;
@@ -0,0 +1,135 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+use crate::interactive::helper;
+use crate::internal;
+use crate::ch1_intro_htlcs;
+use bitcoin::amount::Amount;
+use bitcoin::blockdata::script::ScriptBuf;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::psbt::Psbt;
+use bitcoin::{TxIn, TxOut};
+use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator};
+use internal::bitcoind_client::BitcoindClient;
+use internal::helper::{build_output, pubkey_from_private_key, secp256k1_private_key};
+use ch1_intro_htlcs::solutions::two_of_two_multisig_witness_script;
+use ch1_intro_htlcs::splice::{build_splice_in_transaction, build_splice_out_transaction,
+                              sign_splice_funding_input, splice_funding_witness};
+use std::time::Duration;
+use tokio::time::sleep;
+use helper::{get_bitcoind_client, get_funding_input, get_unspent_utxo, sign_psbt, check_p2wsh_witness,
+             our_channel_keys, counterparty_channel_keys, RegtestFeeEstimator};
+
+// the funding output created by the `funding` command
+const FUNDING_AMOUNT: u64 = 5_000_000;
+
+fn funding_witness_script() -> ScriptBuf {
+    two_of_two_multisig_witness_script(
+        &our_channel_keys().funding_pubkey(),
+        &counterparty_channel_keys().funding_pubkey())
+}
+
+/// Adds both channel parties' signatures to the funding input of a splice
+/// transaction. Normally each of us would sign and send the other our
+/// signature, just like for a commitment transaction.
+fn sign_funding_input(tx: &mut Transaction) {
+    let alice_key = our_channel_keys().funding_key;
+    let bob_key = counterparty_channel_keys().funding_key;
+    let witness_script = funding_witness_script();
+
+    let alice_signature = sign_splice_funding_input(tx, FUNDING_AMOUNT, &witness_script, &alice_key);
+    let bob_signature = sign_splice_funding_input(tx, FUNDING_AMOUNT, &witness_script, &bob_key);
+
+    tx.input[0].witness = splice_funding_witness(&alice_signature, &bob_signature, &witness_script);
+}
+
+async fn broadcast_splice(bitcoind: BitcoindClient, signed_tx: Transaction, fee: u64) {
+    let witness_script = funding_witness_script();
+
+    println!("\n");
+    println!("New funding amount: {} sats", signed_tx.output[0].value.to_sat());
+    println!("Fee: {} sats", fee);
+    println!("\n");
+    println!("Tx ID: {}", signed_tx.compute_txid());
+    println!("\n");
+    println!("Tx Hex: {}", serialize_hex(&signed_tx));
+
+    if !check_p2wsh_witness(&signed_tx, 0, &witness_script, FUNDING_AMOUNT) {
+        return;
+    }
+
+    bitcoind.broadcast_transactions(&[&signed_tx]);
+}
+
+pub async fn run_splice_in(funding_txid: String) {
+
+    // get bitcoin client
+    let bitcoind = get_bitcoind_client().await;
+
+    let funding_txin = get_funding_input(funding_txid, 0);
+
+    // a wallet output to add to the channel, and somewhere for the change
+    let (wallet_txin, wallet_utxo) = get_unspent_utxo(bitcoind.clone()).await;
+    let change_address = bitcoind.get_new_address().await;
+
+    let splice_in_amount = 2_000_000;
+    let feerate = RegtestFeeEstimator.get_est_sat_per_1000_weight(ConfirmationTarget::NonAnchorChannelFee);
+
+    let (mut tx, fee) = build_splice_in_transaction(
+        funding_txin,
+        FUNDING_AMOUNT,
+        vec![(wallet_txin, wallet_utxo.clone())],
+        splice_in_amount,
+        change_address.script_pubkey(),
+        &our_channel_keys().funding_pubkey(),
+        &counterparty_channel_keys().funding_pubkey(),
+        feerate)
+        .expect("wallet output covers the splice");
+
+    let mut psbt = Psbt::from_unsigned_tx(tx.clone()).expect("unsigned transaction");
+    psbt.inputs[0].witness_utxo = Some(build_output(FUNDING_AMOUNT, funding_witness_script().to_p2wsh()));
+    psbt.inputs[1].witness_utxo = Some(wallet_utxo);
+
+    // we sign the funding input, then let the wallet sign its own input
+    sign_funding_input(&mut tx);
+    psbt.inputs[0].final_script_witness = Some(tx.input[0].witness.clone());
+
+    let signed_tx = sign_psbt(bitcoind.clone(), psbt).await;
+
+    broadcast_splice(bitcoind, signed_tx, fee).await;
+
+    // Add a delay to allow the spawned task to complete
+    sleep(Duration::from_secs(2)).await;
+}
+
+pub async fn run_splice_out(funding_txid: String) {
+
+    // get bitcoin client
+    let bitcoind = get_bitcoind_client().await;
+
+    let funding_txin = get_funding_input(funding_txid, 0);
+
+    // pay part of our balance to a fresh wallet address
+    let destination_address = bitcoind.get_new_address().await;
+    let destination = build_output(1_000_000, destination_address.script_pubkey());
+
+    // the `funding` command puts the whole channel on our side
+    let our_balance_msat = FUNDING_AMOUNT * 1000;
+    let feerate = RegtestFeeEstimator.get_est_sat_per_1000_weight(ConfirmationTarget::NonAnchorChannelFee);
+
+    let (mut tx, fee) = build_splice_out_transaction(
+        funding_txin,
+        FUNDING_AMOUNT,
+        our_balance_msat,
+        destination,
+        &our_channel_keys().funding_pubkey(),
+        &counterparty_channel_keys().funding_pubkey(),
+        feerate)
+        .expect("our balance covers the splice");
+
+    sign_funding_input(&mut tx);
+
+    broadcast_splice(bitcoind, tx, fee).await;
+
+    // Add a delay to allow the spawned task to complete
+    sleep(Duration::from_secs(2)).await;
+}
@@ -16,8 +16,11 @@ use bitcoin::blockdata::transaction::Transaction;
 use std::sync::Arc;
 use bitcoin::consensus::{encode, Decodable, Encodable};
 use crate::internal::convert::{
-    ListUnspentResponse, NewAddress, SignedTx, BlockchainInfo, AddressPubkey, MempoolInfo
+    ListUnspentResponse, NewAddress, SignedTx, BlockchainInfo, AddressPubkey, MempoolInfo,
+    FundedPsbt, ProcessedPsbt, FinalizedPsbt, psbt_to_base64
 };
+use bitcoin::psbt::Psbt;
+use bitcoin::Amount;
 use lightning::chain::chaininterface::{BroadcasterInterface, ConfirmationTarget, FeeEstimator};
 use tokio::runtime::Handle;
 
@@ -116,6 +119,41 @@ impl BitcoindClient {
         signed_tx
     }
 
+    /// Asks the wallet to add inputs (and change) paying for `outputs`.
+    /// The outputs keep the order given, with any change after them.
+    pub async fn wallet_create_funded_psbt(&self, outputs: &[(Address, u64)]) -> FundedPsbt {
+        // one object per output keeps them in the order given
+        let outputs_json: Vec<serde_json::Value> = outputs
+            .iter()
+            .map(|(address, amount)| serde_json::json!({ address.to_string(): Amount::from_sat(*amount).to_btc() }))
+            .collect();
+        let options = serde_json::json!({ "changePosition": outputs.len() });
+        self.bitcoind_rpc_client
+            .call_method(
+                "walletcreatefundedpsbt",
+                &[serde_json::json!([]), serde_json::json!(outputs_json), serde_json::json!(0), options],
+            )
+            .await
+            .unwrap()
+    }
+
+    /// Signs the PSBT inputs the wallet owns.
+    pub async fn wallet_process_psbt(&self, psbt: &Psbt) -> ProcessedPsbt {
+        let psbt_json = serde_json::json!(psbt_to_base64(psbt));
+        self.bitcoind_rpc_client
+            .call_method("walletprocesspsbt", &[psbt_json])
+            .await
+            .unwrap()
+    }
+
+    pub async fn finalize_psbt(&self, psbt: &Psbt) -> FinalizedPsbt {
+        let psbt_json = serde_json::json!(psbt_to_base64(psbt));
+        self.bitcoind_rpc_client
+            .call_method("finalizepsbt", &[psbt_json])
+            .await
+            .unwrap()
+    }
+
     pub async fn get_raw_mempool(&self) -> MempoolInfo {
     self.bitcoind_rpc_client
     .call_method("getrawmempool", &[])
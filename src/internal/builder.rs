@@ -2,7 +2,7 @@
 use bitcoin::script::{Builder as BitcoinBuilder, ScriptBuf, ScriptHash};
 use bitcoin::opcodes;
 use bitcoin::secp256k1::ecdsa::Signature;
-use bitcoin::secp256k1::PublicKey;
+use bitcoin::secp256k1::{PublicKey, XOnlyPublicKey};
 use bitcoin::PublicKey as BitcoinPublicKey;
 use bitcoin::script::PushBytes;
 use bitcoin::PubkeyHash;
@@ -63,6 +63,11 @@ impl Builder {
         self
     }
 
+    pub fn push_x_only_key(mut self, key: &XOnlyPublicKey) -> Self {
+        self.inner = self.inner.push_x_only_key(key);
+        self
+    }
+
     pub fn into_script(self) -> ScriptBuf {
         self.inner.into_script()
     }
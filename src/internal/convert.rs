@@ -5,6 +5,9 @@ use std::convert::TryInto;
 use std::str::FromStr;
 use bitcoin::secp256k1::PublicKey;
 use serde_json::Value;
+use bitcoin::psbt::Psbt;
+use bitcoin::consensus::encode;
+use bitcoin::Transaction;
 
 #[derive(Debug)]
 pub struct BlockchainInfo {
@@ -42,6 +45,81 @@ impl TryInto<SignedTx> for JsonResponse {
   }
 }
 
+/// PSBTs travel over RPC as base64.
+pub fn psbt_to_base64(psbt: &Psbt) -> String {
+  base64::encode(psbt.serialize())
+}
+
+pub fn psbt_from_base64(psbt: &str) -> std::io::Result<Psbt> {
+  let bytes = base64::decode(psbt).map_err(|e| {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid PSBT base64: {}", e))
+  })?;
+  Psbt::deserialize(&bytes).map_err(|e| {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid PSBT: {}", e))
+  })
+}
+
+#[derive(Debug)]
+pub struct FundedPsbt {
+  pub psbt: Psbt,
+  pub fee: u64,
+  pub change_position: Option<usize>,
+}
+
+impl TryInto<FundedPsbt> for JsonResponse {
+  type Error = std::io::Error;
+  fn try_into(self) -> std::io::Result<FundedPsbt> {
+    let change_position = self.0["changepos"].as_i64().unwrap();
+    Ok(FundedPsbt {
+      psbt: psbt_from_base64(self.0["psbt"].as_str().unwrap())?,
+      fee: bitcoin::Amount::from_btc(self.0["fee"].as_f64().unwrap()).unwrap().to_sat(),
+      change_position: if change_position < 0 { None } else { Some(change_position as usize) },
+    })
+  }
+}
+
+#[derive(Debug)]
+pub struct ProcessedPsbt {
+  pub psbt: Psbt,
+  pub complete: bool,
+}
+
+impl TryInto<ProcessedPsbt> for JsonResponse {
+  type Error = std::io::Error;
+  fn try_into(self) -> std::io::Result<ProcessedPsbt> {
+    Ok(ProcessedPsbt {
+      psbt: psbt_from_base64(self.0["psbt"].as_str().unwrap())?,
+      complete: self.0["complete"].as_bool().unwrap(),
+    })
+  }
+}
+
+/// `finalizepsbt` returns the extracted transaction once every input is
+/// finalized, and the partially finalized PSBT otherwise.
+#[derive(Debug)]
+pub struct FinalizedPsbt {
+  pub psbt: Option<Psbt>,
+  pub tx: Option<Transaction>,
+  pub complete: bool,
+}
+
+impl TryInto<FinalizedPsbt> for JsonResponse {
+  type Error = std::io::Error;
+  fn try_into(self) -> std::io::Result<FinalizedPsbt> {
+    let psbt = match self.0["psbt"].as_str() {
+      Some(psbt) => Some(psbt_from_base64(psbt)?),
+      None => None,
+    };
+    let tx = match self.0["hex"].as_str() {
+      Some(hex) => Some(encode::deserialize_hex(hex).map_err(|e| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid transaction: {}", e))
+      })?),
+      None => None,
+    };
+    Ok(FinalizedPsbt { psbt, tx, complete: self.0["complete"].as_bool().unwrap() })
+  }
+}
+
 #[derive(Debug)]
 pub struct MempoolInfo {
     pub transaction_ids: Vec<String>,
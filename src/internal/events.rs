@@ -5,7 +5,9 @@ use lightning::ln::types::ChannelId;
 use bitcoin::hash_types::{Txid};
 use bitcoin::script::ScriptBuf;
 use internal::messages::{AcceptChannel, ChannelReady, FundingCreated, FundingSigned, OpenChannel,
-   OnionMessage, NodeAnnouncement, ChannelAnnouncement};
+   OnionMessage, NodeAnnouncement, ChannelAnnouncement, OpenChannelV2, AcceptChannelV2,
+   TxAddInput, TxAddOutput, TxComplete, TxSignatures};
+use bitcoin::Transaction;
 use internal::messages::Message;
 
 /// An event generated by ChannelManager which indicates a message should be sent to a peer (or
@@ -28,6 +30,49 @@ pub enum MessageSendEvent {
     /// The message which should be sent.
     msg: FundingCreated,
   },
+  /// Used to indicate that we've initiated a dual-funded channel open and should send the
+  /// open_channel2 message provided to the given peer.
+  SendOpenChannelV2 {
+    /// The node_id of the node which should receive this message
+    node_id: PublicKey,
+    /// The message which should be sent.
+    msg: OpenChannelV2,
+  },
+  /// Used to indicate that an accept_channel2 message should be sent to the peer with the given node_id.
+  SendAcceptChannelV2 {
+    /// The node_id of the node which should receive this message
+    node_id: PublicKey,
+    /// The message which should be sent.
+    msg: AcceptChannelV2,
+  },
+  /// Used to indicate that a tx_add_input message should be sent to the peer with the given node_id.
+  SendTxAddInput {
+    /// The node_id of the node which should receive this message
+    node_id: PublicKey,
+    /// The message which should be sent.
+    msg: TxAddInput,
+  },
+  /// Used to indicate that a tx_add_output message should be sent to the peer with the given node_id.
+  SendTxAddOutput {
+    /// The node_id of the node which should receive this message
+    node_id: PublicKey,
+    /// The message which should be sent.
+    msg: TxAddOutput,
+  },
+  /// Used to indicate that a tx_complete message should be sent to the peer with the given node_id.
+  SendTxComplete {
+    /// The node_id of the node which should receive this message
+    node_id: PublicKey,
+    /// The message which should be sent.
+    msg: TxComplete,
+  },
+  /// Used to indicate that a tx_signatures message should be sent to the peer with the given node_id.
+  SendTxSignatures {
+    /// The node_id of the node which should receive this message
+    node_id: PublicKey,
+    /// The message which should be sent.
+    msg: TxSignatures,
+  },
 }
 
 
@@ -56,5 +101,35 @@ pub enum Event {
     /// The script which should be used in the transaction output.
     output_script: ScriptBuf,
   },
+  /// The funding transaction is signed and the channel is being watched, but it is not
+  /// confirmed yet. From here on the channel is known by `channel_id`.
+  ChannelPending {
+    /// The channel ID, derived from the funding outpoint or, for dual-funded channels,
+    /// from both peers' revocation basepoints.
+    channel_id: ChannelId,
+    /// The temporary channel ID the channel was known by until now.
+    former_temporary_channel_id: ChannelId,
+    /// The counterparty's node_id.
+    counterparty_node_id: PublicKey,
+    /// The funding outpoint.
+    funding_txo: bitcoin::OutPoint,
+  },
+  /// Both peers agreed on a dual-funded funding transaction, and our inputs need signing.
+  /// Pass their witnesses, in input order, to
+  /// [`ChannelManager::funding_transaction_signed`].
+  ///
+  /// [`ChannelManager::funding_transaction_signed`]: crate::ch2_setup::channel_exercises_v2::ChannelManager::funding_transaction_signed
+  FundingTransactionReadyForSigning {
+    /// The channel being funded.
+    channel_id: ChannelId,
+    /// The counterparty's node_id.
+    counterparty_node_id: PublicKey,
+    /// The funding transaction, without witnesses.
+    unsigned_transaction: Transaction,
+    /// The outputs spent by each input, as needed to sign them.
+    prevouts: Vec<bitcoin::TxOut>,
+    /// Which inputs are ours to sign.
+    local_input_indices: Vec<usize>,
+  },
 }
 
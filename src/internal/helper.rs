@@ -16,6 +16,8 @@ use bitcoin::locktime::absolute::LockTime;
 use internal::builder::Builder;
 use bitcoin::blockdata::opcodes::all as opcodes;
 use bitcoin::{PubkeyHash, WPubkeyHash};
+use bitcoin::psbt::{self, Psbt};
+use std::fmt;
 
 
 pub fn tweak_pubkey(pubkey1: PublicKey, sha_bytes: [u8; 32]) -> PublicKey {
@@ -60,6 +62,34 @@ pub fn bitcoin_pubkey_from_private_key(private_key: &[u8; 32]) -> BitcoinPublicK
     BitcoinPublicKey::new(public_key)
 }
 
+#[derive(Debug)]
+pub enum CombinePsbtsError {
+    /// There were no PSBTs to combine.
+    NoPsbts,
+    /// The PSBTs are not for the same transaction, or disagree.
+    Psbt(psbt::Error),
+}
+
+impl fmt::Display for CombinePsbtsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CombinePsbtsError::NoPsbts => write!(f, "no PSBTs to combine"),
+            CombinePsbtsError::Psbt(e) => write!(f, "can't combine PSBTs: {}", e),
+        }
+    }
+}
+
+/// Merges PSBTs for the same unsigned transaction (BIP 174 Combiner), e.g.
+/// the copies each funder filled in with its own inputs' data and signatures.
+pub fn combine_psbts(psbts: Vec<Psbt>) -> Result<Psbt, CombinePsbtsError> {
+    let mut psbts = psbts.into_iter();
+    let mut combined = psbts.next().ok_or(CombinePsbtsError::NoPsbts)?;
+    for psbt in psbts {
+        combined.combine(psbt).map_err(CombinePsbtsError::Psbt)?;
+    }
+    Ok(combined)
+}
+
 pub fn p2wpkh_output_script(public_key: PublicKey) -> ScriptBuf {
     let pubkey = BitcoinPublicKey::new(public_key);
     ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash().unwrap())
@@ -121,4 +151,156 @@ pub fn build_htlc_offerer_witness_script(
         .push_opcode(opcodes::OP_ENDIF)
         .push_opcode(opcodes::OP_ENDIF)
         .into_script()
-}
\ No newline at end of file
+}
+
+pub fn build_htlc_receiver_witness_script(
+    revocation_pubkey: &PublicKey,
+    remote_htlc_pubkey: &PublicKey,
+    local_htlc_pubkey: &PublicKey,
+    payment_hash160: &[u8; 20],
+    cltv_expiry: u32,
+) -> ScriptBuf {
+    Builder::new()
+        .push_opcode(opcodes::OP_DUP)
+        .push_opcode(opcodes::OP_HASH160)
+        .push_slice(PubkeyHash::hash(&revocation_pubkey.serialize()))
+        .push_opcode(opcodes::OP_EQUAL)
+        .push_opcode(opcodes::OP_IF)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .push_opcode(opcodes::OP_ELSE)
+        .push_slice(remote_htlc_pubkey.serialize())
+        .push_opcode(opcodes::OP_SWAP)
+        .push_opcode(opcodes::OP_SIZE)
+        .push_int(32)
+        .push_opcode(opcodes::OP_EQUAL)
+        .push_opcode(opcodes::OP_IF)
+        .push_opcode(opcodes::OP_HASH160)
+        .push_slice(payment_hash160)
+        .push_opcode(opcodes::OP_EQUALVERIFY)
+        .push_int(2)
+        .push_opcode(opcodes::OP_SWAP)
+        .push_slice(local_htlc_pubkey.serialize())
+        .push_int(2)
+        .push_opcode(opcodes::OP_CHECKMULTISIG)
+        .push_opcode(opcodes::OP_ELSE)
+        .push_opcode(opcodes::OP_DROP)
+        .push_int(cltv_expiry as i64)
+        .push_opcode(opcodes::OP_CLTV)
+        .push_opcode(opcodes::OP_DROP)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .push_opcode(opcodes::OP_ENDIF)
+        .push_opcode(opcodes::OP_ENDIF)
+        .into_script()
+}
+
+/// Offered HTLC script for `option_anchors` channels. The success path is
+/// encumbered by `1 OP_CHECKSEQUENCEVERIFY`, so the counterparty can only
+/// claim it once the commitment has confirmed.
+pub fn build_anchor_htlc_offerer_witness_script(
+    revocation_pubkey: &PublicKey,
+    remote_htlc_pubkey: &PublicKey,
+    local_htlc_pubkey: &PublicKey,
+    payment_hash160: &[u8; 20],
+) -> ScriptBuf {
+    Builder::new()
+        .push_opcode(opcodes::OP_DUP)
+        .push_opcode(opcodes::OP_HASH160)
+        .push_slice(PubkeyHash::hash(&revocation_pubkey.serialize()))
+        .push_opcode(opcodes::OP_EQUAL)
+        .push_opcode(opcodes::OP_IF)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .push_opcode(opcodes::OP_ELSE)
+        .push_slice(remote_htlc_pubkey.serialize())
+        .push_opcode(opcodes::OP_SWAP)
+        .push_opcode(opcodes::OP_SIZE)
+        .push_int(32)
+        .push_opcode(opcodes::OP_EQUAL)
+        .push_opcode(opcodes::OP_NOTIF)
+        .push_opcode(opcodes::OP_DROP)
+        .push_int(2)
+        .push_opcode(opcodes::OP_SWAP)
+        .push_slice(local_htlc_pubkey.serialize())
+        .push_int(2)
+        .push_opcode(opcodes::OP_CHECKMULTISIG)
+        .push_opcode(opcodes::OP_ELSE)
+        .push_opcode(opcodes::OP_HASH160)
+        .push_slice(payment_hash160)
+        .push_opcode(opcodes::OP_EQUALVERIFY)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .push_opcode(opcodes::OP_ENDIF)
+        .push_int(1)
+        .push_opcode(opcodes::OP_CSV)
+        .push_opcode(opcodes::OP_DROP)
+        .push_opcode(opcodes::OP_ENDIF)
+        .into_script()
+}
+
+/// Received HTLC script for `option_anchors` channels. The timeout path is
+/// encumbered by `1 OP_CHECKSEQUENCEVERIFY`.
+pub fn build_anchor_htlc_receiver_witness_script(
+    revocation_pubkey: &PublicKey,
+    remote_htlc_pubkey: &PublicKey,
+    local_htlc_pubkey: &PublicKey,
+    payment_hash160: &[u8; 20],
+    cltv_expiry: u32,
+) -> ScriptBuf {
+    Builder::new()
+        .push_opcode(opcodes::OP_DUP)
+        .push_opcode(opcodes::OP_HASH160)
+        .push_slice(PubkeyHash::hash(&revocation_pubkey.serialize()))
+        .push_opcode(opcodes::OP_EQUAL)
+        .push_opcode(opcodes::OP_IF)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .push_opcode(opcodes::OP_ELSE)
+        .push_slice(remote_htlc_pubkey.serialize())
+        .push_opcode(opcodes::OP_SWAP)
+        .push_opcode(opcodes::OP_SIZE)
+        .push_int(32)
+        .push_opcode(opcodes::OP_EQUAL)
+        .push_opcode(opcodes::OP_IF)
+        .push_opcode(opcodes::OP_HASH160)
+        .push_slice(payment_hash160)
+        .push_opcode(opcodes::OP_EQUALVERIFY)
+        .push_int(2)
+        .push_opcode(opcodes::OP_SWAP)
+        .push_slice(local_htlc_pubkey.serialize())
+        .push_int(2)
+        .push_opcode(opcodes::OP_CHECKMULTISIG)
+        .push_opcode(opcodes::OP_ELSE)
+        .push_opcode(opcodes::OP_DROP)
+        .push_int(cltv_expiry as i64)
+        .push_opcode(opcodes::OP_CLTV)
+        .push_opcode(opcodes::OP_DROP)
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .push_opcode(opcodes::OP_ENDIF)
+        .push_int(1)
+        .push_opcode(opcodes::OP_CSV)
+        .push_opcode(opcodes::OP_DROP)
+        .push_opcode(opcodes::OP_ENDIF)
+        .into_script()
+}
+
+/// Anchor output script: spendable by the funding key right away, or by
+/// anyone after 16 blocks so that leftover anchors can be swept.
+pub fn build_anchor_witness_script(funding_pubkey: &PublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_slice(funding_pubkey.serialize())
+        .push_opcode(opcodes::OP_CHECKSIG)
+        .push_opcode(opcodes::OP_IFDUP)
+        .push_opcode(opcodes::OP_NOTIF)
+        .push_int(16)
+        .push_opcode(opcodes::OP_CSV)
+        .push_opcode(opcodes::OP_ENDIF)
+        .into_script()
+}
+
+/// to_remote script for `option_anchors` channels: the counterparty's
+/// payment key behind a 1-block relative timelock.
+pub fn build_anchor_to_remote_witness_script(remote_pubkey: &PublicKey) -> ScriptBuf {
+    Builder::new()
+        .push_slice(remote_pubkey.serialize())
+        .push_opcode(opcodes::OP_CHECKSIGVERIFY)
+        .push_int(1)
+        .push_opcode(opcodes::OP_CSV)
+        .into_script()
+}
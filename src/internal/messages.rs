@@ -4,6 +4,8 @@ use lightning::types::payment::{PaymentHash};
 use bitcoin::hash_types::{Txid};
 use bitcoin::secp256k1::{self, Secp256k1,ecdsa::Signature};
 use bitcoin::secp256k1::PublicKey;
+use bitcoin::script::ScriptBuf;
+use bitcoin::{Transaction, Witness};
 
 /// BOLT 4 onion packet including hop data for the next peer.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
@@ -33,23 +35,120 @@ pub struct AcceptChannel {
   pub temporary_channel_id: ChannelId,
 }
 
+/// Opens a dual-funded channel, whose funding transaction both peers build
+/// together with the interactive-tx messages below.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpenChannelV2 {
+  /// A temporary channel ID, until the funding is established
+  pub temporary_channel_id: ChannelId,
+  /// The amount the initiator puts into the channel
+  pub funding_satoshis: u64,
+  /// The feerate of the funding transaction
+  pub funding_feerate_perkw: u32,
+  /// The locktime of the funding transaction
+  pub locktime: u32,
+  /// The initiator's key in the 2-of-2 funding output
+  pub funding_pubkey: PublicKey,
+  /// The initiator's revocation basepoint, from which the channel ID is derived
+  pub revocation_basepoint: PublicKey,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AcceptChannelV2 {
+  /// The temporary channel ID from the open_channel2 message
+  pub temporary_channel_id: ChannelId,
+  /// The amount the acceptor puts into the channel, which may be zero
+  pub funding_satoshis: u64,
+  /// The acceptor's key in the 2-of-2 funding output
+  pub funding_pubkey: PublicKey,
+  /// The acceptor's revocation basepoint, from which the channel ID is derived
+  pub revocation_basepoint: PublicKey,
+}
+
+/// Adds one of the sender's inputs to the transaction under construction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxAddInput {
+  /// The channel ID
+  pub channel_id: ChannelId,
+  /// Orders the input in the transaction; even for the initiator, odd for the
+  /// non-initiator
+  pub serial_id: u64,
+  /// The transaction containing the output being spent, so the receiver can
+  /// check its amount and that it is segwit
+  pub prevtx: Transaction,
+  /// The index of the output being spent
+  pub prevtx_out: u32,
+  /// The sequence number of the input
+  pub sequence: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxAddOutput {
+  /// The channel ID
+  pub channel_id: ChannelId,
+  /// Orders the output in the transaction, with the same parity rule as inputs
+  pub serial_id: u64,
+  /// The value of the output
+  pub sats: u64,
+  /// The scriptPubKey of the output
+  pub script: ScriptBuf,
+}
+
+/// Removes an input the sender added earlier.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxRemoveInput {
+  /// The channel ID
+  pub channel_id: ChannelId,
+  /// The serial ID of the input to remove
+  pub serial_id: u64,
+}
+
+/// Removes an output the sender added earlier.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxRemoveOutput {
+  /// The channel ID
+  pub channel_id: ChannelId,
+  /// The serial ID of the output to remove
+  pub serial_id: u64,
+}
+
+/// The sender has nothing more to add. Construction ends once both peers
+/// send `tx_complete` in a row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxComplete {
+  /// The channel ID
+  pub channel_id: ChannelId,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxSignatures {
+  /// The channel ID
+  pub channel_id: ChannelId,
+  /// The txid of the constructed transaction
+  pub tx_hash: Txid,
+  /// The witnesses of the sender's inputs, ordered by serial ID
+  pub witnesses: Vec<Witness>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct FundingCreated {
   /// A temporary channel ID, until the funding is established
   pub temporary_channel_id: ChannelId,
-  pub transaction_id: Txid
+  pub transaction_id: Txid,
+  /// The index of the funding output in the funding transaction
+  pub funding_output_index: u16,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct FundingSigned {
-  /// A temporary channel ID, until the funding is established
+  /// The channel ID, derived from the funding outpoint
   pub channel_id: ChannelId,
   pub signature: Signature,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ChannelReady {
-  /// A temporary channel ID, until the funding is established
+  /// The channel ID, derived from the funding outpoint
   pub channel_id: ChannelId,
 }
 
@@ -84,6 +183,38 @@ pub struct CommitmentSigned {
   pub htlc_signatures: Vec<Signature>,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Shutdown {
+  /// The channel ID
+  pub channel_id: ChannelId,
+  /// The destination of this peer's funds on closing.
+  pub scriptpubkey: ScriptBuf,
+}
+
+/// The minimum and maximum fees which the sender is willing to place on the closing transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClosingSignedFeeRange {
+  /// The minimum absolute fee, in satoshis, which the sender is willing to place on the closing
+  /// transaction.
+  pub min_fee_satoshis: u64,
+  /// The maximum absolute fee, in satoshis, which the sender is willing to place on the closing
+  /// transaction.
+  pub max_fee_satoshis: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClosingSigned {
+  /// The channel ID
+  pub channel_id: ChannelId,
+  /// The proposed total fee for the closing transaction
+  pub fee_satoshis: u64,
+  /// A signature on the closing transaction
+  pub signature: Signature,
+  /// The minimum and maximum fees which the sender is willing to accept, provided only by new
+  /// nodes.
+  pub fee_range: Option<ClosingSignedFeeRange>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ChannelAnnouncement {
   /// Authentication of the announcement by the first public node
@@ -5,4 +5,7 @@ pub mod convert;
 pub mod helper;
 pub mod hex_utils;
 pub mod events;
-pub mod messages;
\ No newline at end of file
+pub mod messages;
+pub mod musig2;
+pub mod script_disasm;
+pub mod script_interpreter;
\ No newline at end of file
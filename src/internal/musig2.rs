@@ -0,0 +1,413 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! MuSig2 (BIP 327) key aggregation and signing, built on the plain
+//! secp256k1 point and scalar operations.
+//!
+//! A signing session goes: aggregate the keys into a [`KeyAggContext`]
+//! (tweaked for taproot), each signer runs [`nonce_gen`] and shares its
+//! [`PubNonce`], everyone builds the same [`Session`] from the aggregated
+//! nonce and the message, each signer produces a [`PartialSignature`], and
+//! any party combines them into a BIP 340 signature for the aggregate key.
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::{self, schnorr, PublicKey, Scalar, Secp256k1, SecretKey, XOnlyPublicKey};
+use bitcoin::taproot::{TapNodeHash, TapTweakHash};
+use std::fmt;
+
+/// The order of the secp256k1 group.
+const CURVE_ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MusigError {
+    /// The signer's public key is not one of the aggregated keys.
+    UnknownSigner,
+    /// The secret nonce was generated for a different public key.
+    NonceKeyMismatch,
+    /// A partial signature does not verify against the signer's nonce and key.
+    InvalidPartialSignature { signer: usize },
+    /// The secret nonce was already spent on a signature, or the session has
+    /// not reached the step that needs it.
+    MissingNonce,
+}
+
+impl fmt::Display for MusigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MusigError::UnknownSigner => write!(f, "signer is not part of the aggregate key"),
+            MusigError::NonceKeyMismatch => write!(f, "secret nonce belongs to another key"),
+            MusigError::InvalidPartialSignature { signer } => {
+                write!(f, "partial signature {} is invalid", signer)
+            }
+            MusigError::MissingNonce => write!(f, "no unused nonce for this signing step"),
+        }
+    }
+}
+
+pub(crate) fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    for chunk in data {
+        engine.input(chunk);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+// Scalars mod n are `SecretKey`s, with `None` standing for zero, which
+// `SecretKey` cannot hold.
+
+pub(crate) fn scalar_from_bytes(mut bytes: [u8; 32]) -> Option<SecretKey> {
+    if Scalar::from_be_bytes(bytes).is_err() {
+        // bytes >= n, and as bytes < 2n a single subtraction reduces them
+        let mut borrow = 0i16;
+        for i in (0..32).rev() {
+            let diff = bytes[i] as i16 - CURVE_ORDER[i] as i16 - borrow;
+            borrow = (diff < 0) as i16;
+            bytes[i] = diff.rem_euclid(256) as u8;
+        }
+    }
+    SecretKey::from_slice(&bytes).ok()
+}
+
+fn scalar_one() -> SecretKey {
+    let mut one = [0u8; 32];
+    one[31] = 1;
+    SecretKey::from_slice(&one).expect("1 is a valid scalar")
+}
+
+/// The generator point G.
+fn generator() -> PublicKey {
+    PublicKey::from_secret_key(&Secp256k1::signing_only(), &scalar_one())
+}
+
+pub(crate) fn scalar_add(a: Option<SecretKey>, b: Option<SecretKey>) -> Option<SecretKey> {
+    match (a, b) {
+        (Some(a), Some(b)) => a.add_tweak(&Scalar::from(b)).ok(),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+pub(crate) fn scalar_mul(a: Option<SecretKey>, b: Option<SecretKey>) -> Option<SecretKey> {
+    match (a, b) {
+        (Some(a), Some(b)) => a.mul_tweak(&Scalar::from(b)).ok(),
+        _ => None,
+    }
+}
+
+pub(crate) fn scalar_negate_if(a: Option<SecretKey>, negate: bool) -> Option<SecretKey> {
+    if negate {
+        a.map(|a| a.negate())
+    } else {
+        a
+    }
+}
+
+pub(crate) fn has_odd_y(point: &PublicKey) -> bool {
+    point.serialize()[0] == 0x03
+}
+
+pub(crate) fn xonly(point: &PublicKey) -> [u8; 32] {
+    point.x_only_public_key().0.serialize()
+}
+
+/// `scalar * point`, where a zero scalar gives no point.
+pub(crate) fn point_mul(point: &PublicKey, scalar: Option<SecretKey>) -> Option<PublicKey> {
+    let secp = Secp256k1::verification_only();
+    scalar.map(|scalar| point.mul_tweak(&secp, &Scalar::from(scalar)).expect("non-zero scalar"))
+}
+
+pub(crate) fn point_add(a: Option<PublicKey>, b: Option<PublicKey>) -> Option<PublicKey> {
+    match (a, b) {
+        (Some(a), Some(b)) => a.combine(&b).ok(),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+/// Sorts public keys by their compressed encoding (BIP 327 `KeySort`), so
+/// both parties aggregate them in the same order.
+pub fn sort_pubkeys(pubkeys: &[PublicKey]) -> Vec<PublicKey> {
+    let mut sorted = pubkeys.to_vec();
+    sorted.sort_by_key(|pubkey| pubkey.serialize());
+    sorted
+}
+
+/// The aggregate of a list of public keys, with any tweaks applied to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyAggContext {
+    pubkeys: Vec<PublicKey>,
+    q: PublicKey,
+    /// Whether the accumulated sign factor `gacc` is -1.
+    gacc_negated: bool,
+    tacc: Option<SecretKey>,
+}
+
+impl KeyAggContext {
+    /// Aggregates `pubkeys` in the given order.
+    pub fn new(pubkeys: &[PublicKey]) -> KeyAggContext {
+        assert!(!pubkeys.is_empty(), "at least one key to aggregate");
+        let mut q = None;
+        for pubkey in pubkeys {
+            q = point_add(q, point_mul(pubkey, key_agg_coefficient(pubkeys, pubkey)));
+        }
+        KeyAggContext {
+            pubkeys: pubkeys.to_vec(),
+            q: q.expect("aggregate key is not infinity"),
+            gacc_negated: false,
+            tacc: None,
+        }
+    }
+
+    /// Applies an x-only tweak, as BIP 341 does to the internal key.
+    pub fn with_xonly_tweak(self, tweak: &Scalar) -> KeyAggContext {
+        let secp = Secp256k1::verification_only();
+        let negate = has_odd_y(&self.q);
+        let q = if negate { self.q.negate(&secp) } else { self.q };
+        KeyAggContext {
+            q: q.add_exp_tweak(&secp, tweak).expect("tweaked key is not infinity"),
+            gacc_negated: self.gacc_negated ^ negate,
+            tacc: scalar_add(scalar_from_bytes(tweak.to_be_bytes()), scalar_negate_if(self.tacc, negate)),
+            pubkeys: self.pubkeys,
+        }
+    }
+
+    /// Tweaks the aggregate key into a taproot output key committing to
+    /// `merkle_root`, or to no scripts at all (BIP 86) if it is `None`.
+    pub fn with_taproot_tweak(self, merkle_root: Option<TapNodeHash>) -> KeyAggContext {
+        let tweak = TapTweakHash::from_key_and_tweak(self.x_only_public_key(), merkle_root).to_scalar();
+        self.with_xonly_tweak(&tweak)
+    }
+
+    pub fn aggregate_pubkey(&self) -> PublicKey {
+        self.q
+    }
+
+    pub fn x_only_public_key(&self) -> XOnlyPublicKey {
+        self.q.x_only_public_key().0
+    }
+
+    pub fn pubkeys(&self) -> &[PublicKey] {
+        &self.pubkeys
+    }
+}
+
+fn key_agg_coefficient(pubkeys: &[PublicKey], pubkey: &PublicKey) -> Option<SecretKey> {
+    // The first key that differs from the first one gets coefficient 1.
+    let second_key = pubkeys.iter().find(|key| **key != pubkeys[0]);
+    if second_key == Some(pubkey) {
+        return Some(scalar_one());
+    }
+    let serialized: Vec<u8> = pubkeys.iter().flat_map(|key| key.serialize()).collect();
+    let list_hash = tagged_hash("KeyAgg list", &[&serialized]);
+    scalar_from_bytes(tagged_hash("KeyAgg coefficient", &[&list_hash, &pubkey.serialize()]))
+}
+
+/// A signer's secret nonce. It is consumed by signing so it cannot be reused,
+/// which would leak the secret key.
+#[derive(Debug)]
+pub struct SecNonce {
+    k1: SecretKey,
+    k2: SecretKey,
+    pubkey: PublicKey,
+}
+
+/// The public half of a signer's nonce, sent to the other signers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PubNonce {
+    pub r1: PublicKey,
+    pub r2: PublicKey,
+}
+
+impl PubNonce {
+    pub fn serialize(&self) -> [u8; 66] {
+        let mut bytes = [0u8; 66];
+        bytes[..33].copy_from_slice(&self.r1.serialize());
+        bytes[33..].copy_from_slice(&self.r2.serialize());
+        bytes
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> Result<PubNonce, secp256k1::Error> {
+        if bytes.len() != 66 {
+            return Err(secp256k1::Error::InvalidPublicKey);
+        }
+        Ok(PubNonce { r1: PublicKey::from_slice(&bytes[..33])?, r2: PublicKey::from_slice(&bytes[33..])? })
+    }
+}
+
+/// Generates a nonce pair (BIP 327 `NonceGen`). `rand` must be fresh
+/// randomness for every session; the other inputs only add defence in depth.
+pub fn nonce_gen(
+    rand: [u8; 32],
+    secret_key: Option<&SecretKey>,
+    pubkey: &PublicKey,
+    aggregate_pubkey: Option<&XOnlyPublicKey>,
+    msg: Option<&[u8]>,
+) -> (SecNonce, PubNonce) {
+    let mut rand = rand;
+    if let Some(secret_key) = secret_key {
+        let aux = tagged_hash("MuSig/aux", &[&secret_key.secret_bytes()]);
+        for (byte, mask) in rand.iter_mut().zip(aux) {
+            *byte ^= mask;
+        }
+    }
+
+    let aggregate_pubkey = aggregate_pubkey.map(|key| key.serialize().to_vec()).unwrap_or_default();
+    let msg_prefixed = match msg {
+        Some(msg) => [&[1u8][..], &(msg.len() as u64).to_be_bytes(), msg].concat(),
+        None => vec![0],
+    };
+    let nonce = |i: u8| {
+        let hash = tagged_hash(
+            "MuSig/nonce",
+            &[
+                &rand,
+                &[33],
+                &pubkey.serialize(),
+                &[aggregate_pubkey.len() as u8],
+                &aggregate_pubkey,
+                &msg_prefixed,
+                &0u32.to_be_bytes(),
+                &[i],
+            ],
+        );
+        scalar_from_bytes(hash).expect("nonce is not zero")
+    };
+
+    let secp = Secp256k1::signing_only();
+    let (k1, k2) = (nonce(0), nonce(1));
+    let pubnonce = PubNonce {
+        r1: PublicKey::from_secret_key(&secp, &k1),
+        r2: PublicKey::from_secret_key(&secp, &k2),
+    };
+    (SecNonce { k1, k2, pubkey: *pubkey }, pubnonce)
+}
+
+/// The sum of every signer's nonces. Either sum may be the point at
+/// infinity (`None`), e.g. when a peer sends the negation of our nonce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AggNonce {
+    pub r1: Option<PublicKey>,
+    pub r2: Option<PublicKey>,
+}
+
+impl AggNonce {
+    /// The point at infinity is serialized as 33 zero bytes (BIP 327
+    /// `cbytes_ext`).
+    fn serialize(&self) -> [u8; 66] {
+        let mut bytes = [0u8; 66];
+        if let Some(r1) = self.r1 {
+            bytes[..33].copy_from_slice(&r1.serialize());
+        }
+        if let Some(r2) = self.r2 {
+            bytes[33..].copy_from_slice(&r2.serialize());
+        }
+        bytes
+    }
+}
+
+pub fn aggregate_nonces(pubnonces: &[PubNonce]) -> AggNonce {
+    let r1: Vec<&PublicKey> = pubnonces.iter().map(|nonce| &nonce.r1).collect();
+    let r2: Vec<&PublicKey> = pubnonces.iter().map(|nonce| &nonce.r2).collect();
+    AggNonce { r1: PublicKey::combine_keys(&r1).ok(), r2: PublicKey::combine_keys(&r2).ok() }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PartialSignature(pub [u8; 32]);
+
+impl PartialSignature {
+    fn scalar(&self) -> Option<SecretKey> {
+        scalar_from_bytes(self.0)
+    }
+}
+
+/// Everything the signers of one message agree on once nonces are exchanged.
+#[derive(Clone, Debug)]
+pub struct Session {
+    key_agg: KeyAggContext,
+    msg: [u8; 32],
+    b: Option<SecretKey>,
+    r: PublicKey,
+    e: Option<SecretKey>,
+}
+
+impl Session {
+    pub fn new(key_agg: &KeyAggContext, aggnonce: &AggNonce, msg: [u8; 32]) -> Session {
+        let q = xonly(&key_agg.q);
+        let b = scalar_from_bytes(tagged_hash("MuSig/noncecoef", &[&aggnonce.serialize(), &q, &msg]));
+        // An infinite session nonce can only come from a dishonest signer,
+        // and BIP 327 replaces it with G so signing still goes ahead.
+        let r2 = aggnonce.r2.and_then(|r2| point_mul(&r2, b));
+        let r = point_add(aggnonce.r1, r2).unwrap_or_else(generator);
+        let e = scalar_from_bytes(tagged_hash("BIP0340/challenge", &[&xonly(&r), &q, &msg]));
+        Session { key_agg: key_agg.clone(), msg, b, r, e }
+    }
+
+    /// Whether the signing key must be negated: `g * gacc` in BIP 327.
+    fn negate_secret_key(&self) -> bool {
+        has_odd_y(&self.key_agg.q) ^ self.key_agg.gacc_negated
+    }
+
+    pub fn partial_sign(&self, secnonce: SecNonce, secret_key: &SecretKey) -> Result<PartialSignature, MusigError> {
+        let secp = Secp256k1::signing_only();
+        let pubkey = PublicKey::from_secret_key(&secp, secret_key);
+        if secnonce.pubkey != pubkey {
+            return Err(MusigError::NonceKeyMismatch);
+        }
+        if !self.key_agg.pubkeys.contains(&pubkey) {
+            return Err(MusigError::UnknownSigner);
+        }
+
+        let negate_nonce = has_odd_y(&self.r);
+        let k1 = scalar_negate_if(Some(secnonce.k1), negate_nonce);
+        let k2 = scalar_negate_if(Some(secnonce.k2), negate_nonce);
+        let a = key_agg_coefficient(&self.key_agg.pubkeys, &pubkey);
+        let d = scalar_negate_if(Some(*secret_key), self.negate_secret_key());
+
+        let s = scalar_add(scalar_add(k1, scalar_mul(self.b, k2)), scalar_mul(scalar_mul(self.e, a), d));
+        Ok(PartialSignature(s.map_or([0; 32], |s| s.secret_bytes())))
+    }
+
+    /// Checks one signer's partial signature before aggregating.
+    pub fn partial_verify(&self, partial_signature: &PartialSignature, pubnonce: &PubNonce, pubkey: &PublicKey) -> bool {
+        let secp = Secp256k1::new();
+        if !self.key_agg.pubkeys.contains(pubkey) {
+            return false;
+        }
+        // s must be below the curve order, not reduced into it.
+        if Scalar::from_be_bytes(partial_signature.0).is_err() {
+            return false;
+        }
+
+        let mut r = point_add(Some(pubnonce.r1), point_mul(&pubnonce.r2, self.b));
+        if has_odd_y(&self.r) {
+            r = r.map(|r| r.negate(&secp));
+        }
+        let a = key_agg_coefficient(&self.key_agg.pubkeys, pubkey);
+        let ea = scalar_negate_if(scalar_mul(self.e, a), self.negate_secret_key());
+        let expected = point_add(r, point_mul(pubkey, ea));
+
+        let actual = partial_signature.scalar().map(|s| PublicKey::from_secret_key(&secp, &s));
+        actual == expected
+    }
+
+    /// Combines every signer's partial signature into a BIP 340 signature
+    /// for the (tweaked) aggregate key.
+    pub fn aggregate(&self, partial_signatures: &[PartialSignature]) -> schnorr::Signature {
+        let mut s = scalar_mul(self.e, scalar_negate_if(self.key_agg.tacc, has_odd_y(&self.key_agg.q)));
+        for partial_signature in partial_signatures {
+            s = scalar_add(s, partial_signature.scalar());
+        }
+
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(&xonly(&self.r));
+        signature[32..].copy_from_slice(&s.map_or([0; 32], |s| s.secret_bytes()));
+        schnorr::Signature::from_slice(&signature).expect("64 bytes")
+    }
+
+    pub fn message(&self) -> [u8; 32] {
+        self.msg
+    }
+}
@@ -0,0 +1,624 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! A disassembler for the scripts we build with `internal::builder::Builder`.
+//!
+//! `disassemble` renders a script as one line of ASM, `pretty_print` as
+//! indented ASM with the Lightning template it matches and a comment on each
+//! key, hash and timelock, and `parse_asm` turns either back into a script.
+//!
+//! Data pushes are written as bare hex and small numbers as `OP_0`..`OP_16`,
+//! so a number pushed with `push_int(144)` appears as its script-number
+//! encoding `9000` (the pretty printer adds `= 144`).
+use crate::internal::script_interpreter::read_num;
+use bitcoin::blockdata::opcodes::all as opcodes;
+use bitcoin::blockdata::opcodes::Opcode;
+use bitcoin::script::{Instruction, Script, ScriptBuf};
+use bitcoin::secp256k1::PublicKey;
+use std::fmt;
+
+/// A script shape we know how to label.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScriptTemplate {
+    P2wpkh {
+        pubkey_hash: [u8; 20],
+    },
+    P2wsh {
+        script_hash: [u8; 32],
+    },
+    /// The funding output's witness script.
+    TwoOfTwoMultisig {
+        pubkey1: PublicKey,
+        pubkey2: PublicKey,
+    },
+    ToLocal {
+        revocation_pubkey: PublicKey,
+        to_self_delay: i64,
+        delayed_pubkey: PublicKey,
+    },
+    OfferedHtlc {
+        revocation_pubkey_hash: [u8; 20],
+        remote_htlc_pubkey: PublicKey,
+        local_htlc_pubkey: PublicKey,
+        payment_hash160: [u8; 20],
+        anchors: bool,
+    },
+    ReceivedHtlc {
+        revocation_pubkey_hash: [u8; 20],
+        remote_htlc_pubkey: PublicKey,
+        local_htlc_pubkey: PublicKey,
+        payment_hash160: [u8; 20],
+        cltv_expiry: i64,
+        anchors: bool,
+    },
+    /// Spendable with the payment preimage, or by `pubkey` after `cltv_expiry`.
+    HashLocked {
+        payment_hash160: [u8; 20],
+        cltv_expiry: i64,
+        pubkey: PublicKey,
+    },
+    TimelockedP2pkh {
+        relative_locktime: i64,
+        pubkey_hash: [u8; 20],
+    },
+    Anchor {
+        funding_pubkey: PublicKey,
+    },
+    /// The `to_remote` witness script of an anchors channel.
+    ToRemoteAnchor {
+        remote_pubkey: PublicKey,
+    },
+}
+
+impl ScriptTemplate {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScriptTemplate::P2wpkh { .. } => "P2WPKH",
+            ScriptTemplate::P2wsh { .. } => "P2WSH",
+            ScriptTemplate::TwoOfTwoMultisig { .. } => "2-of-2 multisig",
+            ScriptTemplate::ToLocal { .. } => "to_local",
+            ScriptTemplate::OfferedHtlc { anchors: false, .. } => "offered HTLC",
+            ScriptTemplate::OfferedHtlc { anchors: true, .. } => "offered HTLC (anchors)",
+            ScriptTemplate::ReceivedHtlc { anchors: false, .. } => "received HTLC",
+            ScriptTemplate::ReceivedHtlc { anchors: true, .. } => "received HTLC (anchors)",
+            ScriptTemplate::HashLocked { .. } => "hash-locked",
+            ScriptTemplate::TimelockedP2pkh { .. } => "timelocked P2PKH",
+            ScriptTemplate::Anchor { .. } => "anchor",
+            ScriptTemplate::ToRemoteAnchor { .. } => "to_remote (anchors)",
+        }
+    }
+}
+
+impl fmt::Display for ScriptTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsmError {
+    /// The token is neither an opcode name nor hex data.
+    UnknownToken(String),
+    /// An explicit push opcode was not followed by its data.
+    MissingPushData(String),
+    /// The data does not have the length the explicit push opcode requires.
+    PushLength { opcode: String, length: usize },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsmError::UnknownToken(token) => write!(f, "unknown token `{}`", token),
+            AsmError::MissingPushData(opcode) => write!(f, "{} is missing its data", opcode),
+            AsmError::PushLength { opcode, length } => {
+                write!(f, "{} cannot push {} bytes", opcode, length)
+            }
+        }
+    }
+}
+
+/// One element of a template: a fixed opcode or number, or a push we capture.
+#[derive(Clone, Copy)]
+enum Pattern {
+    Op(Opcode),
+    Int(i64),
+    Pubkey(&'static str),
+    Hash20(&'static str),
+    Hash32(&'static str),
+    Num(&'static str),
+}
+
+use Pattern::{Hash20, Hash32, Int, Num, Op, Pubkey};
+
+const P2WPKH: &[Pattern] = &[Int(0), Hash20("pubkey hash")];
+
+const P2WSH: &[Pattern] = &[Int(0), Hash32("witness script hash")];
+
+const TWO_OF_TWO_MULTISIG: &[Pattern] = &[
+    Int(2),
+    Pubkey("pubkey1"),
+    Pubkey("pubkey2"),
+    Int(2),
+    Op(opcodes::OP_CHECKMULTISIG),
+];
+
+const TO_LOCAL: &[Pattern] = &[
+    Op(opcodes::OP_IF),
+    Pubkey("revocationpubkey"),
+    Op(opcodes::OP_ELSE),
+    Num("to_self_delay"),
+    Op(opcodes::OP_CSV),
+    Op(opcodes::OP_DROP),
+    Pubkey("local_delayedpubkey"),
+    Op(opcodes::OP_ENDIF),
+    Op(opcodes::OP_CHECKSIG),
+];
+
+/// Shared by both HTLC scripts, up to the preimage check.
+const HTLC_REVOCATION: &[Pattern] = &[
+    Op(opcodes::OP_DUP),
+    Op(opcodes::OP_HASH160),
+    Hash20("RIPEMD160(SHA256(revocationpubkey))"),
+    Op(opcodes::OP_EQUAL),
+    Op(opcodes::OP_IF),
+    Op(opcodes::OP_CHECKSIG),
+    Op(opcodes::OP_ELSE),
+    Pubkey("remote_htlcpubkey"),
+    Op(opcodes::OP_SWAP),
+    Op(opcodes::OP_SIZE),
+    Int(32),
+    Op(opcodes::OP_EQUAL),
+];
+
+const OFFERED_HTLC: &[Pattern] = &[
+    Op(opcodes::OP_NOTIF),
+    Op(opcodes::OP_DROP),
+    Int(2),
+    Op(opcodes::OP_SWAP),
+    Pubkey("local_htlcpubkey"),
+    Int(2),
+    Op(opcodes::OP_CHECKMULTISIG),
+    Op(opcodes::OP_ELSE),
+    Op(opcodes::OP_HASH160),
+    Hash20("RIPEMD160(payment_hash)"),
+    Op(opcodes::OP_EQUALVERIFY),
+    Op(opcodes::OP_CHECKSIG),
+    Op(opcodes::OP_ENDIF),
+];
+
+const RECEIVED_HTLC: &[Pattern] = &[
+    Op(opcodes::OP_IF),
+    Op(opcodes::OP_HASH160),
+    Hash20("RIPEMD160(payment_hash)"),
+    Op(opcodes::OP_EQUALVERIFY),
+    Int(2),
+    Op(opcodes::OP_SWAP),
+    Pubkey("local_htlcpubkey"),
+    Int(2),
+    Op(opcodes::OP_CHECKMULTISIG),
+    Op(opcodes::OP_ELSE),
+    Op(opcodes::OP_DROP),
+    Num("cltv_expiry"),
+    Op(opcodes::OP_CLTV),
+    Op(opcodes::OP_DROP),
+    Op(opcodes::OP_CHECKSIG),
+    Op(opcodes::OP_ENDIF),
+];
+
+const HTLC_END: &[Pattern] = &[Op(opcodes::OP_ENDIF)];
+
+/// Anchors channels add `1 OP_CSV OP_DROP` to every HTLC spending path.
+const HTLC_ANCHORS_END: &[Pattern] = &[
+    Int(1),
+    Op(opcodes::OP_CSV),
+    Op(opcodes::OP_DROP),
+    Op(opcodes::OP_ENDIF),
+];
+
+const HASH_LOCKED: &[Pattern] = &[
+    Op(opcodes::OP_IF),
+    Op(opcodes::OP_HASH160),
+    Hash20("RIPEMD160(payment_hash)"),
+    Op(opcodes::OP_EQUAL),
+    Op(opcodes::OP_ELSE),
+    Num("cltv_expiry"),
+    Op(opcodes::OP_CLTV),
+    Op(opcodes::OP_DROP),
+    Pubkey("pubkey"),
+    Op(opcodes::OP_CHECKSIG),
+    Op(opcodes::OP_ENDIF),
+];
+
+const TIMELOCKED_P2PKH: &[Pattern] = &[
+    Num("relative locktime"),
+    Op(opcodes::OP_CSV),
+    Op(opcodes::OP_DROP),
+    Op(opcodes::OP_DUP),
+    Op(opcodes::OP_HASH160),
+    Hash20("pubkey hash"),
+    Op(opcodes::OP_EQUALVERIFY),
+    Op(opcodes::OP_CHECKSIG),
+];
+
+const ANCHOR: &[Pattern] = &[
+    Pubkey("funding_pubkey"),
+    Op(opcodes::OP_CHECKSIG),
+    Op(opcodes::OP_IFDUP),
+    Op(opcodes::OP_NOTIF),
+    Int(16),
+    Op(opcodes::OP_CSV),
+    Op(opcodes::OP_ENDIF),
+];
+
+const TO_REMOTE_ANCHOR: &[Pattern] = &[
+    Pubkey("remote_pubkey"),
+    Op(opcodes::OP_CHECKSIGVERIFY),
+    Int(1),
+    Op(opcodes::OP_CSV),
+];
+
+enum Capture {
+    Pubkey(PublicKey),
+    Hash20([u8; 20]),
+    Hash32([u8; 32]),
+    Num(i64),
+}
+
+/// What a template captured, in script order, and a comment per instruction.
+struct Match {
+    captures: Vec<Capture>,
+    comments: Vec<Option<String>>,
+}
+
+impl Match {
+    fn pubkey(&self, index: usize) -> PublicKey {
+        match self.captures[index] {
+            Capture::Pubkey(pubkey) => pubkey,
+            _ => unreachable!("capture {} is not a pubkey", index),
+        }
+    }
+
+    fn hash20(&self, index: usize) -> [u8; 20] {
+        match self.captures[index] {
+            Capture::Hash20(hash) => hash,
+            _ => unreachable!("capture {} is not a 20-byte hash", index),
+        }
+    }
+
+    fn hash32(&self, index: usize) -> [u8; 32] {
+        match self.captures[index] {
+            Capture::Hash32(hash) => hash,
+            _ => unreachable!("capture {} is not a 32-byte hash", index),
+        }
+    }
+
+    fn num(&self, index: usize) -> i64 {
+        match self.captures[index] {
+            Capture::Num(num) => num,
+            _ => unreachable!("capture {} is not a number", index),
+        }
+    }
+}
+
+/// The value of a push that `Builder::push_int` could have produced.
+fn instruction_number(instruction: &Instruction) -> Option<i64> {
+    match instruction {
+        Instruction::PushBytes(bytes) => read_num(bytes.as_bytes(), 5).ok(),
+        Instruction::Op(op) if *op == opcodes::OP_PUSHNUM_NEG1 => Some(-1),
+        Instruction::Op(op) => {
+            let code = op.to_u8();
+            if (opcodes::OP_PUSHNUM_1.to_u8()..=opcodes::OP_PUSHNUM_16.to_u8()).contains(&code) {
+                Some((code - opcodes::OP_PUSHNUM_1.to_u8() + 1) as i64)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+fn push_bytes<'a>(instruction: &Instruction<'a>) -> Option<&'a [u8]> {
+    match instruction {
+        Instruction::PushBytes(bytes) => Some(bytes.as_bytes()),
+        Instruction::Op(_) => None,
+    }
+}
+
+/// Matches the whole script against the concatenation of `parts`.
+fn match_pattern(instructions: &[Instruction], parts: &[&[Pattern]]) -> Option<Match> {
+    let pattern: Vec<&Pattern> = parts.iter().flat_map(|part| part.iter()).collect();
+    if pattern.len() != instructions.len() {
+        return None;
+    }
+
+    let mut captures = Vec::new();
+    let mut comments = Vec::new();
+    for (instruction, pattern) in instructions.iter().zip(pattern) {
+        let comment = match *pattern {
+            Op(op) => {
+                if *instruction != Instruction::Op(op) {
+                    return None;
+                }
+                None
+            }
+            Int(expected) => {
+                if instruction_number(instruction) != Some(expected) {
+                    return None;
+                }
+                None
+            }
+            Pubkey(label) => {
+                let pubkey = PublicKey::from_slice(push_bytes(instruction)?).ok()?;
+                captures.push(Capture::Pubkey(pubkey));
+                Some(label.to_string())
+            }
+            Hash20(label) => {
+                captures.push(Capture::Hash20(push_bytes(instruction)?.try_into().ok()?));
+                Some(label.to_string())
+            }
+            Hash32(label) => {
+                captures.push(Capture::Hash32(push_bytes(instruction)?.try_into().ok()?));
+                Some(label.to_string())
+            }
+            Num(label) => {
+                let num = instruction_number(instruction)?;
+                captures.push(Capture::Num(num));
+                Some(format!("{} = {}", label, num))
+            }
+        };
+        comments.push(comment);
+    }
+    Some(Match { captures, comments })
+}
+
+fn identify(instructions: &[Instruction]) -> Option<(ScriptTemplate, Vec<Option<String>>)> {
+    if let Some(m) = match_pattern(instructions, &[P2WPKH]) {
+        let template = ScriptTemplate::P2wpkh { pubkey_hash: m.hash20(0) };
+        return Some((template, m.comments));
+    }
+    if let Some(m) = match_pattern(instructions, &[P2WSH]) {
+        let template = ScriptTemplate::P2wsh { script_hash: m.hash32(0) };
+        return Some((template, m.comments));
+    }
+    if let Some(m) = match_pattern(instructions, &[TWO_OF_TWO_MULTISIG]) {
+        let template = ScriptTemplate::TwoOfTwoMultisig { pubkey1: m.pubkey(0), pubkey2: m.pubkey(1) };
+        return Some((template, m.comments));
+    }
+    if let Some(m) = match_pattern(instructions, &[TO_LOCAL]) {
+        let template = ScriptTemplate::ToLocal {
+            revocation_pubkey: m.pubkey(0),
+            to_self_delay: m.num(1),
+            delayed_pubkey: m.pubkey(2),
+        };
+        return Some((template, m.comments));
+    }
+    for (end, anchors) in [(HTLC_END, false), (HTLC_ANCHORS_END, true)] {
+        if let Some(m) = match_pattern(instructions, &[HTLC_REVOCATION, OFFERED_HTLC, end]) {
+            let template = ScriptTemplate::OfferedHtlc {
+                revocation_pubkey_hash: m.hash20(0),
+                remote_htlc_pubkey: m.pubkey(1),
+                local_htlc_pubkey: m.pubkey(2),
+                payment_hash160: m.hash20(3),
+                anchors,
+            };
+            return Some((template, m.comments));
+        }
+        if let Some(m) = match_pattern(instructions, &[HTLC_REVOCATION, RECEIVED_HTLC, end]) {
+            let template = ScriptTemplate::ReceivedHtlc {
+                revocation_pubkey_hash: m.hash20(0),
+                remote_htlc_pubkey: m.pubkey(1),
+                payment_hash160: m.hash20(2),
+                local_htlc_pubkey: m.pubkey(3),
+                cltv_expiry: m.num(4),
+                anchors,
+            };
+            return Some((template, m.comments));
+        }
+    }
+    if let Some(m) = match_pattern(instructions, &[HASH_LOCKED]) {
+        let template = ScriptTemplate::HashLocked {
+            payment_hash160: m.hash20(0),
+            cltv_expiry: m.num(1),
+            pubkey: m.pubkey(2),
+        };
+        return Some((template, m.comments));
+    }
+    if let Some(m) = match_pattern(instructions, &[TIMELOCKED_P2PKH]) {
+        let template = ScriptTemplate::TimelockedP2pkh { relative_locktime: m.num(0), pubkey_hash: m.hash20(1) };
+        return Some((template, m.comments));
+    }
+    if let Some(m) = match_pattern(instructions, &[ANCHOR]) {
+        let template = ScriptTemplate::Anchor { funding_pubkey: m.pubkey(0) };
+        return Some((template, m.comments));
+    }
+    if let Some(m) = match_pattern(instructions, &[TO_REMOTE_ANCHOR]) {
+        let template = ScriptTemplate::ToRemoteAnchor { remote_pubkey: m.pubkey(0) };
+        return Some((template, m.comments));
+    }
+    None
+}
+
+/// Returns the template `script` matches, if any.
+pub fn identify_template(script: &Script) -> Option<ScriptTemplate> {
+    let instructions: Vec<Instruction> = script.instructions().collect::<Result<_, _>>().ok()?;
+    identify(&instructions).map(|(template, _)| template)
+}
+
+/// Size of the shortest encoding of a push of `len` bytes.
+fn minimal_push_size(len: usize) -> usize {
+    match len {
+        0..=75 => 1 + len,
+        76..=0xff => 2 + len,
+        0x100..=0xffff => 3 + len,
+        _ => 5 + len,
+    }
+}
+
+/// The ASM tokens for each instruction, or the error that stopped decoding.
+fn tokenize(script: &Script) -> (Vec<(Instruction<'_>, String)>, Option<String>) {
+    let bytes = script.as_bytes();
+    let mut tokens = Vec::new();
+    let mut indices = script.instruction_indices().peekable();
+    while let Some(next) = indices.next() {
+        let (index, instruction) = match next {
+            Ok(next) => next,
+            Err(e) => return (tokens, Some(e.to_string())),
+        };
+        let token = match &instruction {
+            Instruction::PushBytes(data) if data.is_empty() => "OP_0".to_string(),
+            Instruction::PushBytes(data) => {
+                let end = match indices.peek() {
+                    Some(Ok((end, _))) => *end,
+                    _ => bytes.len(),
+                };
+                if end - index == minimal_push_size(data.len()) {
+                    hex::encode(data.as_bytes())
+                } else {
+                    // keep the push opcode so the script round-trips exactly
+                    format!("{} {}", Opcode::from(bytes[index]), hex::encode(data.as_bytes()))
+                }
+            }
+            Instruction::Op(op) => match instruction_number(&instruction) {
+                Some(-1) => "OP_1NEGATE".to_string(),
+                Some(n) => format!("OP_{}", n),
+                None => op.to_string(),
+            },
+        };
+        tokens.push((instruction, token));
+    }
+    (tokens, None)
+}
+
+/// Renders `script` as a single line of ASM.
+pub fn disassemble(script: &Script) -> String {
+    let (tokens, error) = tokenize(script);
+    let mut asm: Vec<String> = tokens.into_iter().map(|(_, token)| token).collect();
+    if let Some(error) = error {
+        asm.push(format!("[error: {}]", error));
+    }
+    asm.join(" ")
+}
+
+/// Renders `script` one instruction per line, indented by `OP_IF` depth, with
+/// the matching template as a header and comments on the values it captured.
+pub fn pretty_print(script: &Script) -> String {
+    let (tokens, error) = tokenize(script);
+    let instructions: Vec<Instruction> = tokens.iter().map(|(instruction, _)| *instruction).collect();
+    let (header, comments) = match identify(&instructions) {
+        Some((template, comments)) if error.is_none() => (template.name().to_string(), comments),
+        _ => ("unrecognised script".to_string(), vec![None; tokens.len()]),
+    };
+
+    let mut lines = vec![format!("# {}", header)];
+    let mut depth: usize = 0;
+    for ((instruction, token), comment) in tokens.iter().zip(comments) {
+        let indent = match instruction {
+            Instruction::Op(op) if *op == opcodes::OP_ELSE => depth.saturating_sub(1),
+            Instruction::Op(op) if *op == opcodes::OP_ENDIF => {
+                depth = depth.saturating_sub(1);
+                depth
+            }
+            _ => depth,
+        };
+        if let Instruction::Op(op) = instruction {
+            if *op == opcodes::OP_IF || *op == opcodes::OP_NOTIF {
+                depth += 1;
+            }
+        }
+
+        // small pushes are usually numbers, so show their value
+        let comment = comment.or_else(|| match instruction {
+            Instruction::PushBytes(data) if !data.is_empty() => {
+                read_num(data.as_bytes(), 4).ok().map(|n| format!("= {}", n))
+            }
+            _ => None,
+        });
+        let line = format!("{}{}", "    ".repeat(indent), token);
+        match comment {
+            Some(comment) => lines.push(format!("{}  # {}", line, comment)),
+            None => lines.push(line),
+        }
+    }
+    if let Some(error) = error {
+        lines.push(format!("# error: {}", error));
+    }
+    lines.join("\n")
+}
+
+fn lookup_opcode(name: &str) -> Option<Opcode> {
+    let name = name.to_uppercase();
+    let alias = match name.as_str() {
+        "OP_0" | "OP_FALSE" => Some(opcodes::OP_PUSHBYTES_0),
+        "OP_TRUE" => Some(opcodes::OP_PUSHNUM_1),
+        "OP_1NEGATE" => Some(opcodes::OP_PUSHNUM_NEG1),
+        "OP_CHECKLOCKTIMEVERIFY" | "OP_NOP2" => Some(opcodes::OP_CLTV),
+        "OP_CHECKSEQUENCEVERIFY" | "OP_NOP3" => Some(opcodes::OP_CSV),
+        _ => None,
+    };
+    if alias.is_some() {
+        return alias;
+    }
+    if let Some(n) = name.strip_prefix("OP_").and_then(|n| n.parse::<u8>().ok()) {
+        if (1..=16).contains(&n) && !name.starts_with("OP_0") {
+            return Some(Opcode::from(opcodes::OP_PUSHNUM_1.to_u8() + n - 1));
+        }
+    }
+    (0..=u8::MAX).map(Opcode::from).find(|op| op.to_string() == name)
+}
+
+/// Appends a push of `data`, using `opcode` if given and the shortest push otherwise.
+fn push_data(script: &mut Vec<u8>, opcode: Option<Opcode>, data: &[u8]) -> Result<(), AsmError> {
+    let len = data.len();
+    let code = match opcode {
+        Some(op) => op.to_u8(),
+        None => match minimal_push_size(len) - len {
+            1 => len as u8,
+            2 => opcodes::OP_PUSHDATA1.to_u8(),
+            3 => opcodes::OP_PUSHDATA2.to_u8(),
+            _ => opcodes::OP_PUSHDATA4.to_u8(),
+        },
+    };
+    let length_error = || AsmError::PushLength { opcode: Opcode::from(code).to_string(), length: len };
+
+    script.push(code);
+    if code == opcodes::OP_PUSHDATA1.to_u8() {
+        script.push(u8::try_from(len).map_err(|_| length_error())?);
+    } else if code == opcodes::OP_PUSHDATA2.to_u8() {
+        script.extend_from_slice(&u16::try_from(len).map_err(|_| length_error())?.to_le_bytes());
+    } else if code == opcodes::OP_PUSHDATA4.to_u8() {
+        script.extend_from_slice(&u32::try_from(len).map_err(|_| length_error())?.to_le_bytes());
+    } else if code as usize != len {
+        return Err(length_error());
+    }
+    script.extend_from_slice(data);
+    Ok(())
+}
+
+/// Parses ASM as produced by `disassemble` or `pretty_print` back into a
+/// script. Everything after a `#` on a line is a comment.
+///
+/// Besides opcode names, a token can be hex data, which is pushed with the
+/// shortest push opcode, or an explicit push opcode followed by its data (as
+/// in rust-bitcoin's `OP_PUSHBYTES_33 02...`).
+pub fn parse_asm(asm: &str) -> Result<ScriptBuf, AsmError> {
+    let mut script = Vec::new();
+    let mut tokens = asm
+        .lines()
+        .flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace());
+
+    while let Some(token) = tokens.next() {
+        if let Some(op) = lookup_opcode(token) {
+            let code = op.to_u8();
+            let is_push = (1..=opcodes::OP_PUSHDATA4.to_u8()).contains(&code);
+            if is_push {
+                let data = tokens.next().ok_or_else(|| AsmError::MissingPushData(op.to_string()))?;
+                let data = hex::decode(data).map_err(|_| AsmError::MissingPushData(op.to_string()))?;
+                push_data(&mut script, Some(op), &data)?;
+            } else {
+                script.push(code);
+            }
+        } else if let Ok(data) = hex::decode(token) {
+            push_data(&mut script, None, &data)?;
+        } else {
+            return Err(AsmError::UnknownToken(token.to_string()));
+        }
+    }
+    Ok(ScriptBuf::from_bytes(script))
+}
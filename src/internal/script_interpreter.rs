@@ -0,0 +1,859 @@
+#![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
+//! A segwit v0 script interpreter for checking hand-built witnesses offline.
+//!
+//! It follows Bitcoin Core's `EvalScript` for P2WSH spends, including the
+//! rules bitcoind enforces on relay that Lightning transactions rely on
+//! (MINIMALIF, NULLDUMMY, NULLFAIL, low-S signatures, compressed keys), and
+//! records every opcode with the stack before and after it runs.
+use bitcoin::blockdata::opcodes::all as opcodes;
+use bitcoin::blockdata::opcodes::Opcode;
+use bitcoin::hashes::{hash160, ripemd160, sha1, sha256, sha256d, Hash};
+use bitcoin::script::{read_scriptbool, write_scriptint, Instruction, Script};
+use bitcoin::secp256k1::{self, ecdsa::Signature, Message, PublicKey, Secp256k1};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{Amount, Transaction};
+use std::fmt;
+
+const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+const MAX_OPS_PER_SCRIPT: usize = 201;
+const MAX_STACK_SIZE: usize = 1000;
+const MAX_PUBKEYS_PER_MULTISIG: i64 = 20;
+const LOCKTIME_THRESHOLD: i64 = 500_000_000;
+const SEQUENCE_FINAL: u32 = 0xFFFFFFFF;
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: i64 = 1 << 31;
+const SEQUENCE_LOCKTIME_TYPE_FLAG: i64 = 1 << 22;
+const SEQUENCE_LOCKTIME_MASK: i64 = 0x0000FFFF;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScriptError {
+    /// The last witness element is not the witness script being spent.
+    WitnessScriptMismatch,
+    /// A push or an element on the stack is larger than 520 bytes.
+    PushSize,
+    /// More than 201 non-push opcodes were executed.
+    OpCount,
+    /// The stack and altstack hold more than 1000 elements.
+    StackSize,
+    /// The opcode needed more stack elements than there were.
+    StackUnderflow,
+    /// The script contains a push that runs past its end.
+    BadPush,
+    /// OP_RETURN, or an undefined or reserved opcode, was executed.
+    BadOpcode,
+    /// The opcode is disabled and fails even in an unexecuted branch.
+    DisabledOpcode,
+    UnbalancedConditional,
+    /// The argument to OP_IF or OP_NOTIF was neither empty nor `0x01`.
+    MinimalIf(Vec<u8>),
+    /// OP_VERIFY, OP_NUMEQUALVERIFY or a *VERIFY opcode found a false value.
+    Verify,
+    /// OP_EQUALVERIFY found two different elements.
+    EqualVerify { left: Vec<u8>, right: Vec<u8> },
+    /// An element used as a number is too long or not minimally encoded.
+    InvalidNumber(Vec<u8>),
+    /// The signature is not strict DER or has an undefined sighash type.
+    SigEncoding(Vec<u8>),
+    /// The signature's S value is not in the lower half of the curve order.
+    SigHighS(Vec<u8>),
+    /// The public key is not a valid compressed key.
+    PubkeyType(Vec<u8>),
+    /// A non-empty signature did not verify against this public key.
+    SignatureMismatch { pubkey: Vec<u8> },
+    /// A non-empty OP_CHECKMULTISIG signature verifies against none of the keys.
+    UnknownSignature(Vec<u8>),
+    /// Every signature matches one of the keys, but not in the order the keys
+    /// appear in the script.
+    MultisigOrder,
+    /// The extra element OP_CHECKMULTISIG consumes was not empty.
+    NullDummy,
+    /// OP_CHECKMULTISIG was given a bad key or signature count.
+    MultisigCount,
+    NegativeLocktime,
+    /// OP_CHECKLOCKTIMEVERIFY is not satisfied by the transaction.
+    UnsatisfiedLocktime { required: i64, lock_time: u32, sequence: u32 },
+    /// OP_CHECKSEQUENCEVERIFY is not satisfied by the input.
+    UnsatisfiedSequence { required: i64, sequence: u32, version: i32 },
+    /// The signature hash could not be computed.
+    Sighash(String),
+    /// Execution finished with an empty stack or a false value on top.
+    EvalFalse,
+    /// Execution finished with more than one element on the stack.
+    CleanStack { depth: usize },
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::WitnessScriptMismatch => {
+                write!(f, "last witness element is not the witness script")
+            }
+            ScriptError::PushSize => write!(f, "element larger than {} bytes", MAX_SCRIPT_ELEMENT_SIZE),
+            ScriptError::OpCount => write!(f, "more than {} opcodes", MAX_OPS_PER_SCRIPT),
+            ScriptError::StackSize => write!(f, "more than {} stack elements", MAX_STACK_SIZE),
+            ScriptError::StackUnderflow => write!(f, "not enough elements on the stack"),
+            ScriptError::BadPush => write!(f, "push runs past the end of the script"),
+            ScriptError::BadOpcode => write!(f, "OP_RETURN or undefined opcode executed"),
+            ScriptError::DisabledOpcode => write!(f, "disabled opcode"),
+            ScriptError::UnbalancedConditional => write!(f, "unbalanced OP_IF/OP_ELSE/OP_ENDIF"),
+            ScriptError::MinimalIf(arg) => write!(
+                f,
+                "OP_IF/OP_NOTIF argument must be empty or 01, got {}",
+                format_element(arg)
+            ),
+            ScriptError::Verify => write!(f, "verify failed: top of stack is false"),
+            ScriptError::EqualVerify { left, right } => write!(
+                f,
+                "elements are not equal: {} != {} (wrong preimage or key?)",
+                format_element(left),
+                format_element(right)
+            ),
+            ScriptError::InvalidNumber(element) => {
+                write!(f, "{} is not a valid script number", format_element(element))
+            }
+            ScriptError::SigEncoding(sig) => {
+                write!(f, "signature {} is not strict DER with a standard sighash type", format_element(sig))
+            }
+            ScriptError::SigHighS(sig) => write!(f, "signature {} has a high S value", format_element(sig)),
+            ScriptError::PubkeyType(pubkey) => {
+                write!(f, "{} is not a compressed public key", format_element(pubkey))
+            }
+            ScriptError::SignatureMismatch { pubkey } => {
+                write!(f, "signature does not verify against pubkey {}", format_element(pubkey))
+            }
+            ScriptError::UnknownSignature(sig) => {
+                write!(f, "signature {} does not verify against any key in the script", format_element(sig))
+            }
+            ScriptError::MultisigOrder => write!(
+                f,
+                "signatures are valid but not in the same order as the keys in the script"
+            ),
+            ScriptError::NullDummy => write!(f, "OP_CHECKMULTISIG dummy element must be empty"),
+            ScriptError::MultisigCount => write!(f, "bad OP_CHECKMULTISIG key or signature count"),
+            ScriptError::NegativeLocktime => write!(f, "negative locktime"),
+            ScriptError::UnsatisfiedLocktime { required, lock_time, sequence } => write!(
+                f,
+                "OP_CHECKLOCKTIMEVERIFY requires nLockTime >= {} of the same type and a non-final \
+                 input, but nLockTime is {} and nSequence is {:#010x}",
+                required, lock_time, sequence
+            ),
+            ScriptError::UnsatisfiedSequence { required, sequence, version } => write!(
+                f,
+                "OP_CHECKSEQUENCEVERIFY requires a relative locktime of {:#x}, but nSequence is \
+                 {:#010x} (transaction version {})",
+                required, sequence, version
+            ),
+            ScriptError::Sighash(e) => write!(f, "cannot compute sighash: {}", e),
+            ScriptError::EvalFalse => write!(f, "script finished with a false value on the stack"),
+            ScriptError::CleanStack { depth } => {
+                write!(f, "script finished with {} elements on the stack instead of 1", depth)
+            }
+        }
+    }
+}
+
+/// One opcode of a script run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceStep {
+    /// Position of the opcode among the script's instructions.
+    pub index: usize,
+    pub opcode: String,
+    /// `false` if the opcode sits in a branch that is not executed.
+    pub executed: bool,
+    pub stack_before: Vec<Vec<u8>>,
+    pub stack_after: Vec<Vec<u8>>,
+}
+
+/// Why a script run failed, and where.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptFailure {
+    /// The instruction that failed, or `None` for checks made before or
+    /// after execution.
+    pub index: Option<usize>,
+    pub opcode: Option<String>,
+    pub stack: Vec<Vec<u8>>,
+    pub error: ScriptError,
+}
+
+impl fmt::Display for ScriptFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.index, &self.opcode) {
+            (Some(index), Some(opcode)) => write!(f, "#{} {}: {}", index, opcode, self.error),
+            _ => write!(f, "{}", self.error),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptExecution {
+    pub steps: Vec<TraceStep>,
+    pub result: Result<(), ScriptFailure>,
+}
+
+impl ScriptExecution {
+    pub fn is_valid(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+impl fmt::Display for ScriptExecution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in self.steps.iter() {
+            if step.executed {
+                writeln!(
+                    f,
+                    "#{:<3} {:<24} {} -> {}",
+                    step.index,
+                    step.opcode,
+                    format_stack(&step.stack_before),
+                    format_stack(&step.stack_after)
+                )?;
+            } else {
+                writeln!(f, "#{:<3} {:<24} (not executed)", step.index, step.opcode)?;
+            }
+        }
+        match &self.result {
+            Ok(()) => write!(f, "OK"),
+            Err(failure) => write!(f, "FAILED {}\n     stack: {}", failure, format_stack(&failure.stack)),
+        }
+    }
+}
+
+fn format_element(element: &[u8]) -> String {
+    if element.is_empty() {
+        "<>".to_string()
+    } else {
+        hex::encode(element)
+    }
+}
+
+/// The stack, bottom first.
+fn format_stack(stack: &[Vec<u8>]) -> String {
+    let elements: Vec<String> = stack.iter().map(|element| format_element(element)).collect();
+    format!("[{}]", elements.join(" "))
+}
+
+fn opcode_name(instruction: &Instruction) -> String {
+    match instruction {
+        Instruction::PushBytes(bytes) if bytes.is_empty() => "OP_0".to_string(),
+        Instruction::PushBytes(bytes) => format!("OP_PUSHBYTES_{} {}", bytes.len(), hex::encode(bytes.as_bytes())),
+        Instruction::Op(op) => op.to_string(),
+    }
+}
+
+/// Decodes a minimally-encoded script number of at most `max_len` bytes.
+pub(crate) fn read_num(element: &[u8], max_len: usize) -> Result<i64, ScriptError> {
+    if element.len() > max_len {
+        return Err(ScriptError::InvalidNumber(element.to_vec()));
+    }
+    let last = match element.last() {
+        Some(last) => *last,
+        None => return Ok(0),
+    };
+    if last & 0x7f == 0 && (element.len() == 1 || element[element.len() - 2] & 0x80 == 0) {
+        return Err(ScriptError::InvalidNumber(element.to_vec()));
+    }
+
+    let mut result: i64 = 0;
+    for (i, byte) in element.iter().enumerate() {
+        result |= (*byte as i64) << (8 * i);
+    }
+    if last & 0x80 != 0 {
+        result &= !(0x80 << (8 * (element.len() - 1)));
+        result = -result;
+    }
+    Ok(result)
+}
+
+fn encode_num(n: i64) -> Vec<u8> {
+    let mut buf = [0u8; 8];
+    let len = write_scriptint(&mut buf, n);
+    buf[..len].to_vec()
+}
+
+fn encode_bool(b: bool) -> Vec<u8> {
+    if b {
+        vec![1]
+    } else {
+        vec![]
+    }
+}
+
+struct Interpreter<'a> {
+    secp: Secp256k1<secp256k1::VerifyOnly>,
+    tx: &'a Transaction,
+    input_index: usize,
+    amount: Amount,
+    script: &'a Script,
+    stack: Vec<Vec<u8>>,
+    altstack: Vec<Vec<u8>>,
+    exec: Vec<bool>,
+    op_count: usize,
+    /// Byte offset where the script code for signature hashing starts.
+    code_separator: usize,
+}
+
+impl<'a> Interpreter<'a> {
+    fn pop(&mut self) -> Result<Vec<u8>, ScriptError> {
+        self.stack.pop().ok_or(ScriptError::StackUnderflow)
+    }
+
+    fn pop_num(&mut self) -> Result<i64, ScriptError> {
+        let element = self.pop()?;
+        read_num(&element, 4)
+    }
+
+    /// The element `depth` places from the top (0 is the top).
+    fn peek(&self, depth: usize) -> Result<&Vec<u8>, ScriptError> {
+        if depth >= self.stack.len() {
+            return Err(ScriptError::StackUnderflow);
+        }
+        Ok(&self.stack[self.stack.len() - 1 - depth])
+    }
+
+    fn require(&self, count: usize) -> Result<(), ScriptError> {
+        if self.stack.len() < count {
+            return Err(ScriptError::StackUnderflow);
+        }
+        Ok(())
+    }
+
+    fn run(mut self) -> ScriptExecution {
+        let mut steps = Vec::new();
+
+        for (index, instruction) in self.script.instruction_indices().enumerate() {
+            let (position, instruction) = match instruction {
+                Ok(instruction) => instruction,
+                Err(_) => {
+                    return ScriptExecution {
+                        steps,
+                        result: Err(ScriptFailure {
+                            index: Some(index),
+                            opcode: None,
+                            stack: self.stack,
+                            error: ScriptError::BadPush,
+                        }),
+                    }
+                }
+            };
+
+            let opcode = opcode_name(&instruction);
+            let executed = self.exec.iter().all(|branch| *branch);
+            let stack_before = self.stack.clone();
+
+            let result = self.step(&instruction, executed, position).and_then(|_| {
+                if self.stack.len() + self.altstack.len() > MAX_STACK_SIZE {
+                    return Err(ScriptError::StackSize);
+                }
+                Ok(())
+            });
+
+            if let Err(error) = result {
+                return ScriptExecution {
+                    steps,
+                    result: Err(ScriptFailure {
+                        index: Some(index),
+                        opcode: Some(opcode),
+                        stack: stack_before,
+                        error,
+                    }),
+                };
+            }
+
+            steps.push(TraceStep {
+                index,
+                opcode,
+                executed,
+                stack_before,
+                stack_after: self.stack.clone(),
+            });
+        }
+
+        let error = if !self.exec.is_empty() {
+            Some(ScriptError::UnbalancedConditional)
+        } else if !self.stack.last().is_some_and(|top| read_scriptbool(top)) {
+            Some(ScriptError::EvalFalse)
+        } else if self.stack.len() != 1 {
+            Some(ScriptError::CleanStack { depth: self.stack.len() })
+        } else {
+            None
+        };
+
+        ScriptExecution {
+            steps,
+            result: match error {
+                Some(error) => Err(ScriptFailure { index: None, opcode: None, stack: self.stack, error }),
+                None => Ok(()),
+            },
+        }
+    }
+
+    fn step(&mut self, instruction: &Instruction, executed: bool, position: usize) -> Result<(), ScriptError> {
+        let op = match instruction {
+            Instruction::PushBytes(bytes) => {
+                if bytes.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(ScriptError::PushSize);
+                }
+                if executed {
+                    self.stack.push(bytes.as_bytes().to_vec());
+                }
+                return Ok(());
+            }
+            Instruction::Op(op) => *op,
+        };
+
+        if op.to_u8() > opcodes::OP_PUSHNUM_16.to_u8() {
+            self.op_count += 1;
+            if self.op_count > MAX_OPS_PER_SCRIPT {
+                return Err(ScriptError::OpCount);
+            }
+        }
+
+        match op {
+            opcodes::OP_CAT | opcodes::OP_SUBSTR | opcodes::OP_LEFT | opcodes::OP_RIGHT
+            | opcodes::OP_INVERT | opcodes::OP_AND | opcodes::OP_OR | opcodes::OP_XOR
+            | opcodes::OP_2MUL | opcodes::OP_2DIV | opcodes::OP_MUL | opcodes::OP_DIV
+            | opcodes::OP_MOD | opcodes::OP_LSHIFT | opcodes::OP_RSHIFT => {
+                return Err(ScriptError::DisabledOpcode);
+            }
+            opcodes::OP_VERIF | opcodes::OP_VERNOTIF => return Err(ScriptError::BadOpcode),
+            _ => {}
+        }
+
+        let is_conditional = matches!(
+            op,
+            opcodes::OP_IF | opcodes::OP_NOTIF | opcodes::OP_ELSE | opcodes::OP_ENDIF
+        );
+        if !executed && !is_conditional {
+            return Ok(());
+        }
+
+        match op {
+            opcodes::OP_PUSHNUM_NEG1 => self.stack.push(encode_num(-1)),
+            op if op.to_u8() >= opcodes::OP_PUSHNUM_1.to_u8() && op.to_u8() <= opcodes::OP_PUSHNUM_16.to_u8() => {
+                let n = (op.to_u8() - opcodes::OP_PUSHNUM_1.to_u8() + 1) as i64;
+                self.stack.push(encode_num(n));
+            }
+
+            opcodes::OP_NOP | opcodes::OP_NOP1 | opcodes::OP_NOP4 | opcodes::OP_NOP5
+            | opcodes::OP_NOP6 | opcodes::OP_NOP7 | opcodes::OP_NOP8 | opcodes::OP_NOP9
+            | opcodes::OP_NOP10 => {}
+
+            opcodes::OP_IF | opcodes::OP_NOTIF => {
+                let mut value = false;
+                if executed {
+                    let condition = self.pop()?;
+                    if condition.len() > 1 || (condition.len() == 1 && condition[0] != 1) {
+                        return Err(ScriptError::MinimalIf(condition));
+                    }
+                    value = read_scriptbool(&condition);
+                    if op == opcodes::OP_NOTIF {
+                        value = !value;
+                    }
+                }
+                self.exec.push(value);
+            }
+            opcodes::OP_ELSE => {
+                let branch = self.exec.last_mut().ok_or(ScriptError::UnbalancedConditional)?;
+                *branch = !*branch;
+            }
+            opcodes::OP_ENDIF => {
+                self.exec.pop().ok_or(ScriptError::UnbalancedConditional)?;
+            }
+            opcodes::OP_VERIFY => {
+                if !read_scriptbool(&self.pop()?) {
+                    return Err(ScriptError::Verify);
+                }
+            }
+            opcodes::OP_RETURN => return Err(ScriptError::BadOpcode),
+
+            opcodes::OP_TOALTSTACK => {
+                let element = self.pop()?;
+                self.altstack.push(element);
+            }
+            opcodes::OP_FROMALTSTACK => {
+                let element = self.altstack.pop().ok_or(ScriptError::StackUnderflow)?;
+                self.stack.push(element);
+            }
+            opcodes::OP_2DROP => {
+                self.require(2)?;
+                self.stack.truncate(self.stack.len() - 2);
+            }
+            opcodes::OP_2DUP => {
+                let (a, b) = (self.peek(1)?.clone(), self.peek(0)?.clone());
+                self.stack.extend([a, b]);
+            }
+            opcodes::OP_3DUP => {
+                let (a, b, c) = (self.peek(2)?.clone(), self.peek(1)?.clone(), self.peek(0)?.clone());
+                self.stack.extend([a, b, c]);
+            }
+            opcodes::OP_2OVER => {
+                let (a, b) = (self.peek(3)?.clone(), self.peek(2)?.clone());
+                self.stack.extend([a, b]);
+            }
+            opcodes::OP_2ROT => {
+                self.require(6)?;
+                let start = self.stack.len() - 6;
+                let moved: Vec<Vec<u8>> = self.stack.drain(start..start + 2).collect();
+                self.stack.extend(moved);
+            }
+            opcodes::OP_2SWAP => {
+                self.require(4)?;
+                let len = self.stack.len();
+                self.stack.swap(len - 4, len - 2);
+                self.stack.swap(len - 3, len - 1);
+            }
+            opcodes::OP_IFDUP => {
+                let top = self.peek(0)?.clone();
+                if read_scriptbool(&top) {
+                    self.stack.push(top);
+                }
+            }
+            opcodes::OP_DEPTH => self.stack.push(encode_num(self.stack.len() as i64)),
+            opcodes::OP_DROP => {
+                self.pop()?;
+            }
+            opcodes::OP_DUP => {
+                let top = self.peek(0)?.clone();
+                self.stack.push(top);
+            }
+            opcodes::OP_NIP => {
+                self.require(2)?;
+                let len = self.stack.len();
+                self.stack.remove(len - 2);
+            }
+            opcodes::OP_OVER => {
+                let second = self.peek(1)?.clone();
+                self.stack.push(second);
+            }
+            opcodes::OP_PICK | opcodes::OP_ROLL => {
+                let n = self.pop_num()?;
+                if n < 0 || n as usize >= self.stack.len() {
+                    return Err(ScriptError::StackUnderflow);
+                }
+                let position = self.stack.len() - 1 - n as usize;
+                let element = if op == opcodes::OP_ROLL {
+                    self.stack.remove(position)
+                } else {
+                    self.stack[position].clone()
+                };
+                self.stack.push(element);
+            }
+            opcodes::OP_ROT => {
+                self.require(3)?;
+                let len = self.stack.len();
+                let element = self.stack.remove(len - 3);
+                self.stack.push(element);
+            }
+            opcodes::OP_SWAP => {
+                self.require(2)?;
+                let len = self.stack.len();
+                self.stack.swap(len - 2, len - 1);
+            }
+            opcodes::OP_TUCK => {
+                self.require(2)?;
+                let top = self.peek(0)?.clone();
+                let len = self.stack.len();
+                self.stack.insert(len - 2, top);
+            }
+            opcodes::OP_SIZE => {
+                let size = self.peek(0)?.len() as i64;
+                self.stack.push(encode_num(size));
+            }
+
+            opcodes::OP_EQUAL | opcodes::OP_EQUALVERIFY => {
+                let right = self.pop()?;
+                let left = self.pop()?;
+                let equal = left == right;
+                if op == opcodes::OP_EQUALVERIFY {
+                    if !equal {
+                        return Err(ScriptError::EqualVerify { left, right });
+                    }
+                } else {
+                    self.stack.push(encode_bool(equal));
+                }
+            }
+
+            opcodes::OP_1ADD | opcodes::OP_1SUB | opcodes::OP_NEGATE | opcodes::OP_ABS
+            | opcodes::OP_NOT | opcodes::OP_0NOTEQUAL => {
+                let n = self.pop_num()?;
+                let result = match op {
+                    opcodes::OP_1ADD => n + 1,
+                    opcodes::OP_1SUB => n - 1,
+                    opcodes::OP_NEGATE => -n,
+                    opcodes::OP_ABS => n.abs(),
+                    opcodes::OP_NOT => (n == 0) as i64,
+                    _ => (n != 0) as i64,
+                };
+                self.stack.push(encode_num(result));
+            }
+            opcodes::OP_ADD | opcodes::OP_SUB | opcodes::OP_BOOLAND | opcodes::OP_BOOLOR
+            | opcodes::OP_NUMEQUAL | opcodes::OP_NUMEQUALVERIFY | opcodes::OP_NUMNOTEQUAL
+            | opcodes::OP_LESSTHAN | opcodes::OP_GREATERTHAN | opcodes::OP_LESSTHANOREQUAL
+            | opcodes::OP_GREATERTHANOREQUAL | opcodes::OP_MIN | opcodes::OP_MAX => {
+                let b = self.pop_num()?;
+                let a = self.pop_num()?;
+                let result = match op {
+                    opcodes::OP_ADD => a + b,
+                    opcodes::OP_SUB => a - b,
+                    opcodes::OP_BOOLAND => (a != 0 && b != 0) as i64,
+                    opcodes::OP_BOOLOR => (a != 0 || b != 0) as i64,
+                    opcodes::OP_NUMEQUAL | opcodes::OP_NUMEQUALVERIFY => (a == b) as i64,
+                    opcodes::OP_NUMNOTEQUAL => (a != b) as i64,
+                    opcodes::OP_LESSTHAN => (a < b) as i64,
+                    opcodes::OP_GREATERTHAN => (a > b) as i64,
+                    opcodes::OP_LESSTHANOREQUAL => (a <= b) as i64,
+                    opcodes::OP_GREATERTHANOREQUAL => (a >= b) as i64,
+                    opcodes::OP_MIN => a.min(b),
+                    _ => a.max(b),
+                };
+                if op == opcodes::OP_NUMEQUALVERIFY {
+                    if result == 0 {
+                        return Err(ScriptError::Verify);
+                    }
+                } else {
+                    self.stack.push(encode_num(result));
+                }
+            }
+            opcodes::OP_WITHIN => {
+                let max = self.pop_num()?;
+                let min = self.pop_num()?;
+                let x = self.pop_num()?;
+                self.stack.push(encode_bool(min <= x && x < max));
+            }
+
+            opcodes::OP_RIPEMD160 | opcodes::OP_SHA1 | opcodes::OP_SHA256 | opcodes::OP_HASH160
+            | opcodes::OP_HASH256 => {
+                let element = self.pop()?;
+                let hash = match op {
+                    opcodes::OP_RIPEMD160 => ripemd160::Hash::hash(&element).to_byte_array().to_vec(),
+                    opcodes::OP_SHA1 => sha1::Hash::hash(&element).to_byte_array().to_vec(),
+                    opcodes::OP_SHA256 => sha256::Hash::hash(&element).to_byte_array().to_vec(),
+                    opcodes::OP_HASH160 => hash160::Hash::hash(&element).to_byte_array().to_vec(),
+                    _ => sha256d::Hash::hash(&element).to_byte_array().to_vec(),
+                };
+                self.stack.push(hash);
+            }
+            opcodes::OP_CODESEPARATOR => self.code_separator = position + 1,
+
+            opcodes::OP_CHECKSIG | opcodes::OP_CHECKSIGVERIFY => {
+                let pubkey = self.pop()?;
+                let signature = self.pop()?;
+                let valid = self.check_signature(&signature, &pubkey)?;
+                if !valid && !signature.is_empty() {
+                    return Err(ScriptError::SignatureMismatch { pubkey });
+                }
+                if op == opcodes::OP_CHECKSIGVERIFY {
+                    if !valid {
+                        return Err(ScriptError::Verify);
+                    }
+                } else {
+                    self.stack.push(encode_bool(valid));
+                }
+            }
+            opcodes::OP_CHECKMULTISIG | opcodes::OP_CHECKMULTISIGVERIFY => {
+                let valid = self.check_multisig()?;
+                if op == opcodes::OP_CHECKMULTISIGVERIFY {
+                    if !valid {
+                        return Err(ScriptError::Verify);
+                    }
+                } else {
+                    self.stack.push(encode_bool(valid));
+                }
+            }
+
+            opcodes::OP_CLTV => {
+                let required = read_num(self.peek(0)?, 5)?;
+                self.check_lock_time(required)?;
+            }
+            opcodes::OP_CSV => {
+                let required = read_num(self.peek(0)?, 5)?;
+                self.check_sequence(required)?;
+            }
+
+            _ => return Err(ScriptError::BadOpcode),
+        }
+
+        Ok(())
+    }
+
+    /// Checks a signature (with its trailing sighash byte) against `pubkey`.
+    /// An empty signature is simply invalid; a malformed one is an error.
+    fn check_signature(&self, signature: &[u8], pubkey: &[u8]) -> Result<bool, ScriptError> {
+        if signature.is_empty() {
+            return Ok(false);
+        }
+        let (sighash_byte, der) = signature.split_last().unwrap();
+        let sighash_type = EcdsaSighashType::from_standard(*sighash_byte as u32)
+            .map_err(|_| ScriptError::SigEncoding(signature.to_vec()))?;
+        let sig = Signature::from_der(der).map_err(|_| ScriptError::SigEncoding(signature.to_vec()))?;
+        let mut normalized = sig;
+        normalized.normalize_s();
+        if normalized != sig {
+            return Err(ScriptError::SigHighS(signature.to_vec()));
+        }
+
+        if pubkey.len() != 33 {
+            return Err(ScriptError::PubkeyType(pubkey.to_vec()));
+        }
+        let key = PublicKey::from_slice(pubkey).map_err(|_| ScriptError::PubkeyType(pubkey.to_vec()))?;
+
+        let script_code = Script::from_bytes(&self.script.as_bytes()[self.code_separator..]);
+        let sighash = SighashCache::new(self.tx)
+            .p2wsh_signature_hash(self.input_index, script_code, self.amount, sighash_type)
+            .map_err(|e| ScriptError::Sighash(e.to_string()))?;
+        let message = Message::from_digest(sighash.to_byte_array());
+
+        Ok(self.secp.verify_ecdsa(&message, &sig, &key).is_ok())
+    }
+
+    /// OP_CHECKMULTISIG as in Bitcoin Core: signatures must appear in the
+    /// same order as the keys they sign for.
+    fn check_multisig(&mut self) -> Result<bool, ScriptError> {
+        let key_count = self.pop_num()?;
+        if !(0..=MAX_PUBKEYS_PER_MULTISIG).contains(&key_count) {
+            return Err(ScriptError::MultisigCount);
+        }
+        self.op_count += key_count as usize;
+        if self.op_count > MAX_OPS_PER_SCRIPT {
+            return Err(ScriptError::OpCount);
+        }
+        self.require(key_count as usize)?;
+        let keys = self.stack.split_off(self.stack.len() - key_count as usize);
+
+        let sig_count = self.pop_num()?;
+        if sig_count < 0 || sig_count > key_count {
+            return Err(ScriptError::MultisigCount);
+        }
+        self.require(sig_count as usize)?;
+        let signatures = self.stack.split_off(self.stack.len() - sig_count as usize);
+
+        // The off-by-one bug: one extra element is consumed, and it must be empty.
+        if !self.pop()?.is_empty() {
+            return Err(ScriptError::NullDummy);
+        }
+
+        // Walk keys and signatures from the last one pushed, as Core does.
+        let mut remaining_keys = keys.iter().rev().peekable();
+        let mut remaining_sigs = signatures.iter().rev().peekable();
+        let mut valid = true;
+        while let Some(signature) = remaining_sigs.peek() {
+            let key = match remaining_keys.next() {
+                Some(key) => key,
+                None => {
+                    valid = false;
+                    break;
+                }
+            };
+            if self.check_signature(signature, key)? {
+                remaining_sigs.next();
+            }
+            if remaining_sigs.len() > remaining_keys.len() {
+                valid = false;
+                break;
+            }
+        }
+
+        if valid || signatures.iter().all(|signature| signature.is_empty()) {
+            return Ok(valid);
+        }
+
+        // NULLFAIL: say which signature is at fault.
+        for signature in signatures.iter().filter(|signature| !signature.is_empty()) {
+            let mut matches_a_key = false;
+            for key in keys.iter() {
+                if self.check_signature(signature, key)? {
+                    matches_a_key = true;
+                }
+            }
+            if !matches_a_key {
+                return Err(ScriptError::UnknownSignature(signature.clone()));
+            }
+        }
+        Err(ScriptError::MultisigOrder)
+    }
+
+    /// BIP 65.
+    fn check_lock_time(&self, required: i64) -> Result<(), ScriptError> {
+        if required < 0 {
+            return Err(ScriptError::NegativeLocktime);
+        }
+        let lock_time = self.tx.lock_time.to_consensus_u32();
+        let sequence = self.tx.input[self.input_index].sequence.0;
+        let unsatisfied = ScriptError::UnsatisfiedLocktime { required, lock_time, sequence };
+
+        let same_type = (required < LOCKTIME_THRESHOLD) == ((lock_time as i64) < LOCKTIME_THRESHOLD);
+        if !same_type || required > lock_time as i64 || sequence == SEQUENCE_FINAL {
+            return Err(unsatisfied);
+        }
+        Ok(())
+    }
+
+    /// BIP 112.
+    fn check_sequence(&self, required: i64) -> Result<(), ScriptError> {
+        if required < 0 {
+            return Err(ScriptError::NegativeLocktime);
+        }
+        if required & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return Ok(());
+        }
+        let version = self.tx.version.0;
+        let sequence = self.tx.input[self.input_index].sequence.0;
+        let unsatisfied = ScriptError::UnsatisfiedSequence { required, sequence, version };
+
+        if version < 2 || sequence as i64 & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return Err(unsatisfied);
+        }
+        let mask = SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
+        let required_masked = required & mask;
+        let sequence_masked = sequence as i64 & mask;
+        let same_type = (required_masked < SEQUENCE_LOCKTIME_TYPE_FLAG)
+            == (sequence_masked < SEQUENCE_LOCKTIME_TYPE_FLAG);
+        if !same_type || required_masked > sequence_masked {
+            return Err(unsatisfied);
+        }
+        Ok(())
+    }
+}
+
+/// Runs the P2WSH spend of `tx`'s input `input_index`: the witness elements
+/// before the last one are the initial stack, and the last one must be
+/// `witness_script`. `prevout_value` (in satoshis) is needed for the segwit
+/// signature hash.
+pub fn verify_p2wsh_input(
+    tx: &Transaction,
+    input_index: usize,
+    prevout_value: u64,
+    witness_script: &Script,
+) -> ScriptExecution {
+    let failure = |stack: Vec<Vec<u8>>, error| ScriptExecution {
+        steps: Vec::new(),
+        result: Err(ScriptFailure { index: None, opcode: None, stack, error }),
+    };
+
+    let input = match tx.input.get(input_index) {
+        Some(input) => input,
+        None => return failure(Vec::new(), ScriptError::Sighash(format!("no input {}", input_index))),
+    };
+
+    let mut stack: Vec<Vec<u8>> = input.witness.iter().map(|element| element.to_vec()).collect();
+    if stack.pop().as_deref() != Some(witness_script.as_bytes()) {
+        return failure(stack, ScriptError::WitnessScriptMismatch);
+    }
+    if stack.iter().any(|element| element.len() > MAX_SCRIPT_ELEMENT_SIZE) {
+        return failure(stack, ScriptError::PushSize);
+    }
+
+    Interpreter {
+        secp: Secp256k1::verification_only(),
+        tx,
+        input_index,
+        amount: Amount::from_sat(prevout_value),
+        script: witness_script,
+        stack,
+        altstack: Vec::new(),
+        exec: Vec::new(),
+        op_count: 0,
+        code_separator: 0,
+    }
+    .run()
+}
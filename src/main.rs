@@ -1,10 +1,13 @@
 #![allow(dead_code, unused_imports, unused_variables, unused_must_use)]
 use clap::{Parser, Subcommand, ValueEnum};
-use pl_00_intro::interactive::{funding, refund, commit, htlc, htlc_timeout, htlc_demo, htlc_demo2, mempool};
+use pl_00_intro::interactive::{funding, refund, commit, htlc, htlc_timeout, htlc_demo, htlc_demo2, mempool, penalty, splice, keys};
 use pl_00_intro::ch2_setup::peer_listener_exercise;
 use pl_00_intro::interactive::mempool::MempoolCommand;
 use sha2::{Sha256, Digest};
 use ripemd::{Ripemd160};
+use pl_00_intro::internal::script_disasm::{parse_asm, pretty_print};
+use bitcoin::{Network, ScriptBuf};
+use std::path::PathBuf;
 
 /// Main CLI structure
 #[derive(Parser)]
@@ -47,6 +50,22 @@ enum Commands {
         #[arg(short = 't', long, help = "HTLC Tx ID")]
         htlc_txid: String,
     },
+    Penalty {
+        #[arg(short = 't', long, help = "Funding Tx ID")]
+        funding_txid: String,
+    },
+    SpliceIn {
+        #[arg(short = 't', long, help = "Funding Tx ID")]
+        funding_txid: String,
+    },
+    SpliceOut {
+        #[arg(short = 't', long, help = "Funding Tx ID")]
+        funding_txid: String,
+    },
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommand,
+    },
     PeerListen {
         #[arg(short, long, default_value = "9735", help = "Port to listen on")]
         port: u16,
@@ -67,9 +86,38 @@ enum Commands {
     ToHex {
         #[arg(short = 'd', long, help = "Input string to convert to hex")]
         input_string: String,
+    },
+    Disasm {
+        #[arg(short = 's', long, help = "Script hex to disassemble")]
+        script: Option<String>,
+        #[arg(short = 'a', long, help = "Script ASM to assemble", conflicts_with = "script")]
+        asm: Option<String>,
     }
   }
 
+/// `keys` subcommands
+#[derive(Subcommand)]
+enum KeysCommand {
+    /// Create a node from a new BIP39 mnemonic
+    New {
+        #[arg(short = 'w', long, default_value = "24", help = "Number of words")]
+        words: usize,
+        #[arg(short = 'p', long, help = "File holding an optional BIP39 passphrase, - for stdin")]
+        passphrase_file: Option<PathBuf>,
+        #[arg(short = 'n', long, default_value = "regtest", help = "Network")]
+        network: Network,
+    },
+    /// Restore a node from its BIP39 mnemonic
+    Restore {
+        #[arg(short = 'm', long, default_value = "-", help = "File holding the mnemonic words, - for stdin")]
+        mnemonic_file: PathBuf,
+        #[arg(short = 'p', long, help = "File holding an optional BIP39 passphrase, - for stdin")]
+        passphrase_file: Option<PathBuf>,
+        #[arg(short = 'n', long, default_value = "regtest", help = "Network")]
+        network: Network,
+    },
+}
+
 
 #[tokio::main]
 async fn main() {
@@ -81,6 +129,17 @@ async fn main() {
         Commands::Commit { funding_txid } => commit::run(funding_txid.clone()).await,
         Commands::Htlc { funding_txid } => htlc::run(funding_txid.clone()).await,
         Commands::HtlcTimeout { htlc_txid } => htlc_timeout::run(htlc_txid.clone()).await,
+        Commands::Penalty { funding_txid } => penalty::run(funding_txid.clone()).await,
+        Commands::SpliceIn { funding_txid } => splice::run_splice_in(funding_txid.clone()).await,
+        Commands::SpliceOut { funding_txid } => splice::run_splice_out(funding_txid.clone()).await,
+        Commands::Keys { command } => match command {
+            KeysCommand::New { words, passphrase_file, network } => {
+                keys::run_new(*words, passphrase_file.as_deref(), *network).await
+            },
+            KeysCommand::Restore { mnemonic_file, passphrase_file, network } => {
+                keys::run_restore(mnemonic_file, passphrase_file.as_deref(), *network).await
+            },
+        },
         Commands::PeerListen { port } => peer_listener_exercise::run(*port).await,
         Commands::HtlcDemo => htlc_demo::run().await,
         Commands::HtlcDemo2 { txid } => htlc_demo2::run(txid.clone()).await,
@@ -110,6 +169,23 @@ async fn main() {
             let data = hex::encode(input_string);
 
             println!("Hex: {:?}", data);
+        },
+        Commands::Disasm { script, asm } => {
+            let script = match (script, asm) {
+                (Some(script), _) => match hex::decode(script) {
+                    Ok(bytes) => ScriptBuf::from_bytes(bytes),
+                    Err(e) => return println!("Invalid script hex: {}", e),
+                },
+                (None, Some(asm)) => match parse_asm(asm) {
+                    Ok(script) => script,
+                    Err(e) => return println!("Invalid script ASM: {}", e),
+                },
+                (None, None) => return println!("Pass a script with --script or --asm"),
+            };
+
+            println!("Script (hex): {}", script.to_hex_string());
+            println!("P2WSH scriptPubKey: {}", script.to_p2wsh().to_hex_string());
+            println!("{}", pretty_print(&script));
         }
     }
 }
\ No newline at end of file